@@ -0,0 +1,3 @@
+-- Add migration script here
+-- Lets the expiry sweeper find stale keys without scanning the whole table
+CREATE INDEX idempotency_created_at_idx ON idempotency (created_at);
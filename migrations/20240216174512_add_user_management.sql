@@ -0,0 +1,24 @@
+-- Add migration script here
+BEGIN;
+
+ALTER TABLE users ADD COLUMN email TEXT NULL;
+ALTER TABLE users ADD COLUMN disabled BOOLEAN NOT NULL DEFAULT false;
+
+-- Invited users have no password until they accept their invitation
+ALTER TABLE users ALTER COLUMN password_hash DROP NOT NULL;
+
+CREATE TABLE user_invitations(
+  invitation_token TEXT NOT NULL,
+  user_id          uuid NOT NULL
+    REFERENCES users (user_id) ON DELETE CASCADE,
+  expires_at       timestamptz NOT NULL,
+  PRIMARY KEY (invitation_token)
+);
+
+-- Deleting a user also drops their saved idempotency keys
+ALTER TABLE idempotency DROP CONSTRAINT idempotency_user_id_fkey;
+ALTER TABLE idempotency
+  ADD CONSTRAINT idempotency_user_id_fkey
+  FOREIGN KEY (user_id) REFERENCES users (user_id) ON DELETE CASCADE;
+
+COMMIT;
@@ -0,0 +1,12 @@
+-- Add migration script here
+BEGIN;
+
+-- New accounts get the least privileged role unless told otherwise
+ALTER TABLE users ADD COLUMN role TEXT NOT NULL DEFAULT 'viewer';
+ALTER TABLE users
+  ADD CONSTRAINT users_role_check CHECK (role IN ('owner', 'editor', 'viewer'));
+
+-- Everybody was allowed to do everything before roles existed
+UPDATE users SET role = 'owner';
+
+COMMIT;
@@ -0,0 +1,19 @@
+-- Add migration script here
+BEGIN;
+
+-- The secret is stored as soon as enrollment starts, but only enforced at
+-- login once the user has confirmed it with a valid code.
+ALTER TABLE users ADD COLUMN totp_secret TEXT NULL;
+ALTER TABLE users ADD COLUMN totp_enabled BOOLEAN NOT NULL DEFAULT false;
+-- Last accepted time step, so that a code cannot be replayed
+ALTER TABLE users ADD COLUMN totp_last_step BIGINT NULL;
+
+CREATE TABLE recovery_codes(
+  user_id   uuid NOT NULL
+    REFERENCES users (user_id) ON DELETE CASCADE,
+  code_hash TEXT NOT NULL,
+  used_at   timestamptz NULL,
+  PRIMARY KEY (user_id, code_hash)
+);
+
+COMMIT;
@@ -0,0 +1,9 @@
+-- Add migration script here
+-- Tokens are stored hashed since they grant access to an account
+CREATE TABLE password_reset_tokens(
+  token_hash TEXT NOT NULL,
+  user_id    uuid NOT NULL
+    REFERENCES users (user_id) ON DELETE CASCADE,
+  expires_at timestamptz NOT NULL,
+  PRIMARY KEY (token_hash)
+);
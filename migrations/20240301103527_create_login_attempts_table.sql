@@ -0,0 +1,16 @@
+-- Add migration script here
+-- Attempts on unknown usernames are kept too, without a user_id
+CREATE TABLE login_attempts(
+  attempt_id   uuid NOT NULL,
+  username     TEXT NOT NULL,
+  user_id      uuid NULL
+    REFERENCES users (user_id) ON DELETE CASCADE,
+  ip_address   TEXT NULL,
+  user_agent   TEXT NULL,
+  outcome      TEXT NOT NULL
+    CHECK (outcome IN ('success', 'failure', 'locked')),
+  attempted_at timestamptz NOT NULL,
+  PRIMARY KEY (attempt_id)
+);
+
+CREATE INDEX login_attempts_user_id_idx ON login_attempts (user_id, attempted_at);
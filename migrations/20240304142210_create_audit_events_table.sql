@@ -0,0 +1,17 @@
+-- Add migration script here
+-- The actor is not a foreign key: events must outlive the users they mention,
+-- which is also why the username is copied at the time of the event.
+CREATE TABLE audit_events(
+  event_id       uuid NOT NULL,
+  actor_id       uuid NULL,
+  actor_username TEXT NULL,
+  action         TEXT NOT NULL,
+  target         TEXT NULL,
+  ip_address     TEXT NULL,
+  user_agent     TEXT NULL,
+  request_id     TEXT NULL,
+  occurred_at    timestamptz NOT NULL,
+  PRIMARY KEY (event_id)
+);
+
+CREATE INDEX audit_events_occurred_at_idx ON audit_events (occurred_at);
@@ -0,0 +1,30 @@
+-- Add migration script here
+BEGIN;
+
+-- Tokens go away with their subscriber
+ALTER TABLE subscription_tokens
+  DROP CONSTRAINT subscription_tokens_subscriber_id_fkey,
+  ADD CONSTRAINT subscription_tokens_subscriber_id_fkey
+    FOREIGN KEY (subscriber_id) REFERENCES subscriptions (id) ON DELETE CASCADE;
+
+-- What happened to each subscription over time. `actor_id` is the admin who
+-- did it, if it was not the subscriber.
+CREATE TABLE subscription_events(
+  event_id      uuid NOT NULL,
+  subscriber_id uuid NOT NULL
+    REFERENCES subscriptions (id) ON DELETE CASCADE,
+  event         TEXT NOT NULL,
+  actor_id      uuid NULL,
+  occurred_at   timestamptz NOT NULL,
+  PRIMARY KEY (event_id)
+);
+
+CREATE INDEX subscription_events_subscriber_id_idx
+  ON subscription_events (subscriber_id, occurred_at);
+
+-- Existing subscribers at least have their signup on record
+INSERT INTO subscription_events (event_id, subscriber_id, event, occurred_at)
+SELECT gen_random_uuid(), id, 'subscribed', subscribed_at
+FROM subscriptions;
+
+COMMIT;
@@ -0,0 +1,13 @@
+-- Add migration script here
+-- One row per CSV upload, with the rows that were turned down so that they
+-- can be downloaded and fixed
+CREATE TABLE subscriber_imports(
+  import_id       uuid NOT NULL,
+  actor_id        uuid NULL,
+  file_name       TEXT NOT NULL,
+  imported_count  INTEGER NOT NULL,
+  rejected_count  INTEGER NOT NULL,
+  rejected_report TEXT NOT NULL,
+  created_at      timestamptz NOT NULL,
+  PRIMARY KEY (import_id)
+);
@@ -0,0 +1,8 @@
+-- Add migration script here
+-- Addresses erased at their owner's request, kept only as a SHA-256 hash of
+-- the lowercased address so that they are not imported again
+CREATE TABLE erased_subscribers(
+  email_hash TEXT NOT NULL,
+  erased_at  timestamptz NOT NULL,
+  PRIMARY KEY (email_hash)
+);
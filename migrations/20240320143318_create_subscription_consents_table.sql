@@ -0,0 +1,28 @@
+-- Add migration script here
+-- Evidence of consent, captured at signup and when the confirmation link is
+-- followed. Rows are never updated; they only go away with their subscriber
+-- when the subscriber is deleted or erased.
+CREATE TABLE subscription_consents(
+  consent_id    uuid NOT NULL,
+  subscriber_id uuid NOT NULL
+    REFERENCES subscriptions (id) ON DELETE CASCADE,
+  kind          TEXT NOT NULL,
+  ip_address    TEXT NULL,
+  user_agent    TEXT NULL,
+  source        TEXT NULL,
+  recorded_at   timestamptz NOT NULL,
+  PRIMARY KEY (consent_id)
+);
+
+CREATE INDEX subscription_consents_subscriber_id_idx
+  ON subscription_consents (subscriber_id, recorded_at);
+
+CREATE FUNCTION forbid_consent_updates() RETURNS trigger AS $$
+BEGIN
+  RAISE EXCEPTION 'subscription_consents is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER subscription_consents_append_only
+  BEFORE UPDATE ON subscription_consents
+  FOR EACH ROW EXECUTE FUNCTION forbid_consent_updates();
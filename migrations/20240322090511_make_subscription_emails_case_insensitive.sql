@@ -0,0 +1,42 @@
+-- Add migration script here
+-- Emails are compared without regard to case from now on. Two existing
+-- subscriptions differing only by case cannot both stay, and picking one
+-- would mean throwing away a subscription and its consent records, so the
+-- migration stops and lists them instead. Resolve them from the admin area
+-- (or `DELETE FROM subscriptions WHERE id = ...`) and run it again.
+BEGIN;
+
+DO $$
+DECLARE
+  duplicates TEXT;
+  duplicate_count INTEGER;
+BEGIN
+  SELECT
+    string_agg(format('%s: %s', key, ids), E'\n' ORDER BY key),
+    count(*)
+  INTO duplicates, duplicate_count
+  FROM (
+    SELECT lower(trim(email)) AS key, string_agg(id::text || ' <' || email || '>', ', ') AS ids
+    FROM subscriptions
+    GROUP BY lower(trim(email))
+    HAVING count(*) > 1
+  ) AS groups;
+
+  IF duplicate_count > 0 THEN
+    RAISE EXCEPTION E'% email addresses are subscribed more than once when ignoring case:\n%',
+      duplicate_count, duplicates;
+  END IF;
+END
+$$;
+
+-- New addresses are trimmed and get a lowercase domain when parsed, bring the
+-- existing ones in line. IDN domains stay as they are: punycode conversion
+-- only applies to new signups.
+UPDATE subscriptions
+SET email = substring(trim(email) from '^(.*)@') || '@' || lower(substring(trim(email) from '@([^@]*)$'))
+WHERE email <> substring(trim(email) from '^(.*)@') || '@' || lower(substring(trim(email) from '@([^@]*)$'));
+
+ALTER TABLE subscriptions DROP CONSTRAINT subscriptions_email_key;
+CREATE UNIQUE INDEX subscriptions_email_lower_idx ON subscriptions (lower(email));
+
+COMMIT;
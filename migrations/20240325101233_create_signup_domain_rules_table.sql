@@ -0,0 +1,21 @@
+-- Add migration script here
+-- Admin-managed rules on the email domains allowed to sign up, on top of the
+-- bundled list of disposable domains
+CREATE TABLE signup_domain_rules(
+  rule_id    uuid NOT NULL,
+  pattern    TEXT NOT NULL UNIQUE,
+  kind       TEXT NOT NULL CHECK (kind IN ('block', 'allow')),
+  created_at timestamptz NOT NULL,
+  PRIMARY KEY (rule_id)
+);
+
+-- One counter per domain and reason rather than a row per attempt. Junk
+-- signups from ever new domains would still add rows, so only the most
+-- recently attempted ones are kept, see `domain_policy::check_signup_domain`
+CREATE TABLE blocked_signups(
+  domain          TEXT NOT NULL,
+  reason          TEXT NOT NULL,
+  attempts        BIGINT NOT NULL,
+  last_attempt_at timestamptz NOT NULL,
+  PRIMARY KEY (domain, reason)
+);
@@ -0,0 +1,13 @@
+-- Add migration script here
+-- Custom fields collected at signup, checked against `subscriber_attributes`
+ALTER TABLE subscriptions ADD COLUMN attributes JSONB NOT NULL DEFAULT '{}';
+
+-- The attributes admins allow on subscribers
+CREATE TABLE subscriber_attributes(
+  name       TEXT NOT NULL,
+  label      TEXT NOT NULL,
+  kind       TEXT NOT NULL CHECK (kind IN ('text', 'number', 'boolean', 'date')),
+  required   BOOLEAN NOT NULL,
+  created_at timestamptz NOT NULL,
+  PRIMARY KEY (name)
+);
@@ -0,0 +1,18 @@
+-- Add migration script here
+-- Labels admins put on subscribers, by hand, in bulk or at signup
+CREATE TABLE tags(
+  name       TEXT NOT NULL,
+  created_at timestamptz NOT NULL,
+  PRIMARY KEY (name)
+);
+
+CREATE TABLE subscription_tags(
+  subscriber_id uuid NOT NULL
+    REFERENCES subscriptions (id) ON DELETE CASCADE,
+  tag           TEXT NOT NULL
+    REFERENCES tags (name) ON DELETE CASCADE,
+  tagged_at     timestamptz NOT NULL,
+  PRIMARY KEY (subscriber_id, tag)
+);
+
+CREATE INDEX subscription_tags_tag_idx ON subscription_tags (tag);
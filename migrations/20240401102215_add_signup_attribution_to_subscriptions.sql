@@ -0,0 +1,13 @@
+-- Add migration script here
+-- Where a signup comes from: the form or page it was sent from, the page that
+-- brought the visitor there, and the campaign parameters of the landing page
+ALTER TABLE subscriptions
+  ADD COLUMN source       TEXT NULL,
+  ADD COLUMN referrer     TEXT NULL,
+  ADD COLUMN utm_source   TEXT NULL,
+  ADD COLUMN utm_medium   TEXT NULL,
+  ADD COLUMN utm_campaign TEXT NULL,
+  ADD COLUMN utm_term     TEXT NULL,
+  ADD COLUMN utm_content  TEXT NULL;
+
+CREATE INDEX subscriptions_subscribed_at_idx ON subscriptions (subscribed_at);
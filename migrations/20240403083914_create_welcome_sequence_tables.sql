@@ -0,0 +1,30 @@
+-- Add migration script here
+-- Emails sent to new subscribers after they confirm, each a number of days
+-- after the confirmation
+CREATE TABLE sequence_steps(
+  step_id      uuid NOT NULL,
+  delay_days   INT NOT NULL CHECK (delay_days >= 0),
+  subject      TEXT NOT NULL,
+  text_content TEXT NOT NULL,
+  html_content TEXT NOT NULL,
+  created_at   timestamptz NOT NULL,
+  PRIMARY KEY (step_id)
+);
+
+CREATE TABLE sequence_enrollments(
+  subscriber_id uuid NOT NULL
+    REFERENCES subscriptions (id) ON DELETE CASCADE,
+  enrolled_at   timestamptz NOT NULL,
+  -- Set when the subscriber leaves before the end of the sequence
+  stopped_at    timestamptz NULL,
+  PRIMARY KEY (subscriber_id)
+);
+
+CREATE TABLE sequence_deliveries(
+  subscriber_id uuid NOT NULL
+    REFERENCES sequence_enrollments (subscriber_id) ON DELETE CASCADE,
+  step_id       uuid NOT NULL
+    REFERENCES sequence_steps (step_id) ON DELETE CASCADE,
+  sent_at       timestamptz NOT NULL,
+  PRIMARY KEY (subscriber_id, step_id)
+);
@@ -0,0 +1,16 @@
+-- Add migration script here
+-- When the first confirmation email was sent and how many reminders followed,
+-- to remind and eventually purge the signups that never confirm. Left empty
+-- for the subscribers who were never asked to confirm.
+ALTER TABLE subscriptions
+  ADD COLUMN confirmation_sent_at timestamptz NULL,
+  ADD COLUMN confirmation_reminders INT NOT NULL DEFAULT 0;
+
+-- Signups pending from before get the whole schedule, starting now
+UPDATE subscriptions
+SET confirmation_sent_at = now()
+WHERE status = 'pending_confirmation';
+
+CREATE INDEX subscriptions_pending_confirmation_idx
+  ON subscriptions (confirmation_sent_at)
+  WHERE status = 'pending_confirmation';
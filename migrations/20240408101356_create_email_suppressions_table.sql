@@ -0,0 +1,14 @@
+-- Add migration script here
+-- Addresses that are never sent any email, whatever their subscription says.
+-- Patterns are matched against the whole address, `*` standing for any run of
+-- characters, as in `abuse@*` or `*@*.test.example`.
+CREATE TABLE email_suppressions(
+  suppression_id uuid NOT NULL,
+  pattern        TEXT NOT NULL UNIQUE,
+  reason         TEXT NOT NULL
+    CHECK (reason IN ('legal_request', 'hard_bounce', 'role_address', 'internal', 'other')),
+  -- Where the entry came from, such as a ticket or the file it was uploaded in
+  source         TEXT NOT NULL,
+  created_at     timestamptz NOT NULL,
+  PRIMARY KEY (suppression_id)
+);
@@ -0,0 +1,9 @@
+-- Add migration script here
+-- Invitation tokens grant access to an account, like reset tokens they are
+-- only stored hashed
+BEGIN;
+
+ALTER TABLE user_invitations RENAME COLUMN invitation_token TO token_hash;
+UPDATE user_invitations SET token_hash = encode(sha256(convert_to(token_hash, 'UTF8')), 'hex');
+
+COMMIT;
@@ -0,0 +1,4 @@
+-- Add migration script here
+-- Blocked signups are capped to the most recently attempted domains, the
+-- oldest ones are dropped first
+CREATE INDEX blocked_signups_last_attempt_at_idx ON blocked_signups (last_attempt_at);
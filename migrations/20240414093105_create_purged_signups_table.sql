@@ -0,0 +1,15 @@
+-- Add migration script here
+-- What is left of the signups purged for never confirming: no address or
+-- name, only where and when they came from, so that the signup reports still
+-- count them
+CREATE TABLE purged_signups(
+  subscribed_at timestamptz NOT NULL,
+  purged_at     timestamptz NOT NULL,
+  source        TEXT NULL,
+  referrer      TEXT NULL,
+  utm_source    TEXT NULL,
+  utm_medium    TEXT NULL,
+  utm_campaign  TEXT NULL
+);
+
+CREATE INDEX purged_signups_subscribed_at_idx ON purged_signups (subscribed_at);
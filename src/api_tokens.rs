@@ -0,0 +1,186 @@
+use chrono::{DateTime, Utc};
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::signing::sha256_hex;
+
+const TOKEN_PREFIX: &str = "nl_";
+
+/// What a token may be used for. Tokens never grant more than the role of the
+/// user owning them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiScope {
+    PublishNewsletters,
+}
+
+impl ApiScope {
+    pub const ALL: [ApiScope; 1] = [ApiScope::PublishNewsletters];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApiScope::PublishNewsletters => "newsletters:publish",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            ApiScope::PublishNewsletters => "Publish newsletter issues",
+        }
+    }
+}
+
+impl TryFrom<&str> for ApiScope {
+    type Error = String;
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        ApiScope::ALL
+            .into_iter()
+            .find(|scope| scope.as_str() == s)
+            .ok_or_else(|| format!("{} is not a supported scope.", s))
+    }
+}
+
+pub struct ApiToken {
+    pub token_id: Uuid,
+    pub name: String,
+    pub scopes: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+/// The owner of a token that was presented, with what it allows.
+pub struct TokenOwner {
+    pub user_id: Uuid,
+    pub scopes: Vec<ApiScope>,
+}
+
+fn generate_token() -> String {
+    let mut rng = thread_rng();
+    let secret: String = std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(40)
+        .collect();
+
+    format!("{}{}", TOKEN_PREFIX, secret)
+}
+
+fn hash_token(token: &str) -> String {
+    sha256_hex(token)
+}
+
+/// Creates a token and returns it in clear. This is the only time it is
+/// available, only its hash is stored.
+pub async fn create_token(
+    pool: &PgPool,
+    user_id: Uuid,
+    name: &str,
+    scopes: &[ApiScope],
+) -> Result<String, sqlx::Error> {
+    let token = generate_token();
+    let scopes: Vec<String> = scopes.iter().map(|s| s.as_str().to_owned()).collect();
+
+    sqlx::query!(
+        r#"
+        INSERT INTO api_tokens (token_id, user_id, name, token_hash, scopes, created_at)
+        VALUES ($1, $2, $3, $4, $5, now())
+        "#,
+        Uuid::new_v4(),
+        user_id,
+        name,
+        hash_token(&token),
+        &scopes,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(token)
+}
+
+pub async fn list_tokens(pool: &PgPool, user_id: Uuid) -> Result<Vec<ApiToken>, sqlx::Error> {
+    sqlx::query_as!(
+        ApiToken,
+        r#"
+        SELECT token_id, name, scopes, created_at, last_used_at, revoked_at
+        FROM api_tokens
+        WHERE user_id = $1
+        ORDER BY created_at DESC
+        "#,
+        user_id,
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Returns the name of the revoked token, or `None` if the user has no such
+/// active token.
+pub async fn revoke_token(
+    pool: &PgPool,
+    user_id: Uuid,
+    token_id: Uuid,
+) -> Result<Option<String>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        UPDATE api_tokens
+        SET revoked_at = now()
+        WHERE token_id = $1 AND user_id = $2 AND revoked_at IS NULL
+        RETURNING name
+        "#,
+        token_id,
+        user_id,
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(|r| r.name))
+}
+
+/// Looks up an active token of an enabled user, and records that it was used.
+pub async fn authenticate(pool: &PgPool, token: &str) -> Result<Option<TokenOwner>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        UPDATE api_tokens
+        SET last_used_at = now()
+        FROM users
+        WHERE api_tokens.user_id = users.user_id
+            AND token_hash = $1
+            AND revoked_at IS NULL
+            AND NOT users.disabled
+        RETURNING api_tokens.user_id, scopes
+        "#,
+        hash_token(token),
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(|row| TokenOwner {
+        user_id: row.user_id,
+        scopes: row
+            .scopes
+            .iter()
+            .filter_map(|s| ApiScope::try_from(s.as_str()).ok())
+            .collect(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{generate_token, hash_token, ApiScope};
+
+    #[test]
+    fn tokens_are_prefixed_and_unique() {
+        let first = generate_token();
+        let second = generate_token();
+        assert!(first.starts_with("nl_"));
+        assert_ne!(first, second);
+        assert_ne!(hash_token(&first), hash_token(&second));
+    }
+
+    #[test]
+    fn scopes_round_trip_through_their_names() {
+        for scope in ApiScope::ALL {
+            assert_eq!(ApiScope::try_from(scope.as_str()), Ok(scope));
+        }
+        assert!(ApiScope::try_from("everything").is_err());
+    }
+}
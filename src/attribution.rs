@@ -0,0 +1,222 @@
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Values longer than this are cut, they come straight from the client.
+const MAX_VALUE_LENGTH: usize = 512;
+
+/// Where a signup comes from. Every field is optional and typed by whoever
+/// built the form or the link, so none of them are trusted.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+pub struct Attribution {
+    /// The form or page the signup was sent from.
+    pub source: Option<String>,
+    /// The page that brought the visitor to the signup page.
+    pub referrer: Option<String>,
+    pub utm_source: Option<String>,
+    pub utm_medium: Option<String>,
+    pub utm_campaign: Option<String>,
+    pub utm_term: Option<String>,
+    pub utm_content: Option<String>,
+}
+
+impl Attribution {
+    /// The fields by the name of their form field and query parameter.
+    pub fn fields(&self) -> [(&'static str, Option<&str>); 7] {
+        [
+            ("source", self.source.as_deref()),
+            ("referrer", self.referrer.as_deref()),
+            ("utm_source", self.utm_source.as_deref()),
+            ("utm_medium", self.utm_medium.as_deref()),
+            ("utm_campaign", self.utm_campaign.as_deref()),
+            ("utm_term", self.utm_term.as_deref()),
+            ("utm_content", self.utm_content.as_deref()),
+        ]
+    }
+
+    /// Trims every field, dropping empty ones and cutting long ones, then
+    /// fills the missing ones from `fallback`.
+    pub fn or(self, fallback: Attribution) -> Attribution {
+        fn pick(value: Option<String>, fallback: Option<String>) -> Option<String> {
+            let clean = |value: Option<String>| {
+                value
+                    .map(|v| v.trim().chars().take(MAX_VALUE_LENGTH).collect::<String>())
+                    .filter(|v| !v.is_empty())
+            };
+            clean(value).or_else(|| clean(fallback))
+        }
+
+        Attribution {
+            source: pick(self.source, fallback.source),
+            referrer: pick(self.referrer, fallback.referrer),
+            utm_source: pick(self.utm_source, fallback.utm_source),
+            utm_medium: pick(self.utm_medium, fallback.utm_medium),
+            utm_campaign: pick(self.utm_campaign, fallback.utm_campaign),
+            utm_term: pick(self.utm_term, fallback.utm_term),
+            utm_content: pick(self.utm_content, fallback.utm_content),
+        }
+    }
+}
+
+pub async fn get_attribution(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Option<Attribution>, sqlx::Error> {
+    sqlx::query_as!(
+        Attribution,
+        r#"
+        SELECT source, referrer, utm_source, utm_medium, utm_campaign, utm_term, utm_content
+        FROM subscriptions
+        WHERE id = $1
+        "#,
+        subscriber_id,
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+/// What signups are grouped by in the report.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportGrouping {
+    Source,
+    /// The host of the referrer, full URLs would hardly ever repeat.
+    Referrer,
+    UtmSource,
+    UtmMedium,
+    UtmCampaign,
+}
+
+impl ReportGrouping {
+    pub const ALL: [ReportGrouping; 5] = [
+        ReportGrouping::Source,
+        ReportGrouping::Referrer,
+        ReportGrouping::UtmSource,
+        ReportGrouping::UtmMedium,
+        ReportGrouping::UtmCampaign,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReportGrouping::Source => "source",
+            ReportGrouping::Referrer => "referrer",
+            ReportGrouping::UtmSource => "utm_source",
+            ReportGrouping::UtmMedium => "utm_medium",
+            ReportGrouping::UtmCampaign => "utm_campaign",
+        }
+    }
+}
+
+impl TryFrom<&str> for ReportGrouping {
+    type Error = String;
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        ReportGrouping::ALL
+            .into_iter()
+            .find(|grouping| grouping.as_str() == s)
+            .ok_or_else(|| format!("{} is not a valid grouping.", s))
+    }
+}
+
+pub struct SourceReportRow {
+    /// `None` for the signups that did not say.
+    pub key: Option<String>,
+    pub signups: i64,
+    /// Signups that confirmed at some point, even if they left since.
+    pub confirmed: i64,
+}
+
+impl SourceReportRow {
+    /// As a percentage.
+    pub fn confirmation_rate(&self) -> f64 {
+        if self.signups == 0 {
+            0.0
+        } else {
+            self.confirmed as f64 * 100.0 / self.signups as f64
+        }
+    }
+}
+
+/// Signups made in `[from, until)`, most signups first.
+pub async fn signups_by_source(
+    pool: &PgPool,
+    grouping: ReportGrouping,
+    from: DateTime<Utc>,
+    until: DateTime<Utc>,
+) -> Result<Vec<SourceReportRow>, sqlx::Error> {
+    sqlx::query_as!(
+        SourceReportRow,
+        r#"
+        SELECT
+            CASE $1
+                WHEN 'source' THEN source
+                WHEN 'referrer' THEN substring(referrer FROM '^[A-Za-z][A-Za-z0-9+.-]*://([^/?#:]+)')
+                WHEN 'utm_source' THEN utm_source
+                WHEN 'utm_medium' THEN utm_medium
+                WHEN 'utm_campaign' THEN utm_campaign
+            END AS key,
+            COUNT(*) AS "signups!",
+            COUNT(*) FILTER (WHERE confirmed) AS "confirmed!"
+        FROM (
+            SELECT
+                source, referrer, utm_source, utm_medium, utm_campaign, subscribed_at,
+                status = 'confirmed' OR EXISTS (
+                    SELECT 1 FROM subscription_events
+                    WHERE subscriber_id = subscriptions.id AND event = 'confirmed'
+                ) AS confirmed
+            FROM subscriptions
+            UNION ALL
+            -- Purged signups never confirmed
+            SELECT
+                source, referrer, utm_source, utm_medium, utm_campaign, subscribed_at,
+                false
+            FROM purged_signups
+        ) AS signups
+        WHERE subscribed_at >= $2 AND subscribed_at < $3
+        GROUP BY key
+        ORDER BY "signups!" DESC, key NULLS LAST
+        "#,
+        grouping.as_str(),
+        from,
+        until,
+    )
+    .fetch_all(pool)
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Attribution, SourceReportRow};
+
+    #[test]
+    fn fields_are_cleaned_and_filled_from_the_fallback() {
+        let from_body = Attribution {
+            source: Some(" landing-page ".into()),
+            utm_source: Some("  ".into()),
+            utm_term: Some("x".repeat(600)),
+            ..Default::default()
+        };
+        let from_query = Attribution {
+            source: Some("ignored".into()),
+            utm_source: Some("newsletter".into()),
+            utm_medium: Some("".into()),
+            ..Default::default()
+        };
+
+        let attribution = from_body.or(from_query);
+        assert_eq!(attribution.source.as_deref(), Some("landing-page"));
+        assert_eq!(attribution.utm_source.as_deref(), Some("newsletter"));
+        assert_eq!(attribution.utm_medium, None);
+        assert_eq!(attribution.utm_term.unwrap().len(), 512);
+    }
+
+    #[test]
+    fn the_confirmation_rate_of_no_signups_is_zero() {
+        let row = |signups, confirmed| SourceReportRow {
+            key: None,
+            signups,
+            confirmed,
+        };
+        assert_eq!(row(0, 0).confirmation_rate(), 0.0);
+        assert_eq!(row(4, 1).confirmation_rate(), 25.0);
+    }
+}
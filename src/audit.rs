@@ -0,0 +1,285 @@
+use async_trait::async_trait;
+use axum::{
+    extract::{FromRef, FromRequestParts},
+    http::request::Parts,
+};
+use chrono::{DateTime, Utc};
+use futures::{Stream, TryStreamExt};
+use sqlx::PgPool;
+use tokio::sync::mpsc;
+use tower_http::request_id::RequestId;
+use tracing::error;
+use uuid::Uuid;
+
+use crate::client_info::{ClientInfo, ClientIpSource};
+
+/// How many encoded rows may wait for a slow reader of an export before the
+/// database cursor is paused.
+const BUFFERED_ROWS: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditAction {
+    ApiTokenCreate,
+    ApiTokenRevoke,
+    DomainRuleAdd,
+    DomainRuleRemove,
+    Login,
+    LoginFailed,
+    Logout,
+    PasswordChange,
+    PasswordReset,
+    NewsletterPublish,
+    SequenceStepAdd,
+    SequenceStepRemove,
+    SubscriberAttributeAdd,
+    SubscriberAttributeRemove,
+    SubscriberConfirm,
+    SubscriberDataExport,
+    SubscriberUnsubscribe,
+    SubscriberDelete,
+    SubscriberErase,
+    SubscriberExport,
+    SubscriberImport,
+    SubscriberTag,
+    SubscriberUntag,
+    SuppressionAdd,
+    SuppressionRemove,
+    SuppressionUpload,
+    TagDelete,
+    TwoFactorEnable,
+    TwoFactorDisable,
+    UserInvite,
+    UserDisable,
+    UserEnable,
+    UserRoleChange,
+    UserDelete,
+}
+
+impl AuditAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::ApiTokenCreate => "api_token_create",
+            AuditAction::ApiTokenRevoke => "api_token_revoke",
+            AuditAction::DomainRuleAdd => "domain_rule_add",
+            AuditAction::DomainRuleRemove => "domain_rule_remove",
+            AuditAction::Login => "login",
+            AuditAction::LoginFailed => "login_failed",
+            AuditAction::Logout => "logout",
+            AuditAction::PasswordChange => "password_change",
+            AuditAction::PasswordReset => "password_reset",
+            AuditAction::NewsletterPublish => "newsletter_publish",
+            AuditAction::SequenceStepAdd => "sequence_step_add",
+            AuditAction::SequenceStepRemove => "sequence_step_remove",
+            AuditAction::SubscriberAttributeAdd => "subscriber_attribute_add",
+            AuditAction::SubscriberAttributeRemove => "subscriber_attribute_remove",
+            AuditAction::SubscriberConfirm => "subscriber_confirm",
+            AuditAction::SubscriberDataExport => "subscriber_data_export",
+            AuditAction::SubscriberUnsubscribe => "subscriber_unsubscribe",
+            AuditAction::SubscriberDelete => "subscriber_delete",
+            AuditAction::SubscriberErase => "subscriber_erase",
+            AuditAction::SubscriberExport => "subscriber_export",
+            AuditAction::SubscriberImport => "subscriber_import",
+            AuditAction::SubscriberTag => "subscriber_tag",
+            AuditAction::SubscriberUntag => "subscriber_untag",
+            AuditAction::SuppressionAdd => "suppression_add",
+            AuditAction::SuppressionRemove => "suppression_remove",
+            AuditAction::SuppressionUpload => "suppression_upload",
+            AuditAction::TagDelete => "tag_delete",
+            AuditAction::TwoFactorEnable => "two_factor_enable",
+            AuditAction::TwoFactorDisable => "two_factor_disable",
+            AuditAction::UserInvite => "user_invite",
+            AuditAction::UserDisable => "user_disable",
+            AuditAction::UserEnable => "user_enable",
+            AuditAction::UserRoleChange => "user_role_change",
+            AuditAction::UserDelete => "user_delete",
+        }
+    }
+}
+
+pub struct AuditEvent {
+    pub occurred_at: DateTime<Utc>,
+    pub actor_id: Option<Uuid>,
+    pub actor_username: Option<String>,
+    pub action: String,
+    pub target: Option<String>,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub request_id: Option<String>,
+}
+
+/// Where a request comes from, for the audit log: the client and the ULID
+/// assigned to the request by `MakeUlidRequestId`.
+pub struct AuditContext {
+    client: ClientInfo,
+    request_id: Option<String>,
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for AuditContext
+where
+    S: Send + Sync,
+    ClientIpSource: FromRef<S>,
+{
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(req: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let client = ClientInfo::from_request_parts(req, state).await?;
+        let request_id = req
+            .extensions
+            .get::<RequestId>()
+            .and_then(|id| id.header_value().to_str().ok())
+            .map(str::to_owned);
+
+        Ok(Self { client, request_id })
+    }
+}
+
+impl AuditContext {
+    pub fn client(&self) -> &ClientInfo {
+        &self.client
+    }
+
+    /// Appends an event to the audit log. Failing to do so is logged but does
+    /// not fail the action being audited.
+    pub async fn record(
+        &self,
+        pool: &PgPool,
+        actor_id: Option<Uuid>,
+        action: AuditAction,
+        target: Option<&str>,
+    ) {
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO audit_events (
+                event_id, actor_id, actor_username, action, target,
+                ip_address, user_agent, request_id, occurred_at
+            )
+            SELECT $1, $2, (SELECT username FROM users WHERE user_id = $2), $3, $4, $5, $6, $7, now()
+            "#,
+            Uuid::new_v4(),
+            actor_id,
+            action.as_str(),
+            target,
+            self.client.ip.map(|ip| ip.to_string()),
+            self.client.user_agent.as_deref(),
+            self.request_id.as_deref(),
+        )
+        .execute(pool)
+        .await;
+
+        if let Err(e) = result {
+            error!("failed to record audit event {}: {:?}", action.as_str(), e);
+        }
+    }
+}
+
+pub async fn count_events(pool: &PgPool) -> Result<i64, sqlx::Error> {
+    let row = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM audit_events"#)
+        .fetch_one(pool)
+        .await?;
+
+    Ok(row.count)
+}
+
+/// Most recent events first.
+pub async fn list_events(
+    pool: &PgPool,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<AuditEvent>, sqlx::Error> {
+    sqlx::query_as!(
+        AuditEvent,
+        r#"
+        SELECT
+            occurred_at, actor_id, actor_username, action, target,
+            ip_address, user_agent, request_id
+        FROM audit_events
+        ORDER BY occurred_at DESC, event_id
+        LIMIT $1 OFFSET $2
+        "#,
+        limit,
+        offset,
+    )
+    .fetch_all(pool)
+    .await
+}
+
+const EXPORT_COLUMNS: [&str; 8] = [
+    "occurred_at",
+    "actor_id",
+    "actor_username",
+    "action",
+    "target",
+    "ip_address",
+    "user_agent",
+    "request_id",
+];
+
+fn csv_line<I>(fields: I) -> Result<Vec<u8>, anyhow::Error>
+where
+    I: IntoIterator,
+    I::Item: AsRef<[u8]>,
+{
+    let mut writer = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(vec![]);
+    writer.write_record(fields)?;
+    Ok(writer.into_inner()?)
+}
+
+fn encode_event(event: &AuditEvent) -> Result<Vec<u8>, anyhow::Error> {
+    csv_line([
+        event.occurred_at.to_rfc3339(),
+        event.actor_id.map(|id| id.to_string()).unwrap_or_default(),
+        event.actor_username.clone().unwrap_or_default(),
+        event.action.clone(),
+        event.target.clone().unwrap_or_default(),
+        event.ip_address.clone().unwrap_or_default(),
+        event.user_agent.clone().unwrap_or_default(),
+        event.request_id.clone().unwrap_or_default(),
+    ])
+}
+
+/// Streams the whole log as CSV, most recent events first, read from a
+/// database cursor by a background task like the subscriber exports.
+pub fn export_events(pool: PgPool) -> impl Stream<Item = Result<Vec<u8>, anyhow::Error>> {
+    let (sender, receiver) = mpsc::channel(BUFFERED_ROWS);
+
+    tokio::spawn(async move {
+        let header = csv_line(EXPORT_COLUMNS);
+        let failed = header.is_err();
+        if sender.send(header).await.is_err() || failed {
+            return;
+        }
+
+        let mut events = sqlx::query_as!(
+            AuditEvent,
+            r#"
+            SELECT
+                occurred_at, actor_id, actor_username, action, target,
+                ip_address, user_agent, request_id
+            FROM audit_events
+            ORDER BY occurred_at DESC, event_id
+            "#,
+        )
+        .fetch(&pool);
+
+        loop {
+            let item = match events.try_next().await {
+                Ok(Some(event)) => encode_event(&event),
+                Ok(None) => break,
+                Err(e) => Err(e.into()),
+            };
+            let failed = item.is_err();
+
+            // The reader went away, stop reading from the database
+            if sender.send(item).await.is_err() || failed {
+                break;
+            }
+        }
+    });
+
+    futures::stream::unfold(receiver, |mut receiver| async move {
+        receiver.recv().await.map(|item| (item, receiver))
+    })
+}
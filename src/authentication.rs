@@ -3,7 +3,41 @@ use argon2::{
     PasswordVerifier, Version,
 };
 use secrecy::{ExposeSecret, Secret};
-use sqlx::{types::Uuid, PgPool};
+use sqlx::{types::Uuid, PgExecutor, PgPool};
+
+/// Roles are ordered by privilege, so `role >= Role::Editor` reads as "at
+/// least an editor".
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Role {
+    Viewer,
+    Editor,
+    Owner,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Viewer => "viewer",
+            Role::Editor => "editor",
+            Role::Owner => "owner",
+        }
+    }
+}
+
+impl TryFrom<String> for Role {
+    type Error = String;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.to_lowercase().as_str() {
+            "viewer" => Ok(Self::Viewer),
+            "editor" => Ok(Self::Editor),
+            "owner" => Ok(Self::Owner),
+            other => Err(format!(
+                "{} is not a supported role. Use `owner`, `editor` or `viewer`.",
+                other
+            )),
+        }
+    }
+}
 
 pub struct Credentials {
     pub username: String,
@@ -15,7 +49,7 @@ pub async fn validate_credentials(credentials: Credentials, pool: &PgPool) -> Op
         r#"
         SELECT user_id, password_hash
         FROM users
-        WHERE username = $1
+        WHERE username = $1 AND NOT disabled
         "#,
         credentials.username,
     )
@@ -23,8 +57,11 @@ pub async fn validate_credentials(credentials: Credentials, pool: &PgPool) -> Op
     .await
     .ok()??;
 
+    // Invited users cannot log in until they have picked a password
+    let password_hash = user.password_hash?;
+
     tokio::task::spawn_blocking(move || {
-        verify_password_hash(Secret::new(user.password_hash), credentials.password)
+        verify_password_hash(Secret::new(password_hash), credentials.password)
     })
     .await
     .ok()??;
@@ -65,7 +102,7 @@ pub fn verify_password_hash(
 pub async fn change_password(
     user_id: uuid::Uuid,
     password: Secret<String>,
-    pool: &PgPool,
+    db: impl PgExecutor<'_>,
 ) -> Option<()> {
     let password_hash = tokio::task::spawn_blocking(move || compute_password_hash(password))
         .await
@@ -80,9 +117,27 @@ pub async fn change_password(
         password_hash.expose_secret(),
         user_id
     )
-    .execute(pool)
+    .execute(db)
     .await
     .ok()?;
 
     Some(())
 }
+
+/// Returns the role of an active user, along with whether they have enrolled
+/// in two-factor authentication.
+pub async fn get_role(user_id: Uuid, pool: &PgPool) -> Option<(Role, bool)> {
+    let row = sqlx::query!(
+        r#"
+        SELECT role, totp_enabled
+        FROM users
+        WHERE user_id = $1 AND NOT disabled
+        "#,
+        user_id,
+    )
+    .fetch_optional(pool)
+    .await
+    .ok()??;
+
+    Some((Role::try_from(row.role).ok()?, row.totp_enabled))
+}
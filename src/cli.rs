@@ -0,0 +1,151 @@
+use std::path::PathBuf;
+
+use futures::TryStreamExt;
+use sqlx::PgPool;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use crate::{
+    domain::SubscriptionStatus,
+    subscriber_export::{export_subscribers, parse_date, ExportFilter, ExportFormat},
+};
+
+pub const USAGE: &str = "\
+Usage:
+    email-service
+        Run the server.
+
+    email-service export-subscribers [--format csv|ndjson] [--status STATUS]
+                                     [--from YYYY-MM-DD] [--until YYYY-MM-DD]
+                                     [--output PATH]
+        Write the subscribers to PATH, or to standard output.";
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Command {
+    Serve,
+    ExportSubscribers {
+        filter: ExportFilter,
+        format: ExportFormat,
+        output: Option<PathBuf>,
+    },
+}
+
+/// Parses the command line, without the program name.
+pub fn parse_args<I>(args: I) -> Result<Command, String>
+where
+    I: IntoIterator<Item = String>,
+{
+    let mut args = args.into_iter();
+
+    match args.next().as_deref() {
+        None => Ok(Command::Serve),
+        Some("export-subscribers") => parse_export_args(args),
+        Some(other) => Err(format!("Unknown command {}.", other)),
+    }
+}
+
+fn parse_export_args(mut args: impl Iterator<Item = String>) -> Result<Command, String> {
+    let mut filter = ExportFilter::default();
+    let mut format = ExportFormat::Csv;
+    let mut output = None;
+
+    while let Some(flag) = args.next() {
+        let value = args
+            .next()
+            .ok_or_else(|| format!("{} needs a value.", flag))?;
+
+        match flag.as_str() {
+            "--format" => format = ExportFormat::try_from(value.as_str())?,
+            "--status" => filter.status = Some(SubscriptionStatus::try_from(value.as_str())?),
+            "--from" => filter.subscribed_from = Some(parse_date(&value)?),
+            "--until" => filter.subscribed_until = Some(parse_date(&value)?),
+            "--output" => output = Some(PathBuf::from(value)),
+            _ => return Err(format!("Unknown option {}.", flag)),
+        }
+    }
+
+    Ok(Command::ExportSubscribers {
+        filter,
+        format,
+        output,
+    })
+}
+
+pub async fn run_export(
+    pool: PgPool,
+    filter: ExportFilter,
+    format: ExportFormat,
+    output: Option<PathBuf>,
+) -> anyhow::Result<()> {
+    match output {
+        Some(path) => {
+            write_export(pool, filter, format, tokio::fs::File::create(path).await?).await
+        }
+        None => write_export(pool, filter, format, tokio::io::stdout()).await,
+    }
+}
+
+async fn write_export<W>(
+    pool: PgPool,
+    filter: ExportFilter,
+    format: ExportFormat,
+    mut writer: W,
+) -> anyhow::Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    let rows = export_subscribers(pool, filter, format);
+    futures::pin_mut!(rows);
+
+    while let Some(row) = rows.try_next().await? {
+        writer.write_all(&row).await?;
+    }
+    writer.flush().await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_args, Command};
+    use crate::{
+        domain::SubscriptionStatus,
+        subscriber_export::{ExportFilter, ExportFormat},
+    };
+
+    fn args(s: &str) -> Vec<String> {
+        s.split_whitespace().map(str::to_owned).collect()
+    }
+
+    #[test]
+    fn no_arguments_runs_the_server() {
+        assert_eq!(parse_args(args("")), Ok(Command::Serve));
+    }
+
+    #[test]
+    fn export_options_are_parsed() {
+        let command = parse_args(args(
+            "export-subscribers --format ndjson --status confirmed --from 2024-01-01 --output out.ndjson",
+        ));
+
+        assert_eq!(
+            command,
+            Ok(Command::ExportSubscribers {
+                filter: ExportFilter {
+                    status: Some(SubscriptionStatus::Confirmed),
+                    subscribed_from: Some("2024-01-01".parse().unwrap()),
+                    subscribed_until: None,
+                },
+                format: ExportFormat::Ndjson,
+                output: Some("out.ndjson".into()),
+            })
+        );
+    }
+
+    #[test]
+    fn bad_options_are_rejected() {
+        assert!(parse_args(args("export")).is_err());
+        assert!(parse_args(args("export-subscribers --format xml")).is_err());
+        assert!(parse_args(args("export-subscribers --status")).is_err());
+        assert!(parse_args(args("export-subscribers --verbose yes")).is_err());
+    }
+}
@@ -0,0 +1,66 @@
+use std::net::{IpAddr, SocketAddr};
+
+use async_trait::async_trait;
+use axum::{
+    extract::{ConnectInfo, FromRef, FromRequestParts},
+    http::{header::USER_AGENT, request::Parts},
+};
+
+/// Where the client IP is read from, see `ApplicationSettings::trust_forwarded_for`.
+#[derive(Clone, Copy)]
+pub struct ClientIpSource {
+    pub trust_forwarded_for: bool,
+}
+
+/// The IP address and user agent of whoever sent the request, as far as we
+/// can tell. Neither is guaranteed to be present.
+pub struct ClientInfo {
+    pub ip: Option<IpAddr>,
+    pub user_agent: Option<String>,
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for ClientInfo
+where
+    S: Send + Sync,
+    ClientIpSource: FromRef<S>,
+{
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(req: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let source = ClientIpSource::from_ref(state);
+
+        let forwarded_ip = source
+            .trust_forwarded_for
+            .then(|| forwarded_for(req))
+            .flatten();
+
+        let ip = forwarded_ip.or_else(|| {
+            req.extensions
+                .get::<ConnectInfo<SocketAddr>>()
+                .map(|ConnectInfo(addr)| addr.ip())
+        });
+
+        let user_agent = req
+            .headers
+            .get(USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_owned);
+
+        Ok(Self { ip, user_agent })
+    }
+}
+
+// The proxy appends the address it received the request from, so the last
+// entry is the only one that cannot be forged by the client.
+fn forwarded_for(req: &Parts) -> Option<IpAddr> {
+    req.headers
+        .get("X-Forwarded-For")?
+        .to_str()
+        .ok()?
+        .rsplit(',')
+        .next()?
+        .trim()
+        .parse()
+        .ok()
+}
@@ -8,6 +8,16 @@ pub struct Settings {
     pub database: DatabaseSettings,
     pub application: ApplicationSettings,
     pub email_client: EmailClientSettings,
+    pub idempotency: IdempotencySettings,
+    pub two_factor: TwoFactorSettings,
+    pub login_throttling: LoginThrottlingSettings,
+    pub signup_protection: SignupProtectionSettings,
+    pub welcome_sequence: WelcomeSequenceSettings,
+    pub confirmation_reminders: ConfirmationReminderSettings,
+    #[serde(default)]
+    pub subscription_pages: SubscriptionPagesSettings,
+    #[serde(default)]
+    pub widget: WidgetSettings,
     pub redis_uri: Secret<String>,
 }
 
@@ -17,6 +27,9 @@ pub struct ApplicationSettings {
     pub port: u16,
     pub base_url: String,
     pub secret: Secret<String>,
+    /// Take the client IP from `X-Forwarded-For` rather than from the socket.
+    /// Only enable this behind a reverse proxy that sets the header.
+    pub trust_forwarded_for: bool,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -35,6 +48,95 @@ pub struct EmailClientSettings {
     pub authorization_token: Secret<String>,
 }
 
+#[derive(Debug, Deserialize, Clone)]
+pub struct IdempotencySettings {
+    pub backend: IdempotencyBackend,
+    pub retention_hours: u32,
+    pub lock_timeout_seconds: u64,
+    pub sweep_interval_seconds: u64,
+    pub sweep_batch_size: u32,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct TwoFactorSettings {
+    /// When set, users without two-factor authentication are sent to the
+    /// enrollment page until they set it up.
+    pub enforce: bool,
+    pub issuer: String,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct LoginThrottlingSettings {
+    /// Failures after which the username or IP is locked out.
+    pub max_failures: u32,
+    pub lockout_seconds: u64,
+    /// Delay imposed after the first failure, doubled after each further one.
+    pub base_delay_milliseconds: u64,
+    pub max_delay_seconds: u64,
+    /// How long failures are remembered after the first one.
+    pub failure_window_seconds: u64,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct SignupProtectionSettings {
+    /// Refuse signups filling in the hidden `website` field of the form.
+    pub honeypot: bool,
+    /// Forms sent back sooner than this after being served are refused. When
+    /// it and `pow_difficulty` are zero, the form token is not required.
+    pub min_fill_seconds: u64,
+    pub max_form_age_seconds: u64,
+    /// Leading zero bits the browser has to find a hash with, 0 disables the
+    /// proof of work.
+    pub pow_difficulty: u32,
+    /// Signups allowed per window, 0 disables the limit.
+    pub max_signups_per_ip: u32,
+    pub max_signups_per_email: u32,
+    pub rate_limit_window_seconds: u64,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct WelcomeSequenceSettings {
+    /// How often the worker looks for welcome sequence emails that are due.
+    pub send_interval_seconds: u64,
+    pub batch_size: u32,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ConfirmationReminderSettings {
+    /// Hours after the confirmation email at which a reminder is sent, with a
+    /// fresh link. Reminders that fell due together are sent as one.
+    pub reminder_hours: Vec<u32>,
+    /// Hours after the confirmation email at which signups that are still not
+    /// confirmed are deleted, 0 keeps them. Must come after the last reminder.
+    pub purge_after_hours: u32,
+    pub check_interval_seconds: u64,
+    pub batch_size: u32,
+}
+
+/// Where people subscribing or confirming are sent instead of the built-in
+/// pages. The outcome is added as an `outcome` or `error` query parameter.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct SubscriptionPagesSettings {
+    pub thank_you_url: Option<String>,
+    pub error_url: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct WidgetSettings {
+    /// Origins of the sites embedding the signup widget, such as
+    /// `https://www.example.com`. Browsers only let them call the
+    /// subscription endpoints.
+    #[serde(default)]
+    pub allowed_origins: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum IdempotencyBackend {
+    Postgres,
+    Redis,
+}
+
 pub enum Environment {
     Local,
     Production,
@@ -110,3 +212,65 @@ impl EmailClientSettings {
         SubscriberEmail::parse(self.sender_email.clone())
     }
 }
+
+impl IdempotencySettings {
+    pub fn retention(&self) -> chrono::Duration {
+        chrono::Duration::hours(self.retention_hours.into())
+    }
+
+    pub fn lock_timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.lock_timeout_seconds)
+    }
+
+    pub fn sweep_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.sweep_interval_seconds)
+    }
+}
+
+impl ConfirmationReminderSettings {
+    pub fn check_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.check_interval_seconds)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self
+            .reminder_hours
+            .windows(2)
+            .any(|pair| pair[0] >= pair[1])
+        {
+            return Err("The confirmation reminders must be in increasing order.".into());
+        }
+        let last_reminder = self.reminder_hours.last().copied().unwrap_or(0);
+        if self.purge_after_hours != 0 && self.purge_after_hours <= last_reminder {
+            return Err("Unconfirmed signups must be purged after the last reminder.".into());
+        }
+        Ok(())
+    }
+}
+
+impl WelcomeSequenceSettings {
+    pub fn send_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.send_interval_seconds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ConfirmationReminderSettings;
+
+    #[test]
+    fn unconfirmed_signups_are_purged_after_the_last_reminder() {
+        let settings = |reminder_hours: Vec<u32>, purge_after_hours| ConfirmationReminderSettings {
+            reminder_hours,
+            purge_after_hours,
+            check_interval_seconds: 300,
+            batch_size: 100,
+        };
+
+        assert!(settings(vec![24, 72], 168).validate().is_ok());
+        assert!(settings(vec![24, 72], 0).validate().is_ok());
+        assert!(settings(vec![], 24).validate().is_ok());
+        assert!(settings(vec![72, 24], 168).validate().is_err());
+        assert!(settings(vec![24, 72], 72).validate().is_err());
+    }
+}
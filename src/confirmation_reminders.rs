@@ -0,0 +1,196 @@
+use std::sync::Arc;
+
+use sqlx::PgPool;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::{
+    configuration::ConfirmationReminderSettings,
+    domain::SubscriberEmail,
+    email_client::EmailClient,
+    routes::{generate_subscriptions_token, send_confirmation_email, store_token},
+    subscriber_events::{record_event, SubscriberEvent},
+};
+
+pub async fn run_reminder_worker(
+    pool: PgPool,
+    email_client: Arc<EmailClient>,
+    base_url: Arc<str>,
+    settings: ConfirmationReminderSettings,
+) {
+    let mut interval = tokio::time::interval(settings.check_interval());
+
+    loop {
+        interval.tick().await;
+
+        loop {
+            match send_due_reminders(&pool, &email_client, &base_url, &settings).await {
+                Ok(0) => break,
+                Ok(sent) => {
+                    info!("sent {} confirmation reminders", sent);
+                    if sent < u64::from(settings.batch_size) {
+                        break;
+                    }
+                }
+                Err(e) => {
+                    error!("failed to send the confirmation reminders: {:?}", e);
+                    break;
+                }
+            }
+        }
+
+        match purge_unconfirmed(&pool, &settings).await {
+            Ok(0) => {}
+            Ok(n) => info!("purged {} unconfirmed signups", n),
+            Err(e) => error!("failed to purge unconfirmed signups: {:?}", e),
+        }
+    }
+}
+
+/// Sends a fresh confirmation link to up to `batch_size` pending signups with
+/// a reminder due, oldest first, and returns how many were handled. Signups
+/// that missed several reminders, say while the service was down, only get
+/// one. A reminder that fails is left for the next run, the rest of the batch
+/// still goes out.
+pub async fn send_due_reminders(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+    settings: &ConfirmationReminderSettings,
+) -> Result<u64, anyhow::Error> {
+    let reminder_hours: Vec<i32> = settings
+        .reminder_hours
+        .iter()
+        .map(|&hours| hours as i32)
+        .collect();
+
+    let due = sqlx::query!(
+        r#"
+        SELECT id, email, due AS "due!"
+        FROM (
+            SELECT id, email, confirmation_sent_at, confirmation_reminders, (
+                SELECT COUNT(*)::int FROM unnest($1::int[]) AS hours
+                WHERE confirmation_sent_at + make_interval(hours => hours) <= now()
+            ) AS due
+            FROM subscriptions
+            WHERE status = 'pending_confirmation' AND confirmation_sent_at IS NOT NULL
+        ) pending
+        WHERE confirmation_reminders < due
+        ORDER BY confirmation_sent_at, id
+        LIMIT $2
+        "#,
+        &reminder_hours,
+        i64::from(settings.batch_size),
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut handled = 0;
+    for row in due {
+        match remind(pool, email_client, base_url, row.id, row.email, row.due).await {
+            Ok(()) => handled += 1,
+            Err(e) => warn!(
+                "failed to send a confirmation reminder to subscriber {}: {:?}",
+                row.id, e
+            ),
+        }
+    }
+
+    Ok(handled)
+}
+
+/// Gives up on the reminder if the email cannot be sent, it is tried again
+/// on the next run.
+async fn remind(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+    subscriber_id: Uuid,
+    email: String,
+    reminders: i32,
+) -> Result<(), anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+
+    // Another instance of the worker, or the subscriber confirming, may have
+    // got there first
+    let claimed = sqlx::query!(
+        r#"
+        UPDATE subscriptions SET confirmation_reminders = $2
+        WHERE id = $1 AND confirmation_reminders < $2 AND status = 'pending_confirmation'
+        "#,
+        subscriber_id,
+        reminders,
+    )
+    .execute(&mut *transaction)
+    .await?
+    .rows_affected();
+    if claimed == 0 {
+        return Ok(());
+    }
+
+    let email = match SubscriberEmail::parse(email) {
+        Ok(email) => email,
+        Err(e) => {
+            // Recorded as reminded, retrying would not make it valid
+            warn!(
+                "skipping confirmation reminder to an invalid address: {}",
+                e
+            );
+            transaction.commit().await?;
+            return Ok(());
+        }
+    };
+
+    let token = generate_subscriptions_token();
+    store_token(&mut *transaction, subscriber_id, &token).await?;
+    record_event(
+        &mut *transaction,
+        subscriber_id,
+        SubscriberEvent::Reminded,
+        None,
+    )
+    .await?;
+
+    send_confirmation_email(email_client, &email, base_url, &token).await?;
+
+    transaction.commit().await?;
+
+    Ok(())
+}
+
+/// Deletes the signups still pending `purge_after_hours` after their
+/// confirmation email, once they were sent every reminder. Only their
+/// attribution is kept, in `purged_signups`, for `signups_by_source`.
+pub async fn purge_unconfirmed(
+    pool: &PgPool,
+    settings: &ConfirmationReminderSettings,
+) -> Result<u64, sqlx::Error> {
+    if settings.purge_after_hours == 0 {
+        return Ok(0);
+    }
+
+    // Their tokens and history go with them, see the `ON DELETE CASCADE`s
+    let purged = sqlx::query!(
+        r#"
+        WITH purged AS (
+            DELETE FROM subscriptions
+            WHERE status = 'pending_confirmation'
+                AND confirmation_sent_at + make_interval(hours => $1) <= now()
+                AND confirmation_reminders >= $2
+            RETURNING subscribed_at, source, referrer, utm_source, utm_medium, utm_campaign
+        )
+        INSERT INTO purged_signups (
+            subscribed_at, purged_at, source, referrer, utm_source, utm_medium, utm_campaign
+        )
+        SELECT subscribed_at, now(), source, referrer, utm_source, utm_medium, utm_campaign
+        FROM purged
+        "#,
+        settings.purge_after_hours as i32,
+        settings.reminder_hours.len() as i32,
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+
+    Ok(purged)
+}
@@ -0,0 +1,88 @@
+use axum::http::{header::REFERER, HeaderMap};
+use chrono::{DateTime, Utc};
+use sqlx::{PgExecutor, PgPool};
+use uuid::Uuid;
+
+use crate::client_info::ClientInfo;
+
+/// Sources longer than this are cut, they come straight from the client.
+const MAX_SOURCE_LENGTH: usize = 512;
+
+/// The moments a subscriber gives their consent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConsentKind {
+    Signup,
+    Confirmation,
+}
+
+impl ConsentKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConsentKind::Signup => "signup",
+            ConsentKind::Confirmation => "confirmation",
+        }
+    }
+}
+
+pub struct ConsentRecord {
+    pub kind: String,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub source: Option<String>,
+    pub recorded_at: DateTime<Utc>,
+}
+
+/// Appends to the consent log of a subscriber. `source` is the form or page
+/// the request came from, if known.
+pub async fn record_consent(
+    db: impl PgExecutor<'_>,
+    subscriber_id: Uuid,
+    kind: ConsentKind,
+    client: &ClientInfo,
+    source: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    let source = source.map(|s| s.chars().take(MAX_SOURCE_LENGTH).collect::<String>());
+
+    sqlx::query!(
+        r#"
+        INSERT INTO subscription_consents (
+            consent_id, subscriber_id, kind, ip_address, user_agent, source, recorded_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, now())
+        "#,
+        Uuid::new_v4(),
+        subscriber_id,
+        kind.as_str(),
+        client.ip.map(|ip| ip.to_string()),
+        client.user_agent.as_deref(),
+        source,
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+/// The page a request came from, according to the browser.
+pub fn referer(headers: &HeaderMap) -> Option<&str> {
+    headers.get(REFERER).and_then(|value| value.to_str().ok())
+}
+
+/// Oldest records first.
+pub async fn list_consents(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Vec<ConsentRecord>, sqlx::Error> {
+    sqlx::query_as!(
+        ConsentRecord,
+        r#"
+        SELECT kind, ip_address, user_agent, source, recorded_at
+        FROM subscription_consents
+        WHERE subscriber_id = $1
+        ORDER BY recorded_at
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(pool)
+    .await
+}
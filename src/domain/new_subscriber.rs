@@ -1,19 +1,54 @@
+use serde_json::{Map, Value};
+
 use crate::{
     domain::{SubscriberEmail, SubscriberName},
     routes::SubscribeData,
+    subscriber_attributes::{validate_attributes, AttributeDefinition},
 };
 
 pub struct NewSubscriber {
     pub email: SubscriberEmail,
     pub name: SubscriberName,
+    /// Checked against the attribute definitions, see `validate_attributes`.
+    pub attributes: Map<String, Value>,
+}
+
+/// What is wrong with one field of a signup.
+#[derive(Debug)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
 }
 
-impl TryFrom<SubscribeData> for NewSubscriber {
-    type Error = String;
+impl NewSubscriber {
+    /// Reports the problems of every field at once.
+    pub fn parse(
+        value: SubscribeData,
+        definitions: &[AttributeDefinition],
+    ) -> Result<Self, Vec<FieldError>> {
+        let attributes = validate_attributes(definitions, &value.submitted_attributes());
+        let name = SubscriberName::parse(value.name);
+        let email = SubscriberEmail::parse(value.email);
 
-    fn try_from(value: SubscribeData) -> Result<Self, Self::Error> {
-        let name = SubscriberName::parse(value.name)?;
-        let email = SubscriberEmail::parse(value.email)?;
-        Ok(Self { email, name })
+        match (name, email, attributes) {
+            (Ok(name), Ok(email), Ok(attributes)) => Ok(Self {
+                email,
+                name,
+                attributes,
+            }),
+            (name, email, attributes) => {
+                let mut errors: Vec<FieldError> = [("name", name.err()), ("email", email.err())]
+                    .into_iter()
+                    .filter_map(|(field, error)| {
+                        Some(FieldError {
+                            field: field.to_owned(),
+                            message: error?,
+                        })
+                    })
+                    .collect();
+                errors.extend(attributes.err().unwrap_or_default());
+                Err(errors)
+            }
+        }
     }
 }
@@ -4,11 +4,15 @@ use validator::validate_email;
 pub struct SubscriberEmail(String);
 
 impl SubscriberEmail {
+    /// Validates and normalizes an address: surrounding whitespace is removed
+    /// and the domain is lowercased, with IDN domains converted to punycode.
+    /// The local part keeps its casing, uniqueness ignores it in the database.
     pub fn parse(s: String) -> Result<SubscriberEmail, String> {
-        if validate_email(&s) {
-            Ok(Self(s))
-        } else {
-            Err(format!("{} is not a valid subscriber email", s))
+        let normalized = normalize(s.trim());
+
+        match normalized {
+            Some(email) if validate_email(&email) => Ok(Self(email)),
+            _ => Err(format!("{} is not a valid subscriber email", s)),
         }
     }
 
@@ -19,6 +23,18 @@ impl SubscriberEmail {
     pub fn inner_ref(&self) -> &str {
         &self.0
     }
+
+    /// The part after the `@`, lowercase ASCII.
+    pub fn domain(&self) -> &str {
+        self.0.rsplit_once('@').map_or("", |(_, domain)| domain)
+    }
+}
+
+fn normalize(s: &str) -> Option<String> {
+    let (local, domain) = s.rsplit_once('@')?;
+    let domain = idna::domain_to_ascii(domain).ok()?;
+
+    Some(format!("{}@{}", local, domain))
 }
 
 impl AsRef<str> for SubscriberEmail {
@@ -50,6 +66,24 @@ mod tests {
         assert_err!(SubscriberEmail::parse(email));
     }
 
+    #[test]
+    fn surrounding_whitespace_is_trimmed() {
+        let email = SubscriberEmail::parse("  ursula@domain.com\n".to_string()).unwrap();
+        assert_eq!(email.as_ref(), "ursula@domain.com");
+    }
+
+    #[test]
+    fn domain_is_lowercased_but_not_the_local_part() {
+        let email = SubscriberEmail::parse("Ursula.LeGuin@Domain.COM".to_string()).unwrap();
+        assert_eq!(email.as_ref(), "Ursula.LeGuin@domain.com");
+    }
+
+    #[test]
+    fn idn_domains_are_converted_to_punycode() {
+        let email = SubscriberEmail::parse("ursula@Bücher.example".to_string()).unwrap();
+        assert_eq!(email.as_ref(), "ursula@xn--bcher-kva.example");
+    }
+
     use quickcheck::{quickcheck, Arbitrary};
 
     #[derive(Clone, Debug)]
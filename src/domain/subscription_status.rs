@@ -0,0 +1,49 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubscriptionStatus {
+    PendingConfirmation,
+    Confirmed,
+    Unsubscribed,
+}
+
+impl SubscriptionStatus {
+    pub const ALL: [SubscriptionStatus; 3] = [
+        SubscriptionStatus::PendingConfirmation,
+        SubscriptionStatus::Confirmed,
+        SubscriptionStatus::Unsubscribed,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SubscriptionStatus::PendingConfirmation => "pending_confirmation",
+            SubscriptionStatus::Confirmed => "confirmed",
+            SubscriptionStatus::Unsubscribed => "unsubscribed",
+        }
+    }
+}
+
+impl TryFrom<&str> for SubscriptionStatus {
+    type Error = String;
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        SubscriptionStatus::ALL
+            .into_iter()
+            .find(|status| status.as_str() == s)
+            .ok_or_else(|| format!("{} is not a valid subscription status.", s))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SubscriptionStatus;
+
+    #[test]
+    fn statuses_round_trip_through_their_names() {
+        for status in SubscriptionStatus::ALL {
+            assert_eq!(SubscriptionStatus::try_from(status.as_str()), Ok(status));
+        }
+    }
+
+    #[test]
+    fn unknown_statuses_are_rejected() {
+        assert!(SubscriptionStatus::try_from("deleted").is_err());
+    }
+}
@@ -0,0 +1,347 @@
+use std::collections::HashSet;
+use std::sync::OnceLock;
+
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::domain::SubscriberEmail;
+
+const DISPOSABLE_DOMAINS: &str = include_str!("disposable_domains.txt");
+
+/// How many domains `blocked_signups` keeps counting, the least recently
+/// attempted ones make room for new ones.
+pub const MAX_BLOCKED_DOMAINS: i64 = 1000;
+
+fn disposable_domains() -> &'static HashSet<&'static str> {
+    static DOMAINS: OnceLock<HashSet<&'static str>> = OnceLock::new();
+    DOMAINS.get_or_init(|| {
+        DISPOSABLE_DOMAINS
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .collect()
+    })
+}
+
+/// Whether `domain`, or a domain it is a subdomain of, is in the bundled list.
+fn is_disposable(domain: &str) -> bool {
+    let domains = disposable_domains();
+    let mut candidate = domain;
+    loop {
+        if domains.contains(candidate) {
+            return true;
+        }
+        match candidate.split_once('.') {
+            Some((_, parent)) => candidate = parent,
+            None => return false,
+        }
+    }
+}
+
+/// What an admin-defined rule does to the domains it matches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RuleKind {
+    Block,
+    Allow,
+}
+
+impl RuleKind {
+    pub const ALL: [RuleKind; 2] = [RuleKind::Block, RuleKind::Allow];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RuleKind::Block => "block",
+            RuleKind::Allow => "allow",
+        }
+    }
+}
+
+impl TryFrom<&str> for RuleKind {
+    type Error = String;
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        RuleKind::ALL
+            .into_iter()
+            .find(|kind| kind.as_str() == s)
+            .ok_or_else(|| format!("{} is not a valid rule kind.", s))
+    }
+}
+
+/// Why a signup was refused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockReason {
+    Disposable,
+    Blocklist,
+}
+
+impl BlockReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BlockReason::Disposable => "disposable",
+            BlockReason::Blocklist => "blocklist",
+        }
+    }
+
+    /// The validation error shown to whoever tried to sign up.
+    pub fn message(&self) -> &'static str {
+        match self {
+            BlockReason::Disposable => {
+                "Disposable email addresses are not accepted, please use a permanent address."
+            }
+            BlockReason::Blocklist => "Signups from this email domain are not accepted.",
+        }
+    }
+}
+
+pub struct DomainRule {
+    pub rule_id: Uuid,
+    pub pattern: String,
+    pub kind: String,
+    pub created_at: DateTime<Utc>,
+}
+
+pub struct BlockedSignups {
+    pub domain: String,
+    pub reason: String,
+    pub attempts: i64,
+    pub last_attempt_at: DateTime<Utc>,
+}
+
+/// Cleans up a pattern typed by an admin: a domain, optionally with `*`
+/// standing for any run of characters, as in `*.example.com`.
+pub fn normalize_pattern(pattern: &str) -> Result<String, String> {
+    let pattern = pattern.trim().trim_start_matches('@').to_lowercase();
+    if pattern.is_empty() {
+        return Err("The pattern cannot be empty.".into());
+    }
+
+    // Labels are converted one by one so that wildcards survive
+    let labels = pattern
+        .split('.')
+        .map(|label| {
+            if label.contains('*') || label.is_ascii() {
+                Some(label.to_owned())
+            } else {
+                idna::domain_to_ascii(label).ok()
+            }
+        })
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| format!("{} is not a valid domain pattern.", pattern))?;
+    let normalized = labels.join(".");
+
+    let valid = normalized
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '*'));
+    if !valid || normalized.split('.').any(str::is_empty) {
+        return Err(format!("{} is not a valid domain pattern.", pattern));
+    }
+    if normalized.chars().all(|c| matches!(c, '.' | '*')) {
+        return Err("The pattern would match every domain.".into());
+    }
+
+    Ok(normalized)
+}
+
+/// Whether `domain` matches `pattern`, where `*` matches any run of
+/// characters, dots included.
+pub fn pattern_matches(pattern: &str, domain: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let Some(mut rest) = parts.next().and_then(|first| domain.strip_prefix(first)) else {
+        return false;
+    };
+
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        return rest.is_empty();
+    };
+
+    for part in middle {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+
+    rest.ends_with(last)
+}
+
+/// Allow rules win over everything, then block rules, then the bundled list.
+pub fn evaluate(rules: &[(RuleKind, String)], domain: &str) -> Option<BlockReason> {
+    let matching = |kind| {
+        rules
+            .iter()
+            .any(|(rule_kind, pattern)| *rule_kind == kind && pattern_matches(pattern, domain))
+    };
+
+    if matching(RuleKind::Allow) {
+        None
+    } else if matching(RuleKind::Block) {
+        Some(BlockReason::Blocklist)
+    } else if is_disposable(domain) {
+        Some(BlockReason::Disposable)
+    } else {
+        None
+    }
+}
+
+/// Applies the domain policy to a signup, counting the attempt if it is
+/// refused. Only the `MAX_BLOCKED_DOMAINS` most recently attempted domains
+/// are counted.
+pub async fn check_signup_domain(
+    pool: &PgPool,
+    email: &SubscriberEmail,
+) -> Result<Option<BlockReason>, sqlx::Error> {
+    let rules = sqlx::query!("SELECT pattern, kind FROM signup_domain_rules")
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .filter_map(|row| Some((RuleKind::try_from(row.kind.as_str()).ok()?, row.pattern)))
+        .collect::<Vec<_>>();
+
+    let domain = email.domain();
+    let Some(reason) = evaluate(&rules, domain) else {
+        return Ok(None);
+    };
+
+    sqlx::query!(
+        r#"
+        INSERT INTO blocked_signups (domain, reason, attempts, last_attempt_at)
+        VALUES ($1, $2, 1, now())
+        ON CONFLICT (domain, reason) DO UPDATE
+        SET attempts = blocked_signups.attempts + 1, last_attempt_at = now()
+        "#,
+        domain,
+        reason.as_str(),
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query!(
+        r#"
+        DELETE FROM blocked_signups
+        WHERE (domain, reason) IN (
+            SELECT domain, reason FROM blocked_signups
+            ORDER BY last_attempt_at DESC
+            OFFSET $1
+        )
+        "#,
+        MAX_BLOCKED_DOMAINS,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(Some(reason))
+}
+
+pub async fn list_rules(pool: &PgPool) -> Result<Vec<DomainRule>, sqlx::Error> {
+    sqlx::query_as!(
+        DomainRule,
+        r#"
+        SELECT rule_id, pattern, kind, created_at
+        FROM signup_domain_rules
+        ORDER BY kind, pattern
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// `pattern` must come from `normalize_pattern`. Fails with a unique
+/// violation if there already is a rule for it.
+pub async fn add_rule(pool: &PgPool, pattern: &str, kind: RuleKind) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO signup_domain_rules (rule_id, pattern, kind, created_at)
+        VALUES ($1, $2, $3, now())
+        "#,
+        Uuid::new_v4(),
+        pattern,
+        kind.as_str(),
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Returns the removed rule as `kind pattern`, or `None` if there was no such
+/// rule.
+pub async fn remove_rule(pool: &PgPool, rule_id: Uuid) -> Result<Option<String>, sqlx::Error> {
+    let row = sqlx::query!(
+        "DELETE FROM signup_domain_rules WHERE rule_id = $1 RETURNING pattern, kind",
+        rule_id,
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(|row| format!("{} {}", row.kind, row.pattern)))
+}
+
+/// Most blocked domains first.
+pub async fn blocked_signups(pool: &PgPool) -> Result<Vec<BlockedSignups>, sqlx::Error> {
+    sqlx::query_as!(
+        BlockedSignups,
+        r#"
+        SELECT domain, reason, attempts, last_attempt_at
+        FROM blocked_signups
+        ORDER BY attempts DESC, domain
+        LIMIT 100
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{evaluate, normalize_pattern, pattern_matches, BlockReason, RuleKind};
+
+    #[test]
+    fn wildcards_match_any_run_of_characters() {
+        assert!(pattern_matches("example.com", "example.com"));
+        assert!(!pattern_matches("example.com", "mail.example.com"));
+        assert!(pattern_matches("*.example.com", "mail.example.com"));
+        assert!(pattern_matches("*.example.com", "a.b.example.com"));
+        assert!(!pattern_matches("*.example.com", "example.com"));
+        assert!(pattern_matches("spam*.*", "spammy.net"));
+        assert!(!pattern_matches("spam*.*", "ham.net"));
+    }
+
+    #[test]
+    fn patterns_are_normalized_and_validated() {
+        assert_eq!(normalize_pattern(" @Example.COM ").unwrap(), "example.com");
+        assert_eq!(
+            normalize_pattern("*.Bücher.example").unwrap(),
+            "*.xn--bcher-kva.example"
+        );
+        assert!(normalize_pattern("").is_err());
+        assert!(normalize_pattern("*.*").is_err());
+        assert!(normalize_pattern("exa mple.com").is_err());
+        assert!(normalize_pattern("example..com").is_err());
+    }
+
+    #[test]
+    fn allow_rules_win_over_block_rules_and_the_bundled_list() {
+        let rules = vec![
+            (RuleKind::Block, "*.example.com".to_owned()),
+            (RuleKind::Allow, "good.example.com".to_owned()),
+            (RuleKind::Allow, "yopmail.fr".to_owned()),
+        ];
+
+        assert_eq!(
+            evaluate(&rules, "bad.example.com"),
+            Some(BlockReason::Blocklist)
+        );
+        assert_eq!(evaluate(&rules, "good.example.com"), None);
+        assert_eq!(
+            evaluate(&rules, "mailinator.com"),
+            Some(BlockReason::Disposable)
+        );
+        assert_eq!(
+            evaluate(&rules, "eu.mailinator.com"),
+            Some(BlockReason::Disposable)
+        );
+        assert_eq!(evaluate(&rules, "yopmail.fr"), None);
+        assert_eq!(evaluate(&rules, "example.org"), None);
+    }
+}
@@ -1,16 +1,21 @@
 use core::time;
 
 use crate::domain::SubscriberEmail;
+use crate::suppression::is_suppressed;
 
 use reqwest::{Client, Url};
 use secrecy::{ExposeSecret, Secret};
 use serde::Serialize;
+use sqlx::PgPool;
+use tracing::info;
 
 pub struct EmailClient {
     http_client: Client,
     base_url: String,
     sender: SubscriberEmail,
     authorization_token: Secret<String>,
+    /// Where the suppression list is kept, checked before every send.
+    suppressions: PgPool,
 }
 
 #[derive(Serialize)]
@@ -24,14 +29,26 @@ struct SendEmailRequest<'a> {
 }
 
 impl EmailClient {
-    pub fn new(base_url: String, sender: SubscriberEmail, token: Secret<String>) -> Self {
-        Self::with_timeout(base_url, sender, token, time::Duration::from_secs(5))
+    pub fn new(
+        base_url: String,
+        sender: SubscriberEmail,
+        token: Secret<String>,
+        suppressions: PgPool,
+    ) -> Self {
+        Self::with_timeout(
+            base_url,
+            sender,
+            token,
+            suppressions,
+            time::Duration::from_secs(5),
+        )
     }
 
     pub fn with_timeout(
         base_url: String,
         sender: SubscriberEmail,
         token: Secret<String>,
+        suppressions: PgPool,
         timeout: time::Duration,
     ) -> Self {
         Self {
@@ -39,17 +56,39 @@ impl EmailClient {
             base_url,
             sender,
             authorization_token: token,
+            suppressions,
         }
     }
 }
 
 impl EmailClient {
+    /// Addresses on the suppression list are skipped without an error, as if
+    /// the email had been sent. Failing to check the list fails the send.
     pub async fn send_email(
         &self,
         recipient: &SubscriberEmail,
         subject: &str,
         html_content: &str,
         text_content: &str,
+    ) -> Result<(), anyhow::Error> {
+        if is_suppressed(&self.suppressions, recipient.as_ref()).await? {
+            info!("not sending \"{}\" to a suppressed address", subject);
+            return Ok(());
+        }
+
+        self.send_prefiltered(recipient, subject, html_content, text_content)
+            .await
+    }
+
+    /// Only for newsletter issues, whose recipients were already filtered
+    /// against the suppression list when they were looked up: the list is
+    /// not checked again for each email.
+    pub(crate) async fn send_prefiltered(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
     ) -> Result<(), anyhow::Error> {
         let url = Url::parse(&self.base_url)?.join("email")?;
         let request_body = SendEmailRequest {
@@ -86,6 +125,7 @@ mod tests {
     use fake::faker::lorem::en::{Paragraph, Sentence};
     use fake::{Fake, Faker};
     use secrecy::Secret;
+    use sqlx::PgPool;
     use wiremock::matchers::{any, header, header_exists, method, path};
     use wiremock::{Mock, MockServer, ResponseTemplate};
 
@@ -102,10 +142,13 @@ mod tests {
     }
 
     fn email_client(base_url: String) -> EmailClient {
+        // Never connected, the tests go through `send_prefiltered`
+        let suppressions = PgPool::connect_lazy("postgres://localhost/newsletter").unwrap();
         EmailClient::with_timeout(
             base_url,
             email(),
             Secret::new(Faker.fake()),
+            suppressions,
             Duration::from_millis(100),
         )
     }
@@ -127,7 +170,7 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn send_email_sends_the_expected_request() {
+    async fn send_prefiltered_sends_the_expected_request() {
         // Arrange
         let mock_server = MockServer::start().await;
         let email_client = email_client(mock_server.uri());
@@ -143,14 +186,14 @@ mod tests {
             .await;
 
         let outcome = email_client
-            .send_email(&email(), &subject(), &content(), &content())
+            .send_prefiltered(&email(), &subject(), &content(), &content())
             .await;
 
         assert_ok!(outcome);
     }
 
     #[tokio::test]
-    async fn send_email_fails_if_the_server_returns_500() {
+    async fn send_prefiltered_fails_if_the_server_returns_500() {
         // Arrange
         let mock_server = MockServer::start().await;
         let email_client = email_client(mock_server.uri());
@@ -162,7 +205,7 @@ mod tests {
             .await;
 
         let outcome = email_client
-            .send_email(&email(), &subject(), &content(), &content())
+            .send_prefiltered(&email(), &subject(), &content(), &content())
             .await;
 
         assert_err!(outcome);
@@ -0,0 +1,53 @@
+use sqlx::PgPool;
+use tracing::{error, info};
+
+use crate::configuration::IdempotencySettings;
+
+pub async fn run_expiry_sweeper(pool: PgPool, settings: IdempotencySettings) {
+    let mut interval = tokio::time::interval(settings.sweep_interval());
+
+    loop {
+        interval.tick().await;
+
+        match delete_expired_keys(&pool, &settings).await {
+            Ok(0) => {}
+            Ok(n) => info!("removed {} expired idempotency keys", n),
+            Err(e) => error!("failed to remove expired idempotency keys: {:?}", e),
+        }
+    }
+}
+
+pub async fn delete_expired_keys(
+    pool: &PgPool,
+    settings: &IdempotencySettings,
+) -> Result<u64, sqlx::Error> {
+    let cutoff = chrono::Utc::now() - settings.retention();
+    let batch_size = i64::from(settings.sweep_batch_size);
+    let mut total = 0;
+
+    // Delete in small batches so the sweep never holds long locks on the table
+    loop {
+        let deleted = sqlx::query!(
+            r#"
+            DELETE FROM idempotency
+            WHERE ctid IN (
+                SELECT ctid
+                FROM idempotency
+                WHERE created_at < $1
+                LIMIT $2
+            )
+            "#,
+            cutoff,
+            batch_size
+        )
+        .execute(pool)
+        .await?
+        .rows_affected();
+
+        total += deleted;
+
+        if deleted < batch_size as u64 {
+            return Ok(total);
+        }
+    }
+}
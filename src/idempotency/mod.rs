@@ -1,5 +1,13 @@
+mod expiry;
 mod key;
 mod persistance;
+mod postgres;
+mod redis;
 
+pub use expiry::*;
 pub use key::IdempotencyKey;
-pub use persistance::*;
+pub use persistance::{IdempotencyStore, NextAction, PendingResponse};
+pub use postgres::PostgresIdempotencyStore;
+pub use redis::RedisIdempotencyStore;
+
+use persistance::buffer_response;
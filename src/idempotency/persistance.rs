@@ -1,126 +1,50 @@
-use std::str::FromStr;
-
-use super::IdempotencyKey;
-
+use async_trait::async_trait;
 use axum::{
     body::Body,
-    http::{HeaderMap, HeaderName, HeaderValue, Response, StatusCode},
-    response::IntoResponse,
+    http::{response::Parts, Response},
 };
 use futures::StreamExt;
-use sqlx::{postgres::PgHasArrayType, Acquire, PgPool, Postgres, Transaction};
+use sqlx::{Postgres, Transaction};
 use uuid::Uuid;
 
-#[derive(Debug, sqlx::Type)]
-#[sqlx(type_name = "header_pair")]
-struct HeaderPairRecord {
-    name: String,
-    value: Vec<u8>,
-}
-
-impl PgHasArrayType for HeaderPairRecord {
-    fn array_type_info() -> sqlx::postgres::PgTypeInfo {
-        sqlx::postgres::PgTypeInfo::with_name("_header_pair")
-    }
-}
+use super::{redis::RedisLock, IdempotencyKey};
 
 pub enum NextAction {
-    StartProcessing(Transaction<'static, Postgres>),
+    StartProcessing(PendingResponse),
     ReturnSavedResponse(Response<Body>),
 }
 
-pub async fn try_processing(
-    pool: &PgPool,
-    idempotency_key: &IdempotencyKey,
-    user_id: Uuid,
-) -> Option<NextAction> {
-    let mut transaction = pool.begin().await.ok()?;
-
-    let n_inserted_rows = sqlx::query!(
-        r#"
-        INSERT INTO idempotency (
-            user_id,
-            idempotency_key,
-            created_at
-        )
-        VALUES ($1, $2, now())
-        ON CONFLICT DO NOTHING
-        "#,
-        user_id,
-        idempotency_key.as_ref()
-    )
-    .execute(transaction.acquire().await.ok()?)
-    .await
-    .ok()?
-    .rows_affected();
+/// Proof that the current request owns an idempotency key. It must be handed
+/// back to the store that issued it through `save_response`; dropping it
+/// releases the key so that a retry can start processing again.
+pub struct PendingResponse(pub(super) PendingState);
 
-    if n_inserted_rows > 0 {
-        Some(NextAction::StartProcessing(transaction))
-    } else {
-        let saved_response = get_saved_response(pool, idempotency_key, user_id).await?;
-        Some(NextAction::ReturnSavedResponse(saved_response))
-    }
+pub(super) enum PendingState {
+    Postgres(Box<Transaction<'static, Postgres>>),
+    Redis(RedisLock),
 }
 
-pub async fn get_saved_response(
-    pool: &PgPool,
-    idempotency_key: &IdempotencyKey,
-    user_id: Uuid,
-) -> Option<Response<Body>> {
-    let record = sqlx::query!(
-        r#"
-        SELECT
-            response_status_code,
-            response_headers as "response_headers: Vec<HeaderPairRecord>",
-            response_body
-        FROM idempotency
-        WHERE
-            user_id = $1 AND
-            idempotency_key = $2
-        "#,
-        user_id,
-        idempotency_key.as_ref()
-    )
-    .fetch_optional(pool)
-    .await
-    .ok()??;
-
-    let status_code = StatusCode::from_u16(record.response_status_code?.try_into().ok()?).ok()?;
-
-    let header_map = record.response_headers?.into_iter().fold(
-        HeaderMap::new(),
-        |mut acc, HeaderPairRecord { name, value }| {
-            if let (Ok(name), Ok(value)) =
-                (HeaderName::from_str(&name), HeaderValue::from_bytes(&value))
-            {
-                acc.append(name, value);
-            }
-
-            acc
-        },
-    );
-
-    Some((header_map, status_code).into_response())
+#[async_trait]
+pub trait IdempotencyStore: Send + Sync {
+    /// Claims `idempotency_key` for `user_id`, or returns the response saved by
+    /// the request that claimed it first. Waits for in-flight requests holding
+    /// the same key to finish before deciding.
+    async fn try_processing(
+        &self,
+        idempotency_key: &IdempotencyKey,
+        user_id: Uuid,
+    ) -> Option<NextAction>;
+
+    async fn save_response(
+        &self,
+        pending: PendingResponse,
+        idempotency_key: &IdempotencyKey,
+        user_id: Uuid,
+        http_response: Response<Body>,
+    ) -> Result<Response<Body>, anyhow::Error>;
 }
 
-pub async fn save_response(
-    mut transaction: Transaction<'static, Postgres>,
-    idempotency_key: &IdempotencyKey,
-    user_id: Uuid,
-    http_response: Response<Body>,
-) -> Result<Response<Body>, anyhow::Error> {
-    let status_code = http_response.status().as_u16() as i16;
-
-    let headers = {
-        let mut h = Vec::with_capacity(http_response.headers().len());
-        for (name, value) in http_response.headers().iter() {
-            let name = name.as_str().to_owned();
-            let value = value.as_bytes().to_owned();
-            h.push(HeaderPairRecord { name, value });
-        }
-        h
-    };
-
+pub(super) async fn buffer_response(http_response: Response<Body>) -> (Parts, Vec<u8>) {
     let (parts, body) = http_response.into_parts();
     let bytes = body
         .into_data_stream()
@@ -130,27 +54,5 @@ pub async fn save_response(
         })
         .await;
 
-    sqlx::query_unchecked!(
-        r#"
-        UPDATE idempotency
-        SET
-            response_status_code = $3,
-            response_headers = $4,
-            response_body = $5
-        WHERE
-            user_id = $1 AND
-            idempotency_key = $2
-        "#,
-        user_id,
-        idempotency_key.as_ref(),
-        status_code,
-        headers,
-        bytes
-    )
-    .execute(transaction.acquire().await?)
-    .await?;
-
-    transaction.commit().await?;
-
-    Ok((parts, Body::from(bytes)).into_response())
+    (parts, bytes)
 }
@@ -0,0 +1,178 @@
+use std::str::FromStr;
+
+use super::{buffer_response, IdempotencyKey, IdempotencyStore, NextAction, PendingResponse};
+use crate::idempotency::persistance::PendingState;
+
+use async_trait::async_trait;
+use axum::{
+    body::Body,
+    http::{HeaderMap, HeaderName, HeaderValue, Response, StatusCode},
+    response::IntoResponse,
+};
+use chrono::Utc;
+use sqlx::{postgres::PgHasArrayType, Acquire, PgPool};
+use uuid::Uuid;
+
+#[derive(Debug, sqlx::Type)]
+#[sqlx(type_name = "header_pair")]
+struct HeaderPairRecord {
+    name: String,
+    value: Vec<u8>,
+}
+
+impl PgHasArrayType for HeaderPairRecord {
+    fn array_type_info() -> sqlx::postgres::PgTypeInfo {
+        sqlx::postgres::PgTypeInfo::with_name("_header_pair")
+    }
+}
+
+pub struct PostgresIdempotencyStore {
+    pool: PgPool,
+    retention: chrono::Duration,
+}
+
+impl PostgresIdempotencyStore {
+    pub fn new(pool: PgPool, retention: chrono::Duration) -> Self {
+        Self { pool, retention }
+    }
+
+    async fn get_saved_response(
+        &self,
+        idempotency_key: &IdempotencyKey,
+        user_id: Uuid,
+    ) -> Option<Response<Body>> {
+        let record = sqlx::query!(
+            r#"
+            SELECT
+                response_status_code,
+                response_headers as "response_headers: Vec<HeaderPairRecord>",
+                response_body
+            FROM idempotency
+            WHERE
+                user_id = $1 AND
+                idempotency_key = $2
+            "#,
+            user_id,
+            idempotency_key.as_ref()
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .ok()??;
+
+        let status_code =
+            StatusCode::from_u16(record.response_status_code?.try_into().ok()?).ok()?;
+
+        let header_map = record.response_headers?.into_iter().fold(
+            HeaderMap::new(),
+            |mut acc, HeaderPairRecord { name, value }| {
+                if let (Ok(name), Ok(value)) =
+                    (HeaderName::from_str(&name), HeaderValue::from_bytes(&value))
+                {
+                    acc.append(name, value);
+                }
+
+                acc
+            },
+        );
+
+        let body = Body::from(record.response_body?);
+
+        Some((status_code, header_map, body).into_response())
+    }
+}
+
+#[async_trait]
+impl IdempotencyStore for PostgresIdempotencyStore {
+    async fn try_processing(
+        &self,
+        idempotency_key: &IdempotencyKey,
+        user_id: Uuid,
+    ) -> Option<NextAction> {
+        let mut transaction = self.pool.begin().await.ok()?;
+
+        // Keys older than the retention window are reclaimed as if they were new,
+        // even if the sweeper has not removed them yet.
+        let n_inserted_rows = sqlx::query!(
+            r#"
+            INSERT INTO idempotency (
+                user_id,
+                idempotency_key,
+                created_at
+            )
+            VALUES ($1, $2, now())
+            ON CONFLICT (user_id, idempotency_key) DO UPDATE
+            SET
+                created_at = now(),
+                response_status_code = NULL,
+                response_headers = NULL,
+                response_body = NULL
+            WHERE idempotency.created_at < $3
+            "#,
+            user_id,
+            idempotency_key.as_ref(),
+            Utc::now() - self.retention
+        )
+        .execute(transaction.acquire().await.ok()?)
+        .await
+        .ok()?
+        .rows_affected();
+
+        if n_inserted_rows > 0 {
+            let pending = PendingState::Postgres(Box::new(transaction));
+            Some(NextAction::StartProcessing(PendingResponse(pending)))
+        } else {
+            let saved_response = self.get_saved_response(idempotency_key, user_id).await?;
+            Some(NextAction::ReturnSavedResponse(saved_response))
+        }
+    }
+
+    async fn save_response(
+        &self,
+        pending: PendingResponse,
+        idempotency_key: &IdempotencyKey,
+        user_id: Uuid,
+        http_response: Response<Body>,
+    ) -> Result<Response<Body>, anyhow::Error> {
+        let PendingState::Postgres(mut transaction) = pending.0 else {
+            anyhow::bail!("The idempotency key was not claimed through Postgres");
+        };
+
+        let status_code = http_response.status().as_u16() as i16;
+
+        let headers = {
+            let mut h = Vec::with_capacity(http_response.headers().len());
+            for (name, value) in http_response.headers().iter() {
+                let name = name.as_str().to_owned();
+                let value = value.as_bytes().to_owned();
+                h.push(HeaderPairRecord { name, value });
+            }
+            h
+        };
+
+        let (parts, bytes) = buffer_response(http_response).await;
+
+        sqlx::query_unchecked!(
+            r#"
+            UPDATE idempotency
+            SET
+                response_status_code = $3,
+                response_headers = $4,
+                response_body = $5
+            WHERE
+                user_id = $1 AND
+                idempotency_key = $2
+            "#,
+            user_id,
+            idempotency_key.as_ref(),
+            status_code,
+            headers,
+            bytes
+        )
+        .execute(transaction.acquire().await?)
+        .await?;
+
+        transaction.commit().await?;
+
+        Ok((parts, Body::from(bytes)).into_response())
+    }
+}
@@ -0,0 +1,281 @@
+use std::str::FromStr;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use axum::{
+    body::Body,
+    http::{HeaderMap, HeaderName, HeaderValue, Response, StatusCode},
+    response::IntoResponse,
+};
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinHandle;
+use tower_sessions_redis_store::fred::{
+    clients::RedisPool,
+    interfaces::{KeysInterface, LuaInterface},
+    types::{Expiration, SetOptions},
+};
+use tracing::warn;
+use uuid::Uuid;
+
+use super::{buffer_response, IdempotencyKey, IdempotencyStore, NextAction, PendingResponse};
+use crate::idempotency::persistance::PendingState;
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+enum RedisRecord {
+    /// `owner` tells apart the request holding the key from one whose marker
+    /// expired and was replaced.
+    InProgress { owner: Uuid },
+    Completed {
+        status_code: u16,
+        headers: Vec<(String, Vec<u8>)>,
+        body: Vec<u8>,
+    },
+}
+
+/// Pushes back the expiry of the marker, as long as it is still ours.
+const EXTEND_SCRIPT: &str = r#"
+if redis.call("GET", KEYS[1]) == ARGV[1] then
+    return redis.call("PEXPIRE", KEYS[1], ARGV[2])
+end
+return 0
+"#;
+
+/// Removes the marker, as long as it is still ours.
+const RELEASE_SCRIPT: &str = r#"
+if redis.call("GET", KEYS[1]) == ARGV[1] then
+    return redis.call("DEL", KEYS[1])
+end
+return 0
+"#;
+
+/// Replaces the marker with the saved response, as long as it is still ours.
+const SAVE_SCRIPT: &str = r#"
+if redis.call("GET", KEYS[1]) == ARGV[1] then
+    redis.call("SET", KEYS[1], ARGV[2], "EX", ARGV[3])
+    return 1
+end
+return 0
+"#;
+
+pub struct RedisIdempotencyStore {
+    pool: RedisPool,
+    retention: chrono::Duration,
+    lock_timeout: Duration,
+}
+
+/// Marks a key as being processed. The marker expires after the lock timeout
+/// so that a crashed instance does not hold the key forever, and is kept
+/// alive in the background for as long as the owning request runs. If the
+/// request never saves a response, the marker is removed on drop so that a
+/// retry is not stuck waiting for it to expire.
+pub(super) struct RedisLock {
+    pool: RedisPool,
+    key: String,
+    marker: String,
+    keep_alive: JoinHandle<()>,
+    held: bool,
+}
+
+impl RedisLock {
+    fn new(pool: RedisPool, key: String, marker: String, lock_timeout: Duration) -> Self {
+        let keep_alive = tokio::spawn({
+            let (pool, key, marker) = (pool.clone(), key.clone(), marker.clone());
+            async move {
+                let mut interval =
+                    tokio::time::interval((lock_timeout / 3).max(Duration::from_millis(100)));
+                interval.tick().await;
+                loop {
+                    interval.tick().await;
+                    let extended: Result<i64, _> = pool
+                        .eval(
+                            EXTEND_SCRIPT,
+                            key.clone(),
+                            vec![marker.clone(), lock_timeout.as_millis().to_string()],
+                        )
+                        .await;
+                    match extended {
+                        Ok(0) => {
+                            warn!("lost the idempotency lock on {}", key);
+                            return;
+                        }
+                        Ok(_) => {}
+                        Err(e) => warn!("failed to extend the idempotency lock: {:?}", e),
+                    }
+                }
+            }
+        });
+
+        Self {
+            pool,
+            key,
+            marker,
+            keep_alive,
+            held: true,
+        }
+    }
+}
+
+impl Drop for RedisLock {
+    fn drop(&mut self) {
+        self.keep_alive.abort();
+        if !self.held {
+            return;
+        }
+
+        let (pool, key, marker) = (self.pool.clone(), self.key.clone(), self.marker.clone());
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            runtime.spawn(async move {
+                let _: Result<i64, _> = pool.eval(RELEASE_SCRIPT, key, vec![marker]).await;
+            });
+        }
+    }
+}
+
+impl RedisIdempotencyStore {
+    const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+    pub fn new(pool: RedisPool, retention: chrono::Duration, lock_timeout: Duration) -> Self {
+        Self {
+            pool,
+            retention,
+            lock_timeout,
+        }
+    }
+
+    fn redis_key(idempotency_key: &IdempotencyKey, user_id: Uuid) -> String {
+        format!("idempotency:{}:{}", user_id, idempotency_key.as_ref())
+    }
+
+    async fn try_lock(&self, key: &str) -> Option<Option<RedisLock>> {
+        let marker = serde_json::to_string(&RedisRecord::InProgress {
+            owner: Uuid::new_v4(),
+        })
+        .ok()?;
+        let set: Option<String> = self
+            .pool
+            .set(
+                key,
+                marker.as_str(),
+                Some(Expiration::PX(self.lock_timeout.as_millis() as i64)),
+                Some(SetOptions::NX),
+                false,
+            )
+            .await
+            .ok()?;
+
+        Some(
+            set.map(|_| {
+                RedisLock::new(self.pool.clone(), key.to_owned(), marker, self.lock_timeout)
+            }),
+        )
+    }
+
+    async fn get_record(&self, key: &str) -> Option<Option<RedisRecord>> {
+        let value: Option<String> = self.pool.get(key).await.ok()?;
+        match value {
+            None => Some(None),
+            Some(value) => Some(Some(serde_json::from_str(&value).ok()?)),
+        }
+    }
+}
+
+#[async_trait]
+impl IdempotencyStore for RedisIdempotencyStore {
+    async fn try_processing(
+        &self,
+        idempotency_key: &IdempotencyKey,
+        user_id: Uuid,
+    ) -> Option<NextAction> {
+        let key = Self::redis_key(idempotency_key, user_id);
+
+        // Mirror the Postgres behaviour: a request that finds the key in flight
+        // waits for the owner to finish instead of failing. The owner either
+        // saves a response, releases the key or stops keeping it alive.
+        loop {
+            if let Some(lock) = self.try_lock(&key).await? {
+                return Some(NextAction::StartProcessing(PendingResponse(
+                    PendingState::Redis(lock),
+                )));
+            }
+
+            match self.get_record(&key).await? {
+                Some(RedisRecord::Completed {
+                    status_code,
+                    headers,
+                    body,
+                }) => {
+                    let status_code = StatusCode::from_u16(status_code).ok()?;
+                    let header_map =
+                        headers
+                            .into_iter()
+                            .fold(HeaderMap::new(), |mut acc, (name, value)| {
+                                if let (Ok(name), Ok(value)) =
+                                    (HeaderName::from_str(&name), HeaderValue::from_bytes(&value))
+                                {
+                                    acc.append(name, value);
+                                }
+
+                                acc
+                            });
+
+                    return Some(NextAction::ReturnSavedResponse(
+                        (status_code, header_map, Body::from(body)).into_response(),
+                    ));
+                }
+
+                Some(RedisRecord::InProgress { .. }) | None => {
+                    tokio::time::sleep(Self::POLL_INTERVAL).await;
+                }
+            }
+        }
+    }
+
+    async fn save_response(
+        &self,
+        pending: PendingResponse,
+        idempotency_key: &IdempotencyKey,
+        user_id: Uuid,
+        http_response: Response<Body>,
+    ) -> Result<Response<Body>, anyhow::Error> {
+        let PendingState::Redis(mut lock) = pending.0 else {
+            anyhow::bail!("The idempotency key was not claimed through Redis");
+        };
+
+        let status_code = http_response.status().as_u16();
+        let headers = http_response
+            .headers()
+            .iter()
+            .map(|(name, value)| (name.as_str().to_owned(), value.as_bytes().to_owned()))
+            .collect();
+
+        let (parts, bytes) = buffer_response(http_response).await;
+
+        let record = serde_json::to_string(&RedisRecord::Completed {
+            status_code,
+            headers,
+            body: bytes.clone(),
+        })?;
+
+        debug_assert_eq!(lock.key, Self::redis_key(idempotency_key, user_id));
+
+        let saved: i64 = self
+            .pool
+            .eval(
+                SAVE_SCRIPT,
+                lock.key.clone(),
+                vec![
+                    lock.marker.clone(),
+                    record,
+                    self.retention.num_seconds().to_string(),
+                ],
+            )
+            .await?;
+        if saved == 0 {
+            anyhow::bail!("The idempotency key was claimed by another request meanwhile");
+        }
+        lock.held = false;
+
+        Ok((parts, Body::from(bytes)).into_response())
+    }
+}
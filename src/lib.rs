@@ -1,9 +1,29 @@
+pub mod api_tokens;
+pub mod attribution;
+pub mod audit;
 pub mod authentication;
+pub mod cli;
+pub mod client_info;
 pub mod configuration;
+pub mod confirmation_reminders;
+pub mod consent;
 pub mod domain;
+pub mod domain_policy;
 pub mod email_client;
 pub mod idempotency;
+pub mod login_attempts;
 pub mod routes;
 pub mod session_state;
+pub mod signing;
+pub mod signup_protection;
 pub mod startup;
+pub mod subscriber_attributes;
+pub mod subscriber_data;
+pub mod subscriber_events;
+pub mod subscriber_export;
+pub mod subscriber_import;
+pub mod subscriber_tags;
+pub mod suppression;
 pub mod telemetry;
+pub mod two_factor;
+pub mod welcome_sequence;
@@ -0,0 +1,256 @@
+use std::net::IpAddr;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use tower_sessions_redis_store::fred::{
+    clients::RedisPool,
+    interfaces::KeysInterface,
+    types::{Expiration, RedisKey},
+};
+use uuid::Uuid;
+
+use crate::{client_info::ClientInfo, configuration::LoginThrottlingSettings};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoginOutcome {
+    Success,
+    Failure,
+    /// Refused without checking the credentials, because of earlier failures
+    Locked,
+}
+
+impl LoginOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LoginOutcome::Success => "success",
+            LoginOutcome::Failure => "failure",
+            LoginOutcome::Locked => "locked",
+        }
+    }
+}
+
+pub struct LoginAttempt {
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub outcome: String,
+    pub attempted_at: DateTime<Utc>,
+}
+
+/// Slows down password guessing. Failures are counted in Redis both per
+/// username and per client IP; each failure blocks further attempts for an
+/// exponentially growing delay, and reaching `max_failures` locks the
+/// username or IP out for `lockout_seconds`.
+///
+/// Password reset requests are counted the same way, apart from the login
+/// failures, so that they cannot be used to flood an admin with emails.
+pub struct LoginThrottle {
+    pool: RedisPool,
+    settings: LoginThrottlingSettings,
+}
+
+impl LoginThrottle {
+    pub fn new(pool: RedisPool, settings: LoginThrottlingSettings) -> Self {
+        Self { pool, settings }
+    }
+
+    fn scopes(username: &str, ip: Option<IpAddr>) -> Vec<String> {
+        let mut scopes = vec![format!("username:{}", username)];
+        if let Some(ip) = ip {
+            scopes.push(format!("ip:{}", ip));
+        }
+        scopes
+    }
+
+    fn reset_scopes(username: &str, ip: Option<IpAddr>) -> Vec<String> {
+        Self::scopes(username, ip)
+            .into_iter()
+            .map(|scope| format!("password_reset:{}", scope))
+            .collect()
+    }
+
+    fn failures_key(scope: &str) -> String {
+        format!("login_failures:{}", scope)
+    }
+
+    fn blocked_key(scope: &str) -> String {
+        format!("login_blocked:{}", scope)
+    }
+
+    /// Whether attempts for this username or from this IP are currently refused.
+    pub async fn is_blocked(&self, username: &str, ip: Option<IpAddr>) -> anyhow::Result<bool> {
+        self.any_blocked(Self::scopes(username, ip)).await
+    }
+
+    pub async fn record_failure(&self, username: &str, ip: Option<IpAddr>) -> anyhow::Result<()> {
+        self.count(Self::scopes(username, ip)).await
+    }
+
+    /// Whether reset requests for this username or from this IP are
+    /// currently refused.
+    pub async fn is_reset_blocked(
+        &self,
+        username: &str,
+        ip: Option<IpAddr>,
+    ) -> anyhow::Result<bool> {
+        self.any_blocked(Self::reset_scopes(username, ip)).await
+    }
+
+    pub async fn record_reset_request(
+        &self,
+        username: &str,
+        ip: Option<IpAddr>,
+    ) -> anyhow::Result<()> {
+        self.count(Self::reset_scopes(username, ip)).await
+    }
+
+    async fn any_blocked(&self, scopes: Vec<String>) -> anyhow::Result<bool> {
+        let keys: Vec<RedisKey> = scopes
+            .iter()
+            .map(|scope| Self::blocked_key(scope).into())
+            .collect();
+
+        let blocked: i64 = self.pool.exists(keys).await?;
+        Ok(blocked > 0)
+    }
+
+    async fn count(&self, scopes: Vec<String>) -> anyhow::Result<()> {
+        for scope in scopes {
+            let failures_key = Self::failures_key(&scope);
+            let failures: i64 = self.pool.incr(&failures_key).await?;
+            if failures == 1 {
+                let window = self.settings.failure_window_seconds as i64;
+                let _: bool = self.pool.expire(&failures_key, window).await?;
+            }
+
+            let block = block_duration(&self.settings, failures as u32);
+            if !block.is_zero() {
+                let _: Option<String> = self
+                    .pool
+                    .set(
+                        Self::blocked_key(&scope),
+                        "1",
+                        Some(Expiration::PX(block.as_millis() as i64)),
+                        None,
+                        false,
+                    )
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Forgets the failures of `username`. Those of the IP are kept, so that
+    /// knowing one password does not allow guessing others from the same place.
+    pub async fn record_success(&self, username: &str) -> anyhow::Result<()> {
+        let scope = format!("username:{}", username);
+        let keys: Vec<RedisKey> = vec![
+            Self::failures_key(&scope).into(),
+            Self::blocked_key(&scope).into(),
+        ];
+
+        let _: i64 = self.pool.del(keys).await?;
+        Ok(())
+    }
+}
+
+/// How long further attempts are refused after `failures` failures in a row.
+fn block_duration(settings: &LoginThrottlingSettings, failures: u32) -> Duration {
+    if failures >= settings.max_failures {
+        return Duration::from_secs(settings.lockout_seconds);
+    }
+
+    let exponent = failures.saturating_sub(1).min(31);
+    let delay = settings
+        .base_delay_milliseconds
+        .saturating_mul(1 << exponent)
+        .min(settings.max_delay_seconds.saturating_mul(1000));
+
+    Duration::from_millis(delay)
+}
+
+/// Keeps a trace of the attempt so that the account owner can review it.
+pub async fn record_attempt(
+    pool: &PgPool,
+    username: &str,
+    client: &ClientInfo,
+    outcome: LoginOutcome,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO login_attempts
+            (attempt_id, username, user_id, ip_address, user_agent, outcome, attempted_at)
+        SELECT $1, $2, (SELECT user_id FROM users WHERE username = $2), $3, $4, $5, now()
+        "#,
+        Uuid::new_v4(),
+        username,
+        client.ip.map(|ip| ip.to_string()),
+        client.user_agent.as_deref(),
+        outcome.as_str(),
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn recent_attempts(
+    pool: &PgPool,
+    user_id: Uuid,
+    limit: i64,
+) -> Result<Vec<LoginAttempt>, sqlx::Error> {
+    sqlx::query_as!(
+        LoginAttempt,
+        r#"
+        SELECT ip_address, user_agent, outcome, attempted_at
+        FROM login_attempts
+        WHERE user_id = $1
+        ORDER BY attempted_at DESC
+        LIMIT $2
+        "#,
+        user_id,
+        limit,
+    )
+    .fetch_all(pool)
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::block_duration;
+    use crate::configuration::LoginThrottlingSettings;
+
+    fn settings() -> LoginThrottlingSettings {
+        LoginThrottlingSettings {
+            max_failures: 5,
+            lockout_seconds: 900,
+            base_delay_milliseconds: 500,
+            max_delay_seconds: 3,
+            failure_window_seconds: 900,
+        }
+    }
+
+    #[test]
+    fn the_delay_doubles_after_each_failure() {
+        let settings = settings();
+        assert_eq!(block_duration(&settings, 1), Duration::from_millis(500));
+        assert_eq!(block_duration(&settings, 2), Duration::from_millis(1000));
+        assert_eq!(block_duration(&settings, 3), Duration::from_millis(2000));
+    }
+
+    #[test]
+    fn the_delay_is_capped() {
+        let settings = settings();
+        assert_eq!(block_duration(&settings, 4), Duration::from_secs(3));
+    }
+
+    #[test]
+    fn reaching_max_failures_locks_out() {
+        let settings = settings();
+        assert_eq!(block_duration(&settings, 5), Duration::from_secs(900));
+        assert_eq!(block_duration(&settings, 50), Duration::from_secs(900));
+    }
+}
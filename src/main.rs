@@ -1,14 +1,45 @@
-use email_service::{configuration::get_configuration, startup::Application, telemetry};
+use email_service::{
+    cli::{self, Command},
+    configuration::get_configuration,
+    startup::{get_connection_pool, Application},
+    telemetry,
+};
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    telemetry::init_subscriber(std::io::stdout);
+    let command = match cli::parse_args(std::env::args().skip(1)) {
+        Ok(command) => command,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, cli::USAGE);
+            std::process::exit(2);
+        }
+    };
 
-    let config = get_configuration().expect("failed to read configuration");
+    match command {
+        Command::Serve => {
+            telemetry::init_subscriber(std::io::stdout);
 
-    let app = Application::build(config).await?;
+            let config = get_configuration().expect("failed to read configuration");
 
-    app.run().await?;
+            let app = Application::build(config).await?;
+
+            app.run().await?;
+        }
+
+        Command::ExportSubscribers {
+            filter,
+            format,
+            output,
+        } => {
+            // Standard output may be the export itself
+            telemetry::init_subscriber(std::io::stderr);
+
+            let config = get_configuration().expect("failed to read configuration");
+            let pool = get_connection_pool(&config.database)?;
+
+            cli::run_export(pool, filter, format, output).await?;
+        }
+    }
 
     Ok(())
 }
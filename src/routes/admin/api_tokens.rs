@@ -0,0 +1,226 @@
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    extract::{Path, State},
+    response::{Html, IntoResponse, Redirect, Response},
+};
+use axum_extra::extract::{cookie::Cookie, CookieJar, Form};
+use serde::Deserialize;
+use sqlx::PgPool;
+use time::Duration;
+use tracing::error;
+use uuid::Uuid;
+
+use crate::{
+    api_tokens::{create_token, list_tokens, revoke_token, ApiScope},
+    audit::{AuditAction, AuditContext},
+    session_state::{AuthorizedUser, Viewer},
+};
+
+pub async fn api_tokens_page(
+    cookies: CookieJar,
+    user: AuthorizedUser<Viewer>,
+    State(pool): State<Arc<PgPool>>,
+) -> Response<Body> {
+    let Ok(tokens) = list_tokens(&pool, user.user_id).await else {
+        return Redirect::to("/admin/dashboard").into_response();
+    };
+
+    let flash_html = match cookies.get("_flash") {
+        None => "".into(),
+        Some(cookie) => {
+            format!(
+                "<p><i>{}</i></p>",
+                htmlescape::encode_minimal(cookie.value())
+            )
+        }
+    };
+
+    let rows_html: String = tokens
+        .into_iter()
+        .map(|token| {
+            let last_used = token
+                .last_used_at
+                .map(|at| at.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+                .unwrap_or_else(|| "never".into());
+
+            let status = match token.revoked_at {
+                Some(_) => "revoked".to_string(),
+                None => format!(
+                    r#"
+                    <form action="/admin/api-tokens/{}/revoke" method="post">
+                        <button type="submit">revoke</button>
+                    </form>
+                    "#,
+                    token.token_id
+                ),
+            };
+
+            format!(
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                htmlescape::encode_minimal(&token.name),
+                token.scopes.join(", "),
+                token.created_at.format("%Y-%m-%d %H:%M:%S UTC"),
+                last_used,
+                status
+            )
+        })
+        .collect();
+
+    let scopes_html: String = ApiScope::ALL
+        .iter()
+        .map(|scope| {
+            format!(
+                r#"<label><input type="checkbox" name="scopes" value="{}"> {}</label><br>"#,
+                scope.as_str(),
+                scope.description()
+            )
+        })
+        .collect();
+
+    let html = Html::from(format!(
+        r#"
+        <!DOCTYPE html>
+        <html lang="en">
+        <head>
+            <meta http-equiv="content-type" content="text/html; charset=utf-8">
+            <title>API tokens</title>
+        </head>
+        <body>
+            {flash_html}
+            <p>API tokens let programs act on your behalf by sending an
+            <code>Authorization: Bearer &lt;token&gt;</code> header.</p>
+            <table>
+                <tr><th>Name</th><th>Scopes</th><th>Created</th><th>Last used</th><th>Status</th></tr>
+                {rows_html}
+            </table>
+
+            <h2>Create a token</h2>
+            <form action="/admin/api-tokens" method="post">
+                <label>Name
+                <input type="text" placeholder="What the token is for" name="name">
+                </label>
+                <br>
+
+                {scopes_html}
+
+                <button type="submit">Create token</button>
+            </form>
+            <p><a href="/admin/dashboard">&lt;- Back</a></p>
+        </body>
+        </html>
+        "#,
+    ));
+
+    let cookie = Cookie::build(("_flash", ""))
+        .path("/")
+        .max_age(Duration::ZERO);
+    (CookieJar::new().add(cookie), html).into_response()
+}
+
+#[derive(Deserialize)]
+pub struct NewTokenData {
+    name: String,
+    #[serde(default)]
+    scopes: Vec<String>,
+}
+
+pub async fn create_api_token(
+    user: AuthorizedUser<Viewer>,
+    audit: AuditContext,
+    State(pool): State<Arc<PgPool>>,
+    Form(form): Form<NewTokenData>,
+) -> Response<Body> {
+    let name = form.name.trim();
+    if name.is_empty() {
+        return flash_redirect("The token needs a name.");
+    }
+
+    let Ok(scopes) = form
+        .scopes
+        .iter()
+        .map(|scope| ApiScope::try_from(scope.as_str()))
+        .collect::<Result<Vec<_>, _>>()
+    else {
+        return flash_redirect("The scope is not valid.");
+    };
+
+    if scopes.is_empty() {
+        return flash_redirect("The token needs at least one scope.");
+    }
+
+    let token = match create_token(&pool, user.user_id, name, &scopes).await {
+        Ok(token) => token,
+        Err(e) => {
+            error!("failed to create API token: {:?}", e);
+            return flash_redirect("Failed to create the token.");
+        }
+    };
+
+    audit
+        .record(
+            &pool,
+            Some(user.user_id),
+            AuditAction::ApiTokenCreate,
+            Some(name),
+        )
+        .await;
+
+    // Only the hash is stored, so this is the one chance to see the token
+    Html::from(format!(
+        r#"
+        <!DOCTYPE html>
+        <html lang="en">
+        <head>
+            <meta http-equiv="content-type" content="text/html; charset=utf-8">
+            <title>API tokens</title>
+        </head>
+        <body>
+            <p>Your new token is:</p>
+            <p><code>{token}</code></p>
+            <p>Copy it now, it will not be shown again.</p>
+            <p><a href="/admin/api-tokens">Back to API tokens</a></p>
+        </body>
+        </html>
+        "#,
+    ))
+    .into_response()
+}
+
+pub async fn revoke_api_token(
+    user: AuthorizedUser<Viewer>,
+    audit: AuditContext,
+    State(pool): State<Arc<PgPool>>,
+    Path(token_id): Path<Uuid>,
+) -> Response<Body> {
+    match revoke_token(&pool, user.user_id, token_id).await {
+        Ok(Some(name)) => {
+            audit
+                .record(
+                    &pool,
+                    Some(user.user_id),
+                    AuditAction::ApiTokenRevoke,
+                    Some(&name),
+                )
+                .await;
+            flash_redirect("The token has been revoked.")
+        }
+        Ok(None) => flash_redirect("This token does not exist or was already revoked."),
+        Err(e) => {
+            error!("failed to revoke API token: {:?}", e);
+            flash_redirect("Failed to revoke the token.")
+        }
+    }
+}
+
+// The revoke action lives under `/admin/api-tokens/:token_id/`, so the flash
+// cookie needs an explicit path to be sent back to the tokens page.
+fn flash_redirect(message: &str) -> Response<Body> {
+    let cookie = Cookie::build(("_flash", message.to_owned())).path("/");
+    (
+        CookieJar::new().add(cookie),
+        Redirect::to("/admin/api-tokens"),
+    )
+        .into_response()
+}
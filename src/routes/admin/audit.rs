@@ -0,0 +1,123 @@
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    extract::{Query, State},
+    http::{header, StatusCode},
+    response::{Html, IntoResponse, Response},
+};
+use serde::Deserialize;
+use sqlx::PgPool;
+
+use crate::{
+    audit::{count_events, export_events, list_events, AuditEvent},
+    session_state::{AuthorizedUser, Owner},
+};
+
+const PAGE_SIZE: i64 = 50;
+
+#[derive(Deserialize)]
+pub struct AuditPage {
+    page: Option<i64>,
+}
+
+pub async fn audit_log(
+    _user: AuthorizedUser<Owner>,
+    State(pool): State<Arc<PgPool>>,
+    Query(query): Query<AuditPage>,
+) -> Response<Body> {
+    let page = query.page.unwrap_or(1).max(1);
+
+    let Ok(total) = count_events(&pool).await else {
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    };
+    let page_count = ((total + PAGE_SIZE - 1) / PAGE_SIZE).max(1);
+    if page > page_count {
+        return (StatusCode::NOT_FOUND, "The page does not exist.").into_response();
+    }
+
+    let Ok(events) = list_events(&pool, PAGE_SIZE, (page - 1) * PAGE_SIZE).await else {
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    };
+
+    let rows_html: String = events
+        .iter()
+        .map(|event| {
+            format!(
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                event.occurred_at.format("%Y-%m-%d %H:%M:%S UTC"),
+                htmlescape::encode_minimal(&actor(event)),
+                event.action,
+                htmlescape::encode_minimal(event.target.as_deref().unwrap_or("")),
+                htmlescape::encode_minimal(event.ip_address.as_deref().unwrap_or("")),
+                htmlescape::encode_minimal(event.user_agent.as_deref().unwrap_or("")),
+                htmlescape::encode_minimal(event.request_id.as_deref().unwrap_or("")),
+            )
+        })
+        .collect();
+
+    let mut pagination = String::new();
+    if page > 1 {
+        pagination.push_str(&format!(
+            r#"<a href="/admin/audit?page={}">&lt; Newer</a> "#,
+            page - 1
+        ));
+    }
+    pagination.push_str(&format!("Page {} of {}", page, page_count));
+    if page < page_count {
+        pagination.push_str(&format!(
+            r#" <a href="/admin/audit?page={}">Older &gt;</a>"#,
+            page + 1
+        ));
+    }
+
+    Html::from(format!(
+        r#"
+        <!DOCTYPE html>
+        <html lang="en">
+        <head>
+            <meta http-equiv="content-type" content="text/html; charset=utf-8">
+            <title>Audit log</title>
+        </head>
+        <body>
+            <p><a href="/admin/audit/export">Download as CSV</a></p>
+            <table>
+                <tr>
+                    <th>Time</th><th>Actor</th><th>Action</th><th>Target</th>
+                    <th>IP address</th><th>User agent</th><th>Request id</th>
+                </tr>
+                {rows_html}
+            </table>
+            <p>{pagination}</p>
+            <p><a href="/admin/dashboard">&lt;- Back</a></p>
+        </body>
+        </html>
+        "#,
+    ))
+    .into_response()
+}
+
+pub async fn export_audit_log(
+    _user: AuthorizedUser<Owner>,
+    State(pool): State<Arc<PgPool>>,
+) -> Response<Body> {
+    (
+        [
+            (header::CONTENT_TYPE, "text/csv; charset=utf-8"),
+            (
+                header::CONTENT_DISPOSITION,
+                r#"attachment; filename="audit-log.csv""#,
+            ),
+        ],
+        Body::from_stream(export_events(pool.as_ref().clone())),
+    )
+        .into_response()
+}
+
+fn actor(event: &AuditEvent) -> String {
+    match (&event.actor_username, event.actor_id) {
+        (Some(username), _) => username.clone(),
+        (None, Some(id)) => id.to_string(),
+        (None, None) => "anonymous".into(),
+    }
+}
@@ -3,28 +3,46 @@ use std::sync::Arc;
 use axum::{
     body::Body,
     extract::State,
-    http::StatusCode,
-    response::{Html, IntoResponse, Redirect, Response},
+    response::{Html, IntoResponse, Response},
 };
 use sqlx::PgPool;
 
-use crate::session_state::TypedSession;
+use crate::{
+    authentication::Role,
+    session_state::{AuthorizedUser, Viewer},
+};
 
 use super::get_username;
 
 pub async fn admin_dashboard(
     State(pool): State<Arc<PgPool>>,
-    session: TypedSession,
+    user: AuthorizedUser<Viewer>,
 ) -> Response<Body> {
-    let Ok(user_id) = session.get_user_id().await else {
-        return StatusCode::UNAUTHORIZED.into_response();
-    };
+    let username = get_username(user.user_id, &pool).await.unwrap();
+    let role = user.role.as_str();
 
-    let username = if let Some(user_id) = user_id {
-        get_username(user_id, &pool).await.unwrap()
-    } else {
-        return Redirect::to("/login").into_response();
-    };
+    let mut actions = String::from(
+        r#"<li><a href="/admin/subscribers">Subscribers</a></li>
+        <li><a href="/admin/password">Change password</a></li>
+        <li><a href="/admin/two-factor">Two-factor authentication</a></li>
+        <li><a href="/admin/reports/signups">Signup sources</a></li>
+        <li><a href="/admin/login-activity">Login activity</a></li>
+        <li><a href="/admin/api-tokens">API tokens</a></li>"#,
+    );
+    if user.role >= Role::Editor {
+        actions.push_str(r#"<li><a href="/admin/newsletters">Send a newsletter issue</a></li>"#);
+        actions.push_str(r#"<li><a href="/admin/domain-rules">Signup domain rules</a></li>"#);
+        actions.push_str(
+            r#"<li><a href="/admin/subscriber-attributes">Subscriber attributes</a></li>"#,
+        );
+        actions.push_str(r#"<li><a href="/admin/tags">Subscriber tags</a></li>"#);
+        actions.push_str(r#"<li><a href="/admin/welcome-sequence">Welcome sequence</a></li>"#);
+        actions.push_str(r#"<li><a href="/admin/suppressions">Suppression list</a></li>"#);
+    }
+    if user.role >= Role::Owner {
+        actions.push_str(r#"<li><a href="/admin/users">Manage admin users</a></li>"#);
+        actions.push_str(r#"<li><a href="/admin/audit">Audit log</a></li>"#);
+    }
 
     Html::from(format!(
         r#"
@@ -36,11 +54,11 @@ pub async fn admin_dashboard(
         </head>
         <body>
             <p>Welcome {username}!</p>
+            <p>You are signed in as {role}.</p>
             <p>Available actions:</p>
 
             <ol>
-                <li><a href="/admin/password">Change password</a></li>
-                <li><a href="/admin/newsletters">Send a newsletter issue</a></li>
+                {actions}
                 <li>
                     <form name="logoutForm" action="/logout" method="post">
                         <input type="submit" value="Logout">
@@ -0,0 +1,208 @@
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    extract::{Path, State},
+    response::{Html, IntoResponse, Redirect, Response},
+};
+use axum_extra::extract::{cookie::Cookie, CookieJar, Form};
+use serde::Deserialize;
+use sqlx::PgPool;
+use time::Duration;
+use tracing::error;
+use uuid::Uuid;
+
+use crate::{
+    audit::{AuditAction, AuditContext},
+    domain_policy::{
+        add_rule, blocked_signups, list_rules, normalize_pattern, remove_rule, RuleKind,
+    },
+    session_state::{AuthorizedUser, Editor},
+};
+
+pub async fn domain_rules_page(
+    cookies: CookieJar,
+    _user: AuthorizedUser<Editor>,
+    State(pool): State<Arc<PgPool>>,
+) -> Response<Body> {
+    let (rules, blocked) = match tokio::try_join!(list_rules(&pool), blocked_signups(&pool)) {
+        Ok(lists) => lists,
+        Err(e) => {
+            error!("failed to load the domain rules: {:?}", e);
+            return Redirect::to("/admin/dashboard").into_response();
+        }
+    };
+
+    let flash_html = match cookies.get("_flash") {
+        None => "".into(),
+        Some(cookie) => {
+            format!(
+                "<p><i>{}</i></p>",
+                htmlescape::encode_minimal(cookie.value())
+            )
+        }
+    };
+
+    let rules_html: String = rules
+        .into_iter()
+        .map(|rule| {
+            format!(
+                r#"
+                <tr><td>{}</td><td>{}</td><td>{}</td><td>
+                    <form action="/admin/domain-rules/{}/delete" method="post">
+                        <button type="submit">remove</button>
+                    </form>
+                </td></tr>
+                "#,
+                htmlescape::encode_minimal(&rule.pattern),
+                rule.kind,
+                rule.created_at.format("%Y-%m-%d %H:%M:%S UTC"),
+                rule.rule_id
+            )
+        })
+        .collect();
+
+    let total_blocked: i64 = blocked.iter().map(|row| row.attempts).sum();
+    let blocked_html: String = blocked
+        .into_iter()
+        .map(|row| {
+            format!(
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                htmlescape::encode_minimal(&row.domain),
+                row.reason,
+                row.attempts,
+                row.last_attempt_at.format("%Y-%m-%d %H:%M:%S UTC")
+            )
+        })
+        .collect();
+
+    let html = Html::from(format!(
+        r#"
+        <!DOCTYPE html>
+        <html lang="en">
+        <head>
+            <meta http-equiv="content-type" content="text/html; charset=utf-8">
+            <title>Signup domain rules</title>
+        </head>
+        <body>
+            {flash_html}
+            <p>Signups from well-known disposable email providers are refused.
+            Block rules refuse more domains, allow rules let domains through
+            whatever the other rules say.</p>
+            <p><code>example.com</code> only matches that domain,
+            <code>*.example.com</code> matches its subdomains.</p>
+            <table>
+                <tr><th>Pattern</th><th>Rule</th><th>Added</th><th></th></tr>
+                {rules_html}
+            </table>
+
+            <h2>Add a rule</h2>
+            <form action="/admin/domain-rules" method="post">
+                <label>Domain
+                <input type="text" placeholder="*.example.com" name="pattern">
+                </label>
+                <br>
+                <label><input type="radio" name="kind" value="{block}" checked> Block</label>
+                <label><input type="radio" name="kind" value="{allow}"> Allow</label>
+                <br>
+                <button type="submit">Add rule</button>
+            </form>
+
+            <h2>Blocked signups</h2>
+            <p>{total_blocked} attempts were blocked.</p>
+            <table>
+                <tr><th>Domain</th><th>Reason</th><th>Attempts</th><th>Last attempt</th></tr>
+                {blocked_html}
+            </table>
+            <p><a href="/admin/dashboard">&lt;- Back</a></p>
+        </body>
+        </html>
+        "#,
+        block = RuleKind::Block.as_str(),
+        allow = RuleKind::Allow.as_str(),
+    ));
+
+    let cookie = Cookie::build(("_flash", ""))
+        .path("/")
+        .max_age(Duration::ZERO);
+    (CookieJar::new().add(cookie), html).into_response()
+}
+
+#[derive(Deserialize)]
+pub struct NewDomainRuleData {
+    pattern: String,
+    kind: String,
+}
+
+pub async fn add_domain_rule(
+    user: AuthorizedUser<Editor>,
+    audit: AuditContext,
+    State(pool): State<Arc<PgPool>>,
+    Form(form): Form<NewDomainRuleData>,
+) -> Response<Body> {
+    let Ok(kind) = RuleKind::try_from(form.kind.as_str()) else {
+        return flash_redirect("The rule kind is not valid.");
+    };
+
+    let pattern = match normalize_pattern(&form.pattern) {
+        Ok(pattern) => pattern,
+        Err(e) => return flash_redirect(&e),
+    };
+
+    match add_rule(&pool, &pattern, kind).await {
+        Ok(()) => {}
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+            return flash_redirect("There already is a rule for this domain.");
+        }
+        Err(e) => {
+            error!("failed to add domain rule: {:?}", e);
+            return flash_redirect("Failed to add the rule.");
+        }
+    }
+
+    audit
+        .record(
+            &pool,
+            Some(user.user_id),
+            AuditAction::DomainRuleAdd,
+            Some(&format!("{} {}", kind.as_str(), pattern)),
+        )
+        .await;
+
+    flash_redirect("The rule has been added.")
+}
+
+pub async fn remove_domain_rule(
+    user: AuthorizedUser<Editor>,
+    audit: AuditContext,
+    State(pool): State<Arc<PgPool>>,
+    Path(rule_id): Path<Uuid>,
+) -> Response<Body> {
+    match remove_rule(&pool, rule_id).await {
+        Ok(Some(rule)) => {
+            audit
+                .record(
+                    &pool,
+                    Some(user.user_id),
+                    AuditAction::DomainRuleRemove,
+                    Some(&rule),
+                )
+                .await;
+            flash_redirect("The rule has been removed.")
+        }
+        Ok(None) => flash_redirect("This rule does not exist."),
+        Err(e) => {
+            error!("failed to remove domain rule: {:?}", e);
+            flash_redirect("Failed to remove the rule.")
+        }
+    }
+}
+
+fn flash_redirect(message: &str) -> Response<Body> {
+    let cookie = Cookie::build(("_flash", message.to_owned())).path("/");
+    (
+        CookieJar::new().add(cookie),
+        Redirect::to("/admin/domain-rules"),
+    )
+        .into_response()
+}
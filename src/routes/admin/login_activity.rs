@@ -0,0 +1,58 @@
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    extract::State,
+    response::{Html, IntoResponse, Redirect, Response},
+};
+use sqlx::PgPool;
+
+use crate::{
+    login_attempts::recent_attempts,
+    session_state::{AuthorizedUser, Viewer},
+};
+
+const SHOWN_ATTEMPTS: i64 = 50;
+
+pub async fn login_activity(
+    user: AuthorizedUser<Viewer>,
+    State(pool): State<Arc<PgPool>>,
+) -> Response<Body> {
+    let Ok(attempts) = recent_attempts(&pool, user.user_id, SHOWN_ATTEMPTS).await else {
+        return Redirect::to("/admin/dashboard").into_response();
+    };
+
+    let rows_html: String = attempts
+        .into_iter()
+        .map(|attempt| {
+            format!(
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                attempt.attempted_at.format("%Y-%m-%d %H:%M:%S UTC"),
+                attempt.outcome,
+                htmlescape::encode_minimal(attempt.ip_address.as_deref().unwrap_or("unknown")),
+                htmlescape::encode_minimal(attempt.user_agent.as_deref().unwrap_or("unknown")),
+            )
+        })
+        .collect();
+
+    Html::from(format!(
+        r#"
+        <!DOCTYPE html>
+        <html lang="en">
+        <head>
+            <meta http-equiv="content-type" content="text/html; charset=utf-8">
+            <title>Login activity</title>
+        </head>
+        <body>
+            <p>Recent login attempts on your account:</p>
+            <table>
+                <tr><th>Time</th><th>Outcome</th><th>IP address</th><th>User agent</th></tr>
+                {rows_html}
+            </table>
+            <p><a href="/admin/dashboard">&lt;- Back</a></p>
+        </body>
+        </html>
+        "#,
+    ))
+    .into_response()
+}
@@ -1,18 +1,32 @@
+use std::sync::Arc;
+
 use axum::{
     body::Body,
+    extract::State,
     response::{IntoResponse, Redirect, Response},
 };
 use axum_extra::extract::cookie::{Cookie, CookieJar};
+use sqlx::PgPool;
 
-use crate::session_state::TypedSession;
+use crate::{
+    audit::{AuditAction, AuditContext},
+    session_state::TypedSession,
+};
 
-pub async fn log_out(session: TypedSession) -> Response<Body> {
-    if session.get_user_id().await.unwrap().is_none() {
-        Redirect::to("/login").into_response()
-    } else {
+pub async fn log_out(
+    session: TypedSession,
+    audit: AuditContext,
+    State(pool): State<Arc<PgPool>>,
+) -> Response<Body> {
+    if let Some(user_id) = session.get_user_id().await.unwrap() {
         session.log_out().await.unwrap();
+        audit
+            .record(&pool, Some(user_id), AuditAction::Logout, None)
+            .await;
 
         let cookie = Cookie::new("_flash", "You have successfully logged out.");
         (CookieJar::new().add(cookie), Redirect::to("/login")).into_response()
+    } else {
+        Redirect::to("/login").into_response()
     }
 }
@@ -1,12 +1,40 @@
+mod api_tokens;
+mod audit;
 mod dashboard;
+mod domain_rules;
+mod login_activity;
 mod logout;
 mod newsletter;
 mod password;
+mod signup_sources;
+mod subscriber_attributes;
+mod subscriber_export;
+mod subscriber_import;
+mod subscribers;
+mod suppressions;
+mod tags;
+mod two_factor;
+mod users;
+mod welcome_sequence;
 
+pub use api_tokens::*;
+pub use audit::*;
 pub use dashboard::*;
+pub use domain_rules::*;
+pub use login_activity::*;
 pub use logout::*;
 pub use newsletter::*;
 pub use password::*;
+pub use signup_sources::*;
+pub use subscriber_attributes::*;
+pub use subscriber_export::*;
+pub use subscriber_import::*;
+pub use subscribers::*;
+pub use suppressions::*;
+pub use tags::*;
+pub use two_factor::*;
+pub use users::*;
+pub use welcome_sequence::*;
 
 use sqlx::PgPool;
 use uuid::Uuid;
@@ -12,16 +12,16 @@ use sqlx::PgPool;
 use time::Duration;
 
 use crate::{
+    audit::{AuditAction, AuditContext},
     authentication::{self, validate_credentials, Credentials},
     routes::admin::get_username,
-    session_state::TypedSession,
+    session_state::{AuthorizedUser, Viewer},
 };
 
-pub async fn change_password_form(cookies: CookieJar, session: TypedSession) -> Response<Body> {
-    if session.get_user_id().await.unwrap().is_none() {
-        return Redirect::to("/login").into_response();
-    }
-
+pub async fn change_password_form(
+    cookies: CookieJar,
+    _user: AuthorizedUser<Viewer>,
+) -> Response<Body> {
     let error_html = match cookies.get("_flash") {
         None => "".into(),
         Some(cookie) => {
@@ -70,6 +70,23 @@ pub async fn change_password_form(cookies: CookieJar, session: TypedSession) ->
     (CookieJar::new().add(cookie), html).into_response()
 }
 
+/// Rules every new password must follow, whether it is changed from the admin
+/// area or picked when accepting an invitation.
+pub fn validate_new_password(
+    new_password: &Secret<String>,
+    new_password_check: &Secret<String>,
+) -> Result<(), &'static str> {
+    if new_password.expose_secret() != new_password_check.expose_secret() {
+        return Err("You entered two different new passwords - the field values must match.");
+    }
+
+    if new_password.expose_secret().len() < 10 {
+        return Err("The new password is too short.");
+    }
+
+    Ok(())
+}
+
 #[derive(serde::Deserialize)]
 pub struct ChangePassword {
     current_password: Secret<String>,
@@ -79,30 +96,16 @@ pub struct ChangePassword {
 
 pub async fn change_password(
     State(pool): State<Arc<PgPool>>,
-    session: TypedSession,
+    user: AuthorizedUser<Viewer>,
+    audit: AuditContext,
     Form(form): Form<ChangePassword>,
 ) -> Response<Body> {
-    let Some(user_id) = session.get_user_id().await.unwrap() else {
-        return Redirect::to("/login").into_response();
-    };
+    let user_id = user.user_id;
 
     let username = get_username(user_id, &pool).await.unwrap();
 
-    if form.new_password.expose_secret() != form.new_password_check.expose_secret() {
-        let cookie = Cookie::new(
-            "_flash",
-            "You entered two different new passwords - the field values must match.",
-        );
-
-        return (
-            CookieJar::new().add(cookie),
-            Redirect::to("/admin/password"),
-        )
-            .into_response();
-    }
-
-    if form.new_password.expose_secret().len() < 10 {
-        let cookie = Cookie::new("_flash", "The new password is too short.");
+    if let Err(message) = validate_new_password(&form.new_password, &form.new_password_check) {
+        let cookie = Cookie::new("_flash", message);
 
         return (
             CookieJar::new().add(cookie),
@@ -126,13 +129,17 @@ pub async fn change_password(
             .into_response();
     }
 
-    if authentication::change_password(user_id, form.new_password, &pool)
+    if authentication::change_password(user_id, form.new_password, pool.as_ref())
         .await
         .is_none()
     {
         return Redirect::to("/admin/password").into_response();
     }
 
+    audit
+        .record(&pool, Some(user_id), AuditAction::PasswordChange, None)
+        .await;
+
     let cookie = Cookie::new("_flash", "Your password has been changed.");
     (
         CookieJar::new().add(cookie),
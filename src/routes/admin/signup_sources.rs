@@ -0,0 +1,140 @@
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    extract::{Query, State},
+    http::StatusCode,
+    response::{Html, IntoResponse, Response},
+};
+use chrono::{Days, NaiveDate, Utc};
+use serde::Deserialize;
+use sqlx::PgPool;
+use tracing::error;
+
+use crate::{
+    attribution::{signups_by_source, ReportGrouping},
+    session_state::{AuthorizedUser, Viewer},
+    subscriber_export::parse_date,
+};
+
+/// How far back the report goes when no range is given.
+const DEFAULT_RANGE_DAYS: u64 = 30;
+
+#[derive(Deserialize)]
+pub struct SignupSourcesQuery {
+    group: Option<String>,
+    from: Option<String>,
+    until: Option<String>,
+}
+
+impl SignupSourcesQuery {
+    /// Empty fields, as sent by the form, take their default. Dates are
+    /// inclusive, in UTC.
+    fn parse(&self) -> Result<(ReportGrouping, NaiveDate, NaiveDate), String> {
+        let field = |value: &Option<String>| value.clone().filter(|v| !v.is_empty());
+
+        let grouping = match field(&self.group) {
+            Some(group) => ReportGrouping::try_from(group.as_str())?,
+            None => ReportGrouping::Source,
+        };
+        let until = match field(&self.until) {
+            Some(until) => parse_date(&until)?,
+            None => Utc::now().date_naive(),
+        };
+        let from = match field(&self.from) {
+            Some(from) => parse_date(&from)?,
+            None => until - Days::new(DEFAULT_RANGE_DAYS - 1),
+        };
+
+        if from > until {
+            return Err("The start of the range is after its end.".into());
+        }
+        Ok((grouping, from, until))
+    }
+}
+
+pub async fn signup_sources_report(
+    _user: AuthorizedUser<Viewer>,
+    State(pool): State<Arc<PgPool>>,
+    Query(query): Query<SignupSourcesQuery>,
+) -> Response<Body> {
+    let (grouping, from, until) = match query.parse() {
+        Ok(parsed) => parsed,
+        Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+    };
+
+    let start = from.and_hms_opt(0, 0, 0).unwrap().and_utc();
+    let end = (until + Days::new(1))
+        .and_hms_opt(0, 0, 0)
+        .unwrap()
+        .and_utc();
+
+    let rows = match signups_by_source(&pool, grouping, start, end).await {
+        Ok(rows) => rows,
+        Err(e) => {
+            error!("failed to build the signup sources report: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    let (signups, confirmed) = rows.iter().fold((0, 0), |(signups, confirmed), row| {
+        (signups + row.signups, confirmed + row.confirmed)
+    });
+
+    let rows_html: String = rows
+        .iter()
+        .map(|row| {
+            format!(
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{:.1}%</td></tr>",
+                match &row.key {
+                    Some(key) => htmlescape::encode_minimal(key),
+                    None => "<i>unknown</i>".into(),
+                },
+                row.signups,
+                row.confirmed,
+                row.confirmation_rate(),
+            )
+        })
+        .collect();
+
+    let group_options: String = ReportGrouping::ALL
+        .iter()
+        .map(|option| {
+            let selected = if *option == grouping { " selected" } else { "" };
+            format!(
+                r#"<option value="{0}"{1}>{0}</option>"#,
+                option.as_str(),
+                selected
+            )
+        })
+        .collect();
+
+    Html::from(format!(
+        r#"
+        <!DOCTYPE html>
+        <html lang="en">
+        <head>
+            <meta http-equiv="content-type" content="text/html; charset=utf-8">
+            <title>Signup sources</title>
+        </head>
+        <body>
+            <form action="/admin/reports/signups" method="get">
+                <label>Group by <select name="group">{group_options}</select></label>
+                <label>from <input type="date" name="from" value="{from}"></label>
+                <label>until <input type="date" name="until" value="{until}"></label>
+                <button type="submit">Show</button>
+            </form>
+            <p>{signups} signups from {from} to {until}, {confirmed} confirmed.
+            Signups count as confirmed even if they unsubscribed since.</p>
+            <table>
+                <tr><th>{group}</th><th>Signups</th><th>Confirmed</th><th>Confirmation rate</th></tr>
+                {rows_html}
+            </table>
+            <p><a href="/admin/dashboard">&lt;- Back</a></p>
+        </body>
+        </html>
+        "#,
+        group = grouping.as_str(),
+    ))
+    .into_response()
+}
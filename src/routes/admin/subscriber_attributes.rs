@@ -0,0 +1,207 @@
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    extract::{Path, State},
+    response::{Html, IntoResponse, Redirect, Response},
+};
+use axum_extra::extract::{cookie::Cookie, CookieJar, Form};
+use serde::Deserialize;
+use sqlx::PgPool;
+use time::Duration;
+use tracing::error;
+
+use crate::{
+    audit::{AuditAction, AuditContext},
+    session_state::{AuthorizedUser, Editor},
+    subscriber_attributes::{
+        add_attribute, list_attributes, normalize_name, remove_attribute, AttributeKind,
+    },
+};
+
+pub async fn subscriber_attributes_page(
+    cookies: CookieJar,
+    _user: AuthorizedUser<Editor>,
+    State(pool): State<Arc<PgPool>>,
+) -> Response<Body> {
+    let definitions = match list_attributes(&pool).await {
+        Ok(definitions) => definitions,
+        Err(e) => {
+            error!("failed to load the subscriber attributes: {:?}", e);
+            return Redirect::to("/admin/dashboard").into_response();
+        }
+    };
+
+    let flash_html = match cookies.get("_flash") {
+        None => "".into(),
+        Some(cookie) => {
+            format!(
+                "<p><i>{}</i></p>",
+                htmlescape::encode_minimal(cookie.value())
+            )
+        }
+    };
+
+    let definitions_html: String = definitions
+        .into_iter()
+        .map(|definition| {
+            format!(
+                r#"
+                <tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>
+                    <form action="/admin/subscriber-attributes/{}/delete" method="post">
+                        <button type="submit">remove</button>
+                    </form>
+                </td></tr>
+                "#,
+                definition.name,
+                htmlescape::encode_minimal(&definition.label),
+                definition.kind,
+                if definition.required { "yes" } else { "no" },
+                definition.name
+            )
+        })
+        .collect();
+
+    let kinds_html: String = AttributeKind::ALL
+        .iter()
+        .map(|kind| format!(r#"<option value="{0}">{0}</option>"#, kind.as_str()))
+        .collect();
+
+    let html = Html::from(format!(
+        r#"
+        <!DOCTYPE html>
+        <html lang="en">
+        <head>
+            <meta http-equiv="content-type" content="text/html; charset=utf-8">
+            <title>Subscriber attributes</title>
+        </head>
+        <body>
+            {flash_html}
+            <p>Extra fields collected at signup. Forms send them as
+            <code>attributes[name]</code>, JSON clients in an
+            <code>attributes</code> object. Signups with attributes that are
+            not listed here are refused.</p>
+            <table>
+                <tr><th>Name</th><th>Label</th><th>Type</th><th>Required</th><th></th></tr>
+                {definitions_html}
+            </table>
+            <p>Removing an attribute keeps the values already collected.</p>
+
+            <h2>Add an attribute</h2>
+            <form action="/admin/subscriber-attributes" method="post">
+                <label>Name
+                <input type="text" placeholder="company" name="name">
+                </label>
+                <br>
+                <label>Label
+                <input type="text" placeholder="Company" name="label">
+                </label>
+                <br>
+                <label>Type
+                <select name="kind">{kinds_html}</select>
+                </label>
+                <br>
+                <label><input type="checkbox" name="required" value="true"> Required</label>
+                <br>
+                <button type="submit">Add attribute</button>
+            </form>
+            <p><a href="/admin/dashboard">&lt;- Back</a></p>
+        </body>
+        </html>
+        "#,
+    ));
+
+    let cookie = Cookie::build(("_flash", ""))
+        .path("/")
+        .max_age(Duration::ZERO);
+    (CookieJar::new().add(cookie), html).into_response()
+}
+
+#[derive(Deserialize)]
+pub struct NewSubscriberAttributeData {
+    name: String,
+    label: String,
+    kind: String,
+    #[serde(default)]
+    required: bool,
+}
+
+pub async fn add_subscriber_attribute(
+    user: AuthorizedUser<Editor>,
+    audit: AuditContext,
+    State(pool): State<Arc<PgPool>>,
+    Form(form): Form<NewSubscriberAttributeData>,
+) -> Response<Body> {
+    let Ok(kind) = AttributeKind::try_from(form.kind.as_str()) else {
+        return flash_redirect("The attribute type is not valid.");
+    };
+
+    let name = match normalize_name(&form.name) {
+        Ok(name) => name,
+        Err(e) => return flash_redirect(&e),
+    };
+
+    let label = form.label.trim();
+    let label = if label.is_empty() {
+        name.as_str()
+    } else {
+        label
+    };
+
+    match add_attribute(&pool, &name, label, kind, form.required).await {
+        Ok(()) => {}
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+            return flash_redirect("There already is an attribute with this name.");
+        }
+        Err(e) => {
+            error!("failed to add subscriber attribute: {:?}", e);
+            return flash_redirect("Failed to add the attribute.");
+        }
+    }
+
+    audit
+        .record(
+            &pool,
+            Some(user.user_id),
+            AuditAction::SubscriberAttributeAdd,
+            Some(&format!("{} {}", name, kind.as_str())),
+        )
+        .await;
+
+    flash_redirect("The attribute has been added.")
+}
+
+pub async fn remove_subscriber_attribute(
+    user: AuthorizedUser<Editor>,
+    audit: AuditContext,
+    State(pool): State<Arc<PgPool>>,
+    Path(name): Path<String>,
+) -> Response<Body> {
+    match remove_attribute(&pool, &name).await {
+        Ok(true) => {
+            audit
+                .record(
+                    &pool,
+                    Some(user.user_id),
+                    AuditAction::SubscriberAttributeRemove,
+                    Some(&name),
+                )
+                .await;
+            flash_redirect("The attribute has been removed.")
+        }
+        Ok(false) => flash_redirect("This attribute does not exist."),
+        Err(e) => {
+            error!("failed to remove subscriber attribute: {:?}", e);
+            flash_redirect("Failed to remove the attribute.")
+        }
+    }
+}
+
+fn flash_redirect(message: &str) -> Response<Body> {
+    let cookie = Cookie::build(("_flash", message.to_owned())).path("/");
+    (
+        CookieJar::new().add(cookie),
+        Redirect::to("/admin/subscriber-attributes"),
+    )
+        .into_response()
+}
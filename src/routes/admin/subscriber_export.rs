@@ -0,0 +1,81 @@
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    extract::{Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+};
+use serde::Deserialize;
+use sqlx::PgPool;
+
+use crate::{
+    audit::{AuditAction, AuditContext},
+    domain::SubscriptionStatus,
+    session_state::{AuthorizedUser, Editor},
+    subscriber_export::{export_subscribers, parse_date, ExportFilter, ExportFormat},
+};
+
+#[derive(Deserialize)]
+pub struct ExportQuery {
+    format: Option<String>,
+    status: Option<String>,
+    from: Option<String>,
+    until: Option<String>,
+}
+
+impl ExportQuery {
+    /// Empty fields, as sent by the export form, mean no filter.
+    fn parse(&self) -> Result<(ExportFormat, ExportFilter), String> {
+        let field = |value: &Option<String>| value.clone().filter(|v| !v.is_empty());
+
+        let format = match field(&self.format) {
+            Some(format) => ExportFormat::try_from(format.as_str())?,
+            None => ExportFormat::Csv,
+        };
+
+        let filter = ExportFilter {
+            status: field(&self.status)
+                .map(|s| SubscriptionStatus::try_from(s.as_str()))
+                .transpose()?,
+            subscribed_from: field(&self.from).map(|d| parse_date(&d)).transpose()?,
+            subscribed_until: field(&self.until).map(|d| parse_date(&d)).transpose()?,
+        };
+
+        Ok((format, filter))
+    }
+}
+
+pub async fn export_subscribers_file(
+    user: AuthorizedUser<Editor>,
+    audit: AuditContext,
+    State(pool): State<Arc<PgPool>>,
+    Query(query): Query<ExportQuery>,
+) -> Response<Body> {
+    let (format, filter) = match query.parse() {
+        Ok(parsed) => parsed,
+        Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+    };
+
+    let target = format!("{} {}", format.as_str(), filter.describe());
+    audit
+        .record(
+            &pool,
+            Some(user.user_id),
+            AuditAction::SubscriberExport,
+            Some(target.trim_end()),
+        )
+        .await;
+
+    let disposition = format!(r#"attachment; filename="subscribers.{}""#, format.as_str());
+    let rows = export_subscribers(pool.as_ref().clone(), filter, format);
+
+    (
+        [
+            (header::CONTENT_TYPE, format.content_type().to_owned()),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        Body::from_stream(rows),
+    )
+        .into_response()
+}
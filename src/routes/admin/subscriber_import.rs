@@ -0,0 +1,248 @@
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    extract::{Multipart, Path, State},
+    http::{header, StatusCode},
+    response::{Html, IntoResponse, Redirect, Response},
+};
+use axum_extra::extract::{cookie::Cookie, CookieJar};
+use sqlx::PgPool;
+use time::Duration;
+use tracing::error;
+use uuid::Uuid;
+
+use crate::{
+    audit::{AuditAction, AuditContext},
+    email_client::EmailClient,
+    routes::send_confirmation_email,
+    session_state::{AuthorizedUser, Editor},
+    subscriber_import::{get_import, import_subscribers, parse_csv, reject_erased, ImportMode},
+};
+
+/// Large enough for an audience of a few hundred thousand addresses.
+pub const IMPORT_SIZE_LIMIT: usize = 32 * 1024 * 1024;
+
+pub async fn import_subscribers_form(
+    cookies: CookieJar,
+    _user: AuthorizedUser<Editor>,
+) -> Response<Body> {
+    let flash_html = match cookies.get("_flash") {
+        None => "".into(),
+        Some(cookie) => {
+            format!(
+                "<p><i>{}</i></p>",
+                htmlescape::encode_minimal(cookie.value())
+            )
+        }
+    };
+
+    let html = Html::from(format!(
+        r#"
+        <!DOCTYPE html>
+        <html lang="en">
+        <head>
+            <meta http-equiv="content-type" content="text/html; charset=utf-8">
+            <title>Import subscribers</title>
+        </head>
+        <body>
+            {flash_html}
+            <p>Upload a CSV file with a header row and <code>email</code> and
+            <code>name</code> columns. <code>status</code> and
+            <code>subscribed_at</code> columns are optional.</p>
+            <form action="/admin/subscribers/import" method="post" enctype="multipart/form-data">
+                <input type="file" name="file" accept=".csv,text/csv">
+                <br>
+                <label>
+                <input type="radio" name="mode" value="{send_confirmation}" checked>
+                Send a confirmation email to new subscribers
+                </label>
+                <br>
+                <label>
+                <input type="radio" name="mode" value="{mark_confirmed}">
+                Mark new subscribers as already confirmed
+                </label>
+                <br>
+                <button type="submit">Import</button>
+            </form>
+            <p><a href="/admin/subscribers">&lt;- Back</a></p>
+        </body>
+        </html>
+        "#,
+        send_confirmation = ImportMode::SendConfirmation.as_str(),
+        mark_confirmed = ImportMode::MarkConfirmed.as_str(),
+    ));
+
+    let cookie = Cookie::build(("_flash", ""))
+        .path("/")
+        .max_age(Duration::ZERO);
+    (CookieJar::new().add(cookie), html).into_response()
+}
+
+pub async fn import_subscribers_post(
+    user: AuthorizedUser<Editor>,
+    audit: AuditContext,
+    State(pool): State<Arc<PgPool>>,
+    State(email_client): State<Arc<EmailClient>>,
+    State(base_url): State<Arc<str>>,
+    mut multipart: Multipart,
+) -> Response<Body> {
+    let mut file = None;
+    let mut mode = ImportMode::SendConfirmation;
+
+    loop {
+        let field = match multipart.next_field().await {
+            Ok(Some(field)) => field,
+            Ok(None) => break,
+            Err(e) => return flash_redirect(&format!("The upload failed: {}", e)),
+        };
+
+        match field.name() {
+            Some("file") => {
+                let file_name = field.file_name().unwrap_or("upload.csv").to_owned();
+                match field.bytes().await {
+                    Ok(data) => file = Some((file_name, data)),
+                    Err(e) => return flash_redirect(&format!("The upload failed: {}", e)),
+                }
+            }
+            Some("mode") => {
+                let Ok(value) = field.text().await else {
+                    return flash_redirect("The import mode is not valid.");
+                };
+                let Ok(value) = ImportMode::try_from(value.as_str()) else {
+                    return flash_redirect("The import mode is not valid.");
+                };
+                mode = value;
+            }
+            _ => {}
+        }
+    }
+
+    let Some((file_name, data)) = file.filter(|(_, data)| !data.is_empty()) else {
+        return flash_redirect("Please choose a CSV file to import.");
+    };
+
+    let mut parsed = match parse_csv(&data, mode) {
+        Ok(parsed) => parsed,
+        Err(e) => return flash_redirect(&e),
+    };
+
+    if let Err(e) = reject_erased(&pool, &mut parsed).await {
+        error!("failed to check for erased subscribers: {:?}", e);
+        return flash_redirect("Failed to import the subscribers.");
+    }
+
+    let result = match import_subscribers(&pool, user.user_id, &file_name, &parsed, mode).await {
+        Ok(result) => result,
+        Err(e) => {
+            error!("failed to import subscribers: {:?}", e);
+            return flash_redirect("Failed to import the subscribers.");
+        }
+    };
+
+    audit
+        .record(
+            &pool,
+            Some(user.user_id),
+            AuditAction::SubscriberImport,
+            Some(&file_name),
+        )
+        .await;
+
+    // Large imports would otherwise keep the request open for a long time
+    let pending = result.pending_confirmation;
+    tokio::spawn(async move {
+        for subscriber in pending {
+            if let Err(e) = send_confirmation_email(
+                &email_client,
+                &subscriber.email,
+                &base_url,
+                &subscriber.token,
+            )
+            .await
+            {
+                error!(
+                    "failed to send confirmation email to imported subscriber: {:?}",
+                    e
+                );
+            }
+        }
+    });
+
+    Redirect::to(&format!("/admin/subscribers/import/{}", result.import_id)).into_response()
+}
+
+pub async fn subscriber_import_page(
+    _user: AuthorizedUser<Editor>,
+    State(pool): State<Arc<PgPool>>,
+    Path(import_id): Path<Uuid>,
+) -> Response<Body> {
+    let import = match get_import(&pool, import_id).await {
+        Ok(Some(import)) => import,
+        Ok(None) => return StatusCode::NOT_FOUND.into_response(),
+        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    };
+
+    let rejected_html = if import.rejected_count > 0 {
+        format!(
+            r#"<p>{} rows were rejected. <a href="/admin/subscribers/import/{}/rejected">Download the rejected rows</a> to see why.</p>"#,
+            import.rejected_count, import_id
+        )
+    } else {
+        "<p>No rows were rejected.</p>".into()
+    };
+
+    Html::from(format!(
+        r#"
+        <!DOCTYPE html>
+        <html lang="en">
+        <head>
+            <meta http-equiv="content-type" content="text/html; charset=utf-8">
+            <title>Import subscribers</title>
+        </head>
+        <body>
+            <p>Imported {imported} subscribers from {file_name} on {created_at}.</p>
+            {rejected_html}
+            <p><a href="/admin/subscribers">&lt;- Back to subscribers</a></p>
+        </body>
+        </html>
+        "#,
+        imported = import.imported_count,
+        file_name = htmlescape::encode_minimal(&import.file_name),
+        created_at = import.created_at.format("%Y-%m-%d %H:%M UTC"),
+    ))
+    .into_response()
+}
+
+pub async fn subscriber_import_rejected_rows(
+    _user: AuthorizedUser<Editor>,
+    State(pool): State<Arc<PgPool>>,
+    Path(import_id): Path<Uuid>,
+) -> Response<Body> {
+    let import = match get_import(&pool, import_id).await {
+        Ok(Some(import)) => import,
+        Ok(None) => return StatusCode::NOT_FOUND.into_response(),
+        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    };
+
+    (
+        [
+            (header::CONTENT_TYPE, "text/csv; charset=utf-8"),
+            (
+                header::CONTENT_DISPOSITION,
+                r#"attachment; filename="rejected-rows.csv""#,
+            ),
+        ],
+        import.rejected_report,
+    )
+        .into_response()
+}
+
+fn flash_redirect(message: &str) -> Response<Body> {
+    let cookie = Cookie::build(("_flash", message.to_owned())).path("/");
+    (
+        CookieJar::new().add(cookie),
+        Redirect::to("/admin/subscribers/import"),
+    )
+        .into_response()
+}
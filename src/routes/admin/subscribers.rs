@@ -0,0 +1,948 @@
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{Html, IntoResponse, Redirect, Response},
+};
+use axum_extra::extract::{cookie::Cookie, CookieJar, Form};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use sqlx::PgPool;
+use time::Duration;
+use tracing::error;
+use uuid::Uuid;
+
+use crate::{
+    attribution::get_attribution,
+    audit::{AuditAction, AuditContext},
+    authentication::Role,
+    consent::list_consents,
+    domain::SubscriptionStatus,
+    routes::data_download,
+    session_state::{AuthorizedUser, Editor, Viewer},
+    subscriber_attributes::display_value,
+    subscriber_data::{collect_subscriber_data, email_hash, erase_subscriber},
+    subscriber_events::{get_history, record_event, SubscriberEvent},
+    subscriber_tags::{add_tags, normalize_tag, parse_tags, remove_tags, TagAction},
+    welcome_sequence::{enroll, get_progress, stop_sequence},
+};
+
+const PAGE_SIZE: i64 = 25;
+
+struct SubscriberRow {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: String,
+    subscribed_at: DateTime<Utc>,
+    attributes: serde_json::Value,
+    tags: Vec<String>,
+}
+
+#[derive(Deserialize)]
+pub struct SubscribersQuery {
+    q: Option<String>,
+    status: Option<String>,
+    tag: Option<String>,
+    page: Option<i64>,
+}
+
+/// Which subscribers a search or a bulk action is about.
+struct SubscriberFilter<'a> {
+    search: Option<&'a str>,
+    status: Option<SubscriptionStatus>,
+    tag: Option<String>,
+}
+
+impl<'a> SubscriberFilter<'a> {
+    fn new(search: Option<&'a str>, status: Option<&str>, tag: Option<&str>) -> Self {
+        Self {
+            search: search.map(str::trim).filter(|q| !q.is_empty()),
+            status: status.and_then(|s| SubscriptionStatus::try_from(s).ok()),
+            tag: tag
+                .filter(|tag| !tag.trim().is_empty())
+                .map(|tag| normalize_tag(tag).unwrap_or_else(|_| tag.to_owned())),
+        }
+    }
+
+    fn pattern(&self) -> Option<String> {
+        self.search.map(|q| format!("%{}%", escape_like(q)))
+    }
+
+    fn status(&self) -> Option<&'static str> {
+        self.status.map(|s| s.as_str())
+    }
+
+    /// The search page listing these subscribers.
+    fn page_link(&self, page: i64) -> String {
+        format!(
+            "/admin/subscribers?q={}&status={}&tag={}&page={}",
+            urlencoding::encode(self.search.unwrap_or("")),
+            self.status().unwrap_or(""),
+            urlencoding::encode(self.tag.as_deref().unwrap_or("")),
+            page
+        )
+    }
+}
+
+pub async fn subscribers_page(
+    cookies: CookieJar,
+    user: AuthorizedUser<Viewer>,
+    State(pool): State<Arc<PgPool>>,
+    Query(query): Query<SubscribersQuery>,
+) -> Response<Body> {
+    let filter = SubscriberFilter::new(
+        query.q.as_deref(),
+        query.status.as_deref(),
+        query.tag.as_deref(),
+    );
+    let status_filter = filter.status();
+    let page = query.page.unwrap_or(1).max(1);
+
+    let Ok(total) = count_subscribers(&pool, &filter).await else {
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    };
+    let page_count = ((total + PAGE_SIZE - 1) / PAGE_SIZE).max(1);
+    if page > page_count {
+        return (StatusCode::NOT_FOUND, "The page does not exist.").into_response();
+    }
+
+    let Ok(subscribers) = search_subscribers(&pool, &filter, (page - 1) * PAGE_SIZE).await else {
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    };
+
+    let flash_html = match cookies.get("_flash") {
+        None => "".into(),
+        Some(cookie) => {
+            format!(
+                "<p><i>{}</i></p>",
+                htmlescape::encode_minimal(cookie.value())
+            )
+        }
+    };
+
+    let rows_html: String = subscribers
+        .iter()
+        .map(|subscriber| {
+            format!(
+                r#"<tr><td><a href="/admin/subscribers/{}">{}</a></td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>"#,
+                subscriber.id,
+                htmlescape::encode_minimal(&subscriber.email),
+                htmlescape::encode_minimal(&subscriber.name),
+                subscriber.status,
+                subscriber.subscribed_at.format("%Y-%m-%d %H:%M UTC"),
+                subscriber.tags.join(", "),
+            )
+        })
+        .collect();
+
+    let status_options: String = std::iter::once(("", "any status"))
+        .chain(
+            SubscriptionStatus::ALL
+                .iter()
+                .map(|s| (s.as_str(), s.as_str())),
+        )
+        .map(|(value, label)| {
+            let selected = if Some(value) == status_filter {
+                " selected"
+            } else {
+                ""
+            };
+            format!(r#"<option value="{value}"{selected}>{label}</option>"#)
+        })
+        .collect();
+
+    let mut pagination = String::new();
+    if page > 1 {
+        pagination.push_str(&format!(
+            r#"<a href="{}">&lt; Previous</a> "#,
+            htmlescape::encode_attribute(&filter.page_link(page - 1))
+        ));
+    }
+    pagination.push_str(&format!(
+        "Page {} of {} ({} subscribers)",
+        page, page_count, total
+    ));
+    if page < page_count {
+        pagination.push_str(&format!(
+            r#" <a href="{}">Next &gt;</a>"#,
+            htmlescape::encode_attribute(&filter.page_link(page + 1))
+        ));
+    }
+
+    let search_value = htmlescape::encode_attribute(filter.search.unwrap_or(""));
+    let tag_value = htmlescape::encode_attribute(filter.tag.as_deref().unwrap_or(""));
+
+    let import_export_html = if user.role >= Role::Editor {
+        format!(
+            r#"
+            <form action="/admin/subscribers/tags" method="post">
+                <input type="hidden" name="q" value="{search_value}">
+                <input type="hidden" name="status" value="{status}">
+                <input type="hidden" name="tag" value="{tag_value}">
+                <select name="action">
+                    <option value="add">Add tags</option>
+                    <option value="remove">Remove tags</option>
+                </select>
+                <input type="text" placeholder="beta-tester, vip" name="tags">
+                <button type="submit">Apply to the {total} subscribers found</button>
+            </form>
+            <p><a href="/admin/subscribers/import">Import from CSV</a></p>
+            <form action="/admin/subscribers/export" method="get">
+                <select name="format">
+                    <option value="csv">CSV</option>
+                    <option value="ndjson">NDJSON</option>
+                </select>
+                <select name="status">{status_options}</select>
+                <label>Subscribed from <input type="date" name="from"></label>
+                <label>until <input type="date" name="until"></label>
+                <button type="submit">Export</button>
+            </form>
+            "#,
+            status = status_filter.unwrap_or(""),
+        )
+    } else {
+        "".into()
+    };
+
+    let html = Html::from(format!(
+        r#"
+        <!DOCTYPE html>
+        <html lang="en">
+        <head>
+            <meta http-equiv="content-type" content="text/html; charset=utf-8">
+            <title>Subscribers</title>
+        </head>
+        <body>
+            {flash_html}
+            <form action="/admin/subscribers" method="get">
+                <input type="text" placeholder="Email or name" name="q" value="{search_value}">
+                <select name="status">{status_options}</select>
+                <input type="text" placeholder="Tag" name="tag" value="{tag_value}">
+                <button type="submit">Search</button>
+            </form>
+            <table>
+                <tr><th>Email</th><th>Name</th><th>Status</th><th>Subscribed</th><th>Tags</th></tr>
+                {rows_html}
+            </table>
+            <p>{pagination}</p>
+            {import_export_html}
+            <p><a href="/admin/dashboard">&lt;- Back</a></p>
+        </body>
+        </html>
+        "#,
+    ));
+
+    let cookie = Cookie::build(("_flash", ""))
+        .path("/")
+        .max_age(Duration::ZERO);
+    (CookieJar::new().add(cookie), html).into_response()
+}
+
+pub async fn subscriber_page(
+    cookies: CookieJar,
+    user: AuthorizedUser<Viewer>,
+    State(pool): State<Arc<PgPool>>,
+    Path(subscriber_id): Path<Uuid>,
+) -> Response<Body> {
+    let subscriber = match get_subscriber(&pool, subscriber_id).await {
+        Ok(Some(subscriber)) => subscriber,
+        Ok(None) => return StatusCode::NOT_FOUND.into_response(),
+        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    };
+
+    let (Ok(history), Ok(consents), Ok(attribution), Ok(progress)) = (
+        get_history(&pool, subscriber_id).await,
+        list_consents(&pool, subscriber_id).await,
+        get_attribution(&pool, subscriber_id).await,
+        get_progress(&pool, subscriber_id).await,
+    ) else {
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    };
+
+    let flash_html = match cookies.get("_flash") {
+        None => "".into(),
+        Some(cookie) => {
+            format!(
+                "<p><i>{}</i></p>",
+                htmlescape::encode_minimal(cookie.value())
+            )
+        }
+    };
+
+    let history_html: String = history
+        .iter()
+        .map(|entry| {
+            let actor = match &entry.actor_username {
+                Some(username) => format!(" by {}", htmlescape::encode_minimal(username)),
+                None => "".into(),
+            };
+            format!(
+                "<li>{}: {}{}</li>",
+                entry.occurred_at.format("%Y-%m-%d %H:%M:%S UTC"),
+                entry.event,
+                actor
+            )
+        })
+        .collect();
+
+    let consents_html: String = consents
+        .iter()
+        .map(|consent| {
+            format!(
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                consent.recorded_at.format("%Y-%m-%d %H:%M:%S UTC"),
+                consent.kind,
+                htmlescape::encode_minimal(consent.ip_address.as_deref().unwrap_or("")),
+                htmlescape::encode_minimal(consent.user_agent.as_deref().unwrap_or("")),
+                htmlescape::encode_minimal(consent.source.as_deref().unwrap_or("")),
+            )
+        })
+        .collect();
+
+    let attributes_html: String = subscriber
+        .attributes
+        .as_object()
+        .into_iter()
+        .flatten()
+        .map(|(name, value)| {
+            format!(
+                "<tr><td>{}</td><td>{}</td></tr>",
+                htmlescape::encode_minimal(name),
+                htmlescape::encode_minimal(&display_value(value)),
+            )
+        })
+        .collect();
+
+    let attribution_html: String = attribution
+        .unwrap_or_default()
+        .fields()
+        .into_iter()
+        .filter_map(|(field, value)| {
+            Some(format!(
+                "<tr><td>{}</td><td>{}</td></tr>",
+                field,
+                htmlescape::encode_minimal(value?)
+            ))
+        })
+        .collect();
+
+    let sequence_html = match progress {
+        None => "<p>Not enrolled, they have not confirmed since it was set up.</p>".into(),
+        Some((enrollment, steps)) => {
+            let stopped = match enrollment.stopped_at {
+                Some(stopped_at) => {
+                    format!(", stopped {}", stopped_at.format("%Y-%m-%d %H:%M:%S UTC"))
+                }
+                None => "".into(),
+            };
+            let steps_html: String = steps
+                .iter()
+                .map(|step| {
+                    let sent = match (step.sent_at, enrollment.stopped_at) {
+                        (Some(sent_at), _) => sent_at.format("%Y-%m-%d %H:%M:%S UTC").to_string(),
+                        (None, Some(_)) => "not sent".into(),
+                        (None, None) => "pending".into(),
+                    };
+                    format!(
+                        "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+                        htmlescape::encode_minimal(&step.subject),
+                        step.due_at.format("%Y-%m-%d %H:%M:%S UTC"),
+                        sent
+                    )
+                })
+                .collect();
+            format!(
+                r#"
+                <p>Enrolled {}{}</p>
+                <table>
+                    <tr><th>Email</th><th>Due</th><th>Sent</th></tr>
+                    {}
+                </table>
+                "#,
+                enrollment.enrolled_at.format("%Y-%m-%d %H:%M:%S UTC"),
+                stopped,
+                steps_html
+            )
+        }
+    };
+
+    let mut actions_html = String::new();
+    if user.role >= Role::Editor {
+        let action = |action: &str, label: &str| {
+            format!(
+                r#"
+                <form action="/admin/subscribers/{}/{}" method="post">
+                    <button type="submit">{}</button>
+                </form>
+                "#,
+                subscriber.id, action, label
+            )
+        };
+
+        if subscriber.status != SubscriptionStatus::Confirmed.as_str() {
+            actions_html.push_str(&action("confirm", "Confirm"));
+        }
+        if subscriber.status != SubscriptionStatus::Unsubscribed.as_str() {
+            actions_html.push_str(&action("unsubscribe", "Unsubscribe"));
+        }
+        actions_html.push_str(&format!(
+            r#"
+            <form action="/admin/subscribers/{}/tags" method="post">
+                <select name="action">
+                    <option value="add">Add tags</option>
+                    <option value="remove">Remove tags</option>
+                </select>
+                <input type="text" placeholder="beta-tester, vip" name="tags">
+                <button type="submit">Apply</button>
+            </form>
+            "#,
+            subscriber.id
+        ));
+        actions_html.push_str(&action("delete", "Delete"));
+        actions_html.push_str(&format!(
+            r#"<p><a href="/admin/subscribers/{}/data">Download their personal data</a></p>"#,
+            subscriber.id
+        ));
+        actions_html.push_str(&action("erase", "Erase their personal data"));
+    }
+
+    let html = Html::from(format!(
+        r#"
+        <!DOCTYPE html>
+        <html lang="en">
+        <head>
+            <meta http-equiv="content-type" content="text/html; charset=utf-8">
+            <title>Subscriber</title>
+        </head>
+        <body>
+            {flash_html}
+            <p>Email: {email}</p>
+            <p>Name: {name}</p>
+            <p>Status: {status}</p>
+            <p>Subscribed: {subscribed_at}</p>
+            <p>Tags: {tags}</p>
+            <h2>Attributes</h2>
+            <table>{attributes_html}</table>
+            <h2>Signup source</h2>
+            <table>{attribution_html}</table>
+            <h2>Welcome sequence</h2>
+            {sequence_html}
+            <h2>History</h2>
+            <ul>{history_html}</ul>
+            <h2>Consent</h2>
+            <table>
+                <tr><th>Time</th><th>Given at</th><th>IP address</th><th>User agent</th><th>Source</th></tr>
+                {consents_html}
+            </table>
+            {actions_html}
+            <p><a href="/admin/subscribers">&lt;- Back</a></p>
+        </body>
+        </html>
+        "#,
+        email = htmlescape::encode_minimal(&subscriber.email),
+        name = htmlescape::encode_minimal(&subscriber.name),
+        status = subscriber.status,
+        subscribed_at = subscriber.subscribed_at.format("%Y-%m-%d %H:%M:%S UTC"),
+        tags = subscriber.tags.join(", "),
+    ));
+
+    let cookie = Cookie::build(("_flash", ""))
+        .path("/")
+        .max_age(Duration::ZERO);
+    (CookieJar::new().add(cookie), html).into_response()
+}
+
+pub async fn confirm_subscriber_manually(
+    user: AuthorizedUser<Editor>,
+    audit: AuditContext,
+    State(pool): State<Arc<PgPool>>,
+    Path(subscriber_id): Path<Uuid>,
+) -> Response<Body> {
+    change_status(
+        &pool,
+        &audit,
+        user.user_id,
+        subscriber_id,
+        SubscriptionStatus::Confirmed,
+    )
+    .await
+}
+
+pub async fn unsubscribe_subscriber(
+    user: AuthorizedUser<Editor>,
+    audit: AuditContext,
+    State(pool): State<Arc<PgPool>>,
+    Path(subscriber_id): Path<Uuid>,
+) -> Response<Body> {
+    change_status(
+        &pool,
+        &audit,
+        user.user_id,
+        subscriber_id,
+        SubscriptionStatus::Unsubscribed,
+    )
+    .await
+}
+
+pub async fn delete_subscriber(
+    user: AuthorizedUser<Editor>,
+    audit: AuditContext,
+    State(pool): State<Arc<PgPool>>,
+    Path(subscriber_id): Path<Uuid>,
+) -> Response<Body> {
+    // Their tokens and history go with them, see the `ON DELETE CASCADE`s
+    let deleted = sqlx::query!(
+        r#"DELETE FROM subscriptions WHERE id = $1 RETURNING email"#,
+        subscriber_id
+    )
+    .fetch_optional(pool.as_ref())
+    .await;
+
+    match deleted {
+        Ok(Some(row)) => {
+            audit
+                .record(
+                    &pool,
+                    Some(user.user_id),
+                    AuditAction::SubscriberDelete,
+                    Some(&row.email),
+                )
+                .await;
+            flash_redirect("/admin/subscribers", "The subscriber has been deleted.")
+        }
+        Ok(None) => flash_redirect("/admin/subscribers", "This subscriber does not exist."),
+        Err(e) => {
+            error!("failed to delete subscriber: {:?}", e);
+            flash_redirect(
+                &format!("/admin/subscribers/{}", subscriber_id),
+                "Failed to delete the subscriber.",
+            )
+        }
+    }
+}
+
+pub async fn export_subscriber_data_manually(
+    user: AuthorizedUser<Editor>,
+    audit: AuditContext,
+    State(pool): State<Arc<PgPool>>,
+    Path(subscriber_id): Path<Uuid>,
+) -> Response<Body> {
+    let subscriber = match get_subscriber(&pool, subscriber_id).await {
+        Ok(Some(subscriber)) => subscriber,
+        Ok(None) => return StatusCode::NOT_FOUND.into_response(),
+        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    };
+
+    let data = match collect_subscriber_data(&pool, &subscriber.email).await {
+        Ok(Some(data)) => data,
+        Ok(None) => return StatusCode::NOT_FOUND.into_response(),
+        Err(e) => {
+            error!("failed to collect subscriber data: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    audit
+        .record(
+            &pool,
+            Some(user.user_id),
+            AuditAction::SubscriberDataExport,
+            Some(&subscriber.email),
+        )
+        .await;
+
+    data_download(data)
+}
+
+pub async fn erase_subscriber_manually(
+    user: AuthorizedUser<Editor>,
+    audit: AuditContext,
+    State(pool): State<Arc<PgPool>>,
+    Path(subscriber_id): Path<Uuid>,
+) -> Response<Body> {
+    let subscriber = match get_subscriber(&pool, subscriber_id).await {
+        Ok(Some(subscriber)) => subscriber,
+        Ok(None) => return flash_redirect("/admin/subscribers", "This subscriber does not exist."),
+        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    };
+
+    if let Err(e) = erase_subscriber(&pool, &subscriber.email).await {
+        error!("failed to erase subscriber: {:?}", e);
+        return flash_redirect(
+            &format!("/admin/subscribers/{}", subscriber_id),
+            "Failed to erase the subscriber.",
+        );
+    }
+
+    // The address itself must not survive in the audit log either
+    audit
+        .record(
+            &pool,
+            Some(user.user_id),
+            AuditAction::SubscriberErase,
+            Some(&email_hash(&subscriber.email)),
+        )
+        .await;
+
+    flash_redirect(
+        "/admin/subscribers",
+        "The personal data of the subscriber has been erased.",
+    )
+}
+
+#[derive(Deserialize)]
+pub struct TagsData {
+    tags: String,
+    action: String,
+}
+
+pub async fn tag_subscriber(
+    user: AuthorizedUser<Editor>,
+    audit: AuditContext,
+    State(pool): State<Arc<PgPool>>,
+    Path(subscriber_id): Path<Uuid>,
+    Form(form): Form<TagsData>,
+) -> Response<Body> {
+    let detail_page = format!("/admin/subscribers/{}", subscriber_id);
+
+    let (action, tags) = match parse_tag_action(&form) {
+        Ok(parsed) => parsed,
+        Err(e) => return flash_redirect(&detail_page, &e),
+    };
+
+    let subscriber = match get_subscriber(&pool, subscriber_id).await {
+        Ok(Some(subscriber)) => subscriber,
+        Ok(None) => return flash_redirect("/admin/subscribers", "This subscriber does not exist."),
+        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    };
+
+    match apply_tags(&pool, action, &[subscriber_id], &tags).await {
+        Ok(0) => flash_redirect(&detail_page, "Nothing to change."),
+        Ok(_) => {
+            audit
+                .record(
+                    &pool,
+                    Some(user.user_id),
+                    tag_audit_action(action),
+                    Some(&subscriber.email),
+                )
+                .await;
+            flash_redirect(&detail_page, "The tags have been updated.")
+        }
+        Err(e) => {
+            error!("failed to update subscriber tags: {:?}", e);
+            flash_redirect(&detail_page, "Failed to update the tags.")
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct BulkTagsData {
+    q: Option<String>,
+    status: Option<String>,
+    tag: Option<String>,
+    #[serde(flatten)]
+    tags: TagsData,
+}
+
+/// Applies the tags to every subscriber found by a search, not just to the
+/// page shown.
+pub async fn tag_subscribers(
+    user: AuthorizedUser<Editor>,
+    audit: AuditContext,
+    State(pool): State<Arc<PgPool>>,
+    Form(form): Form<BulkTagsData>,
+) -> Response<Body> {
+    let filter = SubscriberFilter::new(
+        form.q.as_deref(),
+        form.status.as_deref(),
+        form.tag.as_deref(),
+    );
+    let list_page = filter.page_link(1);
+
+    let (action, tags) = match parse_tag_action(&form.tags) {
+        Ok(parsed) => parsed,
+        Err(e) => return flash_redirect(&list_page, &e),
+    };
+
+    let subscriber_ids = match matching_subscriber_ids(&pool, &filter).await {
+        Ok(ids) => ids,
+        Err(e) => {
+            error!("failed to search subscribers: {:?}", e);
+            return flash_redirect(&list_page, "Failed to update the tags.");
+        }
+    };
+
+    match apply_tags(&pool, action, &subscriber_ids, &tags).await {
+        Ok(0) => flash_redirect(&list_page, "Nothing to change."),
+        Ok(changed) => {
+            audit
+                .record(
+                    &pool,
+                    Some(user.user_id),
+                    tag_audit_action(action),
+                    Some(&format!(
+                        "{} on {} subscribers",
+                        tags.join(", "),
+                        subscriber_ids.len()
+                    )),
+                )
+                .await;
+            flash_redirect(
+                &list_page,
+                &format!("The tags have been updated ({} changes).", changed),
+            )
+        }
+        Err(e) => {
+            error!("failed to update subscriber tags: {:?}", e);
+            flash_redirect(&list_page, "Failed to update the tags.")
+        }
+    }
+}
+
+fn parse_tag_action(form: &TagsData) -> Result<(TagAction, Vec<String>), String> {
+    let action = TagAction::try_from(form.action.as_str())?;
+    let tags = parse_tags(&form.tags)?;
+    if tags.is_empty() {
+        return Err("No tags were given.".into());
+    }
+    Ok((action, tags))
+}
+
+fn tag_audit_action(action: TagAction) -> AuditAction {
+    match action {
+        TagAction::Add => AuditAction::SubscriberTag,
+        TagAction::Remove => AuditAction::SubscriberUntag,
+    }
+}
+
+/// Returns how many tags were put on or taken off subscribers.
+async fn apply_tags(
+    pool: &PgPool,
+    action: TagAction,
+    subscriber_ids: &[Uuid],
+    tags: &[String],
+) -> Result<u64, sqlx::Error> {
+    match action {
+        TagAction::Add => {
+            let mut transaction = pool.begin().await?;
+            let changed = add_tags(&mut transaction, subscriber_ids, tags).await?;
+            transaction.commit().await?;
+            Ok(changed)
+        }
+        TagAction::Remove => remove_tags(pool, subscriber_ids, tags).await,
+    }
+}
+
+async fn change_status(
+    pool: &PgPool,
+    audit: &AuditContext,
+    actor_id: Uuid,
+    subscriber_id: Uuid,
+    status: SubscriptionStatus,
+) -> Response<Body> {
+    let (event, action, message) = match status {
+        SubscriptionStatus::Confirmed => (
+            SubscriberEvent::Confirmed,
+            AuditAction::SubscriberConfirm,
+            "The subscriber has been confirmed.",
+        ),
+        SubscriptionStatus::Unsubscribed => (
+            SubscriberEvent::Unsubscribed,
+            AuditAction::SubscriberUnsubscribe,
+            "The subscriber has been unsubscribed.",
+        ),
+        SubscriptionStatus::PendingConfirmation => unreachable!("subscribers are never reset"),
+    };
+
+    let detail_page = format!("/admin/subscribers/{}", subscriber_id);
+
+    match set_status(pool, actor_id, subscriber_id, status, event).await {
+        Ok(Some(email)) => {
+            audit
+                .record(pool, Some(actor_id), action, Some(&email))
+                .await;
+            flash_redirect(&detail_page, message)
+        }
+        Ok(None) => flash_redirect(&detail_page, "Nothing to change."),
+        Err(e) => {
+            error!("failed to change subscriber status: {:?}", e);
+            flash_redirect(&detail_page, "Failed to update the subscriber.")
+        }
+    }
+}
+
+// The actions live under `/admin/subscribers/:subscriber_id/`, so the flash
+// cookie needs an explicit path to be sent back to the pages.
+fn flash_redirect(location: &str, message: &str) -> Response<Body> {
+    let cookie = Cookie::build(("_flash", message.to_owned())).path("/");
+    (CookieJar::new().add(cookie), Redirect::to(location)).into_response()
+}
+
+/// Escapes the characters `LIKE` treats as wildcards, so that searches are
+/// literal.
+fn escape_like(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+async fn count_subscribers(
+    pool: &PgPool,
+    filter: &SubscriberFilter<'_>,
+) -> Result<i64, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT COUNT(*) AS "count!"
+        FROM subscriptions
+        WHERE ($1::text IS NULL OR email ILIKE $1 OR name ILIKE $1)
+            AND ($2::text IS NULL OR status = $2)
+            AND ($3::text IS NULL OR EXISTS (
+                SELECT 1 FROM subscription_tags
+                WHERE subscriber_id = subscriptions.id AND tag = $3
+            ))
+        "#,
+        filter.pattern(),
+        filter.status(),
+        filter.tag,
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(row.count)
+}
+
+async fn search_subscribers(
+    pool: &PgPool,
+    filter: &SubscriberFilter<'_>,
+    offset: i64,
+) -> Result<Vec<SubscriberRow>, sqlx::Error> {
+    sqlx::query_as!(
+        SubscriberRow,
+        r#"
+        SELECT
+            id, email, name, status, subscribed_at, attributes,
+            ARRAY(
+                SELECT tag FROM subscription_tags
+                WHERE subscriber_id = subscriptions.id
+                ORDER BY tag
+            ) AS "tags!"
+        FROM subscriptions
+        WHERE ($1::text IS NULL OR email ILIKE $1 OR name ILIKE $1)
+            AND ($2::text IS NULL OR status = $2)
+            AND ($3::text IS NULL OR EXISTS (
+                SELECT 1 FROM subscription_tags
+                WHERE subscriber_id = subscriptions.id AND tag = $3
+            ))
+        ORDER BY subscribed_at DESC, id
+        LIMIT $4 OFFSET $5
+        "#,
+        filter.pattern(),
+        filter.status(),
+        filter.tag,
+        PAGE_SIZE,
+        offset,
+    )
+    .fetch_all(pool)
+    .await
+}
+
+async fn matching_subscriber_ids(
+    pool: &PgPool,
+    filter: &SubscriberFilter<'_>,
+) -> Result<Vec<Uuid>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT id
+        FROM subscriptions
+        WHERE ($1::text IS NULL OR email ILIKE $1 OR name ILIKE $1)
+            AND ($2::text IS NULL OR status = $2)
+            AND ($3::text IS NULL OR EXISTS (
+                SELECT 1 FROM subscription_tags
+                WHERE subscriber_id = subscriptions.id AND tag = $3
+            ))
+        "#,
+        filter.pattern(),
+        filter.status(),
+        filter.tag,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.into_iter().map(|row| row.id).collect())
+}
+
+async fn get_subscriber(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Option<SubscriberRow>, sqlx::Error> {
+    sqlx::query_as!(
+        SubscriberRow,
+        r#"
+        SELECT
+            id, email, name, status, subscribed_at, attributes,
+            ARRAY(
+                SELECT tag FROM subscription_tags
+                WHERE subscriber_id = subscriptions.id
+                ORDER BY tag
+            ) AS "tags!"
+        FROM subscriptions
+        WHERE id = $1
+        "#,
+        subscriber_id,
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+/// Returns the email of the subscriber if their status actually changed.
+async fn set_status(
+    pool: &PgPool,
+    actor_id: Uuid,
+    subscriber_id: Uuid,
+    status: SubscriptionStatus,
+    event: SubscriberEvent,
+) -> Result<Option<String>, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+
+    let updated = sqlx::query!(
+        r#"
+        UPDATE subscriptions SET status = $2
+        WHERE id = $1 AND status <> $2
+        RETURNING email
+        "#,
+        subscriber_id,
+        status.as_str(),
+    )
+    .fetch_optional(&mut *transaction)
+    .await?;
+
+    if updated.is_some() {
+        record_event(&mut *transaction, subscriber_id, event, Some(actor_id)).await?;
+
+        match status {
+            SubscriptionStatus::Confirmed => enroll(&mut *transaction, subscriber_id).await?,
+            SubscriptionStatus::Unsubscribed => {
+                stop_sequence(&mut *transaction, subscriber_id).await?
+            }
+            SubscriptionStatus::PendingConfirmation => {}
+        }
+    }
+
+    transaction.commit().await?;
+
+    Ok(updated.map(|row| row.email))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::escape_like;
+
+    #[test]
+    fn like_wildcards_are_escaped() {
+        assert_eq!(escape_like("50%_off\\"), "50\\%\\_off\\\\");
+    }
+}
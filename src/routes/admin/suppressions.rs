@@ -0,0 +1,321 @@
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    extract::{Multipart, Path, Query, State},
+    response::{Html, IntoResponse, Redirect, Response},
+};
+use axum_extra::extract::{cookie::Cookie, CookieJar, Form};
+use serde::Deserialize;
+use sqlx::PgPool;
+use time::Duration;
+use tracing::error;
+use uuid::Uuid;
+
+use crate::{
+    audit::{AuditAction, AuditContext},
+    session_state::{AuthorizedUser, Editor},
+    suppression::{
+        add_suppressions, count_suppressions, list_suppressions, normalize_suppression,
+        parse_suppression_list, remove_suppression, SuppressionReason,
+    },
+};
+
+/// The list can hold every hard bounce ever seen, only so many are shown.
+const SHOWN_SUPPRESSIONS: i64 = 100;
+
+/// Invalid lines listed back after an upload.
+const SHOWN_INVALID_LINES: usize = 10;
+
+#[derive(Deserialize)]
+pub struct SuppressionsQuery {
+    q: Option<String>,
+}
+
+pub async fn suppressions_page(
+    cookies: CookieJar,
+    _user: AuthorizedUser<Editor>,
+    State(pool): State<Arc<PgPool>>,
+    Query(query): Query<SuppressionsQuery>,
+) -> Response<Body> {
+    let search = query.q.as_deref().map(str::trim).filter(|q| !q.is_empty());
+
+    let (suppressions, total) = match tokio::try_join!(
+        list_suppressions(&pool, search, SHOWN_SUPPRESSIONS),
+        count_suppressions(&pool, search)
+    ) {
+        Ok(lists) => lists,
+        Err(e) => {
+            error!("failed to load the suppression list: {:?}", e);
+            return Redirect::to("/admin/dashboard").into_response();
+        }
+    };
+
+    let flash_html = match cookies.get("_flash") {
+        None => "".into(),
+        Some(cookie) => {
+            format!(
+                "<p><i>{}</i></p>",
+                htmlescape::encode_minimal(cookie.value())
+            )
+        }
+    };
+
+    let suppressions_html: String = suppressions
+        .into_iter()
+        .map(|suppression| {
+            format!(
+                r#"
+                <tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>
+                    <form action="/admin/suppressions/{}/delete" method="post">
+                        <button type="submit">remove</button>
+                    </form>
+                </td></tr>
+                "#,
+                htmlescape::encode_minimal(&suppression.pattern),
+                suppression.reason,
+                htmlescape::encode_minimal(&suppression.source),
+                suppression.created_at.format("%Y-%m-%d %H:%M:%S UTC"),
+                suppression.suppression_id
+            )
+        })
+        .collect();
+
+    let reason_options: String = SuppressionReason::ALL
+        .iter()
+        .map(|reason| format!(r#"<option value="{0}">{0}</option>"#, reason.as_str()))
+        .collect();
+
+    let html = Html::from(format!(
+        r#"
+        <!DOCTYPE html>
+        <html lang="en">
+        <head>
+            <meta http-equiv="content-type" content="text/html; charset=utf-8">
+            <title>Suppression list</title>
+        </head>
+        <body>
+            {flash_html}
+            <p>No email is ever sent to these addresses, whether they are
+            subscribed or not.</p>
+            <p><code>ursula@example.com</code> is a single address,
+            <code>abuse@*</code> that mailbox on every domain,
+            <code>test.example</code> and <code>*.test.example</code> every
+            address on a domain or on its subdomains.</p>
+            <form action="/admin/suppressions" method="get">
+                <input type="text" placeholder="Search" name="q" value="{search}">
+                <button type="submit">Search</button>
+            </form>
+            <p>{total} entries, the newest {shown} are shown.</p>
+            <table>
+                <tr><th>Address</th><th>Reason</th><th>Source</th><th>Added</th><th></th></tr>
+                {suppressions_html}
+            </table>
+
+            <h2>Add an address</h2>
+            <form action="/admin/suppressions" method="post">
+                <input type="text" placeholder="abuse@*" name="pattern">
+                <select name="reason">{reason_options}</select>
+                <input type="text" placeholder="Source, such as a ticket" name="source">
+                <button type="submit">Add</button>
+            </form>
+
+            <h2>Upload a list</h2>
+            <p>One address or pattern per line. In CSV files only the first
+            column is read.</p>
+            <form action="/admin/suppressions/upload" method="post" enctype="multipart/form-data">
+                <input type="file" name="file" accept=".csv,.txt,text/csv,text/plain">
+                <select name="reason">{reason_options}</select>
+                <input type="text" placeholder="Source" name="source">
+                <button type="submit">Upload</button>
+            </form>
+            <p><a href="/admin/dashboard">&lt;- Back</a></p>
+        </body>
+        </html>
+        "#,
+        search = htmlescape::encode_attribute(search.unwrap_or("")),
+        shown = SHOWN_SUPPRESSIONS,
+    ));
+
+    let cookie = Cookie::build(("_flash", ""))
+        .path("/")
+        .max_age(Duration::ZERO);
+    (CookieJar::new().add(cookie), html).into_response()
+}
+
+#[derive(Deserialize)]
+pub struct NewSuppressionData {
+    pattern: String,
+    reason: String,
+    #[serde(default)]
+    source: String,
+}
+
+pub async fn add_suppression(
+    user: AuthorizedUser<Editor>,
+    audit: AuditContext,
+    State(pool): State<Arc<PgPool>>,
+    Form(form): Form<NewSuppressionData>,
+) -> Response<Body> {
+    let reason = match SuppressionReason::try_from(form.reason.as_str()) {
+        Ok(reason) => reason,
+        Err(e) => return flash_redirect(&e),
+    };
+
+    let pattern = match normalize_suppression(&form.pattern) {
+        Ok(pattern) => pattern,
+        Err(e) => return flash_redirect(&e),
+    };
+
+    let source = match form.source.trim() {
+        "" => "manual",
+        source => source,
+    };
+
+    match add_suppressions(&pool, std::slice::from_ref(&pattern), reason, source).await {
+        Ok(0) => flash_redirect("This address already is on the list."),
+        Ok(_) => {
+            audit
+                .record(
+                    &pool,
+                    Some(user.user_id),
+                    AuditAction::SuppressionAdd,
+                    Some(&pattern),
+                )
+                .await;
+            flash_redirect("The address has been added.")
+        }
+        Err(e) => {
+            error!("failed to add suppression: {:?}", e);
+            flash_redirect("Failed to add the address.")
+        }
+    }
+}
+
+pub async fn upload_suppressions(
+    user: AuthorizedUser<Editor>,
+    audit: AuditContext,
+    State(pool): State<Arc<PgPool>>,
+    mut multipart: Multipart,
+) -> Response<Body> {
+    let mut file = None;
+    let mut reason = None;
+    let mut source = String::new();
+
+    loop {
+        let field = match multipart.next_field().await {
+            Ok(Some(field)) => field,
+            Ok(None) => break,
+            Err(e) => return flash_redirect(&format!("The upload failed: {}", e)),
+        };
+
+        match field.name() {
+            Some("file") => {
+                let file_name = field.file_name().unwrap_or("upload.csv").to_owned();
+                match field.bytes().await {
+                    Ok(data) => file = Some((file_name, data)),
+                    Err(e) => return flash_redirect(&format!("The upload failed: {}", e)),
+                }
+            }
+            Some("reason") => {
+                let Ok(value) = field.text().await else {
+                    return flash_redirect("The reason is not valid.");
+                };
+                match SuppressionReason::try_from(value.as_str()) {
+                    Ok(value) => reason = Some(value),
+                    Err(e) => return flash_redirect(&e),
+                }
+            }
+            Some("source") => {
+                let Ok(value) = field.text().await else {
+                    return flash_redirect("The source is not valid.");
+                };
+                source = value.trim().to_owned();
+            }
+            _ => {}
+        }
+    }
+
+    let Some((file_name, data)) = file.filter(|(_, data)| !data.is_empty()) else {
+        return flash_redirect("Please choose a file to upload.");
+    };
+    let Some(reason) = reason else {
+        return flash_redirect("Please choose a reason.");
+    };
+    if source.is_empty() {
+        source = format!("upload of {}", file_name);
+    }
+
+    let (patterns, invalid) = parse_suppression_list(&data);
+
+    let added = match add_suppressions(&pool, &patterns, reason, &source).await {
+        Ok(added) => added,
+        Err(e) => {
+            error!("failed to upload suppressions: {:?}", e);
+            return flash_redirect("Failed to upload the list.");
+        }
+    };
+
+    audit
+        .record(
+            &pool,
+            Some(user.user_id),
+            AuditAction::SuppressionUpload,
+            Some(&file_name),
+        )
+        .await;
+
+    let mut message = format!(
+        "{} addresses added, {} were already on the list.",
+        added,
+        patterns.len() as u64 - added
+    );
+    if !invalid.is_empty() {
+        let shown: Vec<&str> = invalid
+            .iter()
+            .take(SHOWN_INVALID_LINES)
+            .map(String::as_str)
+            .collect();
+        message.push_str(&format!(
+            " {} lines were not valid: {}",
+            invalid.len(),
+            shown.join(", ")
+        ));
+    }
+    flash_redirect(&message)
+}
+
+pub async fn delete_suppression(
+    user: AuthorizedUser<Editor>,
+    audit: AuditContext,
+    State(pool): State<Arc<PgPool>>,
+    Path(suppression_id): Path<Uuid>,
+) -> Response<Body> {
+    match remove_suppression(&pool, suppression_id).await {
+        Ok(Some(pattern)) => {
+            audit
+                .record(
+                    &pool,
+                    Some(user.user_id),
+                    AuditAction::SuppressionRemove,
+                    Some(&pattern),
+                )
+                .await;
+            flash_redirect("The address has been removed.")
+        }
+        Ok(None) => flash_redirect("This address is not on the list."),
+        Err(e) => {
+            error!("failed to remove suppression: {:?}", e);
+            flash_redirect("Failed to remove the address.")
+        }
+    }
+}
+
+fn flash_redirect(message: &str) -> Response<Body> {
+    let cookie = Cookie::build(("_flash", message.to_owned())).path("/");
+    (
+        CookieJar::new().add(cookie),
+        Redirect::to("/admin/suppressions"),
+    )
+        .into_response()
+}
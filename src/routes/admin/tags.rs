@@ -0,0 +1,163 @@
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    extract::{Path, Query, State},
+    response::{Html, IntoResponse, Redirect, Response},
+};
+use axum_extra::extract::{cookie::Cookie, CookieJar};
+use secrecy::Secret;
+use serde::Deserialize;
+use sqlx::PgPool;
+use time::Duration;
+use tracing::error;
+
+use crate::{
+    audit::{AuditAction, AuditContext},
+    session_state::{AuthorizedUser, Editor},
+    subscriber_tags::{delete_tag, list_tags, parse_tags, signup_tags_token},
+};
+
+#[derive(Deserialize)]
+pub struct TagsQuery {
+    /// Tags to make a signup token for.
+    signup: Option<String>,
+}
+
+pub async fn tags_page(
+    cookies: CookieJar,
+    _user: AuthorizedUser<Editor>,
+    State(pool): State<Arc<PgPool>>,
+    State(base_url): State<Arc<str>>,
+    State(secret): State<Arc<Secret<String>>>,
+    Query(query): Query<TagsQuery>,
+) -> Response<Body> {
+    let tags = match list_tags(&pool).await {
+        Ok(tags) => tags,
+        Err(e) => {
+            error!("failed to load the tags: {:?}", e);
+            return Redirect::to("/admin/dashboard").into_response();
+        }
+    };
+
+    let flash_html = match cookies.get("_flash") {
+        None => "".into(),
+        Some(cookie) => {
+            format!(
+                "<p><i>{}</i></p>",
+                htmlescape::encode_minimal(cookie.value())
+            )
+        }
+    };
+
+    let tags_html: String = tags
+        .into_iter()
+        .map(|tag| {
+            format!(
+                r#"
+                <tr><td><a href="/admin/subscribers?tag={0}">{0}</a></td><td>{1}</td><td>
+                    <form action="/admin/tags/{0}/delete" method="post">
+                        <button type="submit">delete</button>
+                    </form>
+                </td></tr>
+                "#,
+                tag.name, tag.subscribers
+            )
+        })
+        .collect();
+
+    let signup_html = match query.signup.as_deref().map(parse_tags) {
+        None => "".into(),
+        Some(Err(e)) => format!("<p><i>{}</i></p>", htmlescape::encode_minimal(&e)),
+        Some(Ok(tags)) if tags.is_empty() => "".into(),
+        Some(Ok(tags)) => {
+            let token = signup_tags_token(&secret, &tags);
+            let snippets = [
+                format!(r#"<input type="hidden" name="tags" value="{}">"#, token),
+                format!("{}/?tags={}", base_url, urlencoding::encode(&token)),
+                format!(
+                    r#"<div data-newsletter-widget data-tags="{}"></div>"#,
+                    token
+                ),
+            ];
+            format!(
+                r#"
+                <p>In a form posting to <code>/subscriptions</code>:</p>
+                <pre><code>{}</code></pre>
+                <p>As a link to the signup page:</p>
+                <pre><code>{}</code></pre>
+                <p>On the signup widget:</p>
+                <pre><code>{}</code></pre>
+                "#,
+                htmlescape::encode_minimal(&snippets[0]),
+                htmlescape::encode_minimal(&snippets[1]),
+                htmlescape::encode_minimal(&snippets[2]),
+            )
+        }
+    };
+
+    let html = Html::from(format!(
+        r#"
+        <!DOCTYPE html>
+        <html lang="en">
+        <head>
+            <meta http-equiv="content-type" content="text/html; charset=utf-8">
+            <title>Subscriber tags</title>
+        </head>
+        <body>
+            {flash_html}
+            <p>Tags are created when they are first put on a subscriber, from
+            the subscribers pages or at signup.</p>
+            <table>
+                <tr><th>Tag</th><th>Subscribers</th><th></th></tr>
+                {tags_html}
+            </table>
+
+            <h2>Tag people at signup</h2>
+            <form action="/admin/tags" method="get">
+                <input type="text" placeholder="beta-tester, vip" name="signup">
+                <button type="submit">Get the signup code</button>
+            </form>
+            {signup_html}
+            <p><a href="/admin/dashboard">&lt;- Back</a></p>
+        </body>
+        </html>
+        "#,
+    ));
+
+    let cookie = Cookie::build(("_flash", ""))
+        .path("/")
+        .max_age(Duration::ZERO);
+    (CookieJar::new().add(cookie), html).into_response()
+}
+
+pub async fn remove_tag(
+    user: AuthorizedUser<Editor>,
+    audit: AuditContext,
+    State(pool): State<Arc<PgPool>>,
+    Path(name): Path<String>,
+) -> Response<Body> {
+    match delete_tag(&pool, &name).await {
+        Ok(true) => {
+            audit
+                .record(
+                    &pool,
+                    Some(user.user_id),
+                    AuditAction::TagDelete,
+                    Some(&name),
+                )
+                .await;
+            flash_redirect("The tag has been deleted.")
+        }
+        Ok(false) => flash_redirect("This tag does not exist."),
+        Err(e) => {
+            error!("failed to delete tag: {:?}", e);
+            flash_redirect("Failed to delete the tag.")
+        }
+    }
+}
+
+fn flash_redirect(message: &str) -> Response<Body> {
+    let cookie = Cookie::build(("_flash", message.to_owned())).path("/");
+    (CookieJar::new().add(cookie), Redirect::to("/admin/tags")).into_response()
+}
@@ -0,0 +1,235 @@
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    extract::State,
+    response::{Html, IntoResponse, Redirect, Response},
+    Form,
+};
+use axum_extra::extract::{cookie::Cookie, CookieJar};
+use serde::Deserialize;
+use sqlx::PgPool;
+use time::Duration;
+use tracing::error;
+
+use crate::{
+    audit::{AuditAction, AuditContext},
+    configuration::TwoFactorSettings,
+    login_attempts::LoginThrottle,
+    routes::admin::get_username,
+    session_state::TypedSession,
+    two_factor,
+};
+
+// These pages only require a session, not `AuthorizedUser`: when two-factor
+// authentication is enforced, they are where unenrolled users are sent.
+pub async fn two_factor_page(
+    cookies: CookieJar,
+    session: TypedSession,
+    State(pool): State<Arc<PgPool>>,
+    State(settings): State<Arc<TwoFactorSettings>>,
+) -> Response<Body> {
+    let Some(user_id) = session.get_user_id().await.unwrap() else {
+        return Redirect::to("/login").into_response();
+    };
+
+    let Some(status) = two_factor::get_status(user_id, &pool).await else {
+        return Redirect::to("/login").into_response();
+    };
+
+    let flash_html = match cookies.get("_flash") {
+        None => "".into(),
+        Some(cookie) => {
+            format!(
+                "<p><i>{}</i></p>",
+                htmlescape::encode_minimal(cookie.value())
+            )
+        }
+    };
+
+    let content = if status.enabled {
+        let disable_form = if settings.enforce {
+            "<p>Two-factor authentication is required for all users.</p>".to_string()
+        } else {
+            r#"
+            <form action="/admin/two-factor/disable" method="post">
+                <label>Authentication code
+                <input type="text" placeholder="Code from your app or a recovery code" name="code">
+                </label>
+                <button type="submit">Disable two-factor authentication</button>
+            </form>
+            "#
+            .to_string()
+        };
+
+        format!("<p>Two-factor authentication is enabled.</p>{disable_form}")
+    } else {
+        let username = get_username(user_id, &pool).await.unwrap();
+
+        let Some(enrollment) = two_factor::new_enrollment(&settings.issuer, &username) else {
+            return Redirect::to("/admin/dashboard").into_response();
+        };
+
+        if two_factor::store_pending_secret(user_id, &enrollment.secret, &pool)
+            .await
+            .is_none()
+        {
+            return Redirect::to("/admin/dashboard").into_response();
+        }
+
+        let qr_code_html = match enrollment.qr_code_base64 {
+            Some(qr_code) => {
+                format!(r#"<img src="data:image/png;base64,{qr_code}" alt="QR code">"#)
+            }
+            None => "".into(),
+        };
+
+        format!(
+            r#"
+            <p>Scan this code with your authenticator app, then enter the code it shows.</p>
+            {qr_code_html}
+            <p>Provisioning URI: <code>{uri}</code></p>
+            <p>Secret: <code>{secret}</code></p>
+            <form action="/admin/two-factor" method="post">
+                <label>Authentication code
+                <input type="text" placeholder="123456" name="code" autocomplete="one-time-code">
+                </label>
+                <button type="submit">Enable two-factor authentication</button>
+            </form>
+            "#,
+            uri = htmlescape::encode_minimal(&enrollment.provisioning_uri),
+            secret = enrollment.secret,
+        )
+    };
+
+    let html = Html::from(format!(
+        r#"
+        <!DOCTYPE html>
+        <html lang="en">
+        <head>
+            <meta http-equiv="content-type" content="text/html; charset=utf-8">
+            <title>Two-factor authentication</title>
+        </head>
+        <body>
+            {flash_html}
+            {content}
+            <p><a href="/admin/dashboard">&lt;- Back</a></p>
+        </body>
+        </html>
+        "#,
+    ));
+
+    let cookie = Cookie::build(("_flash", "")).max_age(Duration::ZERO);
+    (CookieJar::new().add(cookie), html).into_response()
+}
+
+#[derive(Deserialize)]
+pub struct TwoFactorCode {
+    code: String,
+}
+
+pub async fn enable_two_factor(
+    session: TypedSession,
+    audit: AuditContext,
+    State(pool): State<Arc<PgPool>>,
+    Form(form): Form<TwoFactorCode>,
+) -> Response<Body> {
+    let Some(user_id) = session.get_user_id().await.unwrap() else {
+        return Redirect::to("/login").into_response();
+    };
+
+    let Some(recovery_codes) = two_factor::confirm_enrollment(user_id, &form.code, &pool).await
+    else {
+        let cookie = Cookie::new("_flash", "The code is not valid, please try again.");
+        return (
+            CookieJar::new().add(cookie),
+            Redirect::to("/admin/two-factor"),
+        )
+            .into_response();
+    };
+
+    audit
+        .record(&pool, Some(user_id), AuditAction::TwoFactorEnable, None)
+        .await;
+
+    let codes_html: String = recovery_codes
+        .iter()
+        .map(|code| format!("<li><code>{code}</code></li>"))
+        .collect();
+
+    // Recovery codes are only stored hashed, so this is the one chance to see them
+    Html::from(format!(
+        r#"
+        <!DOCTYPE html>
+        <html lang="en">
+        <head>
+            <meta http-equiv="content-type" content="text/html; charset=utf-8">
+            <title>Two-factor authentication</title>
+        </head>
+        <body>
+            <p>Two-factor authentication is now enabled.</p>
+            <p>Store these recovery codes somewhere safe. Each of them can be used once
+            to log in if you lose access to your authenticator app.</p>
+            <ul>{codes_html}</ul>
+            <p><a href="/admin/dashboard">Continue to the dashboard</a></p>
+        </body>
+        </html>
+        "#,
+    ))
+    .into_response()
+}
+
+pub async fn disable_two_factor(
+    session: TypedSession,
+    audit: AuditContext,
+    State(pool): State<Arc<PgPool>>,
+    State(settings): State<Arc<TwoFactorSettings>>,
+    State(throttle): State<Arc<LoginThrottle>>,
+    Form(form): Form<TwoFactorCode>,
+) -> Response<Body> {
+    let Some(user_id) = session.get_user_id().await.unwrap() else {
+        return Redirect::to("/login").into_response();
+    };
+    let Some(username) = get_username(user_id, &pool).await else {
+        return Redirect::to("/login").into_response();
+    };
+    let client = audit.client();
+
+    // Codes are throttled as at login, so that a session alone is not
+    // enough to guess one
+    let blocked = throttle
+        .is_blocked(&username, client.ip)
+        .await
+        .unwrap_or_else(|e| {
+            error!("failed to check login throttling: {:?}", e);
+            false
+        });
+
+    let message = if settings.enforce {
+        "Two-factor authentication is required for all users."
+    } else if blocked {
+        "Too many failed attempts, please try again later."
+    } else if two_factor::verify_second_factor(user_id, &form.code, &pool)
+        .await
+        .is_none()
+    {
+        if let Err(e) = throttle.record_failure(&username, client.ip).await {
+            error!("failed to record login failure: {:?}", e);
+        }
+        "The code is not valid, please try again."
+    } else if two_factor::disable(user_id, &pool).await.is_none() {
+        "Failed to disable two-factor authentication."
+    } else {
+        audit
+            .record(&pool, Some(user_id), AuditAction::TwoFactorDisable, None)
+            .await;
+        "Two-factor authentication has been disabled."
+    };
+
+    let cookie = Cookie::new("_flash", message);
+    (
+        CookieJar::new().add(cookie),
+        Redirect::to("/admin/two-factor"),
+    )
+        .into_response()
+}
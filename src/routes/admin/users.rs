@@ -0,0 +1,510 @@
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    extract::{Path, State},
+    response::{Html, IntoResponse, Redirect, Response},
+    Form,
+};
+use axum_extra::extract::{cookie::Cookie, CookieJar};
+use chrono::Utc;
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use serde::Deserialize;
+use sqlx::{Acquire, PgExecutor, PgPool};
+use time::Duration;
+use tracing::error;
+use uuid::Uuid;
+
+use super::get_username;
+use crate::{
+    audit::{AuditAction, AuditContext},
+    authentication::Role,
+    domain::SubscriberEmail,
+    email_client::EmailClient,
+    routes::hash_invitation_token,
+    session_state::{AuthorizedUser, Owner, SessionRegistry},
+};
+
+const INVITATION_VALIDITY_HOURS: i64 = 72;
+
+struct UserRow {
+    user_id: Uuid,
+    username: String,
+    email: Option<String>,
+    role: String,
+    disabled: bool,
+    has_password: bool,
+}
+
+pub async fn users_page(
+    cookies: CookieJar,
+    user: AuthorizedUser<Owner>,
+    State(pool): State<Arc<PgPool>>,
+) -> Response<Body> {
+    let current_user_id = user.user_id;
+
+    let Ok(users) = get_users(&pool).await else {
+        return Redirect::to("/admin/dashboard").into_response();
+    };
+
+    let flash_html = match cookies.get("_flash") {
+        None => "".into(),
+        Some(cookie) => {
+            format!(
+                "<p><i>{}</i></p>",
+                htmlescape::encode_minimal(cookie.value())
+            )
+        }
+    };
+
+    let rows_html: String = users
+        .into_iter()
+        .map(|user| {
+            let status = if user.disabled {
+                "disabled"
+            } else if !user.has_password {
+                "invited"
+            } else {
+                "active"
+            };
+
+            let actions = if user.user_id == current_user_id {
+                "(you)".to_string()
+            } else {
+                let toggle = if user.disabled { "enable" } else { "disable" };
+                format!(
+                    r#"
+                    <form action="/admin/users/{id}/{toggle}" method="post">
+                        <button type="submit">{toggle}</button>
+                    </form>
+                    <form action="/admin/users/{id}/delete" method="post">
+                        <button type="submit">delete</button>
+                    </form>
+                    <form action="/admin/users/{id}/role" method="post">
+                        {role_select}
+                        <button type="submit">change role</button>
+                    </form>
+                    "#,
+                    id = user.user_id,
+                    role_select = role_select(&user.role),
+                )
+            };
+
+            format!(
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                htmlescape::encode_minimal(&user.username),
+                htmlescape::encode_minimal(user.email.as_deref().unwrap_or("")),
+                user.role,
+                status,
+                actions
+            )
+        })
+        .collect();
+
+    let new_user_role_select = role_select(Role::Viewer.as_str());
+
+    let html = Html::from(format!(
+        r#"
+        <!DOCTYPE html>
+        <html lang="en">
+        <head>
+            <meta http-equiv="content-type" content="text/html; charset=utf-8">
+            <title>Admin users</title>
+        </head>
+        <body>
+            {flash_html}
+            <table>
+                <tr><th>Username</th><th>Email</th><th>Role</th><th>Status</th><th>Actions</th></tr>
+                {rows_html}
+            </table>
+
+            <h2>Invite a new admin</h2>
+            <form action="/admin/users" method="post">
+                <label>Username
+                <input type="text" placeholder="Username" name="username">
+                </label>
+                <br>
+
+                <label>Email
+                <input type="email" placeholder="Email" name="email">
+                </label>
+                <br>
+
+                <label>Role
+                {new_user_role_select}
+                </label>
+                <br>
+
+                <button type="submit">Send invitation</button>
+            </form>
+            <p><a href="/admin/dashboard">&lt;- Back</a></p>
+        </body>
+        </html>
+        "#,
+    ));
+
+    let cookie = Cookie::build(("_flash", ""))
+        .path("/")
+        .max_age(Duration::ZERO);
+    (CookieJar::new().add(cookie), html).into_response()
+}
+
+#[derive(Deserialize)]
+pub struct NewUserData {
+    username: String,
+    email: String,
+    role: String,
+}
+
+pub async fn create_user(
+    user: AuthorizedUser<Owner>,
+    audit: AuditContext,
+    State(pool): State<Arc<PgPool>>,
+    State(email_client): State<Arc<EmailClient>>,
+    State(base_url): State<Arc<str>>,
+    Form(form): Form<NewUserData>,
+) -> Response<Body> {
+    let Ok(role) = Role::try_from(form.role) else {
+        return flash_redirect("The role is not valid.");
+    };
+
+    let username = form.username.trim().to_owned();
+    if username.is_empty() {
+        return flash_redirect("The username cannot be empty.");
+    }
+
+    let Ok(email) = SubscriberEmail::parse(form.email) else {
+        return flash_redirect("The email address is not valid.");
+    };
+
+    let Ok(mut transaction) = pool.begin().await else {
+        return flash_redirect("Failed to create the user.");
+    };
+
+    let user_id = match insert_invited_user(
+        transaction.acquire().await.unwrap(),
+        &username,
+        &email,
+        role,
+    )
+    .await
+    {
+        Ok(user_id) => user_id,
+
+        Err(sqlx::Error::Database(e)) if e.kind() == sqlx::error::ErrorKind::UniqueViolation => {
+            return flash_redirect("A user with this username already exists.");
+        }
+
+        Err(e) => {
+            error!("failed to insert user: {:?}", e);
+            return flash_redirect("Failed to create the user.");
+        }
+    };
+
+    let token = generate_invitation_token();
+    if store_invitation(transaction.acquire().await.unwrap(), user_id, &token)
+        .await
+        .is_err()
+    {
+        return flash_redirect("Failed to create the user.");
+    }
+
+    let Ok(()) = transaction.commit().await else {
+        return flash_redirect("Failed to create the user.");
+    };
+
+    audit
+        .record(
+            &pool,
+            Some(user.user_id),
+            AuditAction::UserInvite,
+            Some(&username),
+        )
+        .await;
+
+    if send_invitation(&email_client, &email, &base_url, &token)
+        .await
+        .is_err()
+    {
+        return flash_redirect("The user was created, but the invitation could not be sent.");
+    }
+
+    flash_redirect(&format!("An invitation has been sent to {}.", username))
+}
+
+pub async fn disable_user(
+    user: AuthorizedUser<Owner>,
+    audit: AuditContext,
+    State(pool): State<Arc<PgPool>>,
+    State(registry): State<Arc<SessionRegistry>>,
+    Path(user_id): Path<Uuid>,
+) -> Response<Body> {
+    if user.user_id == user_id {
+        return flash_redirect("You cannot disable your own account.");
+    }
+
+    if set_disabled(&pool, user_id, true).await.is_err() {
+        return flash_redirect("Failed to disable the user.");
+    }
+
+    let target = describe_user(&pool, user_id).await;
+    audit
+        .record(
+            &pool,
+            Some(user.user_id),
+            AuditAction::UserDisable,
+            Some(&target),
+        )
+        .await;
+
+    if registry.revoke_all(user_id).await.is_err() {
+        return flash_redirect("The user was disabled, but their sessions could not be revoked.");
+    }
+
+    flash_redirect("The user has been disabled.")
+}
+
+pub async fn enable_user(
+    user: AuthorizedUser<Owner>,
+    audit: AuditContext,
+    State(pool): State<Arc<PgPool>>,
+    Path(user_id): Path<Uuid>,
+) -> Response<Body> {
+    if set_disabled(&pool, user_id, false).await.is_err() {
+        return flash_redirect("Failed to enable the user.");
+    }
+
+    let target = describe_user(&pool, user_id).await;
+    audit
+        .record(
+            &pool,
+            Some(user.user_id),
+            AuditAction::UserEnable,
+            Some(&target),
+        )
+        .await;
+
+    flash_redirect("The user has been enabled.")
+}
+
+#[derive(Deserialize)]
+pub struct ChangeRoleData {
+    role: String,
+}
+
+pub async fn change_user_role(
+    user: AuthorizedUser<Owner>,
+    audit: AuditContext,
+    State(pool): State<Arc<PgPool>>,
+    Path(user_id): Path<Uuid>,
+    Form(form): Form<ChangeRoleData>,
+) -> Response<Body> {
+    if user.user_id == user_id {
+        return flash_redirect("You cannot change your own role.");
+    }
+
+    let Ok(role) = Role::try_from(form.role) else {
+        return flash_redirect("The role is not valid.");
+    };
+
+    let updated = sqlx::query!(
+        r#"UPDATE users SET role = $2 WHERE user_id = $1"#,
+        user_id,
+        role.as_str()
+    )
+    .execute(pool.as_ref())
+    .await;
+
+    if updated.is_err() {
+        return flash_redirect("Failed to change the role.");
+    }
+
+    let target = format!(
+        "{} to {}",
+        describe_user(&pool, user_id).await,
+        role.as_str()
+    );
+    audit
+        .record(
+            &pool,
+            Some(user.user_id),
+            AuditAction::UserRoleChange,
+            Some(&target),
+        )
+        .await;
+
+    flash_redirect("The role has been changed.")
+}
+
+pub async fn delete_user(
+    user: AuthorizedUser<Owner>,
+    audit: AuditContext,
+    State(pool): State<Arc<PgPool>>,
+    State(registry): State<Arc<SessionRegistry>>,
+    Path(user_id): Path<Uuid>,
+) -> Response<Body> {
+    if user.user_id == user_id {
+        return flash_redirect("You cannot delete your own account.");
+    }
+
+    // Described before the row disappears
+    let target = describe_user(&pool, user_id).await;
+
+    let deleted = sqlx::query!(r#"DELETE FROM users WHERE user_id = $1"#, user_id)
+        .execute(pool.as_ref())
+        .await;
+
+    if deleted.is_err() {
+        return flash_redirect("Failed to delete the user.");
+    }
+
+    audit
+        .record(
+            &pool,
+            Some(user.user_id),
+            AuditAction::UserDelete,
+            Some(&target),
+        )
+        .await;
+
+    if registry.revoke_all(user_id).await.is_err() {
+        return flash_redirect("The user was deleted, but their sessions could not be revoked.");
+    }
+
+    flash_redirect("The user has been deleted.")
+}
+
+async fn describe_user(pool: &PgPool, user_id: Uuid) -> String {
+    match get_username(user_id, pool).await {
+        Some(username) => format!("{} ({})", username, user_id),
+        None => user_id.to_string(),
+    }
+}
+
+fn role_select(selected: &str) -> String {
+    let options: String = [Role::Owner, Role::Editor, Role::Viewer]
+        .iter()
+        .map(|role| {
+            let role = role.as_str();
+            let selected = if role == selected { " selected" } else { "" };
+            format!(r#"<option value="{role}"{selected}>{role}</option>"#)
+        })
+        .collect();
+
+    format!(r#"<select name="role">{options}</select>"#)
+}
+
+// The actions live under `/admin/users/:user_id/`, so the flash cookie needs an
+// explicit path to be sent back to the users page.
+fn flash_redirect(message: &str) -> Response<Body> {
+    let cookie = Cookie::build(("_flash", message.to_owned())).path("/");
+    (CookieJar::new().add(cookie), Redirect::to("/admin/users")).into_response()
+}
+
+async fn get_users(pool: &PgPool) -> Result<Vec<UserRow>, sqlx::Error> {
+    sqlx::query_as!(
+        UserRow,
+        r#"
+        SELECT
+            user_id,
+            username,
+            email,
+            role,
+            disabled,
+            password_hash IS NOT NULL AS "has_password!"
+        FROM users
+        ORDER BY username
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+}
+
+async fn set_disabled(pool: &PgPool, user_id: Uuid, disabled: bool) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"UPDATE users SET disabled = $2 WHERE user_id = $1"#,
+        user_id,
+        disabled
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+fn generate_invitation_token() -> String {
+    let mut rng = thread_rng();
+    std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(25)
+        .collect()
+}
+
+async fn insert_invited_user(
+    db: impl PgExecutor<'_>,
+    username: &str,
+    email: &SubscriberEmail,
+    role: Role,
+) -> Result<Uuid, sqlx::Error> {
+    let user_id = Uuid::new_v4();
+
+    sqlx::query!(
+        r#"
+        INSERT INTO users (user_id, username, email, role)
+        VALUES ($1, $2, $3, $4)
+        "#,
+        user_id,
+        username,
+        email.as_ref(),
+        role.as_str()
+    )
+    .execute(db)
+    .await?;
+
+    Ok(user_id)
+}
+
+async fn store_invitation(
+    db: impl PgExecutor<'_>,
+    user_id: Uuid,
+    invitation_token: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO user_invitations (token_hash, user_id, expires_at)
+        VALUES ($1, $2, $3)
+        "#,
+        hash_invitation_token(invitation_token),
+        user_id,
+        Utc::now() + chrono::Duration::hours(INVITATION_VALIDITY_HOURS)
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+async fn send_invitation(
+    client: &EmailClient,
+    email: &SubscriberEmail,
+    base_url: &str,
+    token: &str,
+) -> anyhow::Result<()> {
+    let invitation_link = format!("{}/invitation?invitation_token={}", base_url, token);
+
+    client
+        .send_email(
+            email,
+            "You have been invited to the newsletter admin",
+            &format!(
+                "You have been invited to manage our newsletter.<br>\
+                Visit <a href=\"{}\">this link</a> to choose your password.",
+                invitation_link
+            ),
+            &format!(
+                "You have been invited to manage our newsletter.\nVisit {} to choose your password.",
+                invitation_link
+            ),
+        )
+        .await
+}
@@ -0,0 +1,192 @@
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    extract::{Path, State},
+    response::{Html, IntoResponse, Redirect, Response},
+};
+use axum_extra::extract::{cookie::Cookie, CookieJar, Form};
+use serde::Deserialize;
+use sqlx::PgPool;
+use time::Duration;
+use tracing::error;
+use uuid::Uuid;
+
+use crate::{
+    audit::{AuditAction, AuditContext},
+    session_state::{AuthorizedUser, Editor},
+    welcome_sequence::{add_step, list_steps, remove_step, NewSequenceStep},
+};
+
+pub async fn welcome_sequence_page(
+    cookies: CookieJar,
+    _user: AuthorizedUser<Editor>,
+    State(pool): State<Arc<PgPool>>,
+) -> Response<Body> {
+    let steps = match list_steps(&pool).await {
+        Ok(steps) => steps,
+        Err(e) => {
+            error!("failed to load the welcome sequence: {:?}", e);
+            return Redirect::to("/admin/dashboard").into_response();
+        }
+    };
+
+    let flash_html = match cookies.get("_flash") {
+        None => "".into(),
+        Some(cookie) => {
+            format!(
+                "<p><i>{}</i></p>",
+                htmlescape::encode_minimal(cookie.value())
+            )
+        }
+    };
+
+    let steps_html: String = steps
+        .into_iter()
+        .map(|step| {
+            format!(
+                r#"
+                <tr><td>{}</td><td>{}</td><td>{}</td><td>
+                    <form action="/admin/welcome-sequence/{}/delete" method="post">
+                        <button type="submit">remove</button>
+                    </form>
+                </td></tr>
+                "#,
+                match step.delay_days {
+                    0 => "right away".into(),
+                    1 => "after 1 day".into(),
+                    days => format!("after {} days", days),
+                },
+                htmlescape::encode_minimal(&step.subject),
+                step.sent,
+                step.step_id
+            )
+        })
+        .collect();
+
+    let html = Html::from(format!(
+        r#"
+        <!DOCTYPE html>
+        <html lang="en">
+        <head>
+            <meta http-equiv="content-type" content="text/html; charset=utf-8">
+            <title>Welcome sequence</title>
+        </head>
+        <body>
+            {flash_html}
+            <p>Subscribers are sent these emails once they confirm, each the
+            given number of days after the confirmation. Unsubscribing stops
+            the sequence. An email added later is only sent to the subscribers
+            it was not yet due for.</p>
+            <table>
+                <tr><th>Sent</th><th>Subject</th><th>Subscribers</th><th></th></tr>
+                {steps_html}
+            </table>
+
+            <h2>Add an email</h2>
+            <form action="/admin/welcome-sequence" method="post">
+                <label>Days after confirming
+                <input type="number" min="0" value="0" name="delay_days">
+                </label>
+                <br>
+                <label>Subject
+                <input type="text" placeholder="Subject" name="subject">
+                </label>
+                <br>
+                <label>Content
+                <input type="text" placeholder="Text content" name="text_content">
+                </label>
+                <br>
+                <label>Html content
+                <input type="text" placeholder="Html content" name="html_content">
+                </label>
+                <br>
+                <button type="submit">Add email</button>
+            </form>
+            <p><a href="/admin/dashboard">&lt;- Back</a></p>
+        </body>
+        </html>
+        "#,
+    ));
+
+    let cookie = Cookie::build(("_flash", ""))
+        .path("/")
+        .max_age(Duration::ZERO);
+    (CookieJar::new().add(cookie), html).into_response()
+}
+
+#[derive(Deserialize)]
+pub struct NewSequenceStepData {
+    delay_days: String,
+    subject: String,
+    text_content: String,
+    html_content: String,
+}
+
+pub async fn add_sequence_step(
+    user: AuthorizedUser<Editor>,
+    audit: AuditContext,
+    State(pool): State<Arc<PgPool>>,
+    Form(form): Form<NewSequenceStepData>,
+) -> Response<Body> {
+    let step = match NewSequenceStep::parse(
+        &form.delay_days,
+        &form.subject,
+        &form.text_content,
+        &form.html_content,
+    ) {
+        Ok(step) => step,
+        Err(e) => return flash_redirect(&e),
+    };
+
+    if let Err(e) = add_step(&pool, &step).await {
+        error!("failed to add welcome sequence step: {:?}", e);
+        return flash_redirect("Failed to add the email.");
+    }
+
+    audit
+        .record(
+            &pool,
+            Some(user.user_id),
+            AuditAction::SequenceStepAdd,
+            Some(&step.subject),
+        )
+        .await;
+
+    flash_redirect("The email has been added.")
+}
+
+pub async fn remove_sequence_step(
+    user: AuthorizedUser<Editor>,
+    audit: AuditContext,
+    State(pool): State<Arc<PgPool>>,
+    Path(step_id): Path<Uuid>,
+) -> Response<Body> {
+    match remove_step(&pool, step_id).await {
+        Ok(Some(subject)) => {
+            audit
+                .record(
+                    &pool,
+                    Some(user.user_id),
+                    AuditAction::SequenceStepRemove,
+                    Some(&subject),
+                )
+                .await;
+            flash_redirect("The email has been removed.")
+        }
+        Ok(None) => flash_redirect("This email does not exist."),
+        Err(e) => {
+            error!("failed to remove welcome sequence step: {:?}", e);
+            flash_redirect("Failed to remove the email.")
+        }
+    }
+}
+
+fn flash_redirect(message: &str) -> Response<Body> {
+    let cookie = Cookie::build(("_flash", message.to_owned())).path("/");
+    (
+        CookieJar::new().add(cookie),
+        Redirect::to("/admin/welcome-sequence"),
+    )
+        .into_response()
+}
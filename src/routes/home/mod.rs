@@ -1,5 +1,101 @@
-use axum::response::{Html, IntoResponse};
+use std::sync::Arc;
 
-pub async fn home() -> impl IntoResponse {
-    Html::from(include_str!("home.html"))
+use axum::{
+    extract::{Query, State},
+    http::HeaderMap,
+    response::{Html, IntoResponse},
+};
+use serde::Deserialize;
+use sqlx::PgPool;
+use tracing::error;
+
+use crate::{
+    attribution::Attribution,
+    consent::referer,
+    signup_protection::SignupGuard,
+    subscriber_attributes::{list_attributes, AttributeDefinition, AttributeKind},
+};
+
+#[derive(Deserialize)]
+pub struct HomeQuery {
+    /// Signed tags, passed on to `/subscriptions` by the form.
+    tags: Option<String>,
+    /// Campaign parameters of the link, passed on as well.
+    #[serde(flatten)]
+    attribution: Attribution,
+}
+
+pub async fn home(
+    State(pool): State<Arc<PgPool>>,
+    State(guard): State<Arc<SignupGuard>>,
+    headers: HeaderMap,
+    Query(query): Query<HomeQuery>,
+) -> impl IntoResponse {
+    // The form still works without the optional attributes
+    let definitions = list_attributes(&pool).await.unwrap_or_else(|e| {
+        error!("failed to load the subscriber attributes: {:?}", e);
+        vec![]
+    });
+
+    Html::from(
+        include_str!("home.html")
+            .replace("{{form_token}}", &guard.issue_form_token())
+            .replace("{{pow_difficulty}}", &guard.pow_difficulty().to_string())
+            .replace("{{attribute_inputs}}", &attribute_inputs(&definitions))
+            .replace(
+                "{{attribution_inputs}}",
+                &attribution_inputs(query.attribution, &headers),
+            )
+            .replace(
+                "{{tags}}",
+                &htmlescape::encode_attribute(query.tags.as_deref().unwrap_or("")),
+            ),
+    )
+}
+
+/// Hidden fields carrying the campaign parameters of the link that led here,
+/// and the page it was on.
+fn attribution_inputs(attribution: Attribution, headers: &HeaderMap) -> String {
+    let attribution = attribution.or(Attribution {
+        referrer: referer(headers).map(str::to_owned),
+        ..Default::default()
+    });
+
+    attribution
+        .fields()
+        .into_iter()
+        .filter_map(|(field, value)| {
+            Some(format!(
+                r#"<input type="hidden" name="{}" value="{}">
+    "#,
+                field,
+                htmlescape::encode_attribute(value?)
+            ))
+        })
+        .collect()
+}
+
+fn attribute_inputs(definitions: &[AttributeDefinition]) -> String {
+    definitions
+        .iter()
+        .map(|definition| {
+            let input_type = match AttributeKind::try_from(definition.kind.as_str()) {
+                Ok(AttributeKind::Number) => r#"number" step="any"#,
+                Ok(AttributeKind::Boolean) => r#"checkbox" value="true"#,
+                Ok(AttributeKind::Date) => "date",
+                Ok(AttributeKind::Text) | Err(_) => "text",
+            };
+            format!(
+                r#"<label>{}
+      <input type="{}" name="attributes[{}]"{}>
+    </label>
+    <br>
+    "#,
+                htmlescape::encode_minimal(&definition.label),
+                input_type,
+                definition.name,
+                if definition.required { " required" } else { "" },
+            )
+        })
+        .collect()
 }
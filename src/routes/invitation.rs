@@ -0,0 +1,160 @@
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    extract::{Query, State},
+    response::{Html, IntoResponse, Redirect, Response},
+    Form,
+};
+use axum_extra::extract::{cookie::Cookie, CookieJar};
+use secrecy::Secret;
+use serde::Deserialize;
+use sqlx::{PgExecutor, PgPool};
+use time::Duration;
+use tracing::error;
+use uuid::Uuid;
+
+use crate::{authentication, routes::admin::validate_new_password, signing::sha256_hex};
+
+#[derive(Deserialize)]
+pub struct InvitationParameters {
+    invitation_token: String,
+}
+
+pub async fn invitation_form(
+    cookies: CookieJar,
+    Query(params): Query<InvitationParameters>,
+) -> Response<Body> {
+    let error_html = match cookies.get("_flash") {
+        None => "".into(),
+        Some(cookie) => {
+            format!(
+                "<p><i>{}</i></p>",
+                htmlescape::encode_minimal(cookie.value())
+            )
+        }
+    };
+
+    let token = htmlescape::encode_attribute(&params.invitation_token);
+
+    let html = Html::from(format!(
+        r#"
+        <!DOCTYPE html>
+        <html lang="en">
+        <head>
+            <meta http-equiv="content-type" content="text/html; charset=utf-8">
+            <title>Accept invitation</title>
+        </head>
+        <body>
+            {error_html}
+            <form action="/invitation" method="post">
+                <input type="hidden" name="invitation_token" value="{token}">
+
+                <label>Password
+                <input type="password" placeholder="Choose a password" name="new_password">
+                </label>
+                <br>
+
+                <label>Confirm password
+                <input type="password" placeholder="Type the password again" name="new_password_check">
+                </label>
+                <br>
+
+                <button type="submit">Set password</button>
+            </form>
+        </body>
+        </html>
+        "#,
+    ));
+
+    let cookie = Cookie::build(("_flash", "")).max_age(Duration::ZERO);
+    (CookieJar::new().add(cookie), html).into_response()
+}
+
+#[derive(Deserialize)]
+pub struct AcceptInvitation {
+    invitation_token: String,
+    new_password: Secret<String>,
+    new_password_check: Secret<String>,
+}
+
+pub async fn accept_invitation(
+    State(pool): State<Arc<PgPool>>,
+    Form(form): Form<AcceptInvitation>,
+) -> Response<Body> {
+    let retry_url = format!(
+        "/invitation?invitation_token={}",
+        urlencoding::encode(&form.invitation_token)
+    );
+
+    if let Err(message) = validate_new_password(&form.new_password, &form.new_password_check) {
+        let cookie = Cookie::new("_flash", message);
+        return (CookieJar::new().add(cookie), Redirect::to(&retry_url)).into_response();
+    }
+
+    // Outside of the transaction below, which is rolled back when the
+    // invitation turns out to have expired
+    if let Err(e) = delete_expired_invitations(&pool).await {
+        error!("failed to remove expired invitations: {:?}", e);
+    }
+
+    // The invitation is only used up once the password is stored, so that
+    // it can be tried again if that fails
+    let Ok(mut transaction) = pool.begin().await else {
+        return failed_redirect();
+    };
+
+    let Ok(Some(user_id)) = consume_invitation(&mut *transaction, &form.invitation_token).await
+    else {
+        let cookie = Cookie::new("_flash", "This invitation is invalid or has expired.");
+        return (CookieJar::new().add(cookie), Redirect::to("/login")).into_response();
+    };
+
+    if authentication::change_password(user_id, form.new_password, &mut *transaction)
+        .await
+        .is_none()
+        || transaction.commit().await.is_err()
+    {
+        return failed_redirect();
+    }
+
+    let cookie = Cookie::new("_flash", "Your password has been set, you can now log in.");
+    (CookieJar::new().add(cookie), Redirect::to("/login")).into_response()
+}
+
+fn failed_redirect() -> Response<Body> {
+    let cookie = Cookie::new("_flash", "Failed to set your password.");
+    (CookieJar::new().add(cookie), Redirect::to("/login")).into_response()
+}
+
+/// Tokens are stored hashed, since they grant access to an account.
+pub fn hash_invitation_token(token: &str) -> String {
+    sha256_hex(token)
+}
+
+/// Invitations are single use: the token is deleted as soon as it is redeemed.
+async fn consume_invitation(
+    db: impl PgExecutor<'_>,
+    token: &str,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        DELETE FROM user_invitations
+        WHERE token_hash = $1 AND expires_at > now()
+        RETURNING user_id
+        "#,
+        hash_invitation_token(token),
+    )
+    .fetch_optional(db)
+    .await?;
+
+    Ok(result.map(|r| r.user_id))
+}
+
+async fn delete_expired_invitations(pool: &PgPool) -> Result<(), sqlx::Error> {
+    sqlx::query!("DELETE FROM user_invitations WHERE expires_at <= now()")
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
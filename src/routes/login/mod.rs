@@ -10,12 +10,20 @@ use axum_extra::extract::{cookie::Cookie, CookieJar};
 use secrecy::Secret;
 use sqlx::PgPool;
 use time::Duration;
+use tracing::error;
 
 use crate::{
+    audit::{AuditAction, AuditContext},
     authentication::{validate_credentials, Credentials},
-    session_state::TypedSession,
+    client_info::ClientInfo,
+    login_attempts::{record_attempt, LoginOutcome, LoginThrottle},
+    session_state::{SessionRegistry, TypedSession},
+    two_factor,
 };
 
+/// Wrong codes accepted for one password login.
+const MAX_TWO_FACTOR_FAILURES: u32 = 5;
+
 pub async fn login_get(cookies: CookieJar) -> impl IntoResponse {
     let error_html = match cookies.get("_flash") {
         None => "".into(),
@@ -48,6 +56,7 @@ pub async fn login_get(cookies: CookieJar) -> impl IntoResponse {
 
                 <button type="submit">Login</button>
             </form>
+            <p><a href="/password-reset">Forgot your password?</a></p>
         </body>
         </html>
     "#
@@ -65,25 +74,208 @@ pub struct FormData {
 
 pub async fn login_post(
     session: TypedSession,
+    audit: AuditContext,
     State(pool): State<Arc<PgPool>>,
+    State(registry): State<Arc<SessionRegistry>>,
+    State(throttle): State<Arc<LoginThrottle>>,
     Form(form): Form<FormData>,
 ) -> Response<Body> {
+    let username = form.username;
+    let client = audit.client();
+
+    // Redis being unavailable should not lock everybody out
+    let blocked = throttle
+        .is_blocked(&username, client.ip)
+        .await
+        .unwrap_or_else(|e| {
+            error!("failed to check login throttling: {:?}", e);
+            false
+        });
+
+    if blocked {
+        record(&pool, &username, client, LoginOutcome::Locked).await;
+        let cookie = Cookie::new(
+            "_flash",
+            "Too many failed attempts, please try again later.",
+        );
+        return (CookieJar::new().add(cookie), Redirect::to("/login")).into_response();
+    }
+
     let credentials = Credentials {
-        username: form.username,
+        username: username.clone(),
         password: form.password,
     };
 
     match validate_credentials(credentials, &pool).await {
         Some(user_id) => {
+            record(&pool, &username, client, LoginOutcome::Success).await;
+
             session.renew().await.unwrap();
+
+            let two_factor_enabled = two_factor::get_status(user_id, &pool)
+                .await
+                .map(|status| status.enabled)
+                .unwrap_or(true);
+
+            // The failures are only forgotten once the second factor passes,
+            // it is throttled along with the password
+            if two_factor_enabled {
+                session
+                    .insert_pending_user_id(user_id, &username)
+                    .await
+                    .unwrap();
+                return Redirect::to("/login/two-factor").into_response();
+            }
+
+            if let Err(e) = throttle.record_success(&username).await {
+                error!("failed to reset login throttling: {:?}", e);
+            }
+
             session.insert_user_id(user_id).await.unwrap();
+            registry.register(user_id, &session).await.unwrap();
+            audit
+                .record(&pool, Some(user_id), AuditAction::Login, None)
+                .await;
 
             Redirect::to("/admin/dashboard").into_response()
         }
 
         None => {
+            record(&pool, &username, client, LoginOutcome::Failure).await;
+            audit
+                .record(&pool, None, AuditAction::LoginFailed, Some(&username))
+                .await;
+            if let Err(e) = throttle.record_failure(&username, client.ip).await {
+                error!("failed to record login failure: {:?}", e);
+            }
+
             let cookie = Cookie::new("_flash", "Authentication failed");
             (CookieJar::new().add(cookie), Redirect::to("/login")).into_response()
         }
     }
 }
+
+async fn record(pool: &PgPool, username: &str, client: &ClientInfo, outcome: LoginOutcome) {
+    if let Err(e) = record_attempt(pool, username, client, outcome).await {
+        error!("failed to record login attempt: {:?}", e);
+    }
+}
+
+pub async fn login_two_factor_get(cookies: CookieJar, session: TypedSession) -> Response<Body> {
+    if session.get_pending_user_id().await.unwrap().is_none() {
+        return Redirect::to("/login").into_response();
+    }
+
+    let error_html = match cookies.get("_flash") {
+        None => "".into(),
+        Some(cookie) => {
+            format!(
+                "<p><i>{}</i></p>",
+                htmlescape::encode_minimal(cookie.value())
+            )
+        }
+    };
+
+    let html = Html::from(format!(
+        r#"
+        <!DOCTYPE html>
+        <html lang="en">
+        <head>
+            <meta http-equiv="content-type" content="text/html; charset=utf-8">
+            <title>Two-factor authentication</title>
+        </head>
+        <body>
+            {error_html}
+            <form action="/login/two-factor" method="post">
+                <label>Authentication code
+                <input type="text" placeholder="Code from your app or a recovery code" name="code" autocomplete="one-time-code">
+                </label>
+
+                <button type="submit">Verify</button>
+            </form>
+        </body>
+        </html>
+    "#
+    ));
+
+    let cookie = Cookie::build(("_flash", "")).max_age(Duration::ZERO);
+    (CookieJar::new().add(cookie), html).into_response()
+}
+
+#[derive(serde::Deserialize)]
+pub struct TwoFactorFormData {
+    code: String,
+}
+
+pub async fn login_two_factor_post(
+    session: TypedSession,
+    audit: AuditContext,
+    State(pool): State<Arc<PgPool>>,
+    State(registry): State<Arc<SessionRegistry>>,
+    State(throttle): State<Arc<LoginThrottle>>,
+    Form(form): Form<TwoFactorFormData>,
+) -> Response<Body> {
+    let (Some(user_id), Some(username)) = (
+        session.get_pending_user_id().await.unwrap(),
+        session.get_pending_username().await.unwrap(),
+    ) else {
+        return Redirect::to("/login").into_response();
+    };
+    let client = audit.client();
+
+    let blocked = throttle
+        .is_blocked(&username, client.ip)
+        .await
+        .unwrap_or_else(|e| {
+            error!("failed to check login throttling: {:?}", e);
+            false
+        });
+
+    if blocked {
+        record(&pool, &username, client, LoginOutcome::Locked).await;
+        session.remove_pending_user_id().await.unwrap();
+        let cookie = Cookie::new(
+            "_flash",
+            "Too many failed attempts, please try again later.",
+        );
+        return (CookieJar::new().add(cookie), Redirect::to("/login")).into_response();
+    }
+
+    if two_factor::verify_second_factor(user_id, &form.code, &pool)
+        .await
+        .is_none()
+    {
+        record(&pool, &username, client, LoginOutcome::Failure).await;
+        if let Err(e) = throttle.record_failure(&username, client.ip).await {
+            error!("failed to record login failure: {:?}", e);
+        }
+
+        // Past a few wrong codes the password has to be entered again
+        if session.record_pending_failure().await.unwrap() >= MAX_TWO_FACTOR_FAILURES {
+            session.remove_pending_user_id().await.unwrap();
+            let cookie = Cookie::new("_flash", "Authentication failed");
+            return (CookieJar::new().add(cookie), Redirect::to("/login")).into_response();
+        }
+
+        let cookie = Cookie::new("_flash", "Authentication failed");
+        return (
+            CookieJar::new().add(cookie),
+            Redirect::to("/login/two-factor"),
+        )
+            .into_response();
+    }
+
+    if let Err(e) = throttle.record_success(&username).await {
+        error!("failed to reset login throttling: {:?}", e);
+    }
+
+    session.renew().await.unwrap();
+    session.remove_pending_user_id().await.unwrap();
+    session.insert_user_id(user_id).await.unwrap();
+    registry.register(user_id, &session).await.unwrap();
+    audit
+        .record(&pool, Some(user_id), AuditAction::Login, None)
+        .await;
+
+    Redirect::to("/admin/dashboard").into_response()
+}
@@ -1,13 +1,23 @@
 mod admin;
 mod health_check;
 mod home;
+mod invitation;
 mod login;
+mod password_reset;
+mod subscriber_data;
 mod subscription_confirm;
+mod subscription_outcome;
 mod subscriptions;
+mod widget;
 
 pub use admin::*;
 pub use health_check::*;
 pub use home::*;
+pub use invitation::*;
 pub use login::*;
+pub use password_reset::*;
+pub use subscriber_data::*;
 pub use subscription_confirm::*;
+pub use subscription_outcome::*;
 pub use subscriptions::*;
+pub use widget::*;
@@ -0,0 +1,362 @@
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    extract::{Query, State},
+    response::{Html, IntoResponse, Redirect, Response},
+    Form,
+};
+use axum_extra::extract::{cookie::Cookie, CookieJar};
+use chrono::Utc;
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use secrecy::Secret;
+use serde::Deserialize;
+use sqlx::{PgPool, Postgres, Transaction};
+use time::Duration;
+use tracing::error;
+use uuid::Uuid;
+
+use crate::{
+    audit::{AuditAction, AuditContext},
+    authentication,
+    domain::SubscriberEmail,
+    email_client::EmailClient,
+    login_attempts::LoginThrottle,
+    routes::admin::validate_new_password,
+    session_state::SessionRegistry,
+    signing::sha256_hex,
+};
+
+const RESET_TOKEN_VALIDITY_MINUTES: i64 = 60;
+
+pub async fn password_reset_form(cookies: CookieJar) -> Response<Body> {
+    let error_html = match cookies.get("_flash") {
+        None => "".into(),
+        Some(cookie) => {
+            format!(
+                "<p><i>{}</i></p>",
+                htmlescape::encode_minimal(cookie.value())
+            )
+        }
+    };
+
+    let html = Html::from(format!(
+        r#"
+        <!DOCTYPE html>
+        <html lang="en">
+        <head>
+            <meta http-equiv="content-type" content="text/html; charset=utf-8">
+            <title>Forgot password</title>
+        </head>
+        <body>
+            {error_html}
+            <form action="/password-reset" method="post">
+                <label>Username
+                <input type="text" placeholder="Enter Username" name="username">
+                </label>
+
+                <button type="submit">Send reset link</button>
+            </form>
+            <p><a href="/login">&lt;- Back to login</a></p>
+        </body>
+        </html>
+        "#,
+    ));
+
+    let cookie = Cookie::build(("_flash", "")).max_age(Duration::ZERO);
+    (CookieJar::new().add(cookie), html).into_response()
+}
+
+#[derive(Deserialize)]
+pub struct PasswordResetRequest {
+    username: String,
+}
+
+pub async fn request_password_reset(
+    audit: AuditContext,
+    State(pool): State<Arc<PgPool>>,
+    State(email_client): State<Arc<EmailClient>>,
+    State(base_url): State<Arc<str>>,
+    State(throttle): State<Arc<LoginThrottle>>,
+    Form(form): Form<PasswordResetRequest>,
+) -> Response<Body> {
+    // Whatever happens, the answer is the same so that it cannot be used to
+    // find out which usernames exist.
+    let cookie = Cookie::new(
+        "_flash",
+        "If the account exists, a password reset link has been sent to its email address.",
+    );
+    let response = (CookieJar::new().add(cookie), Redirect::to("/login")).into_response();
+
+    // Requests are counted whether the account exists or not, and refused
+    // silently once there were too many
+    let ip = audit.client().ip;
+    let blocked = throttle
+        .is_reset_blocked(&form.username, ip)
+        .await
+        .unwrap_or_else(|e| {
+            error!("failed to check password reset throttling: {:?}", e);
+            false
+        });
+    if blocked {
+        return response;
+    }
+    if let Err(e) = throttle.record_reset_request(&form.username, ip).await {
+        error!("failed to record password reset request: {:?}", e);
+    }
+
+    let Ok(Some((user_id, email))) = get_user_email(&pool, &form.username).await else {
+        return response;
+    };
+
+    let token = generate_reset_token();
+    if let Err(e) = store_reset_token(&pool, user_id, &token).await {
+        error!("failed to store password reset token: {:?}", e);
+        return response;
+    }
+
+    // Sending happens in the background so that the response time does not
+    // depend on whether the account exists either.
+    tokio::spawn(async move {
+        if let Err(e) = send_reset_email(&email_client, &email, &base_url, &token).await {
+            error!("failed to send password reset email: {:?}", e);
+        }
+    });
+
+    response
+}
+
+#[derive(Deserialize)]
+pub struct ResetParameters {
+    reset_token: String,
+}
+
+pub async fn password_reset_confirm_form(
+    cookies: CookieJar,
+    Query(params): Query<ResetParameters>,
+) -> Response<Body> {
+    let error_html = match cookies.get("_flash") {
+        None => "".into(),
+        Some(cookie) => {
+            format!(
+                "<p><i>{}</i></p>",
+                htmlescape::encode_minimal(cookie.value())
+            )
+        }
+    };
+
+    let token = htmlescape::encode_attribute(&params.reset_token);
+
+    let html = Html::from(format!(
+        r#"
+        <!DOCTYPE html>
+        <html lang="en">
+        <head>
+            <meta http-equiv="content-type" content="text/html; charset=utf-8">
+            <title>Reset password</title>
+        </head>
+        <body>
+            {error_html}
+            <form action="/password-reset/confirm" method="post">
+                <input type="hidden" name="reset_token" value="{token}">
+
+                <label>New password
+                <input type="password" placeholder="Enter new password" name="new_password">
+                </label>
+                <br>
+
+                <label>Confirm new password
+                <input type="password" placeholder="Type the new password again" name="new_password_check">
+                </label>
+                <br>
+
+                <button type="submit">Reset password</button>
+            </form>
+        </body>
+        </html>
+        "#,
+    ));
+
+    let cookie = Cookie::build(("_flash", "")).max_age(Duration::ZERO);
+    (CookieJar::new().add(cookie), html).into_response()
+}
+
+#[derive(Deserialize)]
+pub struct ResetPassword {
+    reset_token: String,
+    new_password: Secret<String>,
+    new_password_check: Secret<String>,
+}
+
+pub async fn reset_password(
+    audit: AuditContext,
+    State(pool): State<Arc<PgPool>>,
+    State(registry): State<Arc<SessionRegistry>>,
+    Form(form): Form<ResetPassword>,
+) -> Response<Body> {
+    if let Err(message) = validate_new_password(&form.new_password, &form.new_password_check) {
+        let retry_url = format!(
+            "/password-reset/confirm?reset_token={}",
+            urlencoding::encode(&form.reset_token)
+        );
+        let cookie = Cookie::new("_flash", message);
+        return (CookieJar::new().add(cookie), Redirect::to(&retry_url)).into_response();
+    }
+
+    // Outside of the transaction below, which is rolled back when the token
+    // turns out to have expired
+    if let Err(e) = delete_expired_reset_tokens(&pool).await {
+        error!("failed to remove expired password reset tokens: {:?}", e);
+    }
+
+    // The token is only used up once the password is stored, so that the
+    // link can be tried again if that fails
+    let Ok(mut transaction) = pool.begin().await else {
+        return login_redirect("Failed to reset your password.");
+    };
+
+    let Ok(Some(user_id)) = consume_reset_token(&mut transaction, &form.reset_token).await else {
+        return login_redirect("This password reset link is invalid or has expired.");
+    };
+
+    if authentication::change_password(user_id, form.new_password, &mut *transaction)
+        .await
+        .is_none()
+        || transaction.commit().await.is_err()
+    {
+        return login_redirect("Failed to reset your password.");
+    }
+
+    audit
+        .record(&pool, Some(user_id), AuditAction::PasswordReset, None)
+        .await;
+
+    // Whoever knew the old password must not stay logged in
+    if let Err(e) = registry.revoke_all(user_id).await {
+        error!("failed to revoke sessions after a password reset: {:?}", e);
+    }
+
+    login_redirect("Your password has been reset, you can now log in.")
+}
+
+// The form is posted under `/password-reset/`, so the flash cookie needs an
+// explicit path to reach the login page.
+fn login_redirect(message: &str) -> Response<Body> {
+    let cookie = Cookie::build(("_flash", message.to_owned())).path("/");
+    (CookieJar::new().add(cookie), Redirect::to("/login")).into_response()
+}
+
+fn generate_reset_token() -> String {
+    let mut rng = thread_rng();
+    std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(32)
+        .collect()
+}
+
+fn hash_reset_token(token: &str) -> String {
+    sha256_hex(token)
+}
+
+async fn get_user_email(
+    pool: &PgPool,
+    username: &str,
+) -> Result<Option<(Uuid, SubscriberEmail)>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT user_id, email
+        FROM users
+        WHERE username = $1 AND NOT disabled
+        "#,
+        username,
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.and_then(|row| {
+        let email = SubscriberEmail::parse(row.email?).ok()?;
+        Some((row.user_id, email))
+    }))
+}
+
+async fn store_reset_token(pool: &PgPool, user_id: Uuid, token: &str) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO password_reset_tokens (token_hash, user_id, expires_at)
+        VALUES ($1, $2, $3)
+        "#,
+        hash_reset_token(token),
+        user_id,
+        Utc::now() + chrono::Duration::minutes(RESET_TOKEN_VALIDITY_MINUTES)
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Redeems a reset token. Every outstanding token of the same user is dropped
+/// along with it, so older links stop working too.
+async fn consume_reset_token(
+    transaction: &mut Transaction<'_, Postgres>,
+    token: &str,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let Some(row) = sqlx::query!(
+        r#"
+        DELETE FROM password_reset_tokens
+        WHERE token_hash = $1 AND expires_at > now()
+        RETURNING user_id
+        "#,
+        hash_reset_token(token),
+    )
+    .fetch_optional(&mut **transaction)
+    .await?
+    else {
+        return Ok(None);
+    };
+
+    sqlx::query!(
+        r#"DELETE FROM password_reset_tokens WHERE user_id = $1"#,
+        row.user_id
+    )
+    .execute(&mut **transaction)
+    .await?;
+
+    Ok(Some(row.user_id))
+}
+
+async fn delete_expired_reset_tokens(pool: &PgPool) -> Result<(), sqlx::Error> {
+    sqlx::query!("DELETE FROM password_reset_tokens WHERE expires_at <= now()")
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+async fn send_reset_email(
+    client: &EmailClient,
+    email: &SubscriberEmail,
+    base_url: &str,
+    token: &str,
+) -> anyhow::Result<()> {
+    let reset_link = format!("{}/password-reset/confirm?reset_token={}", base_url, token);
+
+    client
+        .send_email(
+            email,
+            "Reset your password",
+            &format!(
+                "Someone asked to reset the password of your newsletter admin account.<br>\
+                Visit <a href=\"{}\">this link</a> within {} minutes to choose a new one. \
+                If it was not you, you can ignore this email.",
+                reset_link, RESET_TOKEN_VALIDITY_MINUTES
+            ),
+            &format!(
+                "Someone asked to reset the password of your newsletter admin account.\n\
+                Visit {} within {} minutes to choose a new one. \
+                If it was not you, you can ignore this email.",
+                reset_link, RESET_TOKEN_VALIDITY_MINUTES
+            ),
+        )
+        .await
+}
@@ -0,0 +1,280 @@
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    extract::{Query, State},
+    http::{header, StatusCode},
+    response::{Html, IntoResponse, Response},
+    Form,
+};
+use secrecy::Secret;
+use serde::Deserialize;
+use sqlx::PgPool;
+use tracing::error;
+
+use crate::{
+    domain::SubscriberEmail,
+    email_client::EmailClient,
+    subscriber_data::{
+        collect_subscriber_data, erase_subscriber, signed_query, verify_signature,
+        DataRequestAction, LINK_VALIDITY_HOURS,
+    },
+};
+
+pub async fn subscriber_data_form() -> Html<&'static str> {
+    Html::from(
+        r#"
+        <!DOCTYPE html>
+        <html lang="en">
+        <head>
+            <meta http-equiv="content-type" content="text/html; charset=utf-8">
+            <title>Your data</title>
+        </head>
+        <body>
+            <p>Enter the address you subscribed with. We will email it a link
+            to download or erase the data we hold about you.</p>
+            <form action="/subscriptions/data" method="post">
+                <input type="email" placeholder="Email address" name="email">
+                <br>
+                <label><input type="radio" name="action" value="export" checked> Send me my data</label>
+                <br>
+                <label><input type="radio" name="action" value="erase"> Erase my data</label>
+                <br>
+                <button type="submit">Send the link</button>
+            </form>
+        </body>
+        </html>
+        "#,
+    )
+}
+
+#[derive(Deserialize)]
+pub struct DataRequestForm {
+    email: String,
+    action: String,
+}
+
+pub async fn request_subscriber_data(
+    State(pool): State<Arc<PgPool>>,
+    State(email_client): State<Arc<EmailClient>>,
+    State(base_url): State<Arc<str>>,
+    State(secret): State<Arc<Secret<String>>>,
+    Form(form): Form<DataRequestForm>,
+) -> Response<Body> {
+    let Ok(action) = DataRequestAction::try_from(form.action.as_str()) else {
+        return StatusCode::BAD_REQUEST.into_response();
+    };
+
+    // Whatever happens, the answer is the same so that it cannot be used to
+    // find out who is subscribed.
+    let response = Html::from(
+        r#"
+        <!DOCTYPE html>
+        <html lang="en">
+        <head>
+            <meta http-equiv="content-type" content="text/html; charset=utf-8">
+            <title>Your data</title>
+        </head>
+        <body>
+            <p>If this address is subscribed, we have emailed it a link.</p>
+        </body>
+        </html>
+        "#,
+    )
+    .into_response();
+
+    let Ok(email) = SubscriberEmail::parse(form.email) else {
+        return response;
+    };
+
+    match is_subscribed(&pool, email.as_ref()).await {
+        Ok(true) => {}
+        Ok(false) => return response,
+        Err(e) => {
+            error!("failed to look up subscriber: {:?}", e);
+            return response;
+        }
+    }
+
+    let link = format!(
+        "{}/subscriptions/data/{}?{}",
+        base_url,
+        action.as_str(),
+        signed_query(&secret, action, email.as_ref())
+    );
+
+    tokio::spawn(async move {
+        if let Err(e) = send_data_request_email(&email_client, &email, action, &link).await {
+            error!("failed to send data request email: {:?}", e);
+        }
+    });
+
+    response
+}
+
+#[derive(Deserialize)]
+pub struct SignedParameters {
+    email: String,
+    expires: i64,
+    signature: String,
+}
+
+impl SignedParameters {
+    fn verify(&self, secret: &Secret<String>, action: DataRequestAction) -> bool {
+        verify_signature(secret, action, &self.email, self.expires, &self.signature)
+    }
+}
+
+pub async fn export_subscriber_data(
+    State(pool): State<Arc<PgPool>>,
+    State(secret): State<Arc<Secret<String>>>,
+    Query(params): Query<SignedParameters>,
+) -> Response<Body> {
+    if !params.verify(&secret, DataRequestAction::Export) {
+        return invalid_link();
+    }
+
+    match collect_subscriber_data(&pool, &params.email).await {
+        Ok(Some(data)) => data_download(data),
+        Ok(None) => (StatusCode::NOT_FOUND, "We hold no data about this address.").into_response(),
+        Err(e) => {
+            error!("failed to collect subscriber data: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Following the link only asks for confirmation, so that mail scanners
+/// opening links cannot erase anything.
+pub async fn erase_subscriber_data_form(
+    State(secret): State<Arc<Secret<String>>>,
+    Query(params): Query<SignedParameters>,
+) -> Response<Body> {
+    if !params.verify(&secret, DataRequestAction::Erase) {
+        return invalid_link();
+    }
+
+    Html::from(format!(
+        r#"
+        <!DOCTYPE html>
+        <html lang="en">
+        <head>
+            <meta http-equiv="content-type" content="text/html; charset=utf-8">
+            <title>Erase your data</title>
+        </head>
+        <body>
+            <p>This permanently erases everything we hold about {email_html},
+            including the subscription itself.</p>
+            <form action="/subscriptions/data/erase" method="post">
+                <input type="hidden" name="email" value="{email}">
+                <input type="hidden" name="expires" value="{expires}">
+                <input type="hidden" name="signature" value="{signature}">
+                <button type="submit">Erase my data</button>
+            </form>
+        </body>
+        </html>
+        "#,
+        email_html = htmlescape::encode_minimal(&params.email),
+        email = htmlescape::encode_attribute(&params.email),
+        expires = params.expires,
+        signature = htmlescape::encode_attribute(&params.signature),
+    ))
+    .into_response()
+}
+
+pub async fn erase_subscriber_data(
+    State(pool): State<Arc<PgPool>>,
+    State(secret): State<Arc<Secret<String>>>,
+    Form(params): Form<SignedParameters>,
+) -> Response<Body> {
+    if !params.verify(&secret, DataRequestAction::Erase) {
+        return invalid_link();
+    }
+
+    if let Err(e) = erase_subscriber(&pool, &params.email).await {
+        error!("failed to erase subscriber: {:?}", e);
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+
+    Html::from(
+        r#"
+        <!DOCTYPE html>
+        <html lang="en">
+        <head>
+            <meta http-equiv="content-type" content="text/html; charset=utf-8">
+            <title>Erase your data</title>
+        </head>
+        <body>
+            <p>Your data has been erased.</p>
+        </body>
+        </html>
+        "#,
+    )
+    .into_response()
+}
+
+/// The JSON document returned for data access requests, from here and from
+/// the admin area.
+pub fn data_download(data: serde_json::Value) -> Response<Body> {
+    (
+        [
+            (header::CONTENT_TYPE, "application/json"),
+            (
+                header::CONTENT_DISPOSITION,
+                r#"attachment; filename="subscriber-data.json""#,
+            ),
+        ],
+        serde_json::to_string_pretty(&data).unwrap_or_default(),
+    )
+        .into_response()
+}
+
+fn invalid_link() -> Response<Body> {
+    (
+        StatusCode::FORBIDDEN,
+        "This link is invalid or has expired, please ask for a new one.",
+    )
+        .into_response()
+}
+
+async fn is_subscribed(pool: &PgPool, email: &str) -> Result<bool, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"SELECT EXISTS(SELECT 1 FROM subscriptions WHERE lower(email) = lower($1)) AS "subscribed!""#,
+        email
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(row.subscribed)
+}
+
+async fn send_data_request_email(
+    client: &EmailClient,
+    email: &SubscriberEmail,
+    action: DataRequestAction,
+    link: &str,
+) -> anyhow::Result<()> {
+    let what = match action {
+        DataRequestAction::Export => "download the data we hold about you",
+        DataRequestAction::Erase => "erase the data we hold about you",
+    };
+
+    client
+        .send_email(
+            email,
+            "Your data request",
+            &format!(
+                "Someone asked to {} on our newsletter.<br>\
+                Visit <a href=\"{}\">this link</a> within {} hours to do so. \
+                If it was not you, you can ignore this email.",
+                what, link, LINK_VALIDITY_HOURS
+            ),
+            &format!(
+                "Someone asked to {} on our newsletter.\n\
+                Visit {} within {} hours to do so. \
+                If it was not you, you can ignore this email.",
+                what, link, LINK_VALIDITY_HOURS
+            ),
+        )
+        .await
+}
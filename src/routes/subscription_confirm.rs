@@ -1,11 +1,24 @@
 use std::sync::Arc;
 
 use axum::{
-    extract::{Query, State},
-    http::StatusCode,
+    body::Body,
+    extract::{rejection::QueryRejection, Query, State},
+    http::HeaderMap,
+    response::Response,
 };
 use serde::Deserialize;
 use sqlx::{types::Uuid, PgPool};
+use tracing::error;
+
+use crate::{
+    client_info::ClientInfo,
+    configuration::SubscriptionPagesSettings,
+    consent::{record_consent, referer, ConsentKind},
+    domain::SubscriptionStatus,
+    routes::SubscriptionOutcome,
+    subscriber_events::{record_event, SubscriberEvent},
+    welcome_sequence::enroll,
+};
 
 #[derive(Deserialize)]
 pub struct ConfirmParameters {
@@ -14,34 +27,99 @@ pub struct ConfirmParameters {
 
 pub async fn confirm(
     State(pool): State<Arc<PgPool>>,
-    params: Query<ConfirmParameters>,
-) -> StatusCode {
-    let id = match get_subscriber_id_from_token(&pool, &params.subscription_token).await {
+    State(pages): State<Arc<SubscriptionPagesSettings>>,
+    client: ClientInfo,
+    headers: HeaderMap,
+    params: Result<Query<ConfirmParameters>, QueryRejection>,
+) -> Response<Body> {
+    let outcome = match params {
+        Ok(params) => confirm_token(&pool, &client, &headers, &params.subscription_token).await,
+        Err(_) => SubscriptionOutcome::missing_token(),
+    };
+
+    outcome.respond(&pages, &headers)
+}
+
+async fn confirm_token(
+    pool: &PgPool,
+    client: &ClientInfo,
+    headers: &HeaderMap,
+    subscription_token: &str,
+) -> SubscriptionOutcome {
+    let id = match get_subscriber_id_from_token(pool, subscription_token).await {
         Ok(id) => id,
-        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR,
+        Err(_) => return SubscriptionOutcome::internal_error(),
     };
 
-    match id {
-        None => StatusCode::UNAUTHORIZED,
-        Some(subscriber_id) => {
-            let Ok(()) = confirm_subscriber(&pool, subscriber_id).await else {
-                return StatusCode::INTERNAL_SERVER_ERROR;
-            };
+    let Some(subscriber_id) = id else {
+        return SubscriptionOutcome::invalid_token();
+    };
 
-            StatusCode::OK
+    match confirm_subscriber(pool, subscriber_id, client, referer(headers)).await {
+        Ok(Some(SubscriptionStatus::PendingConfirmation)) => SubscriptionOutcome::confirmed(),
+        Ok(Some(SubscriptionStatus::Confirmed)) => SubscriptionOutcome::already_confirmed(),
+        // Unsubscribing voids the links sent earlier
+        Ok(_) => SubscriptionOutcome::invalid_token(),
+        Err(e) => {
+            error!("failed to confirm subscriber: {:?}", e);
+            SubscriptionOutcome::internal_error()
         }
     }
 }
 
-async fn confirm_subscriber(pool: &PgPool, subscriber_id: Uuid) -> Result<(), sqlx::Error> {
-    sqlx::query!(
-        r#"UPDATE subscriptions SET status = 'confirmed' WHERE id = $1"#,
+/// Returns the status the subscriber had before, if they still exist.
+/// Clicking the link again, or after having been unsubscribed, changes nothing.
+async fn confirm_subscriber(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+    client: &ClientInfo,
+    source: Option<&str>,
+) -> Result<Option<SubscriptionStatus>, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+
+    let Some(row) = sqlx::query!(
+        "SELECT status FROM subscriptions WHERE id = $1 FOR UPDATE",
         subscriber_id,
     )
-    .execute(pool)
-    .await?;
+    .fetch_optional(&mut *transaction)
+    .await?
+    else {
+        return Ok(None);
+    };
+    let status = SubscriptionStatus::try_from(row.status.as_str())
+        .map_err(|e| sqlx::Error::Decode(e.into()))?;
+
+    if status == SubscriptionStatus::PendingConfirmation {
+        sqlx::query!(
+            "UPDATE subscriptions SET status = 'confirmed' WHERE id = $1",
+            subscriber_id,
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        record_event(
+            &mut *transaction,
+            subscriber_id,
+            SubscriberEvent::Confirmed,
+            None,
+        )
+        .await?;
+
+        record_consent(
+            &mut *transaction,
+            subscriber_id,
+            ConsentKind::Confirmation,
+            client,
+            source,
+        )
+        .await?;
+
+        enroll(&mut *transaction, subscriber_id).await?;
+    }
+
+    transaction.commit().await?;
 
-    Ok(())
+    Ok(Some(status))
 }
 
 async fn get_subscriber_id_from_token(
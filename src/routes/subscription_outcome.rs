@@ -0,0 +1,245 @@
+use std::collections::BTreeMap;
+
+use axum::{
+    body::Body,
+    http::{
+        header::{ACCEPT, CONTENT_TYPE},
+        HeaderMap, StatusCode,
+    },
+    response::{Html, IntoResponse, Redirect, Response},
+    Json,
+};
+use serde_json::json;
+
+use crate::{configuration::SubscriptionPagesSettings, domain::FieldError};
+
+/// How a subscription or confirmation request ended. Browsers get a page, or
+/// a redirect to the site's own pages when they are configured, and clients
+/// asking for JSON get a JSON document.
+pub struct SubscriptionOutcome {
+    status: StatusCode,
+    /// Stable identifier for redirects and JSON clients.
+    code: &'static str,
+    title: &'static str,
+    message: String,
+    fields: Vec<FieldError>,
+}
+
+impl SubscriptionOutcome {
+    fn new(status: StatusCode, code: &'static str, title: &'static str, message: &str) -> Self {
+        Self {
+            status,
+            code,
+            title,
+            message: message.to_owned(),
+            fields: Vec::new(),
+        }
+    }
+
+    pub fn subscribed() -> Self {
+        Self::new(
+            StatusCode::OK,
+            "subscribed",
+            "Almost there",
+            "Thanks for subscribing! We have sent you an email, follow the link in it to confirm your subscription.",
+        )
+    }
+
+    pub fn confirmed() -> Self {
+        Self::new(
+            StatusCode::OK,
+            "confirmed",
+            "Subscription confirmed",
+            "Your subscription is confirmed, welcome aboard!",
+        )
+    }
+
+    pub fn already_confirmed() -> Self {
+        Self::new(
+            StatusCode::OK,
+            "already_confirmed",
+            "Subscription confirmed",
+            "Your subscription was already confirmed, there is nothing more to do.",
+        )
+    }
+
+    pub fn already_subscribed() -> Self {
+        Self::new(
+            StatusCode::CONFLICT,
+            "already_subscribed",
+            "Already subscribed",
+            "This address is already subscribed.",
+        )
+    }
+
+    pub fn invalid_fields(fields: Vec<FieldError>) -> Self {
+        Self {
+            fields,
+            ..Self::new(
+                StatusCode::BAD_REQUEST,
+                "invalid_fields",
+                "Please check the form",
+                "Some of the fields are not valid.",
+            )
+        }
+    }
+
+    pub fn missing_fields() -> Self {
+        Self::new(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "missing_fields",
+            "Please check the form",
+            "Both a name and an email address are needed to subscribe.",
+        )
+    }
+
+    /// Turned away by the domain policy or the bot protection.
+    pub fn refused(status: StatusCode, code: &'static str, message: &str) -> Self {
+        Self::new(status, code, "We could not subscribe you", message)
+    }
+
+    pub fn missing_token() -> Self {
+        Self::new(
+            StatusCode::BAD_REQUEST,
+            "missing_token",
+            "Invalid link",
+            "This confirmation link is incomplete, please copy the whole link from the email.",
+        )
+    }
+
+    pub fn invalid_token() -> Self {
+        Self::new(
+            StatusCode::UNAUTHORIZED,
+            "invalid_token",
+            "Invalid link",
+            "This confirmation link is invalid or has expired. Subscribe again to get a new one.",
+        )
+    }
+
+    pub fn internal_error() -> Self {
+        Self::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "internal_error",
+            "Something went wrong",
+            "Something went wrong on our side, please try again later.",
+        )
+    }
+
+    fn is_success(&self) -> bool {
+        self.status.is_success()
+    }
+
+    pub fn respond(self, pages: &SubscriptionPagesSettings, headers: &HeaderMap) -> Response<Body> {
+        if wants_json(headers) {
+            return self.json();
+        }
+
+        let redirect_url = if self.is_success() {
+            pages
+                .thank_you_url
+                .as_deref()
+                .map(|url| with_query(url, "outcome", self.code))
+        } else {
+            pages
+                .error_url
+                .as_deref()
+                .map(|url| with_query(url, "error", self.code))
+        };
+
+        match redirect_url {
+            Some(url) => Redirect::to(&url).into_response(),
+            None => self.html(),
+        }
+    }
+
+    fn json(self) -> Response<Body> {
+        let body = if self.is_success() {
+            json!({
+                "status": self.code,
+                "message": self.message,
+            })
+        } else {
+            json!({
+                "error": {
+                    "code": self.code,
+                    "message": self.message,
+                    "fields": self
+                        .fields
+                        .iter()
+                        .map(|field| (field.field.as_str(), field.message.as_str()))
+                        .collect::<BTreeMap<_, _>>(),
+                }
+            })
+        };
+
+        (self.status, Json(body)).into_response()
+    }
+
+    fn html(self) -> Response<Body> {
+        let fields_html = if self.fields.is_empty() {
+            String::new()
+        } else {
+            format!(
+                "<ul>{}</ul>",
+                self.fields
+                    .iter()
+                    .map(|field| format!(
+                        "<li>{}: {}</li>",
+                        htmlescape::encode_minimal(&field.field),
+                        htmlescape::encode_minimal(&field.message)
+                    ))
+                    .collect::<String>()
+            )
+        };
+
+        let back_html = if self.is_success() {
+            ""
+        } else {
+            r#"<p><a href="/">&lt;- Back to the form</a></p>"#
+        };
+
+        let html = Html::from(format!(
+            r#"
+            <!DOCTYPE html>
+            <html lang="en">
+            <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>{title}</title>
+            </head>
+            <body>
+                <h1>{title}</h1>
+                <p>{message}</p>
+                {fields_html}
+                {back_html}
+            </body>
+            </html>
+            "#,
+            title = self.title,
+            message = htmlescape::encode_minimal(&self.message),
+        ));
+
+        (self.status, html).into_response()
+    }
+}
+
+/// Clients sending JSON get JSON back, whatever they accept.
+fn wants_json(headers: &HeaderMap) -> bool {
+    sends_json(headers)
+        || headers
+            .get_all(ACCEPT)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .any(|value| value.contains("application/json"))
+}
+
+pub fn sends_json(headers: &HeaderMap) -> bool {
+    headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"))
+}
+
+fn with_query(url: &str, name: &str, value: &str) -> String {
+    let separator = if url.contains('?') { '&' } else { '?' };
+    format!("{}{}{}={}", url, separator, name, value)
+}
@@ -1,71 +1,355 @@
+use std::collections::HashMap;
 use std::sync::Arc;
 
-use axum::{extract::State, http::StatusCode, Form};
+use async_trait::async_trait;
+use axum::{
+    body::Body,
+    extract::{
+        rejection::{FormRejection, JsonRejection},
+        FromRequest, Query, Request, State,
+    },
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Form, Json,
+};
 use chrono::Utc;
 use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use secrecy::Secret;
 use serde::Deserialize;
+use serde_json::{Map, Value};
 use sqlx::{types::Uuid, PgExecutor};
 use sqlx::{Acquire, PgPool};
 use tracing::{error, info, warn};
 use ulid::Ulid;
 
-use crate::{domain::NewSubscriber, email_client::EmailClient};
+use crate::{
+    attribution::Attribution,
+    client_info::ClientInfo,
+    configuration::SubscriptionPagesSettings,
+    consent::{record_consent, referer, ConsentKind},
+    domain::{NewSubscriber, SubscriberEmail},
+    domain_policy::check_signup_domain,
+    email_client::EmailClient,
+    routes::{sends_json, SubscriptionOutcome},
+    signup_protection::{SignupChallenge, SignupGuard, SignupRefusal},
+    subscriber_attributes::list_attributes,
+    subscriber_events::{record_event, SubscriberEvent},
+    subscriber_tags::{add_tags, verify_signup_tags},
+};
 
 #[derive(Deserialize)]
 pub struct SubscribeData {
     pub name: String,
     pub email: String,
+    /// Where the signup comes from, when the form says so. Comes before
+    /// `other_fields`, which takes whatever is left.
+    #[serde(flatten)]
+    pub attribution: Attribution,
+    /// The honeypot, see `SignupGuard`.
+    #[serde(default)]
+    pub website: String,
+    #[serde(default)]
+    pub form_token: Option<String>,
+    #[serde(default)]
+    pub pow_nonce: Option<String>,
+    /// Tags to put on the subscriber, signed, see `signup_tags_token`.
+    #[serde(default)]
+    pub tags: Option<String>,
+    /// Custom attributes, as an object in JSON bodies.
+    #[serde(default)]
+    pub attributes: Map<String, Value>,
+    /// Forms send attributes as `attributes[name]` fields instead.
+    #[serde(flatten)]
+    pub other_fields: HashMap<String, Value>,
 }
 
+impl SubscribeData {
+    /// The attributes sent with the signup, whichever way they were sent.
+    pub fn submitted_attributes(&self) -> Map<String, Value> {
+        let from_fields = self.other_fields.iter().filter_map(|(field, value)| {
+            let name = field.strip_prefix("attributes[")?.strip_suffix(']')?;
+            Some((name.to_owned(), value.clone()))
+        });
+
+        self.attributes
+            .clone()
+            .into_iter()
+            .chain(from_fields)
+            .collect()
+    }
+}
+
+/// Links and scripts can pass the signed tags and the attribution in the
+/// query string instead of the body.
+#[derive(Deserialize)]
+pub struct SubscribeQuery {
+    tags: Option<String>,
+    #[serde(flatten)]
+    attribution: Attribution,
+}
+
+/// A signup, sent by the form of the home page or as JSON by the widget and
+/// other scripts.
+pub struct SubscribeBody(pub SubscribeData);
+
+pub enum SubscribeBodyRejection {
+    /// The body was read but a field is missing or has the wrong type
+    Incomplete(String),
+    Other(Response<Body>),
+}
+
+impl IntoResponse for SubscribeBodyRejection {
+    fn into_response(self) -> Response<Body> {
+        match self {
+            SubscribeBodyRejection::Incomplete(e) => {
+                (StatusCode::UNPROCESSABLE_ENTITY, e).into_response()
+            }
+            SubscribeBodyRejection::Other(response) => response,
+        }
+    }
+}
+
+#[async_trait]
+impl<S> FromRequest<S> for SubscribeBody
+where
+    S: Send + Sync,
+{
+    type Rejection = SubscribeBodyRejection;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        if sends_json(req.headers()) {
+            match Json::<SubscribeData>::from_request(req, state).await {
+                Ok(Json(data)) => Ok(Self(data)),
+                Err(JsonRejection::JsonDataError(e)) => {
+                    Err(SubscribeBodyRejection::Incomplete(e.body_text()))
+                }
+                Err(rejection) => Err(SubscribeBodyRejection::Other(rejection.into_response())),
+            }
+        } else {
+            match Form::<SubscribeData>::from_request(req, state).await {
+                Ok(Form(data)) => Ok(Self(data)),
+                Err(FormRejection::FailedToDeserializeFormBody(e)) => {
+                    Err(SubscribeBodyRejection::Incomplete(e.body_text()))
+                }
+                Err(rejection) => Err(SubscribeBodyRejection::Other(rejection.into_response())),
+            }
+        }
+    }
+}
+
+// Every argument is an extractor
+#[allow(clippy::too_many_arguments)]
 pub async fn subscribe(
     State(pool): State<Arc<PgPool>>,
     State(email): State<Arc<EmailClient>>,
     State(base_url): State<Arc<str>>,
-    Form(form): Form<SubscribeData>,
-) -> StatusCode {
+    State(guard): State<Arc<SignupGuard>>,
+    State(pages): State<Arc<SubscriptionPagesSettings>>,
+    State(secret): State<Arc<Secret<String>>>,
+    client: ClientInfo,
+    headers: HeaderMap,
+    Query(query): Query<SubscribeQuery>,
+    body: Result<SubscribeBody, SubscribeBodyRejection>,
+) -> Response<Body> {
+    let outcome = match body {
+        Ok(SubscribeBody(mut form)) => {
+            let tags = form
+                .tags
+                .take()
+                .or(query.tags)
+                .filter(|token| !token.is_empty())
+                .map(|token| {
+                    // The signup goes through, only the tags are dropped
+                    verify_signup_tags(&secret, &token).unwrap_or_else(|| {
+                        warn!("ignoring signup tags with an invalid signature");
+                        vec![]
+                    })
+                })
+                .unwrap_or_default();
+            form.attribution = std::mem::take(&mut form.attribution).or(query.attribution);
+
+            register_subscriber(
+                &pool, &email, &base_url, &guard, &client, &headers, form, &tags,
+            )
+            .await
+        }
+        Err(SubscribeBodyRejection::Incomplete(e)) => {
+            warn!("incomplete subscription: {}", e);
+            SubscriptionOutcome::missing_fields()
+        }
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    outcome.respond(&pages, &headers)
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn register_subscriber(
+    pool: &PgPool,
+    email: &EmailClient,
+    base_url: &str,
+    guard: &SignupGuard,
+    client: &ClientInfo,
+    headers: &HeaderMap,
+    mut form: SubscribeData,
+    tags: &[String],
+) -> SubscriptionOutcome {
     info!("new subscriber {} <{}>", form.name, form.email);
 
-    let Ok(new_subscriber) = NewSubscriber::try_from(form) else {
-        return StatusCode::BAD_REQUEST;
+    let challenge = SignupChallenge {
+        email: &form.email,
+        honeypot: &form.website,
+        form_token: form.form_token.as_deref(),
+        pow_nonce: form.pow_nonce.as_deref(),
     };
+    match guard.check_form(&challenge).await {
+        Ok(()) => {}
+        // Bots are not told they were caught
+        Err(SignupRefusal::Honeypot) => {
+            info!("refused signup caught by the honeypot");
+            return SubscriptionOutcome::subscribed();
+        }
+        Err(refusal) => {
+            info!("refused signup ({})", refusal.as_str());
+            return SubscriptionOutcome::refused(
+                StatusCode::BAD_REQUEST,
+                refusal.as_str(),
+                refusal.message(),
+            );
+        }
+    }
+
+    // Forms that do not say where they are get the page they were sent from
+    let attribution = std::mem::take(&mut form.attribution).or(Attribution {
+        source: referer(headers).map(str::to_owned),
+        ..Default::default()
+    });
+
+    let definitions = match list_attributes(pool).await {
+        Ok(definitions) => definitions,
+        Err(e) => {
+            error!("failed to load the subscriber attributes: {:?}", e);
+            return SubscriptionOutcome::internal_error();
+        }
+    };
+
+    let new_subscriber = match NewSubscriber::parse(form, &definitions) {
+        Ok(new_subscriber) => new_subscriber,
+        Err(fields) => return SubscriptionOutcome::invalid_fields(fields),
+    };
+
+    // Redis being unavailable should not stop signups
+    let within_limits = guard
+        .check_rate_limits(client.ip, &new_subscriber.email)
+        .await
+        .unwrap_or_else(|e| {
+            error!("failed to check signup rate limits: {:?}", e);
+            true
+        });
+    if !within_limits {
+        let refusal = SignupRefusal::RateLimited;
+        info!("refused signup ({})", refusal.as_str());
+        return SubscriptionOutcome::refused(
+            StatusCode::TOO_MANY_REQUESTS,
+            refusal.as_str(),
+            refusal.message(),
+        );
+    }
+
+    match check_signup_domain(pool, &new_subscriber.email).await {
+        Ok(None) => {}
+        Ok(Some(reason)) => {
+            info!(
+                "refused signup from {} ({})",
+                new_subscriber.email.domain(),
+                reason.as_str()
+            );
+            return SubscriptionOutcome::refused(
+                StatusCode::BAD_REQUEST,
+                reason.as_str(),
+                reason.message(),
+            );
+        }
+        Err(e) => {
+            error!("failed to check the signup domain: {:?}", e);
+            return SubscriptionOutcome::internal_error();
+        }
+    }
 
     let Ok(mut transaction) = pool.begin().await else {
-        return StatusCode::INTERNAL_SERVER_ERROR;
+        return SubscriptionOutcome::internal_error();
     };
 
-    let id = match insert_subscriber(transaction.acquire().await.unwrap(), &new_subscriber).await {
+    let id = match insert_subscriber(
+        transaction.acquire().await.unwrap(),
+        &new_subscriber,
+        &attribution,
+    )
+    .await
+    {
         Ok(id) => id,
 
         Err(sqlx::Error::Database(e)) => {
             warn!("database error: {:?}", e);
             return match e.kind() {
-                sqlx::error::ErrorKind::UniqueViolation => StatusCode::CONFLICT,
-                _ => StatusCode::INTERNAL_SERVER_ERROR,
+                sqlx::error::ErrorKind::UniqueViolation => {
+                    SubscriptionOutcome::already_subscribed()
+                }
+                _ => SubscriptionOutcome::internal_error(),
             };
         }
 
         Err(other) => {
             error!("failed to execute query: {:?}", other);
-            return StatusCode::INTERNAL_SERVER_ERROR;
+            return SubscriptionOutcome::internal_error();
         }
     };
 
     let token = generate_subscriptions_token();
     let Ok(()) = store_token(transaction.acquire().await.unwrap(), id, &token).await else {
-        return StatusCode::INTERNAL_SERVER_ERROR;
+        return SubscriptionOutcome::internal_error();
+    };
+
+    if !tags.is_empty() {
+        let Ok(_) = add_tags(transaction.acquire().await.unwrap(), &[id], tags).await else {
+            return SubscriptionOutcome::internal_error();
+        };
+    }
+
+    let Ok(()) = record_event(
+        transaction.acquire().await.unwrap(),
+        id,
+        SubscriberEvent::Subscribed,
+        None,
+    )
+    .await
+    else {
+        return SubscriptionOutcome::internal_error();
+    };
+
+    let Ok(()) = record_consent(
+        transaction.acquire().await.unwrap(),
+        id,
+        ConsentKind::Signup,
+        client,
+        attribution.source.as_deref(),
+    )
+    .await
+    else {
+        return SubscriptionOutcome::internal_error();
     };
 
     let Ok(()) = transaction.commit().await else {
-        return StatusCode::INTERNAL_SERVER_ERROR;
+        return SubscriptionOutcome::internal_error();
     };
 
-    match send_email(&email, &new_subscriber, &base_url, &token).await {
-        Ok(()) => StatusCode::OK,
-        Err(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    match send_confirmation_email(email, &new_subscriber.email, base_url, &token).await {
+        Ok(()) => SubscriptionOutcome::subscribed(),
+        Err(_) => SubscriptionOutcome::internal_error(),
     }
 }
 
-fn generate_subscriptions_token() -> String {
+pub fn generate_subscriptions_token() -> String {
     let mut rng = thread_rng();
     std::iter::repeat_with(|| rng.sample(Alphanumeric))
         .map(char::from)
@@ -76,18 +360,30 @@ fn generate_subscriptions_token() -> String {
 async fn insert_subscriber(
     db: impl PgExecutor<'_>,
     sub: &NewSubscriber,
+    attribution: &Attribution,
 ) -> Result<Uuid, sqlx::Error> {
     let id = Uuid::from_bytes(Ulid::new().to_bytes());
 
     sqlx::query!(
         r#"
-        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
-        VALUES ($1, $2, $3, $4, 'pending_confirmation')
+        INSERT INTO subscriptions (
+            id, email, name, subscribed_at, status, attributes, source, referrer,
+            utm_source, utm_medium, utm_campaign, utm_term, utm_content, confirmation_sent_at
+        )
+        VALUES ($1, $2, $3, $4, 'pending_confirmation', $5, $6, $7, $8, $9, $10, $11, $12, $4)
         "#,
         id,
         sub.email.as_ref(),
         sub.name.as_ref(),
-        Utc::now()
+        Utc::now(),
+        Value::Object(sub.attributes.clone()),
+        attribution.source,
+        attribution.referrer,
+        attribution.utm_source,
+        attribution.utm_medium,
+        attribution.utm_campaign,
+        attribution.utm_term,
+        attribution.utm_content,
     )
     .execute(db)
     .await?;
@@ -114,9 +410,9 @@ pub async fn store_token(
     Ok(())
 }
 
-async fn send_email(
+pub async fn send_confirmation_email(
     client: &EmailClient,
-    subscriber: &NewSubscriber,
+    subscriber_email: &SubscriberEmail,
     base_url: &str,
     token: &str,
 ) -> anyhow::Result<()> {
@@ -127,7 +423,7 @@ async fn send_email(
 
     client
         .send_email(
-            &subscriber.email.clone(),
+            subscriber_email,
             "Welcome!",
             &format!(
                 "Welcome to our newsletter!\nClink {} to confirm.",
@@ -0,0 +1,76 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::State,
+    http::header,
+    response::{Html, IntoResponse},
+    Json,
+};
+use serde_json::json;
+use sqlx::PgPool;
+use tracing::error;
+
+use crate::{signup_protection::SignupGuard, subscriber_attributes::list_attributes};
+
+/// The script of the signup widget, to be embedded on other sites.
+pub async fn widget_script(State(base_url): State<Arc<str>>) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/javascript; charset=utf-8")],
+        include_str!("widget.js").replace("{{base_url}}", &base_url),
+    )
+}
+
+/// The snippet to paste on a site to embed the widget, with a preview.
+pub async fn widget_page(State(base_url): State<Arc<str>>) -> impl IntoResponse {
+    let snippet = format!(
+        r#"<div data-newsletter-widget data-source="my-site"></div>
+<script src="{}/widget.js" defer></script>"#,
+        base_url
+    );
+
+    Html::from(format!(
+        r#"
+        <!DOCTYPE html>
+        <html lang="en">
+        <head>
+            <meta http-equiv="content-type" content="text/html; charset=utf-8">
+            <title>Signup widget</title>
+        </head>
+        <body>
+            <p>Paste this where the signup box should appear. The site also
+            needs to be in the <code>widget.allowed_origins</code> setting.</p>
+            <pre><code>{snippet_html}</code></pre>
+            <h2>Preview</h2>
+            {snippet}
+        </body>
+        </html>
+        "#,
+        snippet_html = htmlescape::encode_minimal(&snippet),
+    ))
+}
+
+/// What the widget needs to pass the bot protection, see `SignupGuard`, and
+/// the attributes it should ask for.
+pub async fn signup_form_token(
+    State(pool): State<Arc<PgPool>>,
+    State(guard): State<Arc<SignupGuard>>,
+) -> impl IntoResponse {
+    let definitions = list_attributes(&pool).await.unwrap_or_else(|e| {
+        error!("failed to load the subscriber attributes: {:?}", e);
+        vec![]
+    });
+
+    Json(json!({
+        "form_token": guard.issue_form_token(),
+        "pow_difficulty": guard.pow_difficulty(),
+        "attributes": definitions
+            .into_iter()
+            .map(|definition| json!({
+                "name": definition.name,
+                "label": definition.label,
+                "type": definition.kind,
+                "required": definition.required,
+            }))
+            .collect::<Vec<_>>(),
+    }))
+}
@@ -0,0 +1,130 @@
+// src/routes/widget/widget.js
+// Turns every element with a `data-newsletter-widget` attribute into a signup
+// form posting to {{base_url}}. A `data-source` attribute on the element is
+// recorded as where the signup comes from, the page URL otherwise, and a
+// `data-tags` attribute holds signed tags to put on the people signing up.
+// The `utm_*` parameters of the page and its referrer are sent along.
+(function () {
+  const baseUrl = "{{base_url}}";
+
+  // Same proof of work as the form of the home page, see
+  // `signup_protection::proof_of_work_holds`.
+  function leadingZeroBits(bytes) {
+    let zeros = 0;
+    for (const byte of bytes) {
+      if (byte !== 0) {
+        return zeros + Math.clz32(byte) - 24;
+      }
+      zeros += 8;
+    }
+    return zeros;
+  }
+
+  async function solve(token, email, difficulty) {
+    const encoder = new TextEncoder();
+    for (let nonce = 0; ; nonce++) {
+      const digest = await crypto.subtle.digest("SHA-256", encoder.encode(token + ":" + email + ":" + nonce));
+      if (leadingZeroBits(new Uint8Array(digest)) >= difficulty) {
+        return String(nonce);
+      }
+    }
+  }
+
+  function describe(result) {
+    if (!result.error) {
+      return result.message;
+    }
+    const fields = Object.entries(result.error.fields || {}).map(([field, problem]) => field + ": " + problem);
+    return [result.error.message, ...fields].join(" ");
+  }
+
+  const inputTypes = { number: "number", boolean: "checkbox", date: "date" };
+
+  // Built from the DOM rather than markup, labels are typed by admins
+  function attributeInput(attribute) {
+    const input = document.createElement("input");
+    input.type = inputTypes[attribute.type] || "text";
+    input.step = "any";
+    input.required = attribute.required;
+    input.dataset.attribute = attribute.name;
+
+    const label = document.createElement("label");
+    label.append(attribute.label + " ", input);
+    return label;
+  }
+
+  function render(container) {
+    container.innerHTML =
+      '<form class="newsletter-widget">' +
+      '<input type="text" name="name" placeholder="Your name" required>' +
+      '<input type="email" name="email" placeholder="Your email address" required>' +
+      '<input type="text" name="website" tabindex="-1" autocomplete="off" aria-hidden="true" style="display: none">' +
+      '<button type="submit">Subscribe</button>' +
+      '<p class="newsletter-widget-message" role="status"></p>' +
+      "</form>";
+
+    const form = container.querySelector("form");
+    const message = container.querySelector(".newsletter-widget-message");
+    // Fetched right away, the time to fill the form counts from now. A token
+    // is good for one signup, each attempt uses up the current one.
+    const fetchChallenge = () => fetch(baseUrl + "/subscriptions/form-token").then((response) => response.json());
+    let challenge = fetchChallenge();
+    challenge.then(({ attributes }) => {
+      const button = form.querySelector("button");
+      (attributes || []).forEach((attribute) => form.insertBefore(attributeInput(attribute), button));
+    });
+
+    form.addEventListener("submit", async (event) => {
+      event.preventDefault();
+      form.querySelector("button").disabled = true;
+
+      try {
+        const { form_token, pow_difficulty } = await challenge;
+        const body = {
+          name: form.elements.name.value,
+          email: form.elements.email.value,
+          website: form.elements.website.value,
+          source: container.dataset.source || window.location.href,
+          form_token: form_token,
+        };
+        const query = new URLSearchParams(window.location.search);
+        ["utm_source", "utm_medium", "utm_campaign", "utm_term", "utm_content"].forEach((parameter) => {
+          if (query.get(parameter)) {
+            body[parameter] = query.get(parameter);
+          }
+        });
+        if (document.referrer) {
+          body.referrer = document.referrer;
+        }
+        if (container.dataset.tags) {
+          body.tags = container.dataset.tags;
+        }
+        const attributes = {};
+        form.querySelectorAll("[data-attribute]").forEach((input) => {
+          attributes[input.dataset.attribute] = input.type === "checkbox" ? input.checked : input.value;
+        });
+        body.attributes = attributes;
+        if (pow_difficulty > 0) {
+          body.pow_nonce = await solve(form_token, body.email, pow_difficulty);
+        }
+
+        const response = await fetch(baseUrl + "/subscriptions", {
+          method: "POST",
+          headers: { "Content-Type": "application/json", Accept: "application/json" },
+          body: JSON.stringify(body),
+        });
+        message.textContent = describe(await response.json());
+        if (response.ok) {
+          form.reset();
+        }
+      } catch (e) {
+        message.textContent = "Something went wrong, please try again later.";
+      } finally {
+        challenge = fetchChallenge();
+        form.querySelector("button").disabled = false;
+      }
+    });
+  }
+
+  document.querySelectorAll("[data-newsletter-widget]").forEach(render);
+})();
@@ -1,12 +1,39 @@
+use std::{marker::PhantomData, str::FromStr, sync::Arc};
+
 use async_trait::async_trait;
-use axum::{extract::FromRequestParts, http::request::Parts};
-use tower_sessions::{session::Error, Session};
+use axum::{
+    body::Body,
+    extract::{FromRef, FromRequestParts},
+    http::{header, request::Parts, StatusCode},
+    response::{IntoResponse, Redirect, Response},
+};
+use sqlx::PgPool;
+use tower_sessions::{
+    session::{Error, Id},
+    Session, SessionStore,
+};
+use tower_sessions_redis_store::{
+    fred::{
+        clients::RedisPool,
+        interfaces::{KeysInterface, SetsInterface},
+    },
+    RedisStore,
+};
 use uuid::Uuid;
 
+use crate::{
+    api_tokens::{self, ApiScope},
+    authentication::{get_role, Role},
+    configuration::TwoFactorSettings,
+};
+
 pub struct TypedSession(Session);
 
 impl TypedSession {
     const USER_ID_KEY: &'static str = "user_id";
+    const PENDING_USER_ID_KEY: &'static str = "pending_two_factor_user_id";
+    const PENDING_USERNAME_KEY: &'static str = "pending_two_factor_username";
+    const PENDING_FAILURES_KEY: &'static str = "pending_two_factor_failures";
 
     pub async fn renew(&self) -> Result<(), Error> {
         self.0.cycle_id().await
@@ -20,6 +47,45 @@ impl TypedSession {
         self.0.get(Self::USER_ID_KEY).await
     }
 
+    /// Records that the password was verified but the second factor is still
+    /// pending. The user is not considered logged in until `insert_user_id`.
+    /// `username` is the one the login throttling is keyed on.
+    pub async fn insert_pending_user_id(&self, user_id: Uuid, username: &str) -> Result<(), Error> {
+        self.0.insert(Self::PENDING_USER_ID_KEY, user_id).await?;
+        self.0.insert(Self::PENDING_USERNAME_KEY, username).await?;
+        self.0.insert(Self::PENDING_FAILURES_KEY, 0u32).await
+    }
+
+    pub async fn get_pending_user_id(&self) -> Result<Option<Uuid>, Error> {
+        self.0.get(Self::PENDING_USER_ID_KEY).await
+    }
+
+    pub async fn get_pending_username(&self) -> Result<Option<String>, Error> {
+        self.0.get(Self::PENDING_USERNAME_KEY).await
+    }
+
+    /// Counts a wrong second factor, returns how many were entered so far.
+    pub async fn record_pending_failure(&self) -> Result<u32, Error> {
+        let failures = self
+            .0
+            .get::<u32>(Self::PENDING_FAILURES_KEY)
+            .await?
+            .unwrap_or(0)
+            + 1;
+        self.0.insert(Self::PENDING_FAILURES_KEY, failures).await?;
+        Ok(failures)
+    }
+
+    pub async fn remove_pending_user_id(&self) -> Result<Option<Uuid>, Error> {
+        self.0.remove_value(Self::PENDING_USERNAME_KEY).await?;
+        self.0.remove_value(Self::PENDING_FAILURES_KEY).await?;
+        self.0.remove(Self::PENDING_USER_ID_KEY).await
+    }
+
+    pub fn id(&self) -> Option<Id> {
+        self.0.id()
+    }
+
     pub async fn log_out(self) -> Result<(), Error> {
         self.0.delete().await
     }
@@ -37,3 +103,202 @@ where
         Ok(Self(session))
     }
 }
+
+pub trait RoleRequirement {
+    const MINIMUM: Role;
+}
+
+pub struct Viewer;
+pub struct Editor;
+pub struct Owner;
+
+impl RoleRequirement for Viewer {
+    const MINIMUM: Role = Role::Viewer;
+}
+
+impl RoleRequirement for Editor {
+    const MINIMUM: Role = Role::Editor;
+}
+
+impl RoleRequirement for Owner {
+    const MINIMUM: Role = Role::Owner;
+}
+
+/// A logged in user holding at least the role required by `R`. Anonymous
+/// requests are redirected to the login page, while users with a lesser role
+/// are turned away with a 403.
+///
+/// The role is read from the database on every request, so changing it takes
+/// effect immediately rather than on the next login. When two-factor
+/// authentication is enforced, users who have not enrolled yet are sent to the
+/// enrollment page instead.
+pub struct AuthorizedUser<R: RoleRequirement> {
+    pub user_id: Uuid,
+    pub role: Role,
+    _requirement: PhantomData<R>,
+}
+
+#[async_trait]
+impl<S, R> FromRequestParts<S> for AuthorizedUser<R>
+where
+    S: Send + Sync,
+    R: RoleRequirement,
+    Arc<PgPool>: FromRef<S>,
+    Arc<TwoFactorSettings>: FromRef<S>,
+{
+    type Rejection = Response<Body>;
+
+    async fn from_request_parts(req: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let session = TypedSession::from_request_parts(req, state)
+            .await
+            .map_err(IntoResponse::into_response)?;
+
+        let Ok(user_id) = session.get_user_id().await else {
+            return Err(StatusCode::INTERNAL_SERVER_ERROR.into_response());
+        };
+
+        let Some(user_id) = user_id else {
+            return Err(Redirect::to("/login").into_response());
+        };
+
+        let pool = Arc::<PgPool>::from_ref(state);
+        let Some((role, two_factor_enabled)) = get_role(user_id, &pool).await else {
+            return Err(Redirect::to("/login").into_response());
+        };
+
+        let two_factor = Arc::<TwoFactorSettings>::from_ref(state);
+        if two_factor.enforce && !two_factor_enabled {
+            return Err(Redirect::to("/admin/two-factor").into_response());
+        }
+
+        if role < R::MINIMUM {
+            return Err(StatusCode::FORBIDDEN.into_response());
+        }
+
+        Ok(Self {
+            user_id,
+            role,
+            _requirement: PhantomData,
+        })
+    }
+}
+
+pub trait ScopeRequirement {
+    const SCOPE: ApiScope;
+}
+
+pub struct PublishNewsletters;
+
+impl ScopeRequirement for PublishNewsletters {
+    const SCOPE: ApiScope = ApiScope::PublishNewsletters;
+}
+
+/// Like `AuthorizedUser`, for endpoints that are also meant to be called by
+/// programs: a request carrying `Authorization: Bearer <token>` is
+/// authenticated with that API token instead of the session. The token must
+/// hold the scope required by `S`, and its owner the role required by `R`.
+pub struct AuthorizedApiUser<R: RoleRequirement, S: ScopeRequirement> {
+    pub user_id: Uuid,
+    pub role: Role,
+    _requirement: PhantomData<(R, S)>,
+}
+
+#[async_trait]
+impl<St, R, S> FromRequestParts<St> for AuthorizedApiUser<R, S>
+where
+    St: Send + Sync,
+    R: RoleRequirement,
+    S: ScopeRequirement,
+    Arc<PgPool>: FromRef<St>,
+    Arc<TwoFactorSettings>: FromRef<St>,
+{
+    type Rejection = Response<Body>;
+
+    async fn from_request_parts(req: &mut Parts, state: &St) -> Result<Self, Self::Rejection> {
+        let bearer_token = req
+            .headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(|token| token.trim().to_owned());
+
+        // Other authorization schemes are not ours to check
+        let Some(token) = bearer_token else {
+            let user = AuthorizedUser::<R>::from_request_parts(req, state).await?;
+            return Ok(Self {
+                user_id: user.user_id,
+                role: user.role,
+                _requirement: PhantomData,
+            });
+        };
+
+        let unauthorized = || {
+            (
+                StatusCode::UNAUTHORIZED,
+                [(header::WWW_AUTHENTICATE, "Bearer")],
+            )
+                .into_response()
+        };
+
+        let pool = Arc::<PgPool>::from_ref(state);
+        let owner = match api_tokens::authenticate(&pool, &token).await {
+            Ok(Some(owner)) => owner,
+            Ok(None) => return Err(unauthorized()),
+            Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR.into_response()),
+        };
+
+        let Some((role, _)) = get_role(owner.user_id, &pool).await else {
+            return Err(unauthorized());
+        };
+
+        if role < R::MINIMUM || !owner.scopes.contains(&S::SCOPE) {
+            return Err(StatusCode::FORBIDDEN.into_response());
+        }
+
+        Ok(Self {
+            user_id: owner.user_id,
+            role,
+            _requirement: PhantomData,
+        })
+    }
+}
+
+/// Keeps track of the sessions opened by each user so that they can all be
+/// revoked at once, e.g. when the account is disabled or deleted.
+pub struct SessionRegistry {
+    pool: RedisPool,
+    store: RedisStore<RedisPool>,
+}
+
+impl SessionRegistry {
+    pub fn new(pool: RedisPool) -> Self {
+        Self {
+            store: RedisStore::new(pool.clone()),
+            pool,
+        }
+    }
+
+    fn key(user_id: Uuid) -> String {
+        format!("user_sessions:{}", user_id)
+    }
+
+    pub async fn register(&self, user_id: Uuid, session: &TypedSession) -> anyhow::Result<()> {
+        let Some(id) = session.id() else {
+            anyhow::bail!("The session has no id yet");
+        };
+
+        let _: i64 = self.pool.sadd(Self::key(user_id), id.to_string()).await?;
+        Ok(())
+    }
+
+    pub async fn revoke_all(&self, user_id: Uuid) -> anyhow::Result<()> {
+        let ids: Vec<String> = self.pool.smembers(Self::key(user_id)).await?;
+
+        for id in ids.iter().filter_map(|id| Id::from_str(id).ok()) {
+            self.store.delete(&id).await?;
+        }
+
+        let _: i64 = self.pool.del(Self::key(user_id)).await?;
+        Ok(())
+    }
+}
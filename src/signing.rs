@@ -0,0 +1,70 @@
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use sha2::{Digest, Sha256};
+
+/// Lowercase hexadecimal, as stored and put in links.
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn unhex(s: &str) -> Option<Vec<u8>> {
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// SHA-256 of `value` in hex, for the tokens and codes that are only stored
+/// hashed.
+pub fn sha256_hex(value: &str) -> String {
+    hex(&Sha256::digest(value.as_bytes()))
+}
+
+fn mac(secret: &Secret<String>, purpose: &str, message: &str) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.expose_secret().as_bytes())
+        .expect("HMAC accepts keys of any size");
+    mac.update(format!("{}\n{}", purpose, message).as_bytes());
+    mac
+}
+
+/// The HMAC-SHA256 of `message` in hex. The purpose comes first, so that a
+/// signature made for one use is never valid for another.
+pub fn sign(secret: &Secret<String>, purpose: &str, message: &str) -> String {
+    hex(&mac(secret, purpose, message).finalize().into_bytes())
+}
+
+/// Whether `signature` is what `sign` gives for the same purpose and
+/// message, compared in constant time.
+pub fn verify(secret: &Secret<String>, purpose: &str, message: &str, signature: &str) -> bool {
+    let Some(signature) = unhex(signature) else {
+        return false;
+    };
+
+    mac(secret, purpose, message)
+        .verify_slice(&signature)
+        .is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use secrecy::Secret;
+
+    use super::{sign, verify};
+
+    #[test]
+    fn signatures_only_hold_for_their_purpose_and_message() {
+        let secret = Secret::new("a-secret".to_string());
+        let signature = sign(&secret, "signup-tags", "vip");
+
+        assert!(verify(&secret, "signup-tags", "vip", &signature));
+        assert!(!verify(&secret, "signup-form", "vip", &signature));
+        assert!(!verify(&secret, "signup-tags", "vip,press", &signature));
+        assert!(!verify(&secret, "signup-tags", "vip", "not hex"));
+        assert!(!verify(
+            &Secret::new("another-secret".to_string()),
+            "signup-tags",
+            "vip",
+            &signature
+        ));
+    }
+}
@@ -0,0 +1,309 @@
+use std::net::IpAddr;
+
+use chrono::Utc;
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use secrecy::Secret;
+use sha2::{Digest, Sha256};
+use tower_sessions_redis_store::fred::{
+    clients::RedisPool,
+    interfaces::KeysInterface,
+    types::{Expiration, SetOptions},
+};
+use tracing::error;
+
+use crate::{
+    configuration::SignupProtectionSettings,
+    domain::SubscriberEmail,
+    signing::{sign, verify},
+};
+
+/// Why a signup was turned away before reaching the database.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignupRefusal {
+    /// The hidden field was filled in, only bots see it
+    Honeypot,
+    /// The form token is missing, forged, too old or already used
+    InvalidFormToken,
+    TooFast,
+    ProofOfWork,
+    RateLimited,
+}
+
+impl SignupRefusal {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SignupRefusal::Honeypot => "honeypot",
+            SignupRefusal::InvalidFormToken => "invalid_form_token",
+            SignupRefusal::TooFast => "too_fast",
+            SignupRefusal::ProofOfWork => "proof_of_work",
+            SignupRefusal::RateLimited => "rate_limited",
+        }
+    }
+
+    pub fn message(&self) -> &'static str {
+        match self {
+            SignupRefusal::Honeypot => "",
+            SignupRefusal::InvalidFormToken => {
+                "The form has expired, please reload the page and try again."
+            }
+            SignupRefusal::TooFast => "The form was sent too quickly, please try again.",
+            SignupRefusal::ProofOfWork => {
+                "Your browser could not prove it is not a bot, please try again."
+            }
+            SignupRefusal::RateLimited => "Too many signup attempts, please try again later.",
+        }
+    }
+}
+
+/// The anti-bot fields of the subscription form, see `home.html`.
+pub struct SignupChallenge<'a> {
+    pub email: &'a str,
+    pub honeypot: &'a str,
+    pub form_token: Option<&'a str>,
+    pub pow_nonce: Option<&'a str>,
+}
+
+/// Layered protection of the public subscription form: a honeypot field, a
+/// signed single-use token giving the minimum time to fill the form, an
+/// optional proof of work over that token and the address, and rate limits
+/// per IP and per email counted in Redis.
+pub struct SignupGuard {
+    pool: RedisPool,
+    secret: Secret<String>,
+    settings: SignupProtectionSettings,
+}
+
+impl SignupGuard {
+    pub fn new(
+        pool: RedisPool,
+        secret: Secret<String>,
+        settings: SignupProtectionSettings,
+    ) -> Self {
+        Self {
+            pool,
+            secret,
+            settings,
+        }
+    }
+
+    pub fn pow_difficulty(&self) -> u32 {
+        self.settings.pow_difficulty
+    }
+
+    /// A token recording when the form was served, for `check_form`. The
+    /// random id tells apart the forms served in the same second.
+    pub fn issue_form_token(&self) -> String {
+        let issued_at = Utc::now().timestamp();
+        let mut rng = thread_rng();
+        let form_id: String = std::iter::repeat_with(|| rng.sample(Alphanumeric))
+            .map(char::from)
+            .take(16)
+            .collect();
+        let signature = sign(
+            &self.secret,
+            "signup-form",
+            &format!("{}\n{}", issued_at, form_id),
+        );
+
+        format!("{}.{}.{}", issued_at, form_id, signature)
+    }
+
+    /// When the form was served and its id, if the token is genuine.
+    fn verify_form_token<'a>(&self, token: &'a str) -> Option<(i64, &'a str)> {
+        let mut parts = token.splitn(3, '.');
+        let issued_at: i64 = parts.next()?.parse().ok()?;
+        let form_id = parts.next()?;
+        let signature = parts.next()?;
+
+        let message = format!("{}\n{}", issued_at, form_id);
+        verify(&self.secret, "signup-form", &message, signature).then_some((issued_at, form_id))
+    }
+
+    /// Marks the form as sent, false if it already was. The mark only has
+    /// to outlive the token.
+    async fn claim_form(&self, form_id: &str) -> anyhow::Result<bool> {
+        let claimed: Option<String> = self
+            .pool
+            .set(
+                format!("signup_forms:{}", form_id),
+                "sent",
+                Some(Expiration::EX(self.settings.max_form_age_seconds as i64)),
+                Some(SetOptions::NX),
+                false,
+            )
+            .await?;
+
+        Ok(claimed.is_some())
+    }
+
+    /// The checks of the submitted form. Each form token is good for one
+    /// signup only.
+    pub async fn check_form(&self, challenge: &SignupChallenge<'_>) -> Result<(), SignupRefusal> {
+        if self.settings.honeypot && !challenge.honeypot.is_empty() {
+            return Err(SignupRefusal::Honeypot);
+        }
+
+        if self.settings.min_fill_seconds == 0 && self.settings.pow_difficulty == 0 {
+            return Ok(());
+        }
+
+        let token = challenge
+            .form_token
+            .ok_or(SignupRefusal::InvalidFormToken)?;
+        let (issued_at, form_id) = self
+            .verify_form_token(token)
+            .ok_or(SignupRefusal::InvalidFormToken)?;
+
+        let age = Utc::now().timestamp() - issued_at;
+        if age > self.settings.max_form_age_seconds as i64 {
+            return Err(SignupRefusal::InvalidFormToken);
+        }
+        if age < self.settings.min_fill_seconds as i64 {
+            return Err(SignupRefusal::TooFast);
+        }
+
+        let nonce = challenge.pow_nonce.unwrap_or("");
+        if !proof_of_work_holds(token, challenge.email, nonce, self.settings.pow_difficulty) {
+            return Err(SignupRefusal::ProofOfWork);
+        }
+
+        // Redis being unavailable should not stop signups
+        match self.claim_form(form_id).await {
+            Ok(false) => Err(SignupRefusal::InvalidFormToken),
+            Ok(true) => Ok(()),
+            Err(e) => {
+                error!("failed to mark the signup form as sent: {:?}", e);
+                Ok(())
+            }
+        }
+    }
+
+    /// Counts the attempt against the limits of the IP and of the address,
+    /// and tells whether both are still within them.
+    pub async fn check_rate_limits(
+        &self,
+        ip: Option<IpAddr>,
+        email: &SubscriberEmail,
+    ) -> anyhow::Result<bool> {
+        let mut scopes = vec![(
+            format!("email:{}", email.as_ref().to_lowercase()),
+            self.settings.max_signups_per_email,
+        )];
+        if let Some(ip) = ip {
+            scopes.push((format!("ip:{}", ip), self.settings.max_signups_per_ip));
+        }
+
+        let mut allowed = true;
+        for (scope, limit) in scopes {
+            if limit == 0 {
+                continue;
+            }
+
+            let key = format!("signup_attempts:{}", scope);
+            let attempts: i64 = self.pool.incr(&key).await?;
+            if attempts == 1 {
+                let window = self.settings.rate_limit_window_seconds as i64;
+                let _: bool = self.pool.expire(&key, window).await?;
+            }
+
+            allowed &= attempts <= limit as i64;
+        }
+
+        Ok(allowed)
+    }
+}
+
+/// Whether SHA-256 of `token:email:nonce` starts with `difficulty` zero
+/// bits, as computed by the script of `home.html`. The address is the one
+/// submitted, as is, so that a solution cannot be reused for another one.
+pub fn proof_of_work_holds(token: &str, email: &str, nonce: &str, difficulty: u32) -> bool {
+    let digest = Sha256::digest(format!("{}:{}:{}", token, email, nonce).as_bytes());
+
+    let mut zeros = 0;
+    for byte in digest {
+        zeros += byte.leading_zeros();
+        if byte != 0 {
+            break;
+        }
+    }
+
+    zeros >= difficulty
+}
+
+#[cfg(test)]
+mod tests {
+    use secrecy::Secret;
+    use tower_sessions_redis_store::fred::{clients::RedisPool, types::RedisConfig};
+
+    use super::{proof_of_work_holds, SignupChallenge, SignupGuard, SignupRefusal};
+    use crate::configuration::SignupProtectionSettings;
+
+    fn guard(min_fill_seconds: u64) -> SignupGuard {
+        // Never connected, the form checks do not use Redis
+        let pool = RedisPool::new(RedisConfig::default(), None, None, None, 1).unwrap();
+        let settings = SignupProtectionSettings {
+            honeypot: true,
+            min_fill_seconds,
+            max_form_age_seconds: 60,
+            pow_difficulty: 0,
+            max_signups_per_ip: 0,
+            max_signups_per_email: 0,
+            rate_limit_window_seconds: 60,
+        };
+        SignupGuard::new(pool, Secret::new("a-secret".to_string()), settings)
+    }
+
+    fn challenge(form_token: Option<&str>) -> SignupChallenge<'_> {
+        SignupChallenge {
+            email: "ursula@example.com",
+            honeypot: "",
+            form_token,
+            pow_nonce: None,
+        }
+    }
+
+    #[test]
+    fn form_tokens_are_checked_against_their_signature_and_age() {
+        let guard = guard(0);
+        let token = guard.issue_form_token();
+        assert!(guard.verify_form_token(&token).is_some());
+        assert_ne!(guard.issue_form_token(), token);
+
+        let (issued_at, rest) = token.split_once('.').unwrap();
+        let earlier = format!("{}.{}", issued_at.parse::<i64>().unwrap() - 10, rest);
+        assert_eq!(guard.verify_form_token(&earlier), None);
+        let (form_id, signature) = rest.split_once('.').unwrap();
+        let other_form = format!("{}.{}x.{}", issued_at, form_id, signature);
+        assert_eq!(guard.verify_form_token(&other_form), None);
+        assert_eq!(guard.verify_form_token("not a token"), None);
+    }
+
+    #[tokio::test]
+    async fn fresh_forms_are_too_fast_and_missing_tokens_invalid() {
+        let guard = guard(5);
+        let token = guard.issue_form_token();
+
+        assert_eq!(
+            guard.check_form(&challenge(Some(&token))).await,
+            Err(SignupRefusal::TooFast)
+        );
+        assert_eq!(
+            guard.check_form(&challenge(None)).await,
+            Err(SignupRefusal::InvalidFormToken)
+        );
+    }
+
+    #[test]
+    fn proof_of_work_counts_leading_zero_bits() {
+        let token = "1700000000.abcdef.0123";
+        let email = "ursula@example.com";
+        assert!(proof_of_work_holds(token, email, "", 0));
+
+        let nonce = (0..)
+            .map(|n: u32| n.to_string())
+            .find(|nonce| proof_of_work_holds(token, email, nonce, 8))
+            .unwrap();
+        assert!(proof_of_work_holds(token, email, &nonce, 8));
+        assert!(!proof_of_work_holds(token, email, &nonce, 257));
+    }
+}
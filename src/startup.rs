@@ -1,8 +1,11 @@
-use std::{net::TcpListener, sync::Arc};
+use std::{
+    net::{SocketAddr, TcpListener},
+    sync::Arc,
+};
 
 use anyhow::Result;
-use axum::extract::FromRef;
-use axum::http::{HeaderValue, Request};
+use axum::extract::{DefaultBodyLimit, FromRef};
+use axum::http::{header, HeaderValue, Method, Request};
 use axum::{
     routing::{get, post},
     Router,
@@ -12,6 +15,7 @@ use sqlx::PgPool;
 use time::Duration;
 use tower::ServiceBuilder;
 use tower_http::{
+    cors::{AllowOrigin, CorsLayer},
     request_id::{MakeRequestId, RequestId},
     trace::{DefaultMakeSpan, DefaultOnRequest, DefaultOnResponse, TraceLayer},
     ServiceBuilderExt,
@@ -24,9 +28,21 @@ use tower_sessions_redis_store::{
 use tracing::info;
 use ulid::Ulid;
 
-use crate::configuration::{DatabaseSettings, Settings};
+use crate::client_info::ClientIpSource;
+use crate::configuration::{
+    ConfirmationReminderSettings, DatabaseSettings, IdempotencyBackend, IdempotencySettings,
+    Settings, SubscriptionPagesSettings, TwoFactorSettings, WelcomeSequenceSettings,
+};
+use crate::confirmation_reminders::run_reminder_worker;
 use crate::email_client::EmailClient;
+use crate::idempotency::{
+    run_expiry_sweeper, IdempotencyStore, PostgresIdempotencyStore, RedisIdempotencyStore,
+};
+use crate::login_attempts::LoginThrottle;
 use crate::routes;
+use crate::session_state::SessionRegistry;
+use crate::signup_protection::SignupGuard;
+use crate::welcome_sequence::run_sequence_worker;
 
 #[derive(Clone)]
 struct MakeUlidRequestId;
@@ -44,6 +60,13 @@ pub struct AppState {
     email: Arc<EmailClient>,
     base_url: Arc<str>,
     secret: Arc<Secret<String>>,
+    idempotency: Arc<dyn IdempotencyStore>,
+    sessions: Arc<SessionRegistry>,
+    two_factor: Arc<TwoFactorSettings>,
+    login_throttle: Arc<LoginThrottle>,
+    signup_guard: Arc<SignupGuard>,
+    subscription_pages: Arc<SubscriptionPagesSettings>,
+    client_ip_source: ClientIpSource,
 }
 
 impl FromRef<AppState> for Arc<PgPool> {
@@ -70,9 +93,57 @@ impl FromRef<AppState> for Arc<Secret<String>> {
     }
 }
 
+impl FromRef<AppState> for Arc<dyn IdempotencyStore> {
+    fn from_ref(input: &AppState) -> Self {
+        Arc::clone(&input.idempotency)
+    }
+}
+
+impl FromRef<AppState> for Arc<SessionRegistry> {
+    fn from_ref(input: &AppState) -> Self {
+        Arc::clone(&input.sessions)
+    }
+}
+
+impl FromRef<AppState> for Arc<TwoFactorSettings> {
+    fn from_ref(input: &AppState) -> Self {
+        Arc::clone(&input.two_factor)
+    }
+}
+
+impl FromRef<AppState> for Arc<LoginThrottle> {
+    fn from_ref(input: &AppState) -> Self {
+        Arc::clone(&input.login_throttle)
+    }
+}
+
+impl FromRef<AppState> for Arc<SignupGuard> {
+    fn from_ref(input: &AppState) -> Self {
+        Arc::clone(&input.signup_guard)
+    }
+}
+
+impl FromRef<AppState> for Arc<SubscriptionPagesSettings> {
+    fn from_ref(input: &AppState) -> Self {
+        Arc::clone(&input.subscription_pages)
+    }
+}
+
+impl FromRef<AppState> for ClientIpSource {
+    fn from_ref(input: &AppState) -> Self {
+        input.client_ip_source
+    }
+}
+
 pub struct Application {
     app: Router,
     listener: TcpListener,
+    db: PgPool,
+    email: Arc<EmailClient>,
+    base_url: Arc<str>,
+    idempotency: IdempotencySettings,
+    welcome_sequence: WelcomeSequenceSettings,
+    confirmation_reminders: ConfirmationReminderSettings,
 }
 
 pub fn get_connection_pool(database: &DatabaseSettings) -> Result<PgPool> {
@@ -84,16 +155,24 @@ impl Application {
         let connection_pool =
             get_connection_pool(&configuration.database).expect("Failed to connecto to Postgres");
 
+        configuration
+            .confirmation_reminders
+            .validate()
+            .map_err(anyhow::Error::msg)?;
+
         let sender_email = configuration
             .email_client
             .sender()
             .expect("Invalid sender email address.");
 
-        let email_client = EmailClient::new(
+        let base_url: Arc<str> = Arc::from(configuration.application.base_url);
+
+        let email_client = Arc::new(EmailClient::new(
             configuration.email_client.base_url.clone(),
             sender_email,
             configuration.email_client.authorization_token.clone(),
-        );
+            connection_pool.clone(),
+        ));
 
         let address = format!(
             "{}:{}",
@@ -124,35 +203,223 @@ impl Application {
         redis_pool.connect();
         redis_pool.wait_for_connect().await?;
 
+        let idempotency_store: Arc<dyn IdempotencyStore> = match configuration.idempotency.backend {
+            IdempotencyBackend::Postgres => Arc::new(PostgresIdempotencyStore::new(
+                connection_pool.clone(),
+                configuration.idempotency.retention(),
+            )),
+            IdempotencyBackend::Redis => Arc::new(RedisIdempotencyStore::new(
+                redis_pool.clone(),
+                configuration.idempotency.retention(),
+                configuration.idempotency.lock_timeout(),
+            )),
+        };
+
+        let session_registry = SessionRegistry::new(redis_pool.clone());
+        let login_throttle = LoginThrottle::new(redis_pool.clone(), configuration.login_throttling);
+        let signup_guard = SignupGuard::new(
+            redis_pool.clone(),
+            configuration.application.secret.clone(),
+            configuration.signup_protection,
+        );
         let session_store = RedisStore::new(redis_pool);
         let session_layer = SessionManagerLayer::new(session_store)
             .with_expiry(Expiry::OnInactivity(Duration::MINUTE));
 
+        // Only the endpoints used by the widget can be called from other sites
+        let allowed_origins = configuration
+            .widget
+            .allowed_origins
+            .iter()
+            .map(|origin| HeaderValue::from_str(origin))
+            .collect::<Result<Vec<_>, _>>()?;
+        let widget_cors = CorsLayer::new()
+            .allow_origin(AllowOrigin::list(allowed_origins))
+            .allow_methods([Method::GET, Method::POST])
+            .allow_headers([header::ACCEPT, header::CONTENT_TYPE]);
+
         let app = Router::new()
             .route("/", get(routes::home))
             .route("/login", get(routes::login_get))
             .route("/login", post(routes::login_post))
+            .route("/login/two-factor", get(routes::login_two_factor_get))
+            .route("/login/two-factor", post(routes::login_two_factor_post))
             .route("/health_check", get(routes::health_check))
-            .route("/subscriptions", post(routes::subscribe))
+            .route(
+                "/subscriptions",
+                post(routes::subscribe).layer(widget_cors.clone()),
+            )
+            .route(
+                "/subscriptions/form-token",
+                get(routes::signup_form_token).layer(widget_cors),
+            )
             .route("/subscriptions/confirm", get(routes::confirm))
+            .route(
+                "/subscriptions/data",
+                get(routes::subscriber_data_form).post(routes::request_subscriber_data),
+            )
+            .route(
+                "/subscriptions/data/export",
+                get(routes::export_subscriber_data),
+            )
+            .route(
+                "/subscriptions/data/erase",
+                get(routes::erase_subscriber_data_form).post(routes::erase_subscriber_data),
+            )
             .route("/admin/dashboard", get(routes::admin_dashboard))
+            .route("/admin/api-tokens", get(routes::api_tokens_page))
+            .route("/admin/api-tokens", post(routes::create_api_token))
+            .route(
+                "/admin/api-tokens/:token_id/revoke",
+                post(routes::revoke_api_token),
+            )
+            .route("/admin/audit", get(routes::audit_log))
+            .route("/admin/domain-rules", get(routes::domain_rules_page))
+            .route("/admin/domain-rules", post(routes::add_domain_rule))
+            .route(
+                "/admin/domain-rules/:rule_id/delete",
+                post(routes::remove_domain_rule),
+            )
+            .route("/admin/audit/export", get(routes::export_audit_log))
+            .route("/admin/login-activity", get(routes::login_activity))
             .route("/admin/password", get(routes::change_password_form))
+            .route("/admin/reports/signups", get(routes::signup_sources_report))
             .route("/admin/password", post(routes::change_password))
             .route("/admin/newsletters", get(routes::newsletter_form))
             .route("/admin/newsletters", post(routes::publish_newsletter))
+            .route(
+                "/admin/subscriber-attributes",
+                get(routes::subscriber_attributes_page).post(routes::add_subscriber_attribute),
+            )
+            .route(
+                "/admin/subscriber-attributes/:name/delete",
+                post(routes::remove_subscriber_attribute),
+            )
+            .route("/admin/subscribers", get(routes::subscribers_page))
+            .route("/admin/subscribers/tags", post(routes::tag_subscribers))
+            .route(
+                "/admin/subscribers/export",
+                get(routes::export_subscribers_file),
+            )
+            .route(
+                "/admin/subscribers/import",
+                get(routes::import_subscribers_form)
+                    .post(routes::import_subscribers_post)
+                    .layer(DefaultBodyLimit::max(routes::IMPORT_SIZE_LIMIT)),
+            )
+            .route(
+                "/admin/subscribers/import/:import_id",
+                get(routes::subscriber_import_page),
+            )
+            .route(
+                "/admin/subscribers/import/:import_id/rejected",
+                get(routes::subscriber_import_rejected_rows),
+            )
+            .route(
+                "/admin/subscribers/:subscriber_id",
+                get(routes::subscriber_page),
+            )
+            .route(
+                "/admin/subscribers/:subscriber_id/confirm",
+                post(routes::confirm_subscriber_manually),
+            )
+            .route(
+                "/admin/subscribers/:subscriber_id/unsubscribe",
+                post(routes::unsubscribe_subscriber),
+            )
+            .route(
+                "/admin/subscribers/:subscriber_id/tags",
+                post(routes::tag_subscriber),
+            )
+            .route(
+                "/admin/subscribers/:subscriber_id/delete",
+                post(routes::delete_subscriber),
+            )
+            .route(
+                "/admin/subscribers/:subscriber_id/data",
+                get(routes::export_subscriber_data_manually),
+            )
+            .route(
+                "/admin/subscribers/:subscriber_id/erase",
+                post(routes::erase_subscriber_manually),
+            )
+            .route(
+                "/admin/suppressions",
+                get(routes::suppressions_page).post(routes::add_suppression),
+            )
+            .route(
+                "/admin/suppressions/upload",
+                post(routes::upload_suppressions)
+                    .layer(DefaultBodyLimit::max(routes::IMPORT_SIZE_LIMIT)),
+            )
+            .route(
+                "/admin/suppressions/:suppression_id/delete",
+                post(routes::delete_suppression),
+            )
+            .route("/admin/tags", get(routes::tags_page))
+            .route("/admin/tags/:name/delete", post(routes::remove_tag))
+            .route("/admin/two-factor", get(routes::two_factor_page))
+            .route("/admin/two-factor", post(routes::enable_two_factor))
+            .route(
+                "/admin/two-factor/disable",
+                post(routes::disable_two_factor),
+            )
+            .route("/admin/users", get(routes::users_page))
+            .route("/admin/users", post(routes::create_user))
+            .route("/admin/users/:user_id/disable", post(routes::disable_user))
+            .route("/admin/users/:user_id/enable", post(routes::enable_user))
+            .route("/admin/users/:user_id/role", post(routes::change_user_role))
+            .route("/admin/users/:user_id/delete", post(routes::delete_user))
+            .route(
+                "/admin/welcome-sequence",
+                get(routes::welcome_sequence_page).post(routes::add_sequence_step),
+            )
+            .route(
+                "/admin/welcome-sequence/:step_id/delete",
+                post(routes::remove_sequence_step),
+            )
+            .route("/invitation", get(routes::invitation_form))
+            .route("/invitation", post(routes::accept_invitation))
+            .route("/password-reset", get(routes::password_reset_form))
+            .route("/password-reset", post(routes::request_password_reset))
+            .route(
+                "/password-reset/confirm",
+                get(routes::password_reset_confirm_form),
+            )
+            .route("/password-reset/confirm", post(routes::reset_password))
             .route("/logout", post(routes::log_out))
+            .route("/widget", get(routes::widget_page))
+            .route("/widget.js", get(routes::widget_script))
             .layer(session_layer)
             .layer(uuid_layer)
             .with_state(AppState {
-                db: Arc::new(connection_pool),
-                email: Arc::new(email_client),
-                base_url: Arc::from(configuration.application.base_url),
+                db: Arc::new(connection_pool.clone()),
+                email: Arc::clone(&email_client),
+                base_url: Arc::clone(&base_url),
                 secret: Arc::new(configuration.application.secret),
+                idempotency: idempotency_store,
+                sessions: Arc::new(session_registry),
+                two_factor: Arc::new(configuration.two_factor),
+                login_throttle: Arc::new(login_throttle),
+                signup_guard: Arc::new(signup_guard),
+                subscription_pages: Arc::new(configuration.subscription_pages),
+                client_ip_source: ClientIpSource {
+                    trust_forwarded_for: configuration.application.trust_forwarded_for,
+                },
             });
 
         info!("starting server");
 
-        Ok(Application { app, listener })
+        Ok(Application {
+            app,
+            listener,
+            db: connection_pool,
+            email: email_client,
+            base_url,
+            idempotency: configuration.idempotency,
+            welcome_sequence: configuration.welcome_sequence,
+            confirmation_reminders: configuration.confirmation_reminders,
+        })
     }
 
     pub fn port(&self) -> u16 {
@@ -160,7 +427,28 @@ impl Application {
     }
 
     pub async fn run(self) -> Result<()> {
-        axum::serve(tokio::net::TcpListener::from_std(self.listener)?, self.app).await?;
+        // Redis expires idempotency keys on its own
+        if self.idempotency.backend == IdempotencyBackend::Postgres {
+            tokio::spawn(run_expiry_sweeper(self.db.clone(), self.idempotency));
+        }
+
+        tokio::spawn(run_sequence_worker(
+            self.db.clone(),
+            Arc::clone(&self.email),
+            self.welcome_sequence,
+        ));
+        tokio::spawn(run_reminder_worker(
+            self.db,
+            self.email,
+            self.base_url,
+            self.confirmation_reminders,
+        ));
+
+        axum::serve(
+            tokio::net::TcpListener::from_std(self.listener)?,
+            self.app.into_make_service_with_connect_info::<SocketAddr>(),
+        )
+        .await?;
         Ok(())
     }
 }
@@ -0,0 +1,332 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use serde_json::{Map, Number, Value};
+use sqlx::PgPool;
+
+use crate::domain::FieldError;
+
+/// Longest text accepted as the value of an attribute.
+const MAX_TEXT_LENGTH: usize = 256;
+
+/// The type of the values of an attribute.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttributeKind {
+    Text,
+    Number,
+    Boolean,
+    Date,
+}
+
+impl AttributeKind {
+    pub const ALL: [AttributeKind; 4] = [
+        AttributeKind::Text,
+        AttributeKind::Number,
+        AttributeKind::Boolean,
+        AttributeKind::Date,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AttributeKind::Text => "text",
+            AttributeKind::Number => "number",
+            AttributeKind::Boolean => "boolean",
+            AttributeKind::Date => "date",
+        }
+    }
+
+    /// Converts a submitted value to the value stored. Forms send everything
+    /// as text, so text is accepted for every type. Empty values are `None`.
+    fn parse(&self, value: &Value) -> Result<Option<Value>, String> {
+        let text = match value {
+            Value::Null => return Ok(None),
+            Value::String(text) if text.trim().is_empty() => return Ok(None),
+            Value::String(text) => Some(text.trim()),
+            _ => None,
+        };
+
+        match (self, text, value) {
+            (AttributeKind::Text, Some(text), _) => {
+                if text.chars().count() > MAX_TEXT_LENGTH {
+                    Err(format!(
+                        "must be at most {} characters long.",
+                        MAX_TEXT_LENGTH
+                    ))
+                } else {
+                    Ok(Some(Value::String(text.to_owned())))
+                }
+            }
+            (AttributeKind::Number, None, Value::Number(number)) => {
+                Ok(Some(Value::Number(number.clone())))
+            }
+            (AttributeKind::Number, Some(text), _) => text
+                .parse::<i64>()
+                .ok()
+                .map(Number::from)
+                .or_else(|| text.parse::<f64>().ok().and_then(Number::from_f64))
+                .map(|number| Some(Value::Number(number)))
+                .ok_or_else(|| "must be a number.".to_owned()),
+            (AttributeKind::Boolean, None, Value::Bool(flag)) => Ok(Some(Value::Bool(*flag))),
+            (AttributeKind::Boolean, Some(text), _) => match text.to_lowercase().as_str() {
+                "true" | "on" | "yes" | "1" => Ok(Some(Value::Bool(true))),
+                "false" | "off" | "no" | "0" => Ok(Some(Value::Bool(false))),
+                _ => Err("must be true or false.".to_owned()),
+            },
+            (AttributeKind::Date, Some(text), _) => NaiveDate::parse_from_str(text, "%Y-%m-%d")
+                .map(|date| Some(Value::String(date.to_string())))
+                .map_err(|_| "must be a date, as YYYY-MM-DD.".to_owned()),
+            (kind, _, _) => Err(format!("must be a {}.", kind.as_str())),
+        }
+    }
+}
+
+impl TryFrom<&str> for AttributeKind {
+    type Error = String;
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        AttributeKind::ALL
+            .into_iter()
+            .find(|kind| kind.as_str() == s)
+            .ok_or_else(|| format!("{} is not a valid attribute type.", s))
+    }
+}
+
+pub struct AttributeDefinition {
+    pub name: String,
+    pub label: String,
+    pub kind: String,
+    pub required: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A stored value as shown to admins and written to CSV exports.
+pub fn display_value(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        Value::Null => String::new(),
+        other => other.to_string(),
+    }
+}
+
+/// Checks an attribute name typed by an admin: lowercase letters, digits and
+/// underscores, starting with a letter, as it is used in forms and exports.
+pub fn normalize_name(name: &str) -> Result<String, String> {
+    let name = name.trim().to_lowercase();
+    let valid = name.len() <= 40
+        && name.starts_with(|c: char| c.is_ascii_lowercase())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+
+    if valid {
+        Ok(name)
+    } else {
+        Err(format!(
+            "{} is not a valid attribute name, use lowercase letters, digits and underscores.",
+            name
+        ))
+    }
+}
+
+/// Checks the attributes sent with a signup against the definitions,
+/// reporting the problems of every attribute at once.
+pub fn validate_attributes(
+    definitions: &[AttributeDefinition],
+    submitted: &Map<String, Value>,
+) -> Result<Map<String, Value>, Vec<FieldError>> {
+    let mut attributes = Map::new();
+    let mut errors = vec![];
+
+    for definition in definitions {
+        let Ok(kind) = AttributeKind::try_from(definition.kind.as_str()) else {
+            continue;
+        };
+        let field = format!("attributes.{}", definition.name);
+
+        match submitted
+            .get(&definition.name)
+            .map(|value| kind.parse(value))
+        {
+            Some(Ok(Some(value))) => {
+                attributes.insert(definition.name.clone(), value);
+            }
+            Some(Err(e)) => errors.push(FieldError {
+                field,
+                message: format!("{} {}", definition.label, e),
+            }),
+            None | Some(Ok(None)) => {
+                if definition.required {
+                    errors.push(FieldError {
+                        field,
+                        message: format!("{} is required.", definition.label),
+                    });
+                }
+            }
+        }
+    }
+
+    for name in submitted.keys() {
+        if !definitions
+            .iter()
+            .any(|definition| &definition.name == name)
+        {
+            errors.push(FieldError {
+                field: format!("attributes.{}", name),
+                message: format!("{} is not a known attribute.", name),
+            });
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(attributes)
+    } else {
+        Err(errors)
+    }
+}
+
+pub async fn list_attributes(pool: &PgPool) -> Result<Vec<AttributeDefinition>, sqlx::Error> {
+    sqlx::query_as!(
+        AttributeDefinition,
+        r#"
+        SELECT name, label, kind, required, created_at
+        FROM subscriber_attributes
+        ORDER BY created_at, name
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// `name` must come from `normalize_name`. Fails with a unique violation if
+/// there already is an attribute with this name.
+pub async fn add_attribute(
+    pool: &PgPool,
+    name: &str,
+    label: &str,
+    kind: AttributeKind,
+    required: bool,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriber_attributes (name, label, kind, required, created_at)
+        VALUES ($1, $2, $3, $4, now())
+        "#,
+        name,
+        label,
+        kind.as_str(),
+        required,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// The values already collected are kept. Returns whether there was such an
+/// attribute.
+pub async fn remove_attribute(pool: &PgPool, name: &str) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!("DELETE FROM subscriber_attributes WHERE name = $1", name)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use serde_json::{json, Map, Value};
+
+    use super::{normalize_name, validate_attributes, AttributeDefinition, AttributeKind};
+
+    fn definition(name: &str, kind: AttributeKind, required: bool) -> AttributeDefinition {
+        AttributeDefinition {
+            name: name.into(),
+            label: name.to_uppercase(),
+            kind: kind.as_str().into(),
+            required,
+            created_at: Utc::now(),
+        }
+    }
+
+    fn submitted(value: Value) -> Map<String, Value> {
+        value.as_object().unwrap().clone()
+    }
+
+    #[test]
+    fn values_are_converted_to_their_type() {
+        let definitions = vec![
+            definition("company", AttributeKind::Text, false),
+            definition("seats", AttributeKind::Number, false),
+            definition("student", AttributeKind::Boolean, false),
+            definition("birthday", AttributeKind::Date, false),
+        ];
+
+        let attributes = validate_attributes(
+            &definitions,
+            &submitted(json!({
+                "company": " Earthsea ",
+                "seats": "12",
+                "student": "on",
+                "birthday": "1929-10-21",
+            })),
+        )
+        .unwrap();
+        assert_eq!(
+            Value::Object(attributes),
+            json!({
+                "company": "Earthsea",
+                "seats": 12,
+                "student": true,
+                "birthday": "1929-10-21",
+            })
+        );
+
+        let attributes = validate_attributes(
+            &definitions,
+            &submitted(json!({ "seats": 1.5, "student": false, "company": "" })),
+        )
+        .unwrap();
+        assert_eq!(
+            Value::Object(attributes),
+            json!({ "seats": 1.5, "student": false })
+        );
+    }
+
+    #[test]
+    fn every_problem_is_reported() {
+        let definitions = vec![
+            definition("company", AttributeKind::Text, true),
+            definition("seats", AttributeKind::Number, false),
+            definition("birthday", AttributeKind::Date, false),
+        ];
+
+        let errors = validate_attributes(
+            &definitions,
+            &submitted(json!({
+                "seats": "many",
+                "birthday": "21/10/1929",
+                "shoe_size": "42",
+            })),
+        )
+        .unwrap_err();
+        let fields: Vec<&str> = errors.iter().map(|error| error.field.as_str()).collect();
+        assert_eq!(
+            fields,
+            vec![
+                "attributes.company",
+                "attributes.seats",
+                "attributes.birthday",
+                "attributes.shoe_size"
+            ]
+        );
+        assert_eq!(errors[0].message, "COMPANY is required.");
+        assert_eq!(errors[1].message, "SEATS must be a number.");
+    }
+
+    #[test]
+    fn names_are_checked() {
+        assert_eq!(normalize_name(" Company_Size ").unwrap(), "company_size");
+        assert!(normalize_name("").is_err());
+        assert!(normalize_name("2fa").is_err());
+        assert!(normalize_name("company size").is_err());
+        assert!(normalize_name(&"a".repeat(41)).is_err());
+    }
+}
@@ -0,0 +1,316 @@
+use std::collections::BTreeMap;
+
+use chrono::{Duration, Utc};
+use secrecy::Secret;
+use serde_json::json;
+use sqlx::PgPool;
+
+use crate::{
+    attribution::get_attribution,
+    consent::list_consents,
+    signing::{sha256_hex, sign, verify},
+    subscriber_import::scrub_rejected_report,
+};
+
+/// How long the links emailed to subscribers stay valid.
+pub const LINK_VALIDITY_HOURS: i64 = 24;
+
+/// What a subscriber can ask about the data held on them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataRequestAction {
+    Export,
+    Erase,
+}
+
+impl DataRequestAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DataRequestAction::Export => "export",
+            DataRequestAction::Erase => "erase",
+        }
+    }
+}
+
+impl TryFrom<&str> for DataRequestAction {
+    type Error = String;
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        [DataRequestAction::Export, DataRequestAction::Erase]
+            .into_iter()
+            .find(|action| action.as_str() == s)
+            .ok_or_else(|| format!("{} is not a valid data request.", s))
+    }
+}
+
+/// The query string of a link letting the owner of `email` perform `action`,
+/// valid for `LINK_VALIDITY_HOURS`.
+pub fn signed_query(secret: &Secret<String>, action: DataRequestAction, email: &str) -> String {
+    let expires = (Utc::now() + Duration::hours(LINK_VALIDITY_HOURS)).timestamp();
+    let signature = sign(secret, action.as_str(), &format!("{}\n{}", email, expires));
+
+    format!(
+        "email={}&expires={}&signature={}",
+        urlencoding::encode(email),
+        expires,
+        signature
+    )
+}
+
+pub fn verify_signature(
+    secret: &Secret<String>,
+    action: DataRequestAction,
+    email: &str,
+    expires: i64,
+    signature: &str,
+) -> bool {
+    if expires < Utc::now().timestamp() {
+        return false;
+    }
+
+    verify(
+        secret,
+        action.as_str(),
+        &format!("{}\n{}", email, expires),
+        signature,
+    )
+}
+
+/// What is kept about an erased address: enough to recognise it, not to
+/// recover it.
+pub fn email_hash(email: &str) -> String {
+    sha256_hex(&email.trim().to_lowercase())
+}
+
+/// Everything held about an email address, as a JSON document, or `None` if
+/// it is not known.
+pub async fn collect_subscriber_data(
+    pool: &PgPool,
+    email: &str,
+) -> Result<Option<serde_json::Value>, sqlx::Error> {
+    let Some(subscription) = sqlx::query!(
+        r#"SELECT id, email, name, status, subscribed_at, attributes FROM subscriptions WHERE lower(email) = lower($1)"#,
+        email,
+    )
+    .fetch_optional(pool)
+    .await?
+    else {
+        return Ok(None);
+    };
+
+    let tokens = sqlx::query!(
+        r#"SELECT subscription_token FROM subscription_tokens WHERE subscriber_id = $1"#,
+        subscription.id,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let tags = sqlx::query!(
+        r#"SELECT tag FROM subscription_tags WHERE subscriber_id = $1 ORDER BY tag"#,
+        subscription.id,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let history = sqlx::query!(
+        r#"
+        SELECT event, occurred_at
+        FROM subscription_events
+        WHERE subscriber_id = $1
+        ORDER BY occurred_at
+        "#,
+        subscription.id,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let sequence_emails = sqlx::query!(
+        r#"
+        SELECT st.subject, d.sent_at
+        FROM sequence_deliveries d
+        JOIN sequence_steps st ON st.step_id = d.step_id
+        WHERE d.subscriber_id = $1
+        ORDER BY d.sent_at
+        "#,
+        subscription.id,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let consents = list_consents(pool, subscription.id).await?;
+    let attribution = get_attribution(pool, subscription.id)
+        .await?
+        .unwrap_or_default();
+
+    Ok(Some(json!({
+        "subscription": {
+            "id": subscription.id,
+            "email": subscription.email,
+            "name": subscription.name,
+            "status": subscription.status,
+            "subscribed_at": subscription.subscribed_at.to_rfc3339(),
+            "attributes": subscription.attributes,
+            "tags": tags.into_iter().map(|row| row.tag).collect::<Vec<_>>(),
+            "signup_source": attribution
+                .fields()
+                .into_iter()
+                .filter_map(|(field, value)| Some((field, value?)))
+                .collect::<BTreeMap<_, _>>(),
+        },
+        "subscription_tokens": tokens
+            .into_iter()
+            .map(|row| row.subscription_token)
+            .collect::<Vec<_>>(),
+        "history": history
+            .into_iter()
+            .map(|row| json!({
+                "event": row.event,
+                "occurred_at": row.occurred_at.to_rfc3339(),
+            }))
+            .collect::<Vec<_>>(),
+        "welcome_sequence": sequence_emails
+            .into_iter()
+            .map(|row| json!({
+                "subject": row.subject,
+                "sent_at": row.sent_at.to_rfc3339(),
+            }))
+            .collect::<Vec<_>>(),
+        "consent": consents
+            .into_iter()
+            .map(|consent| json!({
+                "given_at": consent.kind,
+                "ip_address": consent.ip_address,
+                "user_agent": consent.user_agent,
+                "source": consent.source,
+                "recorded_at": consent.recorded_at.to_rfc3339(),
+            }))
+            .collect::<Vec<_>>(),
+    })))
+}
+
+/// Removes the address from every table and leaves a tombstone in its place,
+/// so that imports skip it from now on. Erasing an unknown address still
+/// leaves the tombstone.
+pub async fn erase_subscriber(pool: &PgPool, email: &str) -> Result<(), anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+
+    // Tokens and history go with the subscription, see the `ON DELETE CASCADE`s
+    sqlx::query!(
+        "DELETE FROM subscriptions WHERE lower(email) = lower($1)",
+        email
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    sqlx::query!(
+        "UPDATE audit_events SET target = '[erased subscriber]' WHERE lower(target) = lower($1)",
+        email
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    let imports = sqlx::query!(
+        r#"
+        SELECT import_id, rejected_report FROM subscriber_imports
+        WHERE strpos(lower(rejected_report), lower($1)) > 0
+        FOR UPDATE
+        "#,
+        email
+    )
+    .fetch_all(&mut *transaction)
+    .await?;
+    for import in imports {
+        sqlx::query!(
+            "UPDATE subscriber_imports SET rejected_report = $1 WHERE import_id = $2",
+            scrub_rejected_report(&import.rejected_report, email)?,
+            import.import_id,
+        )
+        .execute(&mut *transaction)
+        .await?;
+    }
+
+    sqlx::query!(
+        r#"
+        INSERT INTO erased_subscribers (email_hash, erased_at)
+        VALUES ($1, now())
+        ON CONFLICT (email_hash) DO NOTHING
+        "#,
+        email_hash(email),
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    transaction.commit().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use secrecy::Secret;
+
+    use super::{email_hash, signed_query, verify_signature, DataRequestAction};
+
+    fn parse(query: &str) -> (String, i64, String) {
+        let mut fields = query
+            .split('&')
+            .map(|field| field.split_once('=').unwrap().1);
+        (
+            urlencoding::decode(fields.next().unwrap())
+                .unwrap()
+                .into_owned(),
+            fields.next().unwrap().parse().unwrap(),
+            fields.next().unwrap().to_owned(),
+        )
+    }
+
+    #[test]
+    fn signed_links_are_only_valid_for_their_email_and_action() {
+        let secret = Secret::new("a-secret".to_string());
+        let query = signed_query(&secret, DataRequestAction::Export, "ursula@example.com");
+        let (email, expires, signature) = parse(&query);
+        assert_eq!(email, "ursula@example.com");
+
+        let verify = |action, email: &str, signature: &str| {
+            verify_signature(&secret, action, email, expires, signature)
+        };
+        assert!(verify(DataRequestAction::Export, &email, &signature));
+        assert!(!verify(DataRequestAction::Erase, &email, &signature));
+        assert!(!verify(
+            DataRequestAction::Export,
+            "octavia@example.com",
+            &signature
+        ));
+        assert!(!verify(DataRequestAction::Export, &email, "00ff"));
+        assert!(!verify(DataRequestAction::Export, &email, "not hex"));
+
+        let other_secret = Secret::new("another-secret".to_string());
+        assert!(!verify_signature(
+            &other_secret,
+            DataRequestAction::Export,
+            &email,
+            expires,
+            &signature
+        ));
+    }
+
+    #[test]
+    fn expired_links_are_refused() {
+        let secret = Secret::new("a-secret".to_string());
+        let query = signed_query(&secret, DataRequestAction::Erase, "ursula@example.com");
+        let (email, _, signature) = parse(&query);
+
+        assert!(!verify_signature(
+            &secret,
+            DataRequestAction::Erase,
+            &email,
+            0,
+            &signature
+        ));
+    }
+
+    #[test]
+    fn tombstones_ignore_case_and_surrounding_spaces() {
+        assert_eq!(
+            email_hash(" Ursula@Example.com "),
+            email_hash("ursula@example.com")
+        );
+    }
+}
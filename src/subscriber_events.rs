@@ -0,0 +1,75 @@
+use chrono::{DateTime, Utc};
+use sqlx::{PgExecutor, PgPool};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubscriberEvent {
+    Subscribed,
+    Imported,
+    /// Sent a reminder to confirm.
+    Reminded,
+    Confirmed,
+    Unsubscribed,
+}
+
+impl SubscriberEvent {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SubscriberEvent::Subscribed => "subscribed",
+            SubscriberEvent::Imported => "imported",
+            SubscriberEvent::Reminded => "reminded",
+            SubscriberEvent::Confirmed => "confirmed",
+            SubscriberEvent::Unsubscribed => "unsubscribed",
+        }
+    }
+}
+
+pub struct SubscriberHistoryEntry {
+    pub event: String,
+    pub actor_username: Option<String>,
+    pub occurred_at: DateTime<Utc>,
+}
+
+/// Adds an entry to the history of a subscriber. `actor_id` is the admin
+/// acting on their behalf, or `None` when the subscriber did it themselves.
+pub async fn record_event(
+    db: impl PgExecutor<'_>,
+    subscriber_id: Uuid,
+    event: SubscriberEvent,
+    actor_id: Option<Uuid>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO subscription_events (event_id, subscriber_id, event, actor_id, occurred_at)
+        VALUES ($1, $2, $3, $4, now())
+        "#,
+        Uuid::new_v4(),
+        subscriber_id,
+        event.as_str(),
+        actor_id,
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+/// Oldest entries first.
+pub async fn get_history(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Vec<SubscriberHistoryEntry>, sqlx::Error> {
+    sqlx::query_as!(
+        SubscriberHistoryEntry,
+        r#"
+        SELECT e.event, u.username AS "actor_username?", e.occurred_at
+        FROM subscription_events e
+        LEFT JOIN users u ON u.user_id = e.actor_id
+        WHERE e.subscriber_id = $1
+        ORDER BY e.occurred_at
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(pool)
+    .await
+}
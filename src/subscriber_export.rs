@@ -0,0 +1,318 @@
+use chrono::{DateTime, Days, NaiveDate, Utc};
+use futures::{Stream, TryStreamExt};
+use serde::Serialize;
+use serde_json::Value;
+use sqlx::PgPool;
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+use crate::{
+    domain::SubscriptionStatus,
+    subscriber_attributes::{display_value, list_attributes},
+};
+
+/// How many encoded rows may wait for a slow reader before the database cursor
+/// is paused.
+const BUFFERED_ROWS: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    Ndjson,
+}
+
+impl ExportFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Ndjson => "ndjson",
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::Ndjson => "application/x-ndjson",
+        }
+    }
+}
+
+impl TryFrom<&str> for ExportFormat {
+    type Error = String;
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        [ExportFormat::Csv, ExportFormat::Ndjson]
+            .into_iter()
+            .find(|format| format.as_str() == s)
+            .ok_or_else(|| format!("{} is not a supported export format.", s))
+    }
+}
+
+/// Which subscribers to export. Dates are inclusive and compared with the day
+/// of subscription, in UTC.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ExportFilter {
+    pub status: Option<SubscriptionStatus>,
+    pub subscribed_from: Option<NaiveDate>,
+    pub subscribed_until: Option<NaiveDate>,
+}
+
+impl ExportFilter {
+    /// A short summary of the filter, for the audit log.
+    pub fn describe(&self) -> String {
+        let mut parts = vec![];
+        if let Some(status) = self.status {
+            parts.push(format!("status={}", status.as_str()));
+        }
+        if let Some(from) = self.subscribed_from {
+            parts.push(format!("from={}", from));
+        }
+        if let Some(until) = self.subscribed_until {
+            parts.push(format!("until={}", until));
+        }
+        parts.join(" ")
+    }
+}
+
+pub fn parse_date(s: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .map_err(|_| format!("{} is not a valid date, expected YYYY-MM-DD.", s))
+}
+
+struct ExportedSubscriber {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: String,
+    subscribed_at: DateTime<Utc>,
+    attributes: Value,
+}
+
+#[derive(Serialize)]
+struct ExportRecord<'a> {
+    id: Uuid,
+    email: &'a str,
+    name: &'a str,
+    status: &'a str,
+    subscribed_at: String,
+    attributes: &'a Value,
+}
+
+impl ExportedSubscriber {
+    fn record(&self) -> ExportRecord<'_> {
+        ExportRecord {
+            id: self.id,
+            email: &self.email,
+            name: &self.name,
+            status: &self.status,
+            subscribed_at: self.subscribed_at.to_rfc3339(),
+            attributes: &self.attributes,
+        }
+    }
+
+    /// The CSV columns, then one column per attribute in `attribute_names`.
+    fn csv_fields(&self, attribute_names: &[String]) -> Vec<String> {
+        let mut fields = vec![
+            self.id.to_string(),
+            self.email.clone(),
+            self.name.clone(),
+            self.status.clone(),
+            self.subscribed_at.to_rfc3339(),
+        ];
+        fields.extend(attribute_names.iter().map(|name| {
+            self.attributes
+                .get(name)
+                .map(display_value)
+                .unwrap_or_default()
+        }));
+        fields
+    }
+}
+
+fn csv_line(fields: impl IntoIterator<Item = String>) -> Result<Vec<u8>, anyhow::Error> {
+    let mut writer = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(vec![]);
+    writer.write_record(fields)?;
+    Ok(writer.into_inner()?)
+}
+
+/// CSV exports get a column per attribute, named `attributes.<name>`, while
+/// NDJSON exports carry every stored attribute as an object.
+fn encode_header(
+    format: ExportFormat,
+    attribute_names: &[String],
+) -> Option<Result<Vec<u8>, anyhow::Error>> {
+    match format {
+        ExportFormat::Csv => {
+            let columns = ["id", "email", "name", "status", "subscribed_at"]
+                .into_iter()
+                .map(str::to_owned)
+                .chain(
+                    attribute_names
+                        .iter()
+                        .map(|name| format!("attributes.{}", name)),
+                );
+            Some(csv_line(columns))
+        }
+        ExportFormat::Ndjson => None,
+    }
+}
+
+fn encode(
+    format: ExportFormat,
+    subscriber: &ExportedSubscriber,
+    attribute_names: &[String],
+) -> Result<Vec<u8>, anyhow::Error> {
+    match format {
+        ExportFormat::Csv => csv_line(subscriber.csv_fields(attribute_names)),
+        ExportFormat::Ndjson => {
+            let mut line = serde_json::to_vec(&subscriber.record())?;
+            line.push(b'\n');
+            Ok(line)
+        }
+    }
+}
+
+/// Streams the matching subscribers, already encoded, one row at a time. Rows
+/// are read from a database cursor by a background task, so the export never
+/// holds the whole table in memory.
+pub fn export_subscribers(
+    pool: PgPool,
+    filter: ExportFilter,
+    format: ExportFormat,
+) -> impl Stream<Item = Result<Vec<u8>, anyhow::Error>> {
+    let (sender, receiver) = mpsc::channel(BUFFERED_ROWS);
+
+    tokio::spawn(async move {
+        let attribute_names = match list_attributes(&pool).await {
+            Ok(definitions) => definitions
+                .into_iter()
+                .map(|definition| definition.name)
+                .collect::<Vec<_>>(),
+            Err(e) => {
+                let _ = sender.send(Err(e.into())).await;
+                return;
+            }
+        };
+
+        if let Some(header) = encode_header(format, &attribute_names) {
+            let failed = header.is_err();
+            if sender.send(header).await.is_err() || failed {
+                return;
+            }
+        }
+
+        let from = filter
+            .subscribed_from
+            .and_then(|date| date.and_hms_opt(0, 0, 0))
+            .map(|at| at.and_utc());
+        let until = filter
+            .subscribed_until
+            .and_then(|date| date.checked_add_days(Days::new(1)))
+            .and_then(|date| date.and_hms_opt(0, 0, 0))
+            .map(|at| at.and_utc());
+
+        let mut rows = sqlx::query_as!(
+            ExportedSubscriber,
+            r#"
+            SELECT id, email, name, status, subscribed_at, attributes
+            FROM subscriptions
+            WHERE ($1::text IS NULL OR status = $1)
+                AND ($2::timestamptz IS NULL OR subscribed_at >= $2)
+                AND ($3::timestamptz IS NULL OR subscribed_at < $3)
+            ORDER BY subscribed_at, id
+            "#,
+            filter.status.map(|s| s.as_str()),
+            from,
+            until,
+        )
+        .fetch(&pool);
+
+        loop {
+            let item = match rows.try_next().await {
+                Ok(Some(subscriber)) => encode(format, &subscriber, &attribute_names),
+                Ok(None) => break,
+                Err(e) => Err(e.into()),
+            };
+            let failed = item.is_err();
+
+            // The reader went away, stop reading from the database
+            if sender.send(item).await.is_err() || failed {
+                break;
+            }
+        }
+    });
+
+    futures::stream::unfold(receiver, |mut receiver| async move {
+        receiver.recv().await.map(|item| (item, receiver))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use serde_json::json;
+    use uuid::Uuid;
+
+    use super::{encode, encode_header, ExportFilter, ExportFormat, ExportedSubscriber};
+    use crate::domain::SubscriptionStatus;
+
+    fn subscriber() -> ExportedSubscriber {
+        ExportedSubscriber {
+            id: Uuid::nil(),
+            email: "ursula@example.com".into(),
+            name: "Le Guin, Ursula".into(),
+            status: "confirmed".into(),
+            subscribed_at: Utc.with_ymd_and_hms(2024, 3, 1, 12, 30, 0).unwrap(),
+            attributes: json!({ "company": "Earthsea", "seats": 12 }),
+        }
+    }
+
+    #[test]
+    fn csv_rows_are_quoted_when_needed() {
+        let row = encode(ExportFormat::Csv, &subscriber(), &[]).unwrap();
+        assert_eq!(
+            String::from_utf8(row).unwrap(),
+            "00000000-0000-0000-0000-000000000000,ursula@example.com,\"Le Guin, Ursula\",confirmed,2024-03-01T12:30:00+00:00\n"
+        );
+    }
+
+    #[test]
+    fn ndjson_rows_are_one_object_per_line() {
+        let row = encode(ExportFormat::Ndjson, &subscriber(), &[]).unwrap();
+        let row = String::from_utf8(row).unwrap();
+        assert!(row.ends_with("}\n"));
+
+        let value: serde_json::Value = serde_json::from_str(row.trim_end()).unwrap();
+        assert_eq!(value["email"], "ursula@example.com");
+        assert_eq!(value["subscribed_at"], "2024-03-01T12:30:00+00:00");
+        assert_eq!(value["attributes"]["seats"], 12);
+    }
+
+    #[test]
+    fn csv_exports_have_a_column_per_attribute() {
+        let names = vec!["seats".to_owned(), "birthday".to_owned()];
+
+        let header = encode_header(ExportFormat::Csv, &names).unwrap().unwrap();
+        assert_eq!(
+            String::from_utf8(header).unwrap(),
+            "id,email,name,status,subscribed_at,attributes.seats,attributes.birthday\n"
+        );
+
+        let row = encode(ExportFormat::Csv, &subscriber(), &names).unwrap();
+        assert!(String::from_utf8(row)
+            .unwrap()
+            .ends_with(",2024-03-01T12:30:00+00:00,12,\n"));
+    }
+
+    #[test]
+    fn filters_are_described_for_the_audit_log() {
+        let filter = ExportFilter {
+            status: Some(SubscriptionStatus::Confirmed),
+            subscribed_from: Some(super::parse_date("2024-01-01").unwrap()),
+            subscribed_until: None,
+        };
+        assert_eq!(filter.describe(), "status=confirmed from=2024-01-01");
+        assert!(super::parse_date("01/01/2024").is_err());
+    }
+}
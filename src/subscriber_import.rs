@@ -0,0 +1,575 @@
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, NaiveDate, Utc};
+use csv::{ReaderBuilder, Trim};
+use serde_json::Map;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    domain::{NewSubscriber, SubscriberEmail, SubscriberName, SubscriptionStatus},
+    routes::generate_subscriptions_token,
+    subscriber_data::email_hash,
+    subscriber_events::SubscriberEvent,
+};
+
+/// Rows are written this many at a time, to keep each statement reasonably
+/// sized on large files.
+const BATCH_SIZE: usize = 1000;
+
+/// What to do with imported subscribers that do not have a status of their
+/// own in the file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportMode {
+    SendConfirmation,
+    MarkConfirmed,
+}
+
+impl ImportMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ImportMode::SendConfirmation => "send_confirmation",
+            ImportMode::MarkConfirmed => "mark_confirmed",
+        }
+    }
+
+    fn default_status(&self) -> SubscriptionStatus {
+        match self {
+            ImportMode::SendConfirmation => SubscriptionStatus::PendingConfirmation,
+            ImportMode::MarkConfirmed => SubscriptionStatus::Confirmed,
+        }
+    }
+}
+
+impl TryFrom<&str> for ImportMode {
+    type Error = String;
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        [ImportMode::SendConfirmation, ImportMode::MarkConfirmed]
+            .into_iter()
+            .find(|mode| mode.as_str() == s)
+            .ok_or_else(|| format!("{} is not a valid import mode.", s))
+    }
+}
+
+pub struct ImportedRow {
+    pub line: u64,
+    pub subscriber: NewSubscriber,
+    pub status: SubscriptionStatus,
+    pub subscribed_at: Option<DateTime<Utc>>,
+}
+
+pub struct RejectedRow {
+    pub line: u64,
+    pub email: String,
+    pub name: String,
+    pub reason: String,
+}
+
+pub struct ParsedImport {
+    pub rows: Vec<ImportedRow>,
+    pub rejected: Vec<RejectedRow>,
+}
+
+/// Subscribers who still have to confirm, with the token to do so.
+pub struct PendingConfirmation {
+    pub email: SubscriberEmail,
+    pub token: String,
+}
+
+pub struct ImportResult {
+    pub import_id: Uuid,
+    pub pending_confirmation: Vec<PendingConfirmation>,
+}
+
+pub struct SubscriberImport {
+    pub file_name: String,
+    pub imported_count: i32,
+    pub rejected_count: i32,
+    pub rejected_report: String,
+    pub created_at: DateTime<Utc>,
+}
+
+struct Columns {
+    email: usize,
+    name: usize,
+    status: Option<usize>,
+    subscribed_at: Option<usize>,
+}
+
+/// Validates every row of a CSV file with a header. Only a malformed header
+/// fails the whole file, bad rows are set aside with the reason.
+pub fn parse_csv(data: &[u8], mode: ImportMode) -> Result<ParsedImport, String> {
+    let mut reader = ReaderBuilder::new()
+        .trim(Trim::All)
+        .flexible(true)
+        .from_reader(data);
+
+    let headers = reader
+        .headers()
+        .map_err(|e| format!("The file could not be read: {}", e))?;
+    let column = |name: &str| {
+        headers
+            .iter()
+            .position(|header| header.eq_ignore_ascii_case(name))
+    };
+    let (Some(email), Some(name)) = (column("email"), column("name")) else {
+        return Err("The file needs an email and a name column.".into());
+    };
+    let columns = Columns {
+        email,
+        name,
+        status: column("status"),
+        subscribed_at: column("subscribed_at"),
+    };
+
+    let mut parsed = ParsedImport {
+        rows: vec![],
+        rejected: vec![],
+    };
+    let mut seen: HashMap<String, u64> = HashMap::new();
+
+    for record in reader.records() {
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                parsed.rejected.push(RejectedRow {
+                    line: e.position().map(|p| p.line()).unwrap_or_default(),
+                    email: "".into(),
+                    name: "".into(),
+                    reason: e.to_string(),
+                });
+                continue;
+            }
+        };
+
+        let line = record.position().map(|p| p.line()).unwrap_or_default();
+        let field = |index: Option<usize>| {
+            index
+                .and_then(|i| record.get(i))
+                .filter(|value| !value.is_empty())
+        };
+        let email = field(Some(columns.email)).unwrap_or("").to_owned();
+        let name = field(Some(columns.name)).unwrap_or("").to_owned();
+
+        let row = parse_row(
+            line,
+            &email,
+            &name,
+            field(columns.status),
+            field(columns.subscribed_at),
+            mode,
+        )
+        // Addresses differing only by case are the same subscriber
+        .and_then(
+            |row| match seen.get(&row.subscriber.email.as_ref().to_lowercase()) {
+                Some(first_line) => Err(format!("Duplicate of line {}.", first_line)),
+                None => Ok(row),
+            },
+        );
+
+        match row {
+            Ok(row) => {
+                seen.insert(row.subscriber.email.as_ref().to_lowercase(), line);
+                parsed.rows.push(row);
+            }
+            Err(reason) => parsed.rejected.push(RejectedRow {
+                line,
+                email,
+                name,
+                reason,
+            }),
+        }
+    }
+
+    Ok(parsed)
+}
+
+fn parse_row(
+    line: u64,
+    email: &str,
+    name: &str,
+    status: Option<&str>,
+    subscribed_at: Option<&str>,
+    mode: ImportMode,
+) -> Result<ImportedRow, String> {
+    let subscriber = NewSubscriber {
+        email: SubscriberEmail::parse(email.to_owned())?,
+        name: SubscriberName::parse(name.to_owned())?,
+        attributes: Map::new(),
+    };
+
+    let status = match status {
+        Some(status) => SubscriptionStatus::try_from(status)?,
+        None => mode.default_status(),
+    };
+
+    let subscribed_at = subscribed_at.map(parse_date).transpose()?;
+
+    Ok(ImportedRow {
+        line,
+        subscriber,
+        status,
+        subscribed_at,
+    })
+}
+
+/// Accepts full RFC 3339 timestamps as well as plain dates.
+fn parse_date(s: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(at) = DateTime::parse_from_rfc3339(s) {
+        return Ok(at.with_timezone(&Utc));
+    }
+
+    NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .ok()
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .map(|at| at.and_utc())
+        .ok_or_else(|| format!("{} is not a valid date.", s))
+}
+
+/// Sets aside the rows of addresses that were erased at their owner's
+/// request, see `subscriber_data::erase_subscriber`.
+pub async fn reject_erased(pool: &PgPool, parsed: &mut ParsedImport) -> Result<(), sqlx::Error> {
+    let hashes: Vec<String> = parsed
+        .rows
+        .iter()
+        .map(|row| email_hash(row.subscriber.email.as_ref()))
+        .collect();
+
+    let erased: HashSet<String> = sqlx::query!(
+        "SELECT email_hash FROM erased_subscribers WHERE email_hash = ANY($1)",
+        &hashes,
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|row| row.email_hash)
+    .collect();
+
+    if erased.is_empty() {
+        return Ok(());
+    }
+
+    let rows = std::mem::take(&mut parsed.rows);
+    for (row, hash) in rows.into_iter().zip(hashes) {
+        if erased.contains(&hash) {
+            parsed.rejected.push(RejectedRow {
+                line: row.line,
+                email: row.subscriber.email.as_ref().to_owned(),
+                name: row.subscriber.name.as_ref().to_owned(),
+                reason: "This address was erased at its owner's request.".into(),
+            });
+        } else {
+            parsed.rows.push(row);
+        }
+    }
+    parsed.rejected.sort_by_key(|row| row.line);
+
+    Ok(())
+}
+
+pub fn rejected_report(rejected: &[RejectedRow]) -> Result<String, anyhow::Error> {
+    let mut writer = csv::Writer::from_writer(vec![]);
+    writer.write_record(["line", "email", "name", "reason"])?;
+    for row in rejected {
+        writer.write_record([&row.line.to_string(), &row.email, &row.name, &row.reason])?;
+    }
+
+    Ok(String::from_utf8(writer.into_inner()?)?)
+}
+
+/// Blanks out every field but the line number of the report rows that
+/// mention the address, whatever its case: the reason can quote it, and the
+/// name is as personal as the address.
+pub fn scrub_rejected_report(report: &str, email: &str) -> Result<String, anyhow::Error> {
+    let email = email.to_lowercase();
+    let mut reader = csv::Reader::from_reader(report.as_bytes());
+    let mut writer = csv::Writer::from_writer(vec![]);
+    writer.write_record(reader.headers()?)?;
+    for record in reader.records() {
+        let record = record?;
+        if record
+            .iter()
+            .any(|field| field.to_lowercase().contains(&email))
+        {
+            let line = record.get(0).unwrap_or_default();
+            writer.write_record([
+                line,
+                "[erased subscriber]",
+                "[erased subscriber]",
+                "[erased subscriber]",
+            ])?;
+        } else {
+            writer.write_record(&record)?;
+        }
+    }
+
+    Ok(String::from_utf8(writer.into_inner()?)?)
+}
+
+/// Upserts the valid rows in a single transaction and keeps a record of the
+/// import. Existing subscribers get their name updated, but their status only
+/// changes if they had not confirmed yet or the file unsubscribes them:
+/// importing never resubscribes someone who opted out.
+pub async fn import_subscribers(
+    pool: &PgPool,
+    actor_id: Uuid,
+    file_name: &str,
+    parsed: &ParsedImport,
+    mode: ImportMode,
+) -> Result<ImportResult, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    let mut pending_confirmation = vec![];
+
+    for batch in parsed.rows.chunks(BATCH_SIZE) {
+        let pending = upsert_batch(&mut transaction, actor_id, batch).await?;
+        if mode == ImportMode::SendConfirmation {
+            pending_confirmation.extend(store_tokens(&mut transaction, pending).await?);
+        }
+    }
+
+    let import_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriber_imports (
+            import_id, actor_id, file_name, imported_count, rejected_count,
+            rejected_report, created_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, now())
+        "#,
+        import_id,
+        actor_id,
+        file_name,
+        parsed.rows.len() as i32,
+        parsed.rejected.len() as i32,
+        rejected_report(&parsed.rejected)?,
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    transaction.commit().await?;
+
+    Ok(ImportResult {
+        import_id,
+        pending_confirmation,
+    })
+}
+
+/// Returns the subscribers of the batch left pending confirmation.
+async fn upsert_batch(
+    transaction: &mut Transaction<'_, Postgres>,
+    actor_id: Uuid,
+    batch: &[ImportedRow],
+) -> Result<Vec<(Uuid, SubscriberEmail)>, sqlx::Error> {
+    let now = Utc::now();
+    let ids: Vec<Uuid> = batch.iter().map(|_| Uuid::new_v4()).collect();
+    let emails: Vec<String> = batch
+        .iter()
+        .map(|row| row.subscriber.email.as_ref().to_owned())
+        .collect();
+    let names: Vec<String> = batch
+        .iter()
+        .map(|row| row.subscriber.name.as_ref().to_owned())
+        .collect();
+    let subscribed_at: Vec<DateTime<Utc>> = batch
+        .iter()
+        .map(|row| row.subscribed_at.unwrap_or(now))
+        .collect();
+    let statuses: Vec<String> = batch
+        .iter()
+        .map(|row| row.status.as_str().to_owned())
+        .collect();
+
+    let upserted = sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        SELECT * FROM UNNEST($1::uuid[], $2::text[], $3::text[], $4::timestamptz[], $5::text[])
+        ON CONFLICT ((lower(email))) DO UPDATE
+        SET name = EXCLUDED.name,
+            status = CASE
+                WHEN subscriptions.status = 'pending_confirmation'
+                    OR EXCLUDED.status = 'unsubscribed'
+                THEN EXCLUDED.status
+                ELSE subscriptions.status
+            END
+        RETURNING id, email, status
+        "#,
+        &ids,
+        &emails,
+        &names,
+        &subscribed_at,
+        &statuses,
+    )
+    .fetch_all(&mut **transaction)
+    .await?;
+
+    let upserted_ids: Vec<Uuid> = upserted.iter().map(|row| row.id).collect();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscription_events (event_id, subscriber_id, event, actor_id, occurred_at)
+        SELECT gen_random_uuid(), subscriber_id, $2, $3, now()
+        FROM UNNEST($1::uuid[]) AS subscriber_id
+        "#,
+        &upserted_ids,
+        SubscriberEvent::Imported.as_str(),
+        actor_id,
+    )
+    .execute(&mut **transaction)
+    .await?;
+
+    Ok(upserted
+        .into_iter()
+        .filter(|row| row.status == SubscriptionStatus::PendingConfirmation.as_str())
+        .filter_map(|row| Some((row.id, SubscriberEmail::parse(row.email).ok()?)))
+        .collect())
+}
+
+async fn store_tokens(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscribers: Vec<(Uuid, SubscriberEmail)>,
+) -> Result<Vec<PendingConfirmation>, sqlx::Error> {
+    let tokens: Vec<String> = subscribers
+        .iter()
+        .map(|_| generate_subscriptions_token())
+        .collect();
+    let ids: Vec<Uuid> = subscribers.iter().map(|(id, _)| *id).collect();
+
+    sqlx::query!(
+        r#"
+        INSERT INTO subscription_tokens (subscription_token, subscriber_id)
+        SELECT * FROM UNNEST($1::text[], $2::uuid[])
+        "#,
+        &tokens,
+        &ids,
+    )
+    .execute(&mut **transaction)
+    .await?;
+
+    // Reminders start over from this confirmation email
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions SET confirmation_sent_at = now(), confirmation_reminders = 0
+        WHERE id = ANY($1)
+        "#,
+        &ids,
+    )
+    .execute(&mut **transaction)
+    .await?;
+
+    Ok(subscribers
+        .into_iter()
+        .zip(tokens)
+        .map(|((_, email), token)| PendingConfirmation { email, token })
+        .collect())
+}
+
+pub async fn get_import(
+    pool: &PgPool,
+    import_id: Uuid,
+) -> Result<Option<SubscriberImport>, sqlx::Error> {
+    sqlx::query_as!(
+        SubscriberImport,
+        r#"
+        SELECT file_name, imported_count, rejected_count, rejected_report, created_at
+        FROM subscriber_imports
+        WHERE import_id = $1
+        "#,
+        import_id,
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_csv, rejected_report, scrub_rejected_report, ImportMode};
+    use crate::domain::SubscriptionStatus;
+
+    #[test]
+    fn valid_rows_are_kept_and_invalid_ones_rejected_with_a_reason() {
+        let data = b"email,name\nursula@example.com,Ursula\nnot-an-email,Someone\n";
+        let parsed = parse_csv(data, ImportMode::SendConfirmation).unwrap();
+
+        assert_eq!(parsed.rows.len(), 1);
+        assert_eq!(
+            parsed.rows[0].subscriber.email.as_ref(),
+            "ursula@example.com"
+        );
+        assert_eq!(
+            parsed.rows[0].status,
+            SubscriptionStatus::PendingConfirmation
+        );
+
+        assert_eq!(parsed.rejected.len(), 1);
+        assert_eq!(parsed.rejected[0].line, 3);
+        assert!(parsed.rejected[0]
+            .reason
+            .contains("not a valid subscriber email"));
+    }
+
+    #[test]
+    fn optional_columns_override_the_mode() {
+        let data = b"Name,Email,Status,Subscribed_at\n\
+            Ursula,ursula@example.com,unsubscribed,2020-01-31\n\
+            Octavia,octavia@example.com,,2021-06-01T12:00:00Z\n";
+        let parsed = parse_csv(data, ImportMode::MarkConfirmed).unwrap();
+
+        assert_eq!(parsed.rejected.len(), 0);
+        assert_eq!(parsed.rows[0].status, SubscriptionStatus::Unsubscribed);
+        assert_eq!(
+            parsed.rows[0].subscribed_at.unwrap().to_rfc3339(),
+            "2020-01-31T00:00:00+00:00"
+        );
+        assert_eq!(parsed.rows[1].status, SubscriptionStatus::Confirmed);
+    }
+
+    #[test]
+    fn bad_statuses_dates_and_duplicates_are_rejected() {
+        let data = b"email,name,status,subscribed_at\n\
+            a@example.com,A,subscribed,\n\
+            b@example.com,B,,yesterday\n\
+            c@example.com,C,,\n\
+            C@Example.com,C again,,\n";
+        let parsed = parse_csv(data, ImportMode::MarkConfirmed).unwrap();
+
+        assert_eq!(parsed.rows.len(), 1);
+        let reasons: Vec<&str> = parsed.rejected.iter().map(|r| r.reason.as_str()).collect();
+        assert_eq!(
+            reasons,
+            [
+                "subscribed is not a valid subscription status.",
+                "yesterday is not a valid date.",
+                "Duplicate of line 4.",
+            ]
+        );
+    }
+
+    #[test]
+    fn a_file_without_the_required_columns_is_refused() {
+        assert!(parse_csv(b"address,full name\n", ImportMode::MarkConfirmed).is_err());
+    }
+
+    #[test]
+    fn the_report_lists_rejected_rows() {
+        let data = b"email,name\nnot-an-email,\"Someone, Else\"\n";
+        let parsed = parse_csv(data, ImportMode::MarkConfirmed).unwrap();
+        let report = rejected_report(&parsed.rejected).unwrap();
+
+        assert!(report.starts_with("line,email,name,reason\n"));
+        assert!(report.contains("2,not-an-email,\"Someone, Else\","));
+    }
+
+    #[test]
+    fn scrubbing_the_report_removes_the_whole_row_whatever_the_case() {
+        let data = b"email,name,status\nUrsula@Example.com,Ursula Le Guin,sometimes\nnot-an-email,Someone,confirmed\n";
+        let parsed = parse_csv(data, ImportMode::MarkConfirmed).unwrap();
+        let report = rejected_report(&parsed.rejected).unwrap();
+
+        let scrubbed = scrub_rejected_report(&report, "ursula@example.com").unwrap();
+
+        assert!(!scrubbed.to_lowercase().contains("ursula"));
+        assert!(
+            scrubbed.contains("2,[erased subscriber],[erased subscriber],[erased subscriber]\n")
+        );
+        assert!(scrubbed.contains("not-an-email,Someone,"));
+    }
+}
@@ -0,0 +1,215 @@
+use secrecy::Secret;
+use sqlx::{PgConnection, PgExecutor, PgPool};
+use uuid::Uuid;
+
+use crate::signing::{sign, verify};
+
+/// Longest tag name accepted.
+const MAX_TAG_LENGTH: usize = 40;
+
+/// Whether tags are put on subscribers or taken off them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TagAction {
+    Add,
+    Remove,
+}
+
+impl TagAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TagAction::Add => "add",
+            TagAction::Remove => "remove",
+        }
+    }
+}
+
+impl TryFrom<&str> for TagAction {
+    type Error = String;
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        [TagAction::Add, TagAction::Remove]
+            .into_iter()
+            .find(|action| action.as_str() == s)
+            .ok_or_else(|| format!("{} is not a valid tag action.", s))
+    }
+}
+
+pub struct TagSummary {
+    pub name: String,
+    pub subscribers: i64,
+}
+
+/// Checks a tag typed by an admin: lowercase letters, digits and hyphens,
+/// starting with a letter or a digit, such as `conference-2026`.
+pub fn normalize_tag(tag: &str) -> Result<String, String> {
+    let tag = tag.trim().to_lowercase();
+    let valid = tag.len() <= MAX_TAG_LENGTH
+        && tag.starts_with(|c: char| c.is_ascii_lowercase() || c.is_ascii_digit())
+        && tag
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+
+    if valid {
+        Ok(tag)
+    } else {
+        Err(format!(
+            "{} is not a valid tag, use lowercase letters, digits and hyphens.",
+            tag
+        ))
+    }
+}
+
+/// Parses a comma separated list of tags, sorted and without duplicates.
+pub fn parse_tags(tags: &str) -> Result<Vec<String>, String> {
+    let mut tags = tags
+        .split(',')
+        .filter(|tag| !tag.trim().is_empty())
+        .map(normalize_tag)
+        .collect::<Result<Vec<_>, _>>()?;
+    tags.sort();
+    tags.dedup();
+    Ok(tags)
+}
+
+/// The value of the `tags` field or query parameter attaching `tags` to the
+/// people signing up, as `tag,tag.signature`. It is signed so that visitors
+/// cannot tag themselves, and does not expire, as it is pasted in forms.
+pub fn signup_tags_token(secret: &Secret<String>, tags: &[String]) -> String {
+    let tags = tags.join(",");
+    let signature = sign(secret, "signup-tags", &tags);
+
+    format!("{}.{}", tags, signature)
+}
+
+/// The tags of a token made by `signup_tags_token`, or `None` if it was not.
+pub fn verify_signup_tags(secret: &Secret<String>, token: &str) -> Option<Vec<String>> {
+    let (tags, signature) = token.rsplit_once('.')?;
+    if !verify(secret, "signup-tags", tags, signature) {
+        return None;
+    }
+
+    parse_tags(tags).ok()
+}
+
+/// Creates the tags that do not exist yet. Returns how many tags were put on
+/// subscribers who did not have them already.
+pub async fn add_tags(
+    connection: &mut PgConnection,
+    subscriber_ids: &[Uuid],
+    tags: &[String],
+) -> Result<u64, sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO tags (name, created_at)
+        SELECT unnest($1::text[]), now()
+        ON CONFLICT (name) DO NOTHING
+        "#,
+        tags,
+    )
+    .execute(&mut *connection)
+    .await?;
+
+    let result = sqlx::query!(
+        r#"
+        INSERT INTO subscription_tags (subscriber_id, tag, tagged_at)
+        SELECT subscriber_id, tag, now()
+        FROM unnest($1::uuid[]) AS subscriber_id, unnest($2::text[]) AS tag
+        ON CONFLICT (subscriber_id, tag) DO NOTHING
+        "#,
+        subscriber_ids,
+        tags,
+    )
+    .execute(&mut *connection)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+/// Returns how many tags were taken off subscribers. The tags themselves stay.
+pub async fn remove_tags(
+    db: impl PgExecutor<'_>,
+    subscriber_ids: &[Uuid],
+    tags: &[String],
+) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        DELETE FROM subscription_tags
+        WHERE subscriber_id = ANY($1) AND tag = ANY($2)
+        "#,
+        subscriber_ids,
+        tags,
+    )
+    .execute(db)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+pub async fn list_tags(pool: &PgPool) -> Result<Vec<TagSummary>, sqlx::Error> {
+    sqlx::query_as!(
+        TagSummary,
+        r#"
+        SELECT tags.name, COUNT(subscription_tags.subscriber_id) AS "subscribers!"
+        FROM tags
+        LEFT JOIN subscription_tags ON subscription_tags.tag = tags.name
+        GROUP BY tags.name
+        ORDER BY tags.name
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Takes the tag off every subscriber. Returns whether there was such a tag.
+pub async fn delete_tag(pool: &PgPool, name: &str) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!("DELETE FROM tags WHERE name = $1", name)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use secrecy::Secret;
+
+    use super::{normalize_tag, parse_tags, signup_tags_token, verify_signup_tags};
+
+    #[test]
+    fn tags_are_checked() {
+        assert_eq!(normalize_tag(" Beta-Tester ").unwrap(), "beta-tester");
+        assert_eq!(normalize_tag("2026").unwrap(), "2026");
+        assert!(normalize_tag("").is_err());
+        assert!(normalize_tag("-beta").is_err());
+        assert!(normalize_tag("beta tester").is_err());
+        assert!(normalize_tag("beta.tester").is_err());
+        assert!(normalize_tag(&"a".repeat(41)).is_err());
+    }
+
+    #[test]
+    fn tag_lists_are_sorted_and_deduplicated() {
+        assert_eq!(
+            parse_tags("vip, Beta-Tester,,vip ").unwrap(),
+            vec!["beta-tester", "vip"]
+        );
+        assert!(parse_tags(" , ").unwrap().is_empty());
+        assert!(parse_tags("vip, not valid").is_err());
+    }
+
+    #[test]
+    fn signup_tags_are_only_accepted_with_their_signature() {
+        let secret = Secret::new("a-secret".to_string());
+        let token = signup_tags_token(&secret, &["beta-tester".into(), "vip".into()]);
+        assert_eq!(
+            verify_signup_tags(&secret, &token).unwrap(),
+            vec!["beta-tester", "vip"]
+        );
+
+        let (_, signature) = token.rsplit_once('.').unwrap();
+        assert!(verify_signup_tags(&secret, &format!("vip.{}", signature)).is_none());
+        assert!(verify_signup_tags(&secret, "vip").is_none());
+        assert!(verify_signup_tags(&secret, "vip.not hex").is_none());
+
+        let other_secret = Secret::new("another-secret".to_string());
+        assert!(verify_signup_tags(&other_secret, &token).is_none());
+    }
+}
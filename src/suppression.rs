@@ -0,0 +1,265 @@
+use std::collections::BTreeSet;
+
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::domain_policy::{normalize_pattern, pattern_matches};
+
+/// Why an address must not be sent anything.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SuppressionReason {
+    LegalRequest,
+    HardBounce,
+    RoleAddress,
+    Internal,
+    Other,
+}
+
+impl SuppressionReason {
+    pub const ALL: [SuppressionReason; 5] = [
+        SuppressionReason::LegalRequest,
+        SuppressionReason::HardBounce,
+        SuppressionReason::RoleAddress,
+        SuppressionReason::Internal,
+        SuppressionReason::Other,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SuppressionReason::LegalRequest => "legal_request",
+            SuppressionReason::HardBounce => "hard_bounce",
+            SuppressionReason::RoleAddress => "role_address",
+            SuppressionReason::Internal => "internal",
+            SuppressionReason::Other => "other",
+        }
+    }
+}
+
+impl TryFrom<&str> for SuppressionReason {
+    type Error = String;
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        SuppressionReason::ALL
+            .into_iter()
+            .find(|reason| reason.as_str() == s)
+            .ok_or_else(|| format!("{} is not a valid suppression reason.", s))
+    }
+}
+
+pub struct Suppression {
+    pub suppression_id: Uuid,
+    pub pattern: String,
+    pub reason: String,
+    pub source: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Cleans up an entry typed or uploaded by an admin. Entries are matched
+/// against whole addresses, `*` standing for any run of characters: an
+/// address, `abuse@*` for a role address on every domain, or a domain pattern
+/// as for the signup domain rules, which stands for every address on it.
+pub fn normalize_suppression(entry: &str) -> Result<String, String> {
+    let entry = entry.trim().to_lowercase();
+
+    let Some((local, domain)) = entry
+        .rsplit_once('@')
+        .filter(|(local, _)| !local.is_empty())
+    else {
+        return Ok(format!("*@{}", normalize_pattern(&entry)?));
+    };
+
+    if local.chars().any(|c| c.is_whitespace() || c == '@') {
+        return Err(format!("{} is not a valid address pattern.", entry));
+    }
+    let domain = match domain {
+        "*" => "*".to_owned(),
+        domain => normalize_pattern(domain)
+            .map_err(|_| format!("{} is not a valid address pattern.", entry))?,
+    };
+    if local.chars().all(|c| c == '*') && domain == "*" {
+        return Err("The pattern would match every address.".into());
+    }
+
+    Ok(format!("{}@{}", local, domain))
+}
+
+/// Whether `email` is on the suppression list. Only wildcard entries are
+/// matched here, plain addresses are looked up through the index.
+pub async fn is_suppressed(pool: &PgPool, email: &str) -> Result<bool, sqlx::Error> {
+    let email = email.to_lowercase();
+    let patterns = sqlx::query!(
+        r#"
+        SELECT pattern FROM email_suppressions
+        WHERE pattern = $1 OR strpos(pattern, '*') > 0
+        "#,
+        email,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(patterns
+        .iter()
+        .any(|row| pattern_matches(&row.pattern, &email)))
+}
+
+/// The entries of an uploaded list, one per line. Only the first column of
+/// CSV files is read, a header row is skipped. Returns the valid entries,
+/// deduplicated, and the lines that are not.
+pub fn parse_suppression_list(data: &[u8]) -> (Vec<String>, Vec<String>) {
+    let text = String::from_utf8_lossy(data);
+    let mut valid = BTreeSet::new();
+    let mut invalid = vec![];
+
+    for (index, line) in text.lines().enumerate() {
+        let entry = line
+            .split(',')
+            .next()
+            .unwrap_or("")
+            .trim()
+            .trim_matches('"');
+        if entry.is_empty() {
+            continue;
+        }
+        if index == 0
+            && matches!(
+                entry.to_lowercase().as_str(),
+                "email" | "address" | "pattern"
+            )
+        {
+            continue;
+        }
+        match normalize_suppression(entry) {
+            Ok(pattern) => {
+                valid.insert(pattern);
+            }
+            Err(_) => invalid.push(entry.to_owned()),
+        }
+    }
+
+    (valid.into_iter().collect(), invalid)
+}
+
+/// Newest first, at most `limit`, with `search` a case-insensitive substring.
+pub async fn list_suppressions(
+    pool: &PgPool,
+    search: Option<&str>,
+    limit: i64,
+) -> Result<Vec<Suppression>, sqlx::Error> {
+    sqlx::query_as!(
+        Suppression,
+        r#"
+        SELECT suppression_id, pattern, reason, source, created_at
+        FROM email_suppressions
+        WHERE $1::text IS NULL OR strpos(pattern, lower($1)) > 0
+        ORDER BY created_at DESC, pattern
+        LIMIT $2
+        "#,
+        search,
+        limit,
+    )
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn count_suppressions(pool: &PgPool, search: Option<&str>) -> Result<i64, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT COUNT(*) AS "count!"
+        FROM email_suppressions
+        WHERE $1::text IS NULL OR strpos(pattern, lower($1)) > 0
+        "#,
+        search,
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(row.count)
+}
+
+/// `patterns` must come from `normalize_suppression`. Entries already on the
+/// list are left as they are. Returns how many were added.
+pub async fn add_suppressions(
+    pool: &PgPool,
+    patterns: &[String],
+    reason: SuppressionReason,
+    source: &str,
+) -> Result<u64, sqlx::Error> {
+    let ids: Vec<Uuid> = patterns.iter().map(|_| Uuid::new_v4()).collect();
+
+    let added = sqlx::query!(
+        r#"
+        INSERT INTO email_suppressions (suppression_id, pattern, reason, source, created_at)
+        SELECT id, pattern, $3, $4, now()
+        FROM UNNEST($1::uuid[], $2::text[]) AS entries (id, pattern)
+        ON CONFLICT (pattern) DO NOTHING
+        "#,
+        &ids,
+        patterns,
+        reason.as_str(),
+        source,
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+
+    Ok(added)
+}
+
+/// Returns the pattern of the removed entry, if it existed.
+pub async fn remove_suppression(
+    pool: &PgPool,
+    suppression_id: Uuid,
+) -> Result<Option<String>, sqlx::Error> {
+    let removed = sqlx::query!(
+        "DELETE FROM email_suppressions WHERE suppression_id = $1 RETURNING pattern",
+        suppression_id,
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(removed.map(|row| row.pattern))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{normalize_suppression, parse_suppression_list};
+    use crate::domain_policy::pattern_matches;
+
+    #[test]
+    fn entries_are_normalized_into_address_patterns() {
+        assert_eq!(
+            normalize_suppression(" Ursula@Example.COM ").unwrap(),
+            "ursula@example.com"
+        );
+        assert_eq!(normalize_suppression("abuse@*").unwrap(), "abuse@*");
+        assert_eq!(
+            normalize_suppression("test.example").unwrap(),
+            "*@test.example"
+        );
+        assert_eq!(
+            normalize_suppression("@*.test.example").unwrap(),
+            "*@*.test.example"
+        );
+        assert!(normalize_suppression("*@*").is_err());
+        assert!(normalize_suppression("a b@example.com").is_err());
+        assert!(normalize_suppression("ursula@exa mple.com").is_err());
+        assert!(normalize_suppression("").is_err());
+    }
+
+    #[test]
+    fn patterns_match_whole_addresses() {
+        assert!(pattern_matches("abuse@*", "abuse@example.com"));
+        assert!(!pattern_matches("abuse@*", "not-abuse@example.com"));
+        assert!(pattern_matches("*@test.example", "qa@test.example"));
+        assert!(!pattern_matches("*@test.example", "qa@eu.test.example"));
+    }
+
+    #[test]
+    fn uploads_read_the_first_column_and_skip_the_header() {
+        let (valid, invalid) = parse_suppression_list(
+            b"email,reason\nbounced@example.com,550\n\nBounced@example.com\nnot an address\n",
+        );
+        assert_eq!(valid, vec!["bounced@example.com"]);
+        assert_eq!(invalid, vec!["not an address"]);
+    }
+}
@@ -0,0 +1,300 @@
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use secrecy::{ExposeSecret, Secret};
+use sqlx::{types::Uuid, PgPool};
+use totp_rs::{Algorithm, TOTP};
+
+use crate::signing::sha256_hex;
+
+const RECOVERY_CODE_COUNT: usize = 10;
+const TIME_STEP_SECONDS: u64 = 30;
+
+pub struct TwoFactorStatus {
+    pub enabled: bool,
+    pub secret: Option<Secret<String>>,
+}
+
+pub struct Enrollment {
+    pub secret: String,
+    pub provisioning_uri: String,
+    pub qr_code_base64: Option<String>,
+}
+
+fn build_totp(secret: &str, issuer: &str, account_name: &str) -> Option<TOTP> {
+    let bytes = totp_rs::Secret::Encoded(secret.to_owned())
+        .to_bytes()
+        .ok()?;
+    TOTP::new(
+        Algorithm::SHA1,
+        6,
+        1,
+        TIME_STEP_SECONDS,
+        bytes,
+        Some(issuer.to_owned()),
+        account_name.to_owned(),
+    )
+    .ok()
+}
+
+/// Generates a fresh secret together with the `otpauth://` URI that
+/// authenticator apps scan to enroll it.
+pub fn new_enrollment(issuer: &str, account_name: &str) -> Option<Enrollment> {
+    let secret = totp_rs::Secret::generate_secret().to_encoded().to_string();
+    let totp = build_totp(&secret, issuer, account_name)?;
+
+    Some(Enrollment {
+        provisioning_uri: totp.get_url(),
+        qr_code_base64: totp.get_qr_base64().ok(),
+        secret,
+    })
+}
+
+/// Returns the time step matched by `code`, allowing one step of clock drift
+/// either way. Steps at or before `last_step` are rejected so that a code
+/// cannot be used twice.
+pub fn verify_code(secret: &str, code: &str, last_step: Option<i64>) -> Option<i64> {
+    let totp = build_totp(secret, "", "")?;
+    let now = chrono::Utc::now().timestamp() as u64;
+    let current_step = (now / TIME_STEP_SECONDS) as i64;
+    let code = code.trim();
+
+    (current_step - 1..=current_step + 1)
+        .filter(|step| last_step.is_none_or(|last| *step > last))
+        .find(|step| totp.generate(*step as u64 * TIME_STEP_SECONDS) == code)
+}
+
+fn hash_recovery_code(code: &str) -> String {
+    sha256_hex(&code.trim().to_lowercase())
+}
+
+fn generate_recovery_code() -> String {
+    let mut rng = thread_rng();
+    let code: String = std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(|c| char::from(c).to_ascii_lowercase())
+        .take(10)
+        .collect();
+
+    format!("{}-{}", &code[..5], &code[5..])
+}
+
+pub async fn get_status(user_id: Uuid, pool: &PgPool) -> Option<TwoFactorStatus> {
+    let row = sqlx::query!(
+        r#"
+        SELECT totp_enabled, totp_secret
+        FROM users
+        WHERE user_id = $1
+        "#,
+        user_id,
+    )
+    .fetch_optional(pool)
+    .await
+    .ok()??;
+
+    Some(TwoFactorStatus {
+        enabled: row.totp_enabled,
+        secret: row.totp_secret.map(Secret::new),
+    })
+}
+
+/// Stores a secret that is waiting to be confirmed. It has no effect on login
+/// until `confirm_enrollment` succeeds.
+pub async fn store_pending_secret(user_id: Uuid, secret: &str, pool: &PgPool) -> Option<()> {
+    sqlx::query!(
+        r#"
+        UPDATE users
+        SET totp_secret = $2, totp_last_step = NULL
+        WHERE user_id = $1 AND NOT totp_enabled
+        "#,
+        user_id,
+        secret,
+    )
+    .execute(pool)
+    .await
+    .ok()?;
+
+    Some(())
+}
+
+/// Enables two-factor authentication if `code` matches the pending secret, and
+/// returns a new set of recovery codes to show to the user once.
+pub async fn confirm_enrollment(user_id: Uuid, code: &str, pool: &PgPool) -> Option<Vec<String>> {
+    let status = get_status(user_id, pool).await?;
+    if status.enabled {
+        return None;
+    }
+
+    let step = verify_code(status.secret?.expose_secret(), code, None)?;
+
+    let mut transaction = pool.begin().await.ok()?;
+
+    sqlx::query!(
+        r#"
+        UPDATE users
+        SET totp_enabled = true, totp_last_step = $2
+        WHERE user_id = $1
+        "#,
+        user_id,
+        step,
+    )
+    .execute(&mut *transaction)
+    .await
+    .ok()?;
+
+    sqlx::query!(r#"DELETE FROM recovery_codes WHERE user_id = $1"#, user_id)
+        .execute(&mut *transaction)
+        .await
+        .ok()?;
+
+    let codes: Vec<String> = (0..RECOVERY_CODE_COUNT)
+        .map(|_| generate_recovery_code())
+        .collect();
+
+    for code in &codes {
+        sqlx::query!(
+            r#"
+            INSERT INTO recovery_codes (user_id, code_hash)
+            VALUES ($1, $2)
+            "#,
+            user_id,
+            hash_recovery_code(code),
+        )
+        .execute(&mut *transaction)
+        .await
+        .ok()?;
+    }
+
+    transaction.commit().await.ok()?;
+
+    Some(codes)
+}
+
+pub async fn disable(user_id: Uuid, pool: &PgPool) -> Option<()> {
+    let mut transaction = pool.begin().await.ok()?;
+
+    sqlx::query!(
+        r#"
+        UPDATE users
+        SET totp_enabled = false, totp_secret = NULL, totp_last_step = NULL
+        WHERE user_id = $1
+        "#,
+        user_id,
+    )
+    .execute(&mut *transaction)
+    .await
+    .ok()?;
+
+    sqlx::query!(r#"DELETE FROM recovery_codes WHERE user_id = $1"#, user_id)
+        .execute(&mut *transaction)
+        .await
+        .ok()?;
+
+    transaction.commit().await.ok()?;
+
+    Some(())
+}
+
+/// Checks the second factor of an enrolled user: either a code from their
+/// authenticator app or one of their unused recovery codes.
+pub async fn verify_second_factor(user_id: Uuid, code: &str, pool: &PgPool) -> Option<()> {
+    let row = sqlx::query!(
+        r#"
+        SELECT totp_secret, totp_last_step
+        FROM users
+        WHERE user_id = $1 AND totp_enabled
+        "#,
+        user_id,
+    )
+    .fetch_optional(pool)
+    .await
+    .ok()??;
+
+    if let Some(step) = verify_code(&row.totp_secret?, code, row.totp_last_step) {
+        // Only advance the step if nobody else used it in the meantime
+        let updated = sqlx::query!(
+            r#"
+            UPDATE users
+            SET totp_last_step = $2
+            WHERE user_id = $1 AND (totp_last_step IS NULL OR totp_last_step < $2)
+            "#,
+            user_id,
+            step,
+        )
+        .execute(pool)
+        .await
+        .ok()?
+        .rows_affected();
+
+        return (updated > 0).then_some(());
+    }
+
+    let used = sqlx::query!(
+        r#"
+        UPDATE recovery_codes
+        SET used_at = now()
+        WHERE user_id = $1 AND code_hash = $2 AND used_at IS NULL
+        "#,
+        user_id,
+        hash_recovery_code(code),
+    )
+    .execute(pool)
+    .await
+    .ok()?
+    .rows_affected();
+
+    (used > 0).then_some(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{hash_recovery_code, new_enrollment, verify_code, TIME_STEP_SECONDS};
+    use claim::{assert_none, assert_some};
+    use totp_rs::{Algorithm, Secret, TOTP};
+
+    fn code_at(secret: &str, step: i64) -> String {
+        let bytes = Secret::Encoded(secret.to_owned()).to_bytes().unwrap();
+        TOTP::new(Algorithm::SHA1, 6, 1, 30, bytes, None, "".into())
+            .unwrap()
+            .generate(step as u64 * TIME_STEP_SECONDS)
+    }
+
+    fn current_step() -> i64 {
+        chrono::Utc::now().timestamp() / TIME_STEP_SECONDS as i64
+    }
+
+    #[test]
+    fn the_provisioning_uri_contains_the_secret_and_issuer() {
+        let enrollment = new_enrollment("Newsletter", "ursula").unwrap();
+        assert!(enrollment.provisioning_uri.starts_with("otpauth://totp/"));
+        assert!(enrollment.provisioning_uri.contains(&enrollment.secret));
+        assert!(enrollment.provisioning_uri.contains("issuer=Newsletter"));
+    }
+
+    #[test]
+    fn a_current_code_is_accepted() {
+        let secret = new_enrollment("Newsletter", "ursula").unwrap().secret;
+        let code = code_at(&secret, current_step());
+        assert_some!(verify_code(&secret, &code, None));
+    }
+
+    #[test]
+    fn a_code_from_an_old_step_is_rejected() {
+        let secret = new_enrollment("Newsletter", "ursula").unwrap().secret;
+        let code = code_at(&secret, current_step() - 5);
+        assert_none!(verify_code(&secret, &code, None));
+    }
+
+    #[test]
+    fn a_code_cannot_be_replayed() {
+        let secret = new_enrollment("Newsletter", "ursula").unwrap().secret;
+        let code = code_at(&secret, current_step());
+        let step = verify_code(&secret, &code, None).unwrap();
+        assert_none!(verify_code(&secret, &code, Some(step)));
+    }
+
+    #[test]
+    fn recovery_codes_are_hashed_case_insensitively() {
+        assert_eq!(
+            hash_recovery_code("abcde-12345"),
+            hash_recovery_code(" ABCDE-12345 ")
+        );
+    }
+}
@@ -0,0 +1,358 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use sqlx::{PgExecutor, PgPool};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::{
+    configuration::WelcomeSequenceSettings, domain::SubscriberEmail, email_client::EmailClient,
+};
+
+/// Steps further out than this are most likely a typo.
+const MAX_DELAY_DAYS: i32 = 365;
+
+pub struct SequenceStep {
+    pub step_id: Uuid,
+    /// Days after the confirmation.
+    pub delay_days: i32,
+    pub subject: String,
+    pub text_content: String,
+    pub html_content: String,
+    pub created_at: DateTime<Utc>,
+    /// Subscribers it was sent to.
+    pub sent: i64,
+}
+
+pub struct NewSequenceStep {
+    pub delay_days: i32,
+    pub subject: String,
+    pub text_content: String,
+    pub html_content: String,
+}
+
+impl NewSequenceStep {
+    pub fn parse(
+        delay_days: &str,
+        subject: &str,
+        text_content: &str,
+        html_content: &str,
+    ) -> Result<Self, String> {
+        let delay_days = match delay_days.trim().parse::<i32>() {
+            Ok(days) if (0..=MAX_DELAY_DAYS).contains(&days) => days,
+            _ => {
+                return Err(format!(
+                    "The delay must be a number of days between 0 and {}.",
+                    MAX_DELAY_DAYS
+                ))
+            }
+        };
+
+        let subject = subject.trim();
+        if subject.is_empty() {
+            return Err("The subject is missing.".into());
+        }
+        if text_content.trim().is_empty() || html_content.trim().is_empty() {
+            return Err("Both the text and the HTML content are needed.".into());
+        }
+
+        Ok(Self {
+            delay_days,
+            subject: subject.to_owned(),
+            text_content: text_content.to_owned(),
+            html_content: html_content.to_owned(),
+        })
+    }
+}
+
+/// In the order they are sent.
+pub async fn list_steps(pool: &PgPool) -> Result<Vec<SequenceStep>, sqlx::Error> {
+    sqlx::query_as!(
+        SequenceStep,
+        r#"
+        SELECT
+            step_id, delay_days, subject, text_content, html_content, created_at,
+            (SELECT COUNT(*) FROM sequence_deliveries d WHERE d.step_id = st.step_id) AS "sent!"
+        FROM sequence_steps st
+        ORDER BY delay_days, created_at
+        "#
+    )
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn add_step(pool: &PgPool, step: &NewSequenceStep) -> Result<Uuid, sqlx::Error> {
+    let step_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO sequence_steps (step_id, delay_days, subject, text_content, html_content, created_at)
+        VALUES ($1, $2, $3, $4, $5, now())
+        "#,
+        step_id,
+        step.delay_days,
+        step.subject,
+        step.text_content,
+        step.html_content,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(step_id)
+}
+
+/// Returns the subject of the removed step, if it existed.
+pub async fn remove_step(pool: &PgPool, step_id: Uuid) -> Result<Option<String>, sqlx::Error> {
+    let removed = sqlx::query!(
+        "DELETE FROM sequence_steps WHERE step_id = $1 RETURNING subject",
+        step_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(removed.map(|row| row.subject))
+}
+
+/// Starts the sequence for a subscriber who just confirmed. Subscribers go
+/// through it once: confirming again after leaving does not restart it.
+pub async fn enroll(db: impl PgExecutor<'_>, subscriber_id: Uuid) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO sequence_enrollments (subscriber_id, enrolled_at)
+        VALUES ($1, now())
+        ON CONFLICT (subscriber_id) DO NOTHING
+        "#,
+        subscriber_id,
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+/// No more steps are sent to the subscriber.
+pub async fn stop_sequence(
+    db: impl PgExecutor<'_>,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE sequence_enrollments SET stopped_at = now()
+        WHERE subscriber_id = $1 AND stopped_at IS NULL
+        "#,
+        subscriber_id,
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+pub struct Enrollment {
+    pub enrolled_at: DateTime<Utc>,
+    pub stopped_at: Option<DateTime<Utc>>,
+}
+
+pub struct StepProgress {
+    pub subject: String,
+    pub due_at: DateTime<Utc>,
+    pub sent_at: Option<DateTime<Utc>>,
+}
+
+/// Where a subscriber is in the sequence, `None` if they never entered it.
+pub async fn get_progress(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Option<(Enrollment, Vec<StepProgress>)>, sqlx::Error> {
+    let Some(enrollment) = sqlx::query_as!(
+        Enrollment,
+        r#"
+        SELECT enrolled_at, stopped_at
+        FROM sequence_enrollments
+        WHERE subscriber_id = $1
+        "#,
+        subscriber_id,
+    )
+    .fetch_optional(pool)
+    .await?
+    else {
+        return Ok(None);
+    };
+
+    // Leaves out the steps added after they would have been due, which are
+    // never sent to them, see `send_due_emails`
+    let steps = sqlx::query_as!(
+        StepProgress,
+        r#"
+        SELECT
+            st.subject,
+            e.enrolled_at + make_interval(days => st.delay_days) AS "due_at!",
+            d.sent_at AS "sent_at?"
+        FROM sequence_enrollments e
+        CROSS JOIN sequence_steps st
+        LEFT JOIN sequence_deliveries d
+            ON d.subscriber_id = e.subscriber_id AND d.step_id = st.step_id
+        WHERE e.subscriber_id = $1
+            AND e.enrolled_at + make_interval(days => st.delay_days) >= st.created_at
+        ORDER BY st.delay_days, st.created_at
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(Some((enrollment, steps)))
+}
+
+pub async fn run_sequence_worker(
+    pool: PgPool,
+    email_client: Arc<EmailClient>,
+    settings: WelcomeSequenceSettings,
+) {
+    let mut interval = tokio::time::interval(settings.send_interval());
+
+    loop {
+        interval.tick().await;
+
+        if let Err(e) = stop_departed_subscribers(&pool).await {
+            error!(
+                "failed to stop the welcome sequence of departed subscribers: {:?}",
+                e
+            );
+        }
+
+        loop {
+            match send_due_emails(&pool, &email_client, settings.batch_size).await {
+                Ok(0) => break,
+                Ok(sent) => {
+                    info!("sent {} welcome sequence emails", sent);
+                    if sent < u64::from(settings.batch_size) {
+                        break;
+                    }
+                }
+                Err(e) => {
+                    error!("failed to send the welcome sequence emails: {:?}", e);
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Stops the sequence of subscribers who are no longer confirmed, whichever
+/// way they left, such as an import.
+pub async fn stop_departed_subscribers(pool: &PgPool) -> Result<u64, sqlx::Error> {
+    let stopped = sqlx::query!(
+        r#"
+        UPDATE sequence_enrollments e SET stopped_at = now()
+        FROM subscriptions s
+        WHERE s.id = e.subscriber_id
+            AND e.stopped_at IS NULL
+            AND s.status <> 'confirmed'
+        "#
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+
+    Ok(stopped)
+}
+
+/// Sends up to `batch_size` of the steps that are due, oldest first, and
+/// returns how many were handled. A step the email API fails to send is left
+/// for the next run, the rest of the batch still goes out.
+///
+/// A step added after it would have been due for a subscriber is not sent to
+/// them, so that adding a step does not mail everyone who confirmed before.
+pub async fn send_due_emails(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    batch_size: u32,
+) -> Result<u64, anyhow::Error> {
+    let due = sqlx::query!(
+        r#"
+        SELECT e.subscriber_id, s.email, st.step_id, st.subject, st.text_content, st.html_content
+        FROM sequence_enrollments e
+        JOIN subscriptions s ON s.id = e.subscriber_id
+        JOIN sequence_steps st
+            ON e.enrolled_at + make_interval(days => st.delay_days) <= now()
+            AND e.enrolled_at + make_interval(days => st.delay_days) >= st.created_at
+        WHERE e.stopped_at IS NULL
+            AND s.status = 'confirmed'
+            AND NOT EXISTS (
+                SELECT 1 FROM sequence_deliveries d
+                WHERE d.subscriber_id = e.subscriber_id AND d.step_id = st.step_id
+            )
+        ORDER BY e.enrolled_at + make_interval(days => st.delay_days)
+        LIMIT $1
+        "#,
+        i64::from(batch_size),
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut handled = 0;
+    for row in due {
+        let mut transaction = pool.begin().await?;
+
+        // Another instance of the worker may be on it already, the insert
+        // waits for it and then finds the delivery
+        let claimed = sqlx::query!(
+            r#"
+            INSERT INTO sequence_deliveries (subscriber_id, step_id, sent_at)
+            VALUES ($1, $2, now())
+            ON CONFLICT (subscriber_id, step_id) DO NOTHING
+            "#,
+            row.subscriber_id,
+            row.step_id,
+        )
+        .execute(&mut *transaction)
+        .await?
+        .rows_affected();
+
+        if claimed == 1 {
+            match SubscriberEmail::parse(row.email) {
+                Ok(email) => {
+                    if let Err(e) = email_client
+                        .send_email(&email, &row.subject, &row.html_content, &row.text_content)
+                        .await
+                    {
+                        warn!(
+                            "failed to send welcome sequence step {} to subscriber {}: {:?}",
+                            row.step_id, row.subscriber_id, e
+                        );
+                        transaction.rollback().await?;
+                        continue;
+                    }
+                }
+                // Recorded as sent, retrying would not make it valid
+                Err(e) => warn!(
+                    "skipping welcome sequence email to an invalid address: {}",
+                    e
+                ),
+            }
+        }
+
+        transaction.commit().await?;
+        handled += 1;
+    }
+
+    Ok(handled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::NewSequenceStep;
+
+    #[test]
+    fn steps_need_a_delay_in_range_a_subject_and_both_contents() {
+        let step = NewSequenceStep::parse(" 3 ", " The best of ", "text", "<p>html</p>").unwrap();
+        assert_eq!(step.delay_days, 3);
+        assert_eq!(step.subject, "The best of");
+
+        assert!(NewSequenceStep::parse("-1", "Subject", "text", "html").is_err());
+        assert!(NewSequenceStep::parse("366", "Subject", "text", "html").is_err());
+        assert!(NewSequenceStep::parse("soon", "Subject", "text", "html").is_err());
+        assert!(NewSequenceStep::parse("0", "  ", "text", "html").is_err());
+        assert!(NewSequenceStep::parse("0", "Subject", "text", " ").is_err());
+    }
+}
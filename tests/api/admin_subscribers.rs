@@ -0,0 +1,233 @@
+use sqlx::PgPool;
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestSubscriber, TestUser};
+
+async fn subscriber_status(pool: &PgPool, subscriber_id: Uuid) -> String {
+    sqlx::query!(
+        "SELECT status FROM subscriptions WHERE id = $1",
+        subscriber_id
+    )
+    .fetch_one(pool)
+    .await
+    .unwrap()
+    .status
+}
+
+#[tokio::test]
+async fn subscribers_can_be_searched_by_email_or_name() {
+    let app = spawn_app().await;
+    app.insert_subscriber(TestSubscriber::new("ursula@example.com").name("Ursula Le Guin"))
+        .await;
+    app.insert_subscriber(TestSubscriber::new("octavia@example.com").name("Octavia Butler"))
+        .await;
+    app.test_user.login(&app).await;
+
+    let html_page = app.get_admin_subscribers_html("").await;
+    assert!(html_page.contains("ursula@example.com"));
+    assert!(html_page.contains("octavia@example.com"));
+
+    let html_page = app.get_admin_subscribers_html("q=URSULA").await;
+    assert!(html_page.contains("ursula@example.com"));
+    assert!(!html_page.contains("octavia@example.com"));
+
+    let html_page = app.get_admin_subscribers_html("q=butler").await;
+    assert!(!html_page.contains("ursula@example.com"));
+    assert!(html_page.contains("octavia@example.com"));
+}
+
+#[tokio::test]
+async fn search_terms_are_not_treated_as_wildcards() {
+    let app = spawn_app().await;
+    app.insert_subscriber(TestSubscriber::new("ursula@example.com").name("Ursula Le Guin"))
+        .await;
+    app.test_user.login(&app).await;
+
+    let html_page = app.get_admin_subscribers_html("q=%25").await;
+    assert!(!html_page.contains("ursula@example.com"));
+}
+
+#[tokio::test]
+async fn subscribers_can_be_filtered_by_status() {
+    let app = spawn_app().await;
+    app.insert_subscriber(TestSubscriber::new("ursula@example.com").name("Ursula Le Guin"))
+        .await;
+    app.insert_subscriber(
+        TestSubscriber::new("octavia@example.com")
+            .name("Octavia Butler")
+            .status("pending_confirmation"),
+    )
+    .await;
+    app.test_user.login(&app).await;
+
+    let html_page = app
+        .get_admin_subscribers_html("status=pending_confirmation")
+        .await;
+    assert!(!html_page.contains("ursula@example.com"));
+    assert!(html_page.contains("octavia@example.com"));
+}
+
+#[tokio::test]
+async fn subscribers_are_paginated() {
+    let app = spawn_app().await;
+    for i in 0..30 {
+        app.insert_subscriber(
+            TestSubscriber::new(&format!("reader{}@example.com", i)).name("Reader"),
+        )
+        .await;
+    }
+    app.test_user.login(&app).await;
+
+    let html_page = app.get_admin_subscribers_html("").await;
+    assert!(html_page.contains("Page 1 of 2 (30 subscribers)"));
+    assert_eq!(html_page.matches("@example.com").count(), 25);
+
+    let html_page = app.get_admin_subscribers_html("page=2").await;
+    assert!(html_page.contains("Page 2 of 2"));
+    assert_eq!(html_page.matches("@example.com").count(), 5);
+}
+
+#[tokio::test]
+async fn out_of_range_pages_are_rejected() {
+    let app = spawn_app().await;
+    for i in 0..30 {
+        app.insert_subscriber(
+            TestSubscriber::new(&format!("reader{}@example.com", i)).name("Reader"),
+        )
+        .await;
+    }
+    app.test_user.login(&app).await;
+
+    for page in ["3".to_string(), i64::MAX.to_string()] {
+        let response = app
+            .http_client
+            .get(format!("{}/admin/subscribers?page={}", &app.address, page))
+            .send()
+            .await
+            .expect("Failed to execute request.");
+        assert_eq!(response.status().as_u16(), 404);
+    }
+
+    // Past the end of the filtered list too
+    let response = app
+        .http_client
+        .get(format!(
+            "{}/admin/subscribers?status=pending_confirmation&page=2",
+            &app.address
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn the_detail_page_shows_the_history_of_a_subscriber() {
+    let app = spawn_app().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(&[("email", "ursula@example.com"), ("name", "le guin")])
+        .await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    let subscriber_id = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db)
+        .await
+        .unwrap()
+        .id;
+
+    app.test_user.login(&app).await;
+    let html_page = app.get_admin_subscriber_html(subscriber_id).await;
+    assert!(html_page.contains("ursula@example.com"));
+    assert!(html_page.contains("Status: confirmed"));
+    assert!(html_page.contains(": subscribed</li>"));
+    assert!(html_page.contains(": confirmed</li>"));
+}
+
+#[tokio::test]
+async fn editors_can_confirm_and_unsubscribe_subscribers() {
+    let app = spawn_app().await;
+    let subscriber_id = app
+        .insert_subscriber(
+            TestSubscriber::new("ursula@example.com")
+                .name("Ursula Le Guin")
+                .status("pending_confirmation"),
+        )
+        .await;
+
+    let editor = TestUser::generate_with_role("editor");
+    editor.store(&app.db).await;
+    editor.login(&app).await;
+
+    let response = app
+        .post_admin_subscriber_action(subscriber_id, "confirm")
+        .await;
+    assert_is_redirect_to(&response, &format!("/admin/subscribers/{}", subscriber_id));
+    assert_eq!(subscriber_status(&app.db, subscriber_id).await, "confirmed");
+
+    let response = app
+        .post_admin_subscriber_action(subscriber_id, "unsubscribe")
+        .await;
+    assert_is_redirect_to(&response, &format!("/admin/subscribers/{}", subscriber_id));
+    assert_eq!(
+        subscriber_status(&app.db, subscriber_id).await,
+        "unsubscribed"
+    );
+
+    let html_page = app.get_admin_subscriber_html(subscriber_id).await;
+    assert!(html_page.contains("The subscriber has been unsubscribed."));
+    assert!(html_page.contains(&format!(": confirmed by {}</li>", editor.username)));
+    assert!(html_page.contains(&format!(": unsubscribed by {}</li>", editor.username)));
+}
+
+#[tokio::test]
+async fn deleting_a_subscriber_removes_their_tokens() {
+    let app = spawn_app().await;
+    let subscriber_id = app
+        .insert_subscriber(
+            TestSubscriber::new("ursula@example.com")
+                .name("Ursula Le Guin")
+                .status("pending_confirmation"),
+        )
+        .await;
+    sqlx::query!(
+        "INSERT INTO subscription_tokens (subscription_token, subscriber_id) VALUES ($1, $2)",
+        "a-subscription-token",
+        subscriber_id,
+    )
+    .execute(&app.db)
+    .await
+    .unwrap();
+    app.test_user.login(&app).await;
+
+    let response = app
+        .post_admin_subscriber_action(subscriber_id, "delete")
+        .await;
+    assert_is_redirect_to(&response, "/admin/subscribers");
+
+    let html_page = app.get_admin_subscribers_html("").await;
+    assert!(html_page.contains("The subscriber has been deleted."));
+    assert!(!html_page.contains("ursula@example.com"));
+
+    let tokens = sqlx::query!(
+        "SELECT COUNT(*) AS \"count!\" FROM subscription_tokens WHERE subscriber_id = $1",
+        subscriber_id
+    )
+    .fetch_one(&app.db)
+    .await
+    .unwrap();
+    assert_eq!(tokens.count, 0);
+}
@@ -0,0 +1,279 @@
+use reqwest::Client;
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp, TestUser};
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_users() {
+    let app = spawn_app().await;
+
+    let response = app.get_admin_users().await;
+    assert_is_redirect_to(&response, "/login");
+
+    let response = app
+        .post_admin_users(&serde_json::json!({
+            "username": "new-admin",
+            "email": "new-admin@example.com",
+            "role": "editor",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+
+    let response = app
+        .post_admin_user_action(app.test_user.user_id, "delete")
+        .await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn the_users_page_lists_admins() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let html_page = app.get_admin_users_html().await;
+    assert!(html_page.contains(&app.test_user.username));
+}
+
+#[tokio::test]
+async fn an_invited_user_can_set_a_password_and_log_in() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_admin_users(&serde_json::json!({
+            "username": "new-admin",
+            "email": "new-admin@example.com",
+            "role": "editor",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/users");
+
+    let html_page = app.get_admin_users_html().await;
+    assert!(html_page.contains("An invitation has been sent to new-admin."));
+    assert!(html_page.contains("invited"));
+
+    // The user cannot log in before accepting the invitation
+    let client = app.new_client();
+    let response = app
+        .post_login_with(
+            &client,
+            &serde_json::json!({
+                "username": "new-admin",
+                "password": "",
+            }),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/login");
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let invitation_link = app.get_confirmation_links(email_request).html;
+    let token = invitation_link
+        .query_pairs()
+        .find(|(k, _)| k == "invitation_token")
+        .unwrap()
+        .1
+        .into_owned();
+
+    let new_password = Uuid::new_v4().to_string();
+    let response = app
+        .post_accept_invitation(&serde_json::json!({
+            "invitation_token": &token,
+            "new_password": &new_password,
+            "new_password_check": &new_password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+
+    let response = app
+        .post_login_with(
+            &client,
+            &serde_json::json!({
+                "username": "new-admin",
+                "password": &new_password,
+            }),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    // Invitations are single use
+    let response = app
+        .post_accept_invitation(&serde_json::json!({
+            "invitation_token": &token,
+            "new_password": "another-long-password",
+            "new_password_check": "another-long-password",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("This invitation is invalid or has expired."));
+}
+
+#[tokio::test]
+async fn invitation_tokens_are_stored_hashed_and_expired_ones_are_removed() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.mock_email_server().await;
+
+    app.post_admin_users(&serde_json::json!({
+        "username": "new-admin",
+        "email": "new-admin@example.com",
+        "role": "editor",
+    }))
+    .await;
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let invitation_link = app.get_confirmation_links(email_request).html;
+    let token = invitation_link
+        .query_pairs()
+        .find(|(k, _)| k == "invitation_token")
+        .unwrap()
+        .1
+        .into_owned();
+    let stored = sqlx::query!("SELECT token_hash FROM user_invitations")
+        .fetch_one(&app.db)
+        .await
+        .unwrap();
+    assert_ne!(stored.token_hash, token);
+
+    sqlx::query!("UPDATE user_invitations SET expires_at = now() - interval '1 hour'")
+        .execute(&app.db)
+        .await
+        .unwrap();
+    let response = app
+        .post_accept_invitation(&serde_json::json!({
+            "invitation_token": &token,
+            "new_password": "a-long-enough-password",
+            "new_password_check": "a-long-enough-password",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("This invitation is invalid or has expired."));
+
+    let remaining = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM user_invitations"#)
+        .fetch_one(&app.db)
+        .await
+        .unwrap();
+    assert_eq!(remaining.count, 0);
+}
+
+#[tokio::test]
+async fn disabling_a_user_revokes_their_sessions() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let other_user = TestUser::generate();
+    other_user.store(&app.db).await;
+
+    let client = app.new_client();
+    let response = app
+        .post_login_with(
+            &client,
+            &serde_json::json!({
+                "username": &other_user.username,
+                "password": &other_user.password,
+            }),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    let response = get_dashboard(&app, &client).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app
+        .post_admin_user_action(other_user.user_id, "disable")
+        .await;
+    assert_is_redirect_to(&response, "/admin/users");
+
+    let response = get_dashboard(&app, &client).await;
+    assert_is_redirect_to(&response, "/login");
+
+    let response = app
+        .post_login_with(
+            &client,
+            &serde_json::json!({
+                "username": &other_user.username,
+                "password": &other_user.password,
+            }),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/login");
+
+    let response = app
+        .post_admin_user_action(other_user.user_id, "enable")
+        .await;
+    assert_is_redirect_to(&response, "/admin/users");
+
+    let response = app
+        .post_login_with(
+            &client,
+            &serde_json::json!({
+                "username": &other_user.username,
+                "password": &other_user.password,
+            }),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn deleting_a_user_revokes_their_sessions() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let other_user = TestUser::generate();
+    other_user.store(&app.db).await;
+
+    let client = app.new_client();
+    app.post_login_with(
+        &client,
+        &serde_json::json!({
+            "username": &other_user.username,
+            "password": &other_user.password,
+        }),
+    )
+    .await;
+
+    let response = app
+        .post_admin_user_action(other_user.user_id, "delete")
+        .await;
+    assert_is_redirect_to(&response, "/admin/users");
+
+    let response = get_dashboard(&app, &client).await;
+    assert_is_redirect_to(&response, "/login");
+
+    let html_page = app.get_admin_users_html().await;
+    assert!(!html_page.contains(&other_user.username));
+}
+
+#[tokio::test]
+async fn you_cannot_delete_yourself() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .post_admin_user_action(app.test_user.user_id, "delete")
+        .await;
+    assert_is_redirect_to(&response, "/admin/users");
+
+    let html_page = app.get_admin_users_html().await;
+    assert!(html_page.contains("You cannot delete your own account."));
+}
+
+async fn get_dashboard(app: &TestApp, client: &Client) -> reqwest::Response {
+    client
+        .get(format!("{}/admin/dashboard", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
@@ -0,0 +1,210 @@
+use reqwest::Client;
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp, TestSubscriber, TestUser};
+
+#[tokio::test]
+async fn a_token_can_publish_a_newsletter_without_a_session() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.insert_subscriber(TestSubscriber::new("ursula_le_guin@gmail.com"))
+        .await;
+    let token = create_token(&app, "CMS").await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = publish_with_token(&app, &token, &Uuid::new_v4().to_string()).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn idempotency_keys_are_scoped_to_the_token_owner() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.insert_subscriber(TestSubscriber::new("ursula_le_guin@gmail.com"))
+        .await;
+    let token = create_token(&app, "CMS").await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let idempotency_key = Uuid::new_v4().to_string();
+    let response = publish_with_token(&app, &token, &idempotency_key).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // The same key used from the browser session of the same user is a replay
+    let response = app
+        .post_newsletters(newsletter_body(&idempotency_key))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn the_last_use_of_a_token_is_tracked() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let token = create_token(&app, "CMS").await;
+
+    let html_page = get_tokens_html(&app).await;
+    assert!(html_page.contains("CMS"));
+    assert!(html_page.contains("never"));
+
+    publish_with_token(&app, &token, &Uuid::new_v4().to_string()).await;
+
+    let html_page = get_tokens_html(&app).await;
+    assert!(!html_page.contains("never"));
+}
+
+#[tokio::test]
+async fn a_revoked_token_is_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let token = create_token(&app, "CMS").await;
+
+    let token_id = sqlx::query!("SELECT token_id FROM api_tokens")
+        .fetch_one(&app.db)
+        .await
+        .unwrap()
+        .token_id;
+
+    let response = app
+        .http_client
+        .post(format!(
+            "{}/admin/api-tokens/{}/revoke",
+            &app.address, token_id
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_is_redirect_to(&response, "/admin/api-tokens");
+
+    let html_page = get_tokens_html(&app).await;
+    assert!(html_page.contains("The token has been revoked."));
+
+    let response = publish_with_token(&app, &token, &Uuid::new_v4().to_string()).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn an_unknown_token_is_rejected() {
+    let app = spawn_app().await;
+
+    let response =
+        publish_with_token(&app, "nl_not-a-real-token", &Uuid::new_v4().to_string()).await;
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(
+        response.headers().get("WWW-Authenticate").unwrap(),
+        "Bearer"
+    );
+}
+
+#[tokio::test]
+async fn a_token_does_not_grant_more_than_the_role_of_its_owner() {
+    let app = spawn_app().await;
+
+    let viewer = TestUser::generate_with_role("viewer");
+    viewer.store(&app.db).await;
+    viewer.login(&app).await;
+    let token = create_token(&app, "CMS").await;
+
+    let response = publish_with_token(&app, &token, &Uuid::new_v4().to_string()).await;
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn a_token_needs_at_least_one_scope() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .http_client
+        .post(format!("{}/admin/api-tokens", &app.address))
+        .form(&serde_json::json!({ "name": "CMS" }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_is_redirect_to(&response, "/admin/api-tokens");
+
+    let html_page = get_tokens_html(&app).await;
+    assert!(html_page.contains("The token needs at least one scope."));
+}
+
+#[tokio::test]
+async fn tokens_are_not_accepted_on_html_pages() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let token = create_token(&app, "CMS").await;
+
+    let response = Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap()
+        .get(format!("{}/admin/dashboard", &app.address))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_is_redirect_to(&response, "/login");
+}
+
+async fn create_token(app: &TestApp, name: &str) -> String {
+    let html_page = app
+        .http_client
+        .post(format!("{}/admin/api-tokens", &app.address))
+        .form(&[("name", name), ("scopes", "newsletters:publish")])
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .text()
+        .await
+        .unwrap();
+
+    let start = html_page.find("nl_").expect("No token in the page");
+    html_page[start..].split('<').next().unwrap().to_owned()
+}
+
+async fn get_tokens_html(app: &TestApp) -> String {
+    app.http_client
+        .get(format!("{}/admin/api-tokens", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .text()
+        .await
+        .unwrap()
+}
+
+fn newsletter_body(idempotency_key: &str) -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": idempotency_key
+    })
+}
+
+// Sent from a client without any cookie, so only the token can authenticate
+async fn publish_with_token(
+    app: &TestApp,
+    token: &str,
+    idempotency_key: &str,
+) -> reqwest::Response {
+    Client::new()
+        .post(format!("{}/admin/newsletters", &app.address))
+        .bearer_auth(token)
+        .json(&newsletter_body(idempotency_key))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
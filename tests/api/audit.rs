@@ -0,0 +1,195 @@
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+
+#[tokio::test]
+async fn logins_and_logouts_are_audited_with_their_origin() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let login_request_id = request_id(&response);
+
+    let html_page = get_audit_html(&app, 1).await;
+    assert!(html_page.contains("<td>login</td>"));
+    assert!(html_page.contains(&app.test_user.username));
+    assert!(html_page.contains(&app.client_ip.to_string()));
+    assert!(html_page.contains(&login_request_id));
+
+    app.post_logout().await;
+    app.test_user.login(&app).await;
+
+    let html_page = get_audit_html(&app, 1).await;
+    assert!(html_page.contains("<td>logout</td>"));
+}
+
+#[tokio::test]
+async fn failed_logins_are_audited() {
+    let app = spawn_app().await;
+
+    app.post_login(&serde_json::json!({
+        "username": "someone-else",
+        "password": "wrong-password"
+    }))
+    .await;
+    app.test_user.login(&app).await;
+
+    let html_page = get_audit_html(&app, 1).await;
+    assert!(html_page.contains("<td>login_failed</td>"));
+    assert!(html_page.contains("someone-else"));
+}
+
+#[tokio::test]
+async fn password_changes_are_audited() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let new_password = Uuid::new_v4().to_string();
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "current_password": &app.test_user.password,
+            "new_password": &new_password,
+            "new_password_check": &new_password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/password");
+
+    let html_page = get_audit_html(&app, 1).await;
+    assert!(html_page.contains("<td>password_change</td>"));
+}
+
+#[tokio::test]
+async fn newsletter_issues_are_audited() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Audited issue",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let html_page = get_audit_html(&app, 1).await;
+    assert!(html_page.contains("<td>newsletter_publish</td>"));
+    assert!(html_page.contains("Audited issue"));
+}
+
+#[tokio::test]
+async fn the_audit_log_is_paginated() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    for i in 0..60 {
+        sqlx::query!(
+            r#"
+            INSERT INTO audit_events (event_id, action, target, occurred_at)
+            VALUES ($1, 'test_action', $2, now() - interval '1 day')
+            "#,
+            Uuid::new_v4(),
+            format!("target-{}", i),
+        )
+        .execute(&app.db)
+        .await
+        .unwrap();
+    }
+
+    let first_page = get_audit_html(&app, 1).await;
+    assert!(first_page.contains("Page 1 of 2"));
+    assert!(first_page.contains(r#"href="/admin/audit?page=2""#));
+
+    let second_page = get_audit_html(&app, 2).await;
+    assert!(second_page.contains("Page 2 of 2"));
+    assert!(second_page.contains("test_action"));
+}
+
+#[tokio::test]
+async fn out_of_range_pages_are_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // The login above is the only event, on the first page
+    for page in ["2".to_string(), i64::MAX.to_string()] {
+        let response = app
+            .http_client
+            .get(format!("{}/admin/audit?page={}", &app.address, page))
+            .send()
+            .await
+            .expect("Failed to execute request.");
+        assert_eq!(response.status().as_u16(), 404);
+    }
+
+    let response = app
+        .http_client
+        .get(format!("{}/admin/audit?page=1", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn the_audit_log_can_be_exported_as_csv() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .http_client
+        .get(format!("{}/admin/audit/export", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response
+        .headers()
+        .get("Content-Type")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .starts_with("text/csv"));
+
+    let body = response.text().await.unwrap();
+    let mut lines = body.lines();
+    assert_eq!(
+        lines.next().unwrap(),
+        "occurred_at,actor_id,actor_username,action,target,ip_address,user_agent,request_id"
+    );
+    assert!(lines.any(|line| line.contains(",login,")));
+}
+
+fn request_id(response: &reqwest::Response) -> String {
+    response
+        .headers()
+        .get("x-request-id")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_owned()
+}
+
+async fn get_audit_html(app: &TestApp, page: u32) -> String {
+    app.http_client
+        .get(format!("{}/admin/audit?page={}", &app.address, page))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .text()
+        .await
+        .unwrap()
+}
@@ -0,0 +1,406 @@
+use reqwest::Method;
+use uuid::Uuid;
+
+use crate::helpers::{spawn_app, TestApp, TestUser};
+
+enum Payload {
+    None,
+    Form(serde_json::Value),
+    Json(serde_json::Value),
+}
+
+struct AdminRoute {
+    method: Method,
+    path: String,
+    payload: Payload,
+    minimum_role: &'static str,
+}
+
+fn admin_routes() -> Vec<AdminRoute> {
+    let target = Uuid::new_v4();
+    let route = |method, path: &str, payload, minimum_role| AdminRoute {
+        method,
+        path: path.to_owned(),
+        payload,
+        minimum_role,
+    };
+
+    vec![
+        route(Method::GET, "/admin/dashboard", Payload::None, "viewer"),
+        route(Method::GET, "/admin/password", Payload::None, "viewer"),
+        route(
+            Method::GET,
+            "/admin/login-activity",
+            Payload::None,
+            "viewer",
+        ),
+        route(
+            Method::GET,
+            "/admin/reports/signups",
+            Payload::None,
+            "viewer",
+        ),
+        route(Method::GET, "/admin/subscribers", Payload::None, "viewer"),
+        route(
+            Method::GET,
+            &format!("/admin/subscribers/{}", target),
+            Payload::None,
+            "viewer",
+        ),
+        route(
+            Method::POST,
+            &format!("/admin/subscribers/{}/confirm", target),
+            Payload::None,
+            "editor",
+        ),
+        route(
+            Method::POST,
+            &format!("/admin/subscribers/{}/tags", target),
+            Payload::Form(serde_json::json!({ "action": "add", "tags": "vip" })),
+            "editor",
+        ),
+        route(
+            Method::POST,
+            "/admin/subscribers/tags",
+            Payload::Form(serde_json::json!({ "action": "add", "tags": "vip" })),
+            "editor",
+        ),
+        route(Method::GET, "/admin/tags", Payload::None, "editor"),
+        route(
+            Method::POST,
+            "/admin/tags/vip/delete",
+            Payload::None,
+            "editor",
+        ),
+        route(
+            Method::GET,
+            "/admin/welcome-sequence",
+            Payload::None,
+            "editor",
+        ),
+        route(
+            Method::POST,
+            "/admin/welcome-sequence",
+            Payload::Form(serde_json::json!({
+                "delay_days": "0",
+                "subject": "Welcome",
+                "text_content": "Hello",
+                "html_content": "<p>Hello</p>",
+            })),
+            "editor",
+        ),
+        route(
+            Method::POST,
+            &format!("/admin/welcome-sequence/{}/delete", target),
+            Payload::None,
+            "editor",
+        ),
+        route(
+            Method::POST,
+            &format!("/admin/subscribers/{}/unsubscribe", target),
+            Payload::None,
+            "editor",
+        ),
+        route(
+            Method::POST,
+            &format!("/admin/subscribers/{}/delete", target),
+            Payload::None,
+            "editor",
+        ),
+        route(
+            Method::GET,
+            &format!("/admin/subscribers/{}/data", target),
+            Payload::None,
+            "editor",
+        ),
+        route(
+            Method::POST,
+            &format!("/admin/subscribers/{}/erase", target),
+            Payload::None,
+            "editor",
+        ),
+        route(
+            Method::GET,
+            "/admin/subscribers/export",
+            Payload::None,
+            "editor",
+        ),
+        route(
+            Method::GET,
+            "/admin/subscribers/import",
+            Payload::None,
+            "editor",
+        ),
+        route(
+            Method::POST,
+            "/admin/subscribers/import",
+            Payload::None,
+            "editor",
+        ),
+        route(
+            Method::GET,
+            &format!("/admin/subscribers/import/{}", target),
+            Payload::None,
+            "editor",
+        ),
+        route(
+            Method::GET,
+            &format!("/admin/subscribers/import/{}/rejected", target),
+            Payload::None,
+            "editor",
+        ),
+        route(Method::GET, "/admin/audit", Payload::None, "owner"),
+        route(Method::GET, "/admin/audit/export", Payload::None, "owner"),
+        route(Method::GET, "/admin/domain-rules", Payload::None, "editor"),
+        route(
+            Method::POST,
+            "/admin/domain-rules",
+            Payload::Form(serde_json::json!({
+                "pattern": "*.example.net",
+                "kind": "block",
+            })),
+            "editor",
+        ),
+        route(
+            Method::POST,
+            &format!("/admin/domain-rules/{}/delete", target),
+            Payload::None,
+            "editor",
+        ),
+        route(
+            Method::GET,
+            "/admin/subscriber-attributes",
+            Payload::None,
+            "editor",
+        ),
+        route(
+            Method::POST,
+            "/admin/subscriber-attributes",
+            Payload::Form(serde_json::json!({
+                "name": "company",
+                "label": "Company",
+                "kind": "text",
+            })),
+            "editor",
+        ),
+        route(
+            Method::POST,
+            "/admin/subscriber-attributes/company/delete",
+            Payload::None,
+            "editor",
+        ),
+        route(Method::GET, "/admin/suppressions", Payload::None, "editor"),
+        route(
+            Method::POST,
+            "/admin/suppressions",
+            Payload::Form(serde_json::json!({
+                "pattern": "abuse@*",
+                "reason": "role_address",
+                "source": "",
+            })),
+            "editor",
+        ),
+        route(
+            Method::POST,
+            "/admin/suppressions/upload",
+            Payload::None,
+            "editor",
+        ),
+        route(
+            Method::POST,
+            &format!("/admin/suppressions/{}/delete", target),
+            Payload::None,
+            "editor",
+        ),
+        route(
+            Method::POST,
+            "/admin/password",
+            Payload::Form(serde_json::json!({
+                "current_password": "wrong-password",
+                "new_password": "a-new-password",
+                "new_password_check": "a-new-password",
+            })),
+            "viewer",
+        ),
+        route(Method::GET, "/admin/newsletters", Payload::None, "editor"),
+        route(
+            Method::POST,
+            "/admin/newsletters",
+            Payload::Json(serde_json::json!({
+                "title": "Newsletter title",
+                "text_content": "Newsletter body as plain text",
+                "html_content": "<p>Newsletter body as HTML</p>",
+                "idempotency_key": Uuid::new_v4().to_string()
+            })),
+            "owner",
+        ),
+        route(Method::GET, "/admin/api-tokens", Payload::None, "viewer"),
+        route(
+            Method::POST,
+            "/admin/api-tokens",
+            Payload::Form(serde_json::json!({ "name": "" })),
+            "viewer",
+        ),
+        route(
+            Method::POST,
+            &format!("/admin/api-tokens/{}/revoke", target),
+            Payload::None,
+            "viewer",
+        ),
+        route(Method::GET, "/admin/two-factor", Payload::None, "viewer"),
+        route(
+            Method::POST,
+            "/admin/two-factor",
+            Payload::Form(serde_json::json!({ "code": "000000" })),
+            "viewer",
+        ),
+        route(
+            Method::POST,
+            "/admin/two-factor/disable",
+            Payload::Form(serde_json::json!({ "code": "000000" })),
+            "viewer",
+        ),
+        route(Method::GET, "/admin/users", Payload::None, "owner"),
+        route(
+            Method::POST,
+            "/admin/users",
+            Payload::Form(serde_json::json!({
+                "username": "new-admin",
+                "email": "not-an-email",
+                "role": "viewer",
+            })),
+            "owner",
+        ),
+        route(
+            Method::POST,
+            &format!("/admin/users/{}/disable", target),
+            Payload::None,
+            "owner",
+        ),
+        route(
+            Method::POST,
+            &format!("/admin/users/{}/enable", target),
+            Payload::None,
+            "owner",
+        ),
+        route(
+            Method::POST,
+            &format!("/admin/users/{}/role", target),
+            Payload::Form(serde_json::json!({ "role": "viewer" })),
+            "owner",
+        ),
+        route(
+            Method::POST,
+            &format!("/admin/users/{}/delete", target),
+            Payload::None,
+            "owner",
+        ),
+    ]
+}
+
+fn rank(role: &str) -> u8 {
+    match role {
+        "viewer" => 0,
+        "editor" => 1,
+        "owner" => 2,
+        _ => unreachable!(),
+    }
+}
+
+async fn send(app: &TestApp, route: &AdminRoute) -> reqwest::Response {
+    let request = app.http_client.request(
+        route.method.clone(),
+        format!("{}{}", &app.address, route.path),
+    );
+
+    let request = match &route.payload {
+        Payload::None => request,
+        Payload::Form(body) => request.form(body),
+        Payload::Json(body) => request.json(body),
+    };
+
+    request.send().await.expect("Failed to execute request.")
+}
+
+async fn check_role_against_every_admin_route(role: &'static str) {
+    let app = spawn_app().await;
+
+    let user = TestUser::generate_with_role(role);
+    user.store(&app.db).await;
+    user.login(&app).await;
+
+    for route in admin_routes() {
+        let response = send(&app, &route).await;
+        let status = response.status().as_u16();
+
+        if rank(role) >= rank(route.minimum_role) {
+            assert_ne!(
+                status, 403,
+                "{} should be allowed to {} {}",
+                role, route.method, route.path
+            );
+        } else {
+            assert_eq!(
+                status, 403,
+                "{} should not be allowed to {} {}",
+                role, route.method, route.path
+            );
+        }
+    }
+}
+
+#[tokio::test]
+async fn owners_can_use_every_admin_route() {
+    check_role_against_every_admin_route("owner").await;
+}
+
+#[tokio::test]
+async fn editors_can_draft_but_not_send_or_manage_users() {
+    check_role_against_every_admin_route("editor").await;
+}
+
+#[tokio::test]
+async fn viewers_can_only_see_reports() {
+    check_role_against_every_admin_route("viewer").await;
+}
+
+#[tokio::test]
+async fn anonymous_users_are_redirected_to_login_on_every_admin_route() {
+    let app = spawn_app().await;
+
+    for route in admin_routes() {
+        let response = send(&app, &route).await;
+
+        assert_eq!(
+            response.status().as_u16(),
+            303,
+            "{} {} should redirect to login",
+            route.method,
+            route.path
+        );
+        assert_eq!(response.headers().get("Location").unwrap(), "/login");
+    }
+}
+
+#[tokio::test]
+async fn role_changes_take_effect_immediately() {
+    let app = spawn_app().await;
+
+    let editor = TestUser::generate_with_role("editor");
+    editor.store(&app.db).await;
+    editor.login(&app).await;
+
+    let response = app.get_newsletter_form().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    sqlx::query!(
+        "UPDATE users SET role = 'viewer' WHERE user_id = $1",
+        editor.user_id
+    )
+    .execute(&app.db)
+    .await
+    .unwrap();
+
+    let response = app.get_newsletter_form().await;
+    assert_eq!(response.status().as_u16(), 403);
+}
@@ -0,0 +1,241 @@
+use chrono::{Duration, Utc};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use email_service::{
+    attribution::{signups_by_source, ReportGrouping},
+    configuration::ConfirmationReminderSettings,
+    confirmation_reminders::{purge_unconfirmed, send_due_reminders},
+};
+
+use crate::helpers::{spawn_app, TestApp, TestSubscriber};
+
+const BASE_URL: &str = "http://127.0.0.1";
+
+fn settings() -> ConfirmationReminderSettings {
+    ConfirmationReminderSettings {
+        reminder_hours: vec![24, 72],
+        purge_after_hours: 168,
+        check_interval_seconds: 300,
+        batch_size: 10,
+    }
+}
+
+/// Moves the confirmation emails back in time.
+async fn travel_hours(app: &TestApp, hours: i32) {
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET confirmation_sent_at = confirmation_sent_at - make_interval(hours => $1)
+        "#,
+        hours
+    )
+    .execute(&app.db)
+    .await
+    .unwrap();
+}
+
+async fn emails_sent(app: &TestApp) -> usize {
+    app.email_server.received_requests().await.unwrap().len()
+}
+
+#[tokio::test]
+async fn pending_signups_are_reminded_with_a_fresh_link_that_confirms_them() {
+    let app = spawn_app().await;
+    app.mock_email_server().await;
+    let client = app.email_client();
+
+    app.post_subscriptions(&[("name", "le guin"), ("email", "ursula@example.com")])
+        .await;
+    assert_eq!(emails_sent(&app).await, 1);
+
+    assert_eq!(
+        send_due_reminders(&app.db, &client, BASE_URL, &settings())
+            .await
+            .unwrap(),
+        0
+    );
+
+    travel_hours(&app, 25).await;
+    assert_eq!(
+        send_due_reminders(&app.db, &client, BASE_URL, &settings())
+            .await
+            .unwrap(),
+        1
+    );
+    assert_eq!(
+        send_due_reminders(&app.db, &client, BASE_URL, &settings())
+            .await
+            .unwrap(),
+        0
+    );
+
+    let requests = app.email_server.received_requests().await.unwrap();
+    assert_eq!(requests.len(), 2);
+    let first_link = app.get_confirmation_links(&requests[0]).html;
+    let reminder_link = app.get_confirmation_links(&requests[1]).html;
+    assert_ne!(first_link, reminder_link);
+
+    reqwest::get(reminder_link)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "confirmed");
+
+    travel_hours(&app, 100).await;
+    send_due_reminders(&app.db, &client, BASE_URL, &settings())
+        .await
+        .unwrap();
+    assert_eq!(emails_sent(&app).await, 2);
+}
+
+#[tokio::test]
+async fn reminders_that_fell_due_together_are_sent_once() {
+    let app = spawn_app().await;
+    app.mock_email_server().await;
+    let client = app.email_client();
+    let subscriber_id = app
+        .insert_subscriber(
+            TestSubscriber::new("ursula@example.com")
+                .status("pending_confirmation")
+                .confirmation_sent(),
+        )
+        .await;
+
+    travel_hours(&app, 80).await;
+    assert_eq!(
+        send_due_reminders(&app.db, &client, BASE_URL, &settings())
+            .await
+            .unwrap(),
+        1
+    );
+    assert_eq!(
+        send_due_reminders(&app.db, &client, BASE_URL, &settings())
+            .await
+            .unwrap(),
+        0
+    );
+    assert_eq!(emails_sent(&app).await, 1);
+
+    app.test_user.login(&app).await;
+    let html_page = app.get_admin_subscriber_html(subscriber_id).await;
+    assert!(html_page.contains(": reminded</li>"));
+}
+
+#[tokio::test]
+async fn failed_reminders_are_tried_again() {
+    let app = spawn_app().await;
+    let client = app.email_client();
+    app.insert_subscriber(
+        TestSubscriber::new("ursula@example.com")
+            .status("pending_confirmation")
+            .confirmation_sent(),
+    )
+    .await;
+    app.insert_subscriber(
+        TestSubscriber::new("octavia@example.com")
+            .status("pending_confirmation")
+            .confirmation_sent(),
+    )
+    .await;
+    travel_hours(&app, 25).await;
+
+    // Ursula was asked first, her reminder fails and Octavia's still goes out
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .up_to_n_times(1)
+        .mount(&app.email_server)
+        .await;
+    app.mock_email_server().await;
+    assert_eq!(
+        send_due_reminders(&app.db, &client, BASE_URL, &settings())
+            .await
+            .unwrap(),
+        1
+    );
+
+    assert_eq!(
+        send_due_reminders(&app.db, &client, BASE_URL, &settings())
+            .await
+            .unwrap(),
+        1
+    );
+    assert_eq!(emails_sent(&app).await, 3);
+}
+
+#[tokio::test]
+async fn signups_still_pending_after_the_last_reminder_are_purged() {
+    let app = spawn_app().await;
+    app.mock_email_server().await;
+    let client = app.email_client();
+    app.insert_subscriber(
+        TestSubscriber::new("pending@example.com")
+            .status("pending_confirmation")
+            .source("blog")
+            .confirmation_sent(),
+    )
+    .await;
+    app.insert_subscriber(TestSubscriber::new("confirmed@example.com").confirmation_sent())
+        .await;
+    // Imported without being sent a confirmation email
+    app.insert_subscriber(
+        TestSubscriber::new("imported@example.com").status("pending_confirmation"),
+    )
+    .await;
+
+    travel_hours(&app, 200).await;
+    // Not before they got their last reminder
+    assert_eq!(purge_unconfirmed(&app.db, &settings()).await.unwrap(), 0);
+    send_due_reminders(&app.db, &client, BASE_URL, &settings())
+        .await
+        .unwrap();
+    assert_eq!(purge_unconfirmed(&app.db, &settings()).await.unwrap(), 1);
+
+    let remaining: Vec<String> = sqlx::query!("SELECT email FROM subscriptions ORDER BY email")
+        .fetch_all(&app.db)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|row| row.email)
+        .collect();
+    assert_eq!(
+        remaining,
+        vec!["confirmed@example.com", "imported@example.com"]
+    );
+    // The purged signup still counts, as one that never confirmed
+    let report = signups_by_source(
+        &app.db,
+        ReportGrouping::Source,
+        Utc::now() - Duration::days(1),
+        Utc::now() + Duration::days(1),
+    )
+    .await
+    .unwrap();
+    let blog = report
+        .iter()
+        .find(|row| row.key.as_deref() == Some("blog"))
+        .unwrap();
+    assert_eq!((blog.signups, blog.confirmed), (1, 0));
+
+    let keep_forever = ConfirmationReminderSettings {
+        purge_after_hours: 0,
+        ..settings()
+    };
+    app.insert_subscriber(
+        TestSubscriber::new("late@example.com")
+            .status("pending_confirmation")
+            .confirmation_sent(),
+    )
+    .await;
+    travel_hours(&app, 1000).await;
+    send_due_reminders(&app.db, &client, BASE_URL, &keep_forever)
+        .await
+        .unwrap();
+    assert_eq!(purge_unconfirmed(&app.db, &keep_forever).await.unwrap(), 0);
+}
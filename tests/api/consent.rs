@@ -0,0 +1,126 @@
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::spawn_app;
+
+#[tokio::test]
+async fn signups_and_confirmations_are_recorded_in_the_consent_log() {
+    let app = spawn_app().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.http_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("User-Agent", "signup-browser/1.0")
+        .form(&[
+            ("email", "ursula@example.com"),
+            ("name", "le guin"),
+            ("source", "footer-form"),
+        ])
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    app.http_client
+        .get(confirmation_links.html)
+        .header("User-Agent", "mail-client/2.0")
+        .header("Referer", "https://mail.example.com/inbox")
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    let records = sqlx::query!(
+        "SELECT kind, ip_address, user_agent, source FROM subscription_consents ORDER BY recorded_at"
+    )
+    .fetch_all(&app.db)
+    .await
+    .unwrap();
+    assert_eq!(records.len(), 2);
+
+    let client_ip = app.client_ip.to_string();
+    assert_eq!(records[0].kind, "signup");
+    assert_eq!(records[0].ip_address.as_deref(), Some(client_ip.as_str()));
+    assert_eq!(records[0].user_agent.as_deref(), Some("signup-browser/1.0"));
+    assert_eq!(records[0].source.as_deref(), Some("footer-form"));
+
+    assert_eq!(records[1].kind, "confirmation");
+    assert_eq!(records[1].ip_address.as_deref(), Some(client_ip.as_str()));
+    assert_eq!(records[1].user_agent.as_deref(), Some("mail-client/2.0"));
+    assert_eq!(
+        records[1].source.as_deref(),
+        Some("https://mail.example.com/inbox")
+    );
+
+    let subscriber_id = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db)
+        .await
+        .unwrap()
+        .id;
+    app.test_user.login(&app).await;
+    let html_page = app.get_admin_subscriber_html(subscriber_id).await;
+    assert!(html_page.contains("<td>signup</td>"));
+    assert!(html_page.contains("footer-form"));
+    assert!(html_page.contains("<td>confirmation</td>"));
+    assert!(html_page.contains("mail-client/2.0"));
+}
+
+#[tokio::test]
+async fn the_signup_page_is_the_source_when_the_form_does_not_say() {
+    let app = spawn_app().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.http_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Referer", "https://blog.example.com/post")
+        .form(&[("email", "ursula@example.com"), ("name", "le guin")])
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    let record = sqlx::query!("SELECT source FROM subscription_consents")
+        .fetch_one(&app.db)
+        .await
+        .unwrap();
+    assert_eq!(
+        record.source.as_deref(),
+        Some("https://blog.example.com/post")
+    );
+}
+
+#[tokio::test]
+async fn the_consent_log_cannot_be_rewritten() {
+    let app = spawn_app().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(&[("email", "ursula@example.com"), ("name", "le guin")])
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let result = sqlx::query!("UPDATE subscription_consents SET source = 'forged'")
+        .execute(&app.db)
+        .await;
+    assert!(result.is_err());
+}
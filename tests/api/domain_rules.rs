@@ -0,0 +1,143 @@
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use email_service::domain_policy::MAX_BLOCKED_DOMAINS;
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+
+async fn post_domain_rule(app: &TestApp, pattern: &str, kind: &str) -> reqwest::Response {
+    app.http_client
+        .post(format!("{}/admin/domain-rules", &app.address))
+        .form(&[("pattern", pattern), ("kind", kind)])
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn get_domain_rules_html(app: &TestApp) -> String {
+    app.http_client
+        .get(format!("{}/admin/domain-rules", &app.address))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap()
+}
+
+async fn subscribe(app: &TestApp, email: &str) -> reqwest::Response {
+    app.post_subscriptions(&[("email", email), ("name", "le guin")])
+        .await
+}
+
+#[tokio::test]
+async fn disposable_addresses_are_refused_with_a_clear_error() {
+    let app = spawn_app().await;
+
+    let response = subscribe(&app, "ursula@Mailinator.com").await;
+    assert_eq!(response.status().as_u16(), 400);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("Disposable email addresses are not accepted"));
+
+    let saved = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_optional(&app.db)
+        .await
+        .unwrap();
+    assert!(saved.is_none());
+}
+
+#[tokio::test]
+async fn admins_can_block_domains_with_wildcards() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = post_domain_rule(&app, "*.Example.net", "block").await;
+    assert_is_redirect_to(&response, "/admin/domain-rules");
+    assert!(get_domain_rules_html(&app)
+        .await
+        .contains("<td>*.example.net</td><td>block</td>"));
+
+    let response = subscribe(&app, "ursula@mail.example.net").await;
+    assert_eq!(response.status().as_u16(), 400);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("Signups from this email domain are not accepted."));
+}
+
+#[tokio::test]
+async fn allow_rules_let_disposable_domains_through() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    post_domain_rule(&app, "yopmail.com", "allow").await;
+
+    let response = subscribe(&app, "ursula@yopmail.com").await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn admins_see_how_many_signups_were_blocked() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    subscribe(&app, "ursula@mailinator.com").await;
+    subscribe(&app, "octavia@mailinator.com").await;
+
+    let html_page = get_domain_rules_html(&app).await;
+    assert!(html_page.contains("2 attempts were blocked."));
+    assert!(html_page.contains("<td>mailinator.com</td><td>disposable</td><td>2</td>"));
+}
+
+#[tokio::test]
+async fn only_the_most_recently_blocked_domains_are_kept() {
+    let app = spawn_app().await;
+    sqlx::query!(
+        r#"
+        INSERT INTO blocked_signups (domain, reason, attempts, last_attempt_at)
+        SELECT 'junk' || n || '.example', 'blocklist', 1, now() - make_interval(mins => n::int)
+        FROM generate_series(1, $1::bigint) AS n
+        "#,
+        MAX_BLOCKED_DOMAINS,
+    )
+    .execute(&app.db)
+    .await
+    .unwrap();
+
+    subscribe(&app, "ursula@mailinator.com").await;
+
+    let domains: Vec<String> = sqlx::query!("SELECT domain FROM blocked_signups")
+        .fetch_all(&app.db)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|row| row.domain)
+        .collect();
+    assert_eq!(domains.len() as i64, MAX_BLOCKED_DOMAINS);
+    assert!(domains.iter().any(|domain| domain == "mailinator.com"));
+    let oldest = format!("junk{}.example", MAX_BLOCKED_DOMAINS);
+    assert!(!domains.contains(&oldest));
+}
+
+#[tokio::test]
+async fn invalid_patterns_are_refused() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = post_domain_rule(&app, "*.*", "block").await;
+    assert_is_redirect_to(&response, "/admin/domain-rules");
+    assert!(get_domain_rules_html(&app)
+        .await
+        .contains("The pattern would match every domain."));
+}
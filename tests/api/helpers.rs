@@ -1,13 +1,23 @@
+use std::net::{IpAddr, Ipv4Addr};
+
 use argon2::{password_hash::SaltString, Argon2, PasswordHasher};
+use chrono::{DateTime, Utc};
 use once_cell::sync::Lazy;
-use reqwest::Client;
+use reqwest::{
+    header::{HeaderMap, HeaderValue},
+    Client,
+};
+use secrecy::Secret;
 use serde::Serialize;
 use sqlx::{types::Uuid, Connection, Executor, PgConnection, PgPool};
 use ulid::Ulid;
-use wiremock::MockServer;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
 
 use email_service::{
-    configuration::{get_configuration, DatabaseSettings},
+    configuration::{get_configuration, DatabaseSettings, Settings},
+    domain::SubscriberEmail,
+    email_client::EmailClient,
     startup::{self, Application},
     telemetry,
 };
@@ -29,6 +39,7 @@ pub struct TestApp {
     pub address: String,
     pub port: u16,
     pub db: PgPool,
+    pub client_ip: IpAddr,
     pub http_client: Client,
     pub email_server: MockServer,
     pub test_user: TestUser,
@@ -38,18 +49,24 @@ pub struct TestUser {
     pub user_id: Uuid,
     pub username: String,
     pub password: String,
+    pub role: &'static str,
 }
 
 impl TestUser {
     pub fn generate() -> Self {
+        Self::generate_with_role("owner")
+    }
+
+    pub fn generate_with_role(role: &'static str) -> Self {
         Self {
             user_id: Uuid::new_v4(),
             username: Uuid::new_v4().to_string(),
             password: Uuid::new_v4().to_string(),
+            role,
         }
     }
 
-    async fn store(&self, pool: &PgPool) {
+    pub async fn store(&self, pool: &PgPool) {
         let salt = SaltString::generate(&mut rand::thread_rng());
         let password_hash = Argon2::default()
             .hash_password(self.password.as_bytes(), &salt)
@@ -57,11 +74,12 @@ impl TestUser {
             .to_string();
 
         sqlx::query!(
-            "INSERT INTO users (user_id, username, password_hash)
-            VALUES ($1, $2, $3)",
+            "INSERT INTO users (user_id, username, password_hash, role)
+            VALUES ($1, $2, $3, $4)",
             self.user_id,
             self.username,
             password_hash,
+            self.role,
         )
         .execute(pool)
         .await
@@ -78,7 +96,64 @@ impl TestUser {
     }
 }
 
+/// A subscriber inserted straight into the database, confirmed unless said
+/// otherwise.
+pub struct TestSubscriber {
+    email: String,
+    name: String,
+    status: String,
+    subscribed_at: DateTime<Utc>,
+    source: Option<String>,
+    confirmation_sent_at: Option<DateTime<Utc>>,
+}
+
+impl TestSubscriber {
+    pub fn new(email: &str) -> Self {
+        Self {
+            email: email.to_owned(),
+            name: "Reader".into(),
+            status: "confirmed".into(),
+            subscribed_at: Utc::now(),
+            source: None,
+            confirmation_sent_at: None,
+        }
+    }
+
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = name.to_owned();
+        self
+    }
+
+    pub fn status(mut self, status: &str) -> Self {
+        self.status = status.to_owned();
+        self
+    }
+
+    pub fn subscribed_at(mut self, subscribed_at: DateTime<Utc>) -> Self {
+        self.subscribed_at = subscribed_at;
+        self
+    }
+
+    pub fn source(mut self, source: &str) -> Self {
+        self.source = Some(source.to_owned());
+        self
+    }
+
+    /// Marks the confirmation email as sent just now.
+    pub fn confirmation_sent(mut self) -> Self {
+        self.confirmation_sent_at = Some(Utc::now());
+        self
+    }
+}
+
 pub async fn spawn_app() -> TestApp {
+    spawn_app_with(|_| {}).await
+}
+
+pub async fn spawn_app_with<F>(customise: F) -> TestApp
+where
+    F: FnOnce(&mut Settings),
+{
     Lazy::force(&TRACING);
 
     let email_server = MockServer::start().await;
@@ -92,6 +167,16 @@ pub async fn spawn_app() -> TestApp {
         c.application.port = 0;
         // Use the mock server as email API
         c.email_client.base_url = email_server.uri();
+        // Tests that need login delays opt back into them
+        c.login_throttling.base_delay_milliseconds = 0;
+        // Tests post the subscription form directly, and share addresses
+        // across apps through Redis; those about bot protection opt back in
+        c.signup_protection.min_fill_seconds = 0;
+        c.signup_protection.max_signups_per_email = 0;
+        // Each app gets its own client IP, so that throttling does not leak
+        // between tests running in parallel
+        c.application.trust_forwarded_for = true;
+        customise(&mut c);
         c
     };
 
@@ -112,16 +197,14 @@ pub async fn spawn_app() -> TestApp {
     let test_user = TestUser::generate();
     test_user.store(&db).await;
 
-    let http_client = Client::builder()
-        .redirect(reqwest::redirect::Policy::none())
-        .cookie_store(true)
-        .build()
-        .unwrap();
+    let client_ip: IpAddr = Ipv4Addr::from(rand::random::<u32>()).into();
+    let http_client = build_client(client_ip);
 
     TestApp {
         address,
         port,
         db,
+        client_ip,
         http_client,
         email_server,
         test_user,
@@ -131,7 +214,7 @@ pub async fn spawn_app() -> TestApp {
 impl TestApp {
     pub async fn post_subscriptions<T: Serialize>(&self, form: &T) -> reqwest::Response {
         self.http_client
-            .post(&format!("{}/subscriptions", &self.address))
+            .post(format!("{}/subscriptions", &self.address))
             .form(form)
             .send()
             .await
@@ -140,7 +223,7 @@ impl TestApp {
 
     pub async fn post_subscriptions_raw(&self, body: &str) -> reqwest::Response {
         self.http_client
-            .post(&format!("{}/subscriptions", &self.address))
+            .post(format!("{}/subscriptions", &self.address))
             .header("Content-Type", "application/x-www-form-urlencoded")
             .body(body.to_owned())
             .send()
@@ -150,19 +233,20 @@ impl TestApp {
 
     pub async fn get_newsletter_form(&self) -> reqwest::Response {
         self.http_client
-            .get(&format!("{}/admin/newsletters", &self.address))
+            .get(format!("{}/admin/newsletters", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
     }
 
+    #[allow(dead_code)]
     pub async fn get_newsletter_form_html(&self) -> String {
         self.get_newsletter_form().await.text().await.unwrap()
     }
 
     pub async fn post_newsletters(&self, body: serde_json::Value) -> reqwest::Response {
         self.http_client
-            .post(&format!("{}/admin/newsletters", &self.address))
+            .post(format!("{}/admin/newsletters", &self.address))
             .basic_auth(&self.test_user.username, Some(&self.test_user.password))
             .json(&body)
             .send()
@@ -189,18 +273,82 @@ impl TestApp {
             confirmation_link
         };
 
-        let html = get_link(&body["HtmlBody"].as_str().unwrap());
-        let plain_text = get_link(&body["TextBody"].as_str().unwrap());
+        let html = get_link(body["HtmlBody"].as_str().unwrap());
+        let plain_text = get_link(body["TextBody"].as_str().unwrap());
 
         ConfirmationLinks { html, plain_text }
     }
 
+    /// Some emails are sent in the background, after the response.
+    pub async fn wait_for_emails(&self, count: usize) -> Vec<wiremock::Request> {
+        for _ in 0..50 {
+            let requests = self.email_server.received_requests().await.unwrap();
+            if requests.len() >= count {
+                return requests;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        panic!("Expected {} email(s) to be sent.", count);
+    }
+
+    pub async fn insert_subscriber(&self, subscriber: TestSubscriber) -> Uuid {
+        let subscriber_id = Uuid::new_v4();
+        sqlx::query!(
+            r#"
+            INSERT INTO subscriptions (
+                id, email, name, subscribed_at, status, source, confirmation_sent_at
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            "#,
+            subscriber_id,
+            subscriber.email,
+            subscriber.name,
+            subscriber.subscribed_at,
+            subscriber.status,
+            subscriber.source,
+            subscriber.confirmation_sent_at,
+        )
+        .execute(&self.db)
+        .await
+        .expect("Failed to insert test subscriber.");
+
+        subscriber_id
+    }
+
+    /// A client pointed at the mock email server, for driving background
+    /// jobs directly.
+    pub fn email_client(&self) -> EmailClient {
+        EmailClient::new(
+            self.email_server.uri(),
+            SubscriberEmail::parse("newsletter@example.com".into()).unwrap(),
+            Secret::new("token".into()),
+            self.db.clone(),
+        )
+    }
+
+    /// Accepts every email sent to the email server.
+    pub async fn mock_email_server(&self) {
+        Mock::given(path("/email"))
+            .and(method("POST"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&self.email_server)
+            .await;
+    }
+
     pub async fn post_login<Body>(&self, body: &Body) -> reqwest::Response
     where
         Body: serde::Serialize,
     {
-        self.http_client
-            .post(&format!("{}/login", &self.address))
+        self.post_login_with(&self.http_client, body).await
+    }
+
+    /// Logs in through `client`, leaving the session of `http_client` alone.
+    pub async fn post_login_with<Body>(&self, client: &Client, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        client
+            .post(format!("{}/login", &self.address))
             .form(body)
             .send()
             .await
@@ -209,7 +357,7 @@ impl TestApp {
 
     pub async fn get_login_html(&self) -> String {
         self.http_client
-            .get(&format!("{}/login", &self.address))
+            .get(format!("{}/login", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -220,7 +368,7 @@ impl TestApp {
 
     pub async fn get_admin_dashboard(&self) -> reqwest::Response {
         self.http_client
-            .get(&format!("{}/admin/dashboard", &self.address))
+            .get(format!("{}/admin/dashboard", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -232,7 +380,7 @@ impl TestApp {
 
     pub async fn get_change_password(&self) -> reqwest::Response {
         self.http_client
-            .get(&format!("{}/admin/password", &self.address))
+            .get(format!("{}/admin/password", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -243,7 +391,7 @@ impl TestApp {
         Body: serde::Serialize,
     {
         self.http_client
-            .post(&format!("{}/admin/password", &self.address))
+            .post(format!("{}/admin/password", &self.address))
             .form(body)
             .send()
             .await
@@ -254,15 +402,225 @@ impl TestApp {
         self.get_change_password().await.text().await.unwrap()
     }
 
+    pub async fn get_admin_users(&self) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/admin/users", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_admin_users_html(&self) -> String {
+        self.get_admin_users().await.text().await.unwrap()
+    }
+
+    pub async fn post_admin_users<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/admin/users", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_admin_user_action(&self, user_id: Uuid, action: &str) -> reqwest::Response {
+        self.http_client
+            .post(format!(
+                "{}/admin/users/{}/{}",
+                &self.address, user_id, action
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_admin_subscribers(&self, query: &str) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/admin/subscribers?{}", &self.address, query))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_admin_subscribers_html(&self, query: &str) -> String {
+        self.get_admin_subscribers(query)
+            .await
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_admin_subscriber_html(&self, subscriber_id: Uuid) -> String {
+        self.http_client
+            .get(format!(
+                "{}/admin/subscribers/{}",
+                &self.address, subscriber_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_admin_subscriber_action(
+        &self,
+        subscriber_id: Uuid,
+        action: &str,
+    ) -> reqwest::Response {
+        self.http_client
+            .post(format!(
+                "{}/admin/subscribers/{}/{}",
+                &self.address, subscriber_id, action
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// reqwest is built without multipart support, so the body is put
+    /// together by hand.
+    pub async fn post_subscriber_import(&self, csv: &str, mode: &str) -> reqwest::Response {
+        let boundary = "import-boundary";
+        let body = format!(
+            "--{boundary}\r\n\
+            Content-Disposition: form-data; name=\"mode\"\r\n\r\n\
+            {mode}\r\n\
+            --{boundary}\r\n\
+            Content-Disposition: form-data; name=\"file\"; filename=\"audience.csv\"\r\n\
+            Content-Type: text/csv\r\n\r\n\
+            {csv}\r\n\
+            --{boundary}--\r\n"
+        );
+
+        self.http_client
+            .post(format!("{}/admin/subscribers/import", &self.address))
+            .header(
+                "Content-Type",
+                format!("multipart/form-data; boundary={}", boundary),
+            )
+            .body(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_accept_invitation<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/invitation", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_password_reset<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/password-reset", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_password_reset_confirm<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/password-reset/confirm", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// A client with its own cookie jar, to act as a second browser.
+    pub fn new_client(&self) -> Client {
+        build_client(self.client_ip)
+    }
+
+    pub async fn get_two_factor_html(&self) -> String {
+        self.http_client
+            .get(format!("{}/admin/two-factor", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_two_factor<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/admin/two-factor", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_two_factor_disable<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/admin/two-factor/disable", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_login_two_factor<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/login/two-factor", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn post_logout(&self) -> reqwest::Response {
         self.http_client
-            .post(&format!("{}/logout", &self.address))
+            .post(format!("{}/logout", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
     }
 }
 
+fn build_client(client_ip: IpAddr) -> Client {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        "X-Forwarded-For",
+        HeaderValue::from_str(&client_ip.to_string()).unwrap(),
+    );
+
+    Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .cookie_store(true)
+        .default_headers(headers)
+        .build()
+        .unwrap()
+}
+
 pub async fn configure_database(config: &DatabaseSettings) -> PgPool {
     let mut connection = PgConnection::connect(&config.connection_string_without_db())
         .await
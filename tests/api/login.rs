@@ -1,11 +1,16 @@
-use crate::helpers::{assert_is_redirect_to, spawn_app};
+use std::time::Duration;
+
+use reqwest::Client;
+use uuid::Uuid;
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with, TestApp};
 
 #[tokio::test]
 async fn an_error_flash_message_is_set_on_failure() {
     let app = spawn_app().await;
 
     let login_body = serde_json::json!({
-        "username": "random-username",
+        "username": Uuid::new_v4().to_string(),
         "password": "random-password"
     });
 
@@ -37,3 +42,136 @@ async fn redirect_to_admin_dashboard_after_login_success() {
     let html_page = app.get_admin_dashboard_html().await;
     assert!(html_page.contains(&format!("Welcome {}", app.test_user.username)));
 }
+
+#[tokio::test]
+async fn the_flash_message_does_not_reveal_which_credential_was_wrong() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": "wrong-password"
+        }))
+        .await;
+    let wrong_password = response.cookies().find(|c| c.name() == "_flash").unwrap();
+
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": Uuid::new_v4().to_string(),
+            "password": "wrong-password"
+        }))
+        .await;
+    let unknown_user = response.cookies().find(|c| c.name() == "_flash").unwrap();
+
+    assert_eq!(wrong_password.value(), unknown_user.value());
+}
+
+#[tokio::test]
+async fn a_username_is_locked_out_after_too_many_failures() {
+    let app = spawn_app_with(|c| c.login_throttling.max_failures = 3).await;
+
+    for _ in 0..3 {
+        let response = login(&app, &app.http_client, "wrong-password").await;
+        assert_is_redirect_to(&response, "/login");
+    }
+
+    // Even the right password is refused now, from anywhere
+    let client = client_with_ip("203.0.113.7");
+    let response = login(&app, &client, &app.test_user.password).await;
+    assert_is_redirect_to(&response, "/login");
+
+    let html_page = client
+        .get(format!("{}/login", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("Too many failed attempts, please try again later."));
+}
+
+#[tokio::test]
+async fn an_ip_is_locked_out_after_too_many_failures() {
+    let app = spawn_app_with(|c| c.login_throttling.max_failures = 3).await;
+
+    for _ in 0..3 {
+        let response = app
+            .post_login(&serde_json::json!({
+                "username": Uuid::new_v4().to_string(),
+                "password": "wrong-password"
+            }))
+            .await;
+        assert_is_redirect_to(&response, "/login");
+    }
+
+    let response = login(&app, &app.http_client, &app.test_user.password).await;
+    assert_is_redirect_to(&response, "/login");
+
+    // The username itself is not locked
+    let client = client_with_ip("203.0.113.8");
+    let response = login(&app, &client, &app.test_user.password).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn attempts_are_delayed_after_a_failure() {
+    let app = spawn_app_with(|c| c.login_throttling.base_delay_milliseconds = 1000).await;
+
+    login(&app, &app.http_client, "wrong-password").await;
+
+    let response = login(&app, &app.http_client, &app.test_user.password).await;
+    assert_is_redirect_to(&response, "/login");
+
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+
+    let response = login(&app, &app.http_client, &app.test_user.password).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn login_attempts_are_shown_to_the_account_owner() {
+    let app = spawn_app().await;
+
+    login(&app, &app.http_client, "wrong-password").await;
+    let response = login(&app, &app.http_client, &app.test_user.password).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    let html_page = app
+        .http_client
+        .get(format!("{}/admin/login-activity", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .text()
+        .await
+        .unwrap();
+
+    assert!(html_page.contains("failure"));
+    assert!(html_page.contains("success"));
+    assert!(html_page.contains(&app.client_ip.to_string()));
+}
+
+async fn login(app: &TestApp, client: &Client, password: &str) -> reqwest::Response {
+    client
+        .post(format!("{}/login", &app.address))
+        .form(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": password,
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+fn client_with_ip(ip: &str) -> Client {
+    let mut headers = reqwest::header::HeaderMap::new();
+    headers.insert("X-Forwarded-For", ip.parse().unwrap());
+
+    Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .cookie_store(true)
+        .default_headers(headers)
+        .build()
+        .unwrap()
+}
@@ -1,8 +1,28 @@
 mod admin_dashboard;
+mod admin_subscribers;
+mod admin_users;
+mod api_tokens;
+mod audit;
+mod authorization;
 mod change_password;
+mod confirmation_reminders;
+mod consent;
+mod domain_rules;
 mod health_check;
 mod helpers;
 mod login;
 mod newsletter;
+mod password_reset;
+mod signup_attribution;
+mod signup_protection;
+mod subscriber_attributes;
+mod subscriber_data;
+mod subscriber_export;
+mod subscriber_import;
+mod subscriber_tags;
 mod subscriptions;
 mod subscriptions_confirm;
+mod suppressions;
+mod two_factor;
+mod welcome_sequence;
+mod widget;
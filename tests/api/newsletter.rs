@@ -1,4 +1,11 @@
-use crate::helpers::{assert_is_redirect_to, spawn_app, ConfirmationLinks, TestApp};
+use email_service::{
+    configuration::{IdempotencyBackend, IdempotencySettings},
+    idempotency::delete_expired_keys,
+};
+
+use crate::helpers::{
+    assert_is_redirect_to, spawn_app, spawn_app_with, ConfirmationLinks, TestApp,
+};
 
 use wiremock::matchers::{any, method, path};
 use wiremock::{Mock, ResponseTemplate};
@@ -36,6 +43,8 @@ async fn you_must_be_logged_in_to_send_a_newsletter_form() {
 async fn newsletters_returns_400_for_invalid_data() {
     let app = spawn_app().await;
 
+    app.test_user.login(&app).await;
+
     let test_cases = vec![
         (
             serde_json::json!({
@@ -200,6 +209,194 @@ async fn concurrent_form_submission_is_handled_gracefully() {
     );
 }
 
+#[tokio::test]
+async fn expired_idempotency_keys_are_treated_as_new() {
+    let app = spawn_app().await;
+
+    app.test_user.login(&app).await;
+
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+
+    let response = app.post_newsletters(newsletter_request_body.clone()).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    sqlx::query!("UPDATE idempotency SET created_at = now() - interval '3 days'")
+        .execute(&app.db)
+        .await
+        .unwrap();
+
+    let response = app.post_newsletters(newsletter_request_body.clone()).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn the_sweeper_only_removes_expired_idempotency_keys() {
+    let app = spawn_app().await;
+
+    app.test_user.login(&app).await;
+
+    for _ in 0..3 {
+        let newsletter_request_body = serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": uuid::Uuid::new_v4().to_string()
+        });
+
+        let response = app.post_newsletters(newsletter_request_body).await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    sqlx::query!(
+        "UPDATE idempotency SET created_at = now() - interval '3 days'
+        WHERE idempotency_key IN (SELECT idempotency_key FROM idempotency LIMIT 2)"
+    )
+    .execute(&app.db)
+    .await
+    .unwrap();
+
+    let settings = IdempotencySettings {
+        backend: IdempotencyBackend::Postgres,
+        retention_hours: 48,
+        lock_timeout_seconds: 30,
+        sweep_interval_seconds: 600,
+        sweep_batch_size: 1,
+    };
+
+    let deleted = delete_expired_keys(&app.db, &settings).await.unwrap();
+    assert_eq!(deleted, 2);
+
+    let remaining = sqlx::query!("SELECT COUNT(*) AS count FROM idempotency")
+        .fetch_one(&app.db)
+        .await
+        .unwrap();
+    assert_eq!(remaining.count, Some(1));
+}
+
+#[tokio::test]
+async fn newsletter_creation_is_idempotent_with_the_redis_backend() {
+    let app = spawn_app_with(|c| c.idempotency.backend = IdempotencyBackend::Redis).await;
+
+    app.test_user.login(&app).await;
+
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+
+    let response = app.post_newsletters(newsletter_request_body.clone()).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.post_newsletters(newsletter_request_body.clone()).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let saved = sqlx::query!("SELECT COUNT(*) AS count FROM idempotency")
+        .fetch_one(&app.db)
+        .await
+        .unwrap();
+    assert_eq!(saved.count, Some(0));
+}
+
+#[tokio::test]
+async fn concurrent_form_submission_is_handled_gracefully_with_the_redis_backend() {
+    let app = spawn_app_with(|c| c.idempotency.backend = IdempotencyBackend::Redis).await;
+
+    create_confirmed_subscriber(&app).await;
+
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_delay(std::time::Duration::from_secs(1)))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+
+    let response1 = app.post_newsletters(newsletter_request_body.clone());
+    let response2 = app.post_newsletters(newsletter_request_body.clone());
+    let (response1, response2) = tokio::join!(response1, response2);
+
+    assert_eq!(response1.status(), response2.status());
+    assert_eq!(
+        response1.text().await.unwrap(),
+        response2.text().await.unwrap()
+    );
+}
+
+#[tokio::test]
+async fn a_redis_lock_is_kept_while_the_newsletter_is_being_sent() {
+    let app = spawn_app_with(|c| {
+        c.idempotency.backend = IdempotencyBackend::Redis;
+        c.idempotency.lock_timeout_seconds = 1;
+    })
+    .await;
+
+    create_confirmed_subscriber(&app).await;
+
+    app.test_user.login(&app).await;
+
+    // The delivery outlasts the lock timeout several times over
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_delay(std::time::Duration::from_secs(3)))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+
+    let response1 = app.post_newsletters(newsletter_request_body.clone());
+    let response2 = async {
+        tokio::time::sleep(std::time::Duration::from_millis(1500)).await;
+        app.post_newsletters(newsletter_request_body.clone()).await
+    };
+    let (response1, response2) = tokio::join!(response1, response2);
+
+    assert_eq!(response1.status().as_u16(), 200);
+    assert_eq!(response2.status().as_u16(), 200);
+    assert_eq!(
+        response1.text().await.unwrap(),
+        response2.text().await.unwrap()
+    );
+}
+
 async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
     let _mock_guard = Mock::given(path("/email"))
         .and(method("POST"))
@@ -223,7 +420,7 @@ async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
         .pop()
         .unwrap();
 
-    app.get_confirmation_links(&email_request)
+    app.get_confirmation_links(email_request)
 }
 
 async fn create_confirmed_subscriber(app: &TestApp) {
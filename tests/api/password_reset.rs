@@ -0,0 +1,280 @@
+use std::time::Duration;
+
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with, TestApp};
+
+#[tokio::test]
+async fn the_login_page_links_to_password_reset() {
+    let app = spawn_app().await;
+
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains(r#"href="/password-reset""#));
+}
+
+#[tokio::test]
+async fn the_response_does_not_reveal_whether_the_user_exists() {
+    let app = spawn_app().await;
+    set_email(&app, "owner@example.com").await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_password_reset(&serde_json::json!({ "username": &app.test_user.username }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+    let known_user_page = app.get_login_html().await;
+
+    let response = app
+        .post_password_reset(&serde_json::json!({ "username": "nobody" }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+    let unknown_user_page = app.get_login_html().await;
+
+    assert_eq!(known_user_page, unknown_user_page);
+    assert!(known_user_page.contains("If the account exists"));
+
+    // Only the existing user is sent an email
+    app.wait_for_emails(1).await;
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(app.email_server.received_requests().await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn a_user_can_reset_their_password_with_the_emailed_link() {
+    let app = spawn_app().await;
+    set_email(&app, "owner@example.com").await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_password_reset(&serde_json::json!({ "username": &app.test_user.username }))
+        .await;
+    let token = reset_token(&app).await;
+
+    let new_password = Uuid::new_v4().to_string();
+    let response = app
+        .post_password_reset_confirm(&serde_json::json!({
+            "reset_token": &token,
+            "new_password": &new_password,
+            "new_password_check": &new_password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("Your password has been reset, you can now log in."));
+
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &new_password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    // The link is single use
+    let response = app
+        .post_password_reset_confirm(&serde_json::json!({
+            "reset_token": &token,
+            "new_password": "another-long-password",
+            "new_password_check": "another-long-password",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("This password reset link is invalid or has expired."));
+}
+
+#[tokio::test]
+async fn the_new_password_must_follow_the_usual_rules() {
+    let app = spawn_app().await;
+    set_email(&app, "owner@example.com").await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_password_reset(&serde_json::json!({ "username": &app.test_user.username }))
+        .await;
+    let token = reset_token(&app).await;
+
+    let response = app
+        .post_password_reset_confirm(&serde_json::json!({
+            "reset_token": &token,
+            "new_password": "short",
+            "new_password_check": "short",
+        }))
+        .await;
+    let location = response
+        .headers()
+        .get("Location")
+        .unwrap()
+        .to_str()
+        .unwrap();
+    assert!(location.starts_with("/password-reset/confirm?reset_token="));
+
+    // The token has not been used up by the failed attempt
+    let new_password = Uuid::new_v4().to_string();
+    let response = app
+        .post_password_reset_confirm(&serde_json::json!({
+            "reset_token": &token,
+            "new_password": &new_password,
+            "new_password_check": &new_password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("Your password has been reset"));
+}
+
+#[tokio::test]
+async fn an_expired_link_is_rejected() {
+    let app = spawn_app().await;
+    set_email(&app, "owner@example.com").await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_password_reset(&serde_json::json!({ "username": &app.test_user.username }))
+        .await;
+    let token = reset_token(&app).await;
+
+    sqlx::query!("UPDATE password_reset_tokens SET expires_at = now() - interval '1 minute'")
+        .execute(&app.db)
+        .await
+        .unwrap();
+
+    let new_password = Uuid::new_v4().to_string();
+    let response = app
+        .post_password_reset_confirm(&serde_json::json!({
+            "reset_token": &token,
+            "new_password": &new_password,
+            "new_password_check": &new_password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("This password reset link is invalid or has expired."));
+    let remaining = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM password_reset_tokens"#)
+        .fetch_one(&app.db)
+        .await
+        .unwrap();
+    assert_eq!(remaining.count, 0);
+
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn repeated_requests_are_throttled() {
+    let app = spawn_app_with(|c| c.login_throttling.max_failures = 3).await;
+    set_email(&app, "owner@example.com").await;
+    app.mock_email_server().await;
+
+    for _ in 0..5 {
+        let response = app
+            .post_password_reset(&serde_json::json!({ "username": &app.test_user.username }))
+            .await;
+        assert_is_redirect_to(&response, "/login");
+    }
+
+    app.wait_for_emails(3).await;
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(app.email_server.received_requests().await.unwrap().len(), 3);
+
+    // Reset requests do not lock the account out of logging in
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn resetting_the_password_revokes_existing_sessions() {
+    let app = spawn_app().await;
+    set_email(&app, "owner@example.com").await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.test_user.login(&app).await;
+    let response = app.get_admin_dashboard().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.post_password_reset(&serde_json::json!({ "username": &app.test_user.username }))
+        .await;
+    let token = reset_token(&app).await;
+
+    let new_password = Uuid::new_v4().to_string();
+    app.post_password_reset_confirm(&serde_json::json!({
+        "reset_token": &token,
+        "new_password": &new_password,
+        "new_password_check": &new_password,
+    }))
+    .await;
+
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+async fn set_email(app: &TestApp, email: &str) {
+    sqlx::query!(
+        "UPDATE users SET email = $2 WHERE user_id = $1",
+        app.test_user.user_id,
+        email
+    )
+    .execute(&app.db)
+    .await
+    .unwrap();
+}
+
+async fn reset_token(app: &TestApp) -> String {
+    let email_request = &app.wait_for_emails(1).await[0];
+    let reset_link = app.get_confirmation_links(email_request).html;
+    assert_eq!(reset_link.path(), "/password-reset/confirm");
+
+    reset_link
+        .query_pairs()
+        .find(|(k, _)| k == "reset_token")
+        .unwrap()
+        .1
+        .into_owned()
+}
@@ -0,0 +1,183 @@
+use chrono::{Duration, Utc};
+
+use crate::helpers::{spawn_app, TestSubscriber, TestUser};
+
+#[tokio::test]
+async fn the_source_and_campaign_of_a_signup_are_stored() {
+    let app = spawn_app().await;
+    app.mock_email_server().await;
+
+    let response = app
+        .http_client
+        .post(format!(
+            "{}/subscriptions?utm_source=mastodon&utm_campaign=spring",
+            &app.address
+        ))
+        .form(&[
+            ("name", "le guin"),
+            ("email", "ursula@example.com"),
+            ("source", "landing-page"),
+            ("referrer", "https://social.example/@earthsea"),
+            ("utm_source", "newsletter"),
+            ("utm_medium", "email"),
+        ])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    let saved = sqlx::query!(
+        r#"
+        SELECT source, referrer, utm_source, utm_medium, utm_campaign, utm_term
+        FROM subscriptions
+        "#
+    )
+    .fetch_one(&app.db)
+    .await
+    .unwrap();
+    assert_eq!(saved.source.as_deref(), Some("landing-page"));
+    assert_eq!(
+        saved.referrer.as_deref(),
+        Some("https://social.example/@earthsea")
+    );
+    // The body wins over the query string
+    assert_eq!(saved.utm_source.as_deref(), Some("newsletter"));
+    assert_eq!(saved.utm_medium.as_deref(), Some("email"));
+    assert_eq!(saved.utm_campaign.as_deref(), Some("spring"));
+    assert_eq!(saved.utm_term, None);
+}
+
+#[tokio::test]
+async fn forms_without_a_source_are_attributed_to_their_page() {
+    let app = spawn_app().await;
+    app.mock_email_server().await;
+
+    app.http_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Referer", "https://blog.example/posts/42")
+        .form(&[("name", "le guin"), ("email", "ursula@example.com")])
+        .send()
+        .await
+        .unwrap();
+
+    let saved = sqlx::query!("SELECT source, referrer FROM subscriptions")
+        .fetch_one(&app.db)
+        .await
+        .unwrap();
+    assert_eq!(
+        saved.source.as_deref(),
+        Some("https://blog.example/posts/42")
+    );
+    assert_eq!(saved.referrer, None);
+}
+
+#[tokio::test]
+async fn the_signup_page_passes_on_the_campaign_and_referrer() {
+    let app = spawn_app().await;
+
+    let html_page = app
+        .http_client
+        .get(format!(
+            "{}/?utm_source=mastodon&utm_campaign=spring",
+            &app.address
+        ))
+        .header("Referer", "https://social.example/")
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains(r#"<input type="hidden" name="utm_source" value="mastodon">"#));
+    assert!(html_page.contains(r#"<input type="hidden" name="utm_campaign" value="spring">"#));
+    assert!(html_page.contains(&format!(
+        r#"<input type="hidden" name="referrer" value="{}">"#,
+        htmlescape::encode_attribute("https://social.example/")
+    )));
+    assert!(!html_page.contains(r#"name="utm_medium""#));
+}
+
+#[tokio::test]
+async fn the_report_groups_signups_by_source_over_a_date_range() {
+    let app = spawn_app().await;
+    let in_range = Utc::now() - Duration::days(2);
+    app.insert_subscriber(
+        TestSubscriber::new("reader1@example.com")
+            .source("blog")
+            .subscribed_at(in_range),
+    )
+    .await;
+    app.insert_subscriber(
+        TestSubscriber::new("reader2@example.com")
+            .source("blog")
+            .status("pending_confirmation")
+            .subscribed_at(in_range),
+    )
+    .await;
+    app.insert_subscriber(
+        TestSubscriber::new("reader3@example.com")
+            .source("blog")
+            .subscribed_at(in_range),
+    )
+    .await;
+    app.insert_subscriber(
+        TestSubscriber::new("reader4@example.com")
+            .source("blog")
+            .status("unsubscribed")
+            .subscribed_at(in_range),
+    )
+    .await;
+    app.insert_subscriber(TestSubscriber::new("reader5@example.com").subscribed_at(in_range))
+        .await;
+    app.insert_subscriber(
+        TestSubscriber::new("reader6@example.com")
+            .source("podcast")
+            .subscribed_at(Utc::now() - Duration::days(90)),
+    )
+    .await;
+
+    let viewer = TestUser::generate_with_role("viewer");
+    viewer.store(&app.db).await;
+    viewer.login(&app).await;
+
+    let html_page = app
+        .http_client
+        .get(format!("{}/admin/reports/signups", &app.address))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("<tr><td>blog</td><td>4</td><td>2</td><td>50.0%</td></tr>"));
+    assert!(
+        html_page.contains("<tr><td><i>unknown</i></td><td>1</td><td>1</td><td>100.0%</td></tr>")
+    );
+    assert!(!html_page.contains("podcast"));
+
+    let from = (Utc::now() - Duration::days(100)).format("%Y-%m-%d");
+    let html_page = app
+        .http_client
+        .get(format!(
+            "{}/admin/reports/signups?group=source&from={}&until=",
+            &app.address, from
+        ))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("<tr><td>podcast</td><td>1</td><td>1</td><td>100.0%</td></tr>"));
+
+    let response = app
+        .http_client
+        .get(format!(
+            "{}/admin/reports/signups?group=color",
+            &app.address
+        ))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 400);
+}
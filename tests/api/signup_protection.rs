@@ -0,0 +1,219 @@
+use email_service::signup_protection::proof_of_work_holds;
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::{spawn_app, spawn_app_with, TestApp};
+
+/// The signed token embedded in the subscription form of the home page.
+async fn get_form_token(app: &TestApp) -> String {
+    let html_page = app
+        .http_client
+        .get(&app.address)
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+
+    let marker = r#"name="form_token" value=""#;
+    let start = html_page.find(marker).unwrap() + marker.len();
+    let end = start + html_page[start..].find('"').unwrap();
+    html_page[start..end].to_owned()
+}
+
+async fn subscriber_count(app: &TestApp) -> i64 {
+    sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM subscriptions"#)
+        .fetch_one(&app.db)
+        .await
+        .unwrap()
+        .count
+}
+
+#[tokio::test]
+async fn filling_in_the_honeypot_pretends_to_succeed() {
+    let app = spawn_app().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscriptions(&[
+            ("email", "ursula@example.com"),
+            ("name", "le guin"),
+            ("website", "https://spam.example.com"),
+        ])
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_count(&app).await, 0);
+}
+
+#[tokio::test]
+async fn forms_sent_back_too_quickly_are_refused() {
+    let app = spawn_app_with(|c| c.signup_protection.min_fill_seconds = 1).await;
+    app.mock_email_server().await;
+
+    let form_token = get_form_token(&app).await;
+    let form = [
+        ("email", "ursula@example.com"),
+        ("name", "le guin"),
+        ("form_token", form_token.as_str()),
+    ];
+
+    let response = app.post_subscriptions(&form).await;
+    assert_eq!(response.status().as_u16(), 400);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("The form was sent too quickly"));
+
+    tokio::time::sleep(std::time::Duration::from_millis(1100)).await;
+    let response = app.post_subscriptions(&form).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn missing_or_forged_form_tokens_are_refused() {
+    let app = spawn_app_with(|c| c.signup_protection.min_fill_seconds = 1).await;
+
+    let response = app
+        .post_subscriptions(&[("email", "ursula@example.com"), ("name", "le guin")])
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    let form_token = get_form_token(&app).await;
+    let (_, signature) = form_token.split_once('.').unwrap();
+    let forged = format!("1000000000.{}", signature);
+    let response = app
+        .post_subscriptions(&[
+            ("email", "ursula@example.com"),
+            ("name", "le guin"),
+            ("form_token", forged.as_str()),
+        ])
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("The form has expired"));
+}
+
+#[tokio::test]
+async fn a_proof_of_work_can_be_required() {
+    let app = spawn_app_with(|c| c.signup_protection.pow_difficulty = 8).await;
+    app.mock_email_server().await;
+
+    let form_token = get_form_token(&app).await;
+    let response = app
+        .post_subscriptions(&[
+            ("email", "ursula@example.com"),
+            ("name", "le guin"),
+            ("form_token", form_token.as_str()),
+            ("pow_nonce", "not-a-solution"),
+        ])
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    let nonce = (0..)
+        .map(|n: u32| n.to_string())
+        .find(|nonce| proof_of_work_holds(&form_token, "ursula@example.com", nonce, 8))
+        .unwrap();
+    let response = app
+        .post_subscriptions(&[
+            ("email", "ursula@example.com"),
+            ("name", "le guin"),
+            ("form_token", form_token.as_str()),
+            ("pow_nonce", nonce.as_str()),
+        ])
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn a_form_token_is_good_for_one_signup_and_one_address() {
+    let app = spawn_app_with(|c| c.signup_protection.pow_difficulty = 8).await;
+    app.mock_email_server().await;
+
+    let form_token = get_form_token(&app).await;
+    let nonce = (0..)
+        .map(|n: u32| n.to_string())
+        .find(|nonce| proof_of_work_holds(&form_token, "ursula@example.com", nonce, 8))
+        .unwrap();
+    let form = [
+        ("email", "ursula@example.com"),
+        ("name", "le guin"),
+        ("form_token", form_token.as_str()),
+        ("pow_nonce", nonce.as_str()),
+    ];
+
+    // The solution only holds for the address it was computed for
+    let response = app
+        .post_subscriptions(&[
+            ("email", "octavia@example.com"),
+            ("name", "butler"),
+            ("form_token", form_token.as_str()),
+            ("pow_nonce", nonce.as_str()),
+        ])
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    let response = app.post_subscriptions(&form).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.post_subscriptions(&form).await;
+    assert_eq!(response.status().as_u16(), 400);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("The form has expired"));
+}
+
+#[tokio::test]
+async fn signups_are_rate_limited_per_ip() {
+    let app = spawn_app_with(|c| c.signup_protection.max_signups_per_ip = 2).await;
+    app.mock_email_server().await;
+
+    for name in ["ursula", "octavia"] {
+        let email = format!("{}@example.com", name);
+        let response = app
+            .post_subscriptions(&[("email", email.as_str()), ("name", name)])
+            .await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    let response = app
+        .post_subscriptions(&[("email", "ann@example.com"), ("name", "ann")])
+        .await;
+    assert_eq!(response.status().as_u16(), 429);
+    assert_eq!(subscriber_count(&app).await, 2);
+}
+
+#[tokio::test]
+async fn signups_are_rate_limited_per_email() {
+    let app = spawn_app_with(|c| c.signup_protection.max_signups_per_email = 1).await;
+    app.mock_email_server().await;
+
+    // Redis is shared between tests, the address must be unique to this one
+    let email = format!("{}@example.com", Uuid::new_v4());
+    let response = app
+        .post_subscriptions(&[("email", email.as_str()), ("name", "le guin")])
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app
+        .post_subscriptions(&[
+            ("email", email.to_uppercase().as_str()),
+            ("name", "le guin"),
+        ])
+        .await;
+    assert_eq!(response.status().as_u16(), 429);
+}
@@ -0,0 +1,215 @@
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+
+async fn post_attribute(
+    app: &TestApp,
+    name: &str,
+    kind: &str,
+    required: bool,
+) -> reqwest::Response {
+    let mut form = vec![("name", name), ("label", name), ("kind", kind)];
+    if required {
+        form.push(("required", "true"));
+    }
+
+    app.http_client
+        .post(format!("{}/admin/subscriber-attributes", &app.address))
+        .form(&form)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn define_attributes(app: &TestApp) {
+    app.test_user.login(app).await;
+    let response = post_attribute(app, "company", "text", true).await;
+    assert_is_redirect_to(&response, "/admin/subscriber-attributes");
+    post_attribute(app, "seats", "number", false).await;
+}
+
+#[tokio::test]
+async fn admins_define_the_allowed_attributes() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    post_attribute(&app, " Company ", "text", true).await;
+    let html_page = app
+        .http_client
+        .get(format!("{}/admin/subscriber-attributes", &app.address))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("<td>company</td><td>Company</td><td>text</td><td>yes</td>"));
+    assert!(html_page.contains("<i>The attribute has been added.</i>"));
+
+    let response = post_attribute(&app, "company", "number", false).await;
+    assert_is_redirect_to(&response, "/admin/subscriber-attributes");
+    let response = post_attribute(&app, "company size", "text", false).await;
+    assert_is_redirect_to(&response, "/admin/subscriber-attributes");
+    let definitions = sqlx::query!("SELECT name, kind FROM subscriber_attributes")
+        .fetch_all(&app.db)
+        .await
+        .unwrap();
+    assert_eq!(definitions.len(), 1);
+    assert_eq!(definitions[0].kind, "text");
+
+    let response = app
+        .http_client
+        .post(format!(
+            "{}/admin/subscriber-attributes/company/delete",
+            &app.address
+        ))
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/admin/subscriber-attributes");
+    let definitions = sqlx::query!("SELECT name FROM subscriber_attributes")
+        .fetch_all(&app.db)
+        .await
+        .unwrap();
+    assert!(definitions.is_empty());
+}
+
+#[tokio::test]
+async fn attributes_sent_with_a_form_are_stored_with_their_type() {
+    let app = spawn_app().await;
+    define_attributes(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscriptions(&[
+            ("name", "le guin"),
+            ("email", "ursula@example.com"),
+            ("attributes[company]", "Earthsea"),
+            ("attributes[seats]", "12"),
+        ])
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let saved = sqlx::query!("SELECT attributes FROM subscriptions")
+        .fetch_one(&app.db)
+        .await
+        .unwrap();
+    assert_eq!(
+        saved.attributes,
+        serde_json::json!({ "company": "Earthsea", "seats": 12 })
+    );
+}
+
+#[tokio::test]
+async fn attributes_sent_as_json_are_validated() {
+    let app = spawn_app().await;
+    define_attributes(&app).await;
+
+    let response = app
+        .http_client
+        .post(format!("{}/subscriptions", &app.address))
+        .json(&serde_json::json!({
+            "name": "le guin",
+            "email": "ursula@example.com",
+            "attributes": { "seats": "many", "shoe_size": 42 },
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 400);
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    let fields = &body["error"]["fields"];
+    assert_eq!(fields["attributes.company"], "company is required.");
+    assert_eq!(fields["attributes.seats"], "seats must be a number.");
+    assert_eq!(
+        fields["attributes.shoe_size"],
+        "shoe_size is not a known attribute."
+    );
+
+    let saved = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_optional(&app.db)
+        .await
+        .unwrap();
+    assert!(saved.is_none());
+}
+
+#[tokio::test]
+async fn the_signup_forms_ask_for_the_attributes() {
+    let app = spawn_app().await;
+    define_attributes(&app).await;
+
+    let html_page = app
+        .http_client
+        .get(&app.address)
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains(r#"name="attributes[company]" required>"#));
+    assert!(html_page.contains(r#"<input type="number" step="any" name="attributes[seats]">"#));
+
+    let token: serde_json::Value = app
+        .http_client
+        .get(format!("{}/subscriptions/form-token", &app.address))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(token["attributes"][1]["name"], "seats");
+    assert_eq!(token["attributes"][1]["type"], "number");
+}
+
+#[tokio::test]
+async fn attributes_are_shown_to_admins_and_exported() {
+    let app = spawn_app().await;
+    define_attributes(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions(&[
+        ("name", "le guin"),
+        ("email", "ursula@example.com"),
+        ("attributes[company]", "Earthsea, Inc."),
+    ])
+    .await;
+    let subscriber = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db)
+        .await
+        .unwrap();
+
+    let html_page = app.get_admin_subscriber_html(subscriber.id).await;
+    assert!(html_page.contains("<tr><td>company</td><td>Earthsea, Inc.</td></tr>"));
+
+    let csv = app
+        .http_client
+        .get(format!(
+            "{}/admin/subscribers/export?format=csv",
+            &app.address
+        ))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    let mut lines = csv.lines();
+    assert_eq!(
+        lines.next().unwrap(),
+        "id,email,name,status,subscribed_at,attributes.company,attributes.seats"
+    );
+    assert!(lines.next().unwrap().ends_with(r#","Earthsea, Inc.","#));
+}
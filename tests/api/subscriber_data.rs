@@ -0,0 +1,241 @@
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+
+/// Subscribes through the public form and returns the id of the subscriber.
+async fn subscribe(app: &TestApp, email: &str) -> Uuid {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(&[("email", email), ("name", "le guin")])
+        .await
+        .error_for_status()
+        .unwrap();
+
+    sqlx::query!("SELECT id FROM subscriptions WHERE email = $1", email)
+        .fetch_one(&app.db)
+        .await
+        .unwrap()
+        .id
+}
+
+async fn post_data_request(app: &TestApp, email: &str, action: &str) -> reqwest::Response {
+    app.http_client
+        .post(format!("{}/subscriptions/data", &app.address))
+        .form(&[("email", email), ("action", action)])
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn subscriber_count(app: &TestApp) -> i64 {
+    sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM subscriptions"#)
+        .fetch_one(&app.db)
+        .await
+        .unwrap()
+        .count
+}
+
+#[tokio::test]
+async fn admins_can_download_everything_held_about_a_subscriber() {
+    let app = spawn_app().await;
+    let subscriber_id = subscribe(&app, "ursula@example.com").await;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .http_client
+        .get(format!(
+            "{}/admin/subscribers/{}/data",
+            &app.address, subscriber_id
+        ))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    let data: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(data["subscription"]["email"], "ursula@example.com");
+    assert_eq!(data["subscription"]["status"], "pending_confirmation");
+    assert_eq!(data["subscription_tokens"].as_array().unwrap().len(), 1);
+    assert_eq!(data["history"][0]["event"], "subscribed");
+    assert_eq!(data["consent"][0]["given_at"], "signup");
+    assert_eq!(
+        data["consent"][0]["ip_address"],
+        app.client_ip.to_string().as_str()
+    );
+}
+
+#[tokio::test]
+async fn erased_subscribers_are_gone_and_cannot_be_imported_again() {
+    let app = spawn_app().await;
+    let subscriber_id = subscribe(&app, "ursula@example.com").await;
+    app.test_user.login(&app).await;
+    app.post_admin_subscriber_action(subscriber_id, "confirm")
+        .await;
+
+    let response = app
+        .post_admin_subscriber_action(subscriber_id, "erase")
+        .await;
+    assert_is_redirect_to(&response, "/admin/subscribers");
+    assert_eq!(subscriber_count(&app).await, 0);
+
+    let audit_html = app
+        .http_client
+        .get(format!("{}/admin/audit", &app.address))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(audit_html.contains("<td>subscriber_erase</td>"));
+    assert!(!audit_html.contains("ursula@example.com"));
+
+    let csv = "email,name\nUrsula@Example.com,Ursula\noctavia@example.com,Octavia\n";
+    app.post_subscriber_import(csv, "mark_confirmed").await;
+
+    let emails: Vec<String> = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_all(&app.db)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|row| row.email)
+        .collect();
+    assert_eq!(emails, ["octavia@example.com"]);
+}
+
+#[tokio::test]
+async fn erasure_covers_audit_events_whatever_the_casing_of_the_address() {
+    let app = spawn_app().await;
+    let subscriber_id = subscribe(&app, "ursula@example.com").await;
+    app.test_user.login(&app).await;
+    sqlx::query!(
+        r#"
+        INSERT INTO audit_events (event_id, action, target, occurred_at)
+        VALUES ($1, 'subscriber_import', 'Ursula@Example.com', now())
+        "#,
+        Uuid::new_v4(),
+    )
+    .execute(&app.db)
+    .await
+    .unwrap();
+
+    app.post_admin_subscriber_action(subscriber_id, "erase")
+        .await;
+
+    let remaining = sqlx::query!(
+        r#"SELECT COUNT(*) AS "count!" FROM audit_events WHERE lower(target) = 'ursula@example.com'"#
+    )
+    .fetch_one(&app.db)
+    .await
+    .unwrap();
+    assert_eq!(remaining.count, 0);
+}
+
+#[tokio::test]
+async fn erasure_scrubs_whole_rows_of_import_reports_whatever_the_casing() {
+    let app = spawn_app().await;
+    let subscriber_id = subscribe(&app, "ursula@example.com").await;
+    app.test_user.login(&app).await;
+    let csv = "email,name,status\nUrsula@Example.com,Ursula Le Guin,sometimes\nnot-an-email,Octavia,confirmed\n";
+    app.post_subscriber_import(csv, "mark_confirmed").await;
+
+    app.post_admin_subscriber_action(subscriber_id, "erase")
+        .await;
+
+    let report = sqlx::query!("SELECT rejected_report FROM subscriber_imports")
+        .fetch_one(&app.db)
+        .await
+        .unwrap()
+        .rejected_report;
+    assert!(!report.to_lowercase().contains("ursula"));
+    assert!(!report.contains("Le Guin"));
+    assert!(report.contains("not-an-email,Octavia,"));
+}
+
+#[tokio::test]
+async fn subscribers_can_download_their_data_through_an_emailed_link() {
+    let app = spawn_app().await;
+    subscribe(&app, "ursula@example.com").await;
+
+    let response = post_data_request(&app, "ursula@example.com", "export").await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("If this address is subscribed, we have emailed it a link."));
+
+    let email_request = &app.wait_for_emails(2).await[1];
+    let link = app.get_confirmation_links(email_request).html;
+    assert_eq!(link.path(), "/subscriptions/data/export");
+
+    let response = reqwest::get(link.clone()).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    let data: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(data["subscription"]["email"], "ursula@example.com");
+
+    // The signature covers the address
+    let tampered = link
+        .as_str()
+        .replace("ursula%40example.com", "octavia%40example.com");
+    let response = reqwest::get(tampered).await.unwrap();
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn subscribers_can_erase_their_data_through_an_emailed_link() {
+    let app = spawn_app().await;
+    subscribe(&app, "ursula@example.com").await;
+
+    post_data_request(&app, "ursula@example.com", "erase").await;
+    let email_request = &app.wait_for_emails(2).await[1];
+    let link = app.get_confirmation_links(email_request).html;
+    assert_eq!(link.path(), "/subscriptions/data/erase");
+
+    // Opening the link alone erases nothing
+    let html_page = reqwest::get(link.clone())
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("Erase my data"));
+    assert_eq!(subscriber_count(&app).await, 1);
+
+    let params: Vec<(String, String)> = link.query_pairs().into_owned().collect();
+    let response = reqwest::Client::new()
+        .post(format!("{}/subscriptions/data/erase", &app.address))
+        .form(&params)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_count(&app).await, 0);
+}
+
+#[tokio::test]
+async fn requests_for_unknown_addresses_send_nothing() {
+    let app = spawn_app().await;
+
+    let response = post_data_request(&app, "nobody@example.com", "export").await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("If this address is subscribed, we have emailed it a link."));
+
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+    assert!(app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .is_empty());
+}
@@ -0,0 +1,88 @@
+use crate::helpers::{spawn_app, TestApp, TestSubscriber};
+
+async fn get_export(app: &TestApp, query: &str) -> reqwest::Response {
+    app.http_client
+        .get(format!(
+            "{}/admin/subscribers/export?{}",
+            &app.address, query
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn seed(app: &TestApp) {
+    app.insert_subscriber(
+        TestSubscriber::new("a@example.com").subscribed_at("2024-01-10T08:00:00Z".parse().unwrap()),
+    )
+    .await;
+    app.insert_subscriber(
+        TestSubscriber::new("b@example.com")
+            .status("pending_confirmation")
+            .subscribed_at("2024-01-20T08:00:00Z".parse().unwrap()),
+    )
+    .await;
+    app.insert_subscriber(
+        TestSubscriber::new("c@example.com").subscribed_at("2024-02-05T23:59:00Z".parse().unwrap()),
+    )
+    .await;
+    app.insert_subscriber(
+        TestSubscriber::new("d@example.com").subscribed_at("2024-02-06T00:00:00Z".parse().unwrap()),
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn subscribers_are_exported_as_csv_filtered_by_status() {
+    let app = spawn_app().await;
+    seed(&app).await;
+    app.test_user.login(&app).await;
+
+    let response = get_export(&app, "format=csv&status=confirmed").await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers().get("Content-Type").unwrap(),
+        "text/csv; charset=utf-8"
+    );
+
+    let body = response.text().await.unwrap();
+    let mut lines = body.lines();
+    assert_eq!(lines.next(), Some("id,email,name,status,subscribed_at"));
+    let emails: Vec<&str> = lines.map(|line| line.split(',').nth(1).unwrap()).collect();
+    assert_eq!(emails, ["a@example.com", "c@example.com", "d@example.com"]);
+}
+
+#[tokio::test]
+async fn subscribers_are_exported_as_ndjson_within_a_date_range() {
+    let app = spawn_app().await;
+    seed(&app).await;
+    app.test_user.login(&app).await;
+
+    let response = get_export(
+        &app,
+        "format=ndjson&status=&from=2024-01-15&until=2024-02-05",
+    )
+    .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let body = response.text().await.unwrap();
+    let emails: Vec<String> = body
+        .lines()
+        .map(|line| {
+            let value: serde_json::Value = serde_json::from_str(line).unwrap();
+            value["email"].as_str().unwrap().to_owned()
+        })
+        .collect();
+    assert_eq!(emails, ["b@example.com", "c@example.com"]);
+}
+
+#[tokio::test]
+async fn invalid_export_filters_are_rejected_with_a_400() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    for query in ["format=xml", "status=gone", "from=yesterday"] {
+        let response = get_export(&app, query).await;
+        assert_eq!(response.status().as_u16(), 400, "{}", query);
+    }
+}
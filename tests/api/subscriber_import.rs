@@ -0,0 +1,143 @@
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+
+/// Follows the redirect of a successful import to its summary page.
+async fn import_summary_location(response: &reqwest::Response) -> String {
+    assert_eq!(response.status().as_u16(), 303);
+    let location = response
+        .headers()
+        .get("Location")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_owned();
+    assert!(location.starts_with("/admin/subscribers/import/"));
+    location
+}
+
+async fn get_text(app: &TestApp, location: &str) -> String {
+    app.http_client
+        .get(format!("{}{}", &app.address, location))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap()
+}
+
+async fn statuses(app: &TestApp) -> Vec<(String, String)> {
+    sqlx::query!("SELECT email, status FROM subscriptions ORDER BY email")
+        .fetch_all(&app.db)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|row| (row.email, row.status))
+        .collect()
+}
+
+#[tokio::test]
+async fn valid_rows_are_imported_and_invalid_ones_reported() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let csv = "email,name,subscribed_at\n\
+        ursula@example.com,Ursula Le Guin,2020-01-31\n\
+        not-an-email,Someone\n\
+        octavia@example.com,Octavia Butler,\n";
+    let response = app.post_subscriber_import(csv, "mark_confirmed").await;
+    let location = import_summary_location(&response).await;
+
+    assert_eq!(
+        statuses(&app).await,
+        [
+            ("octavia@example.com".into(), "confirmed".into()),
+            ("ursula@example.com".into(), "confirmed".into()),
+        ]
+    );
+
+    let html_page = get_text(&app, &location).await;
+    assert!(html_page.contains("Imported 2 subscribers from audience.csv"));
+    assert!(html_page.contains("1 rows were rejected."));
+
+    let report = get_text(&app, &format!("{}/rejected", location)).await;
+    assert!(report.starts_with("line,email,name,reason\n"));
+    assert!(report.contains("3,not-an-email,Someone,"));
+}
+
+#[tokio::test]
+async fn imported_rows_can_be_sent_a_confirmation_email() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    let csv = "email,name\nursula@example.com,Ursula\noctavia@example.com,Octavia\n";
+    let response = app.post_subscriber_import(csv, "send_confirmation").await;
+    import_summary_location(&response).await;
+
+    let email_request = &app.wait_for_emails(2).await[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    let statuses = statuses(&app).await;
+    assert_eq!(
+        statuses
+            .iter()
+            .filter(|(_, status)| status == "confirmed")
+            .count(),
+        1
+    );
+    assert_eq!(
+        statuses
+            .iter()
+            .filter(|(_, status)| status == "pending_confirmation")
+            .count(),
+        1
+    );
+}
+
+#[tokio::test]
+async fn importing_never_resubscribes_someone_who_opted_out() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let csv = "email,name,status\nursula@example.com,Ursula,unsubscribed\n";
+    app.post_subscriber_import(csv, "mark_confirmed").await;
+
+    let csv = "email,name\nursula@example.com,Ursula K. Le Guin\n";
+    app.post_subscriber_import(csv, "mark_confirmed").await;
+
+    let row = sqlx::query!("SELECT name, status FROM subscriptions")
+        .fetch_one(&app.db)
+        .await
+        .unwrap();
+    assert_eq!(row.name, "Ursula K. Le Guin");
+    assert_eq!(row.status, "unsubscribed");
+}
+
+#[tokio::test]
+async fn a_file_without_the_required_columns_is_refused() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .post_subscriber_import("address\nursula@example.com\n", "mark_confirmed")
+        .await;
+    assert_is_redirect_to(&response, "/admin/subscribers/import");
+
+    let html_page = get_text(&app, "/admin/subscribers/import").await;
+    assert!(html_page.contains("The file needs an email and a name column."));
+    assert!(statuses(&app).await.is_empty());
+}
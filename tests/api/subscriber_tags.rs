@@ -0,0 +1,250 @@
+use sqlx::PgPool;
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp, TestSubscriber};
+
+async fn tags_of(pool: &PgPool, email: &str) -> Vec<String> {
+    sqlx::query!(
+        r#"
+        SELECT tag FROM subscription_tags
+        JOIN subscriptions ON subscriptions.id = subscription_tags.subscriber_id
+        WHERE email = $1
+        ORDER BY tag
+        "#,
+        email,
+    )
+    .fetch_all(pool)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|row| row.tag)
+    .collect()
+}
+
+/// The signed value attaching `tags` at signup, as given on the tags page.
+async fn signup_token(app: &TestApp, tags: &str) -> String {
+    let html_page = app
+        .http_client
+        .get(format!("{}/admin/tags?signup={}", &app.address, tags))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    let link = html_page.split("/?tags=").nth(1).unwrap();
+    let token = &link[..link.find("</code>").unwrap()];
+    urlencoding::decode(token).unwrap().into_owned()
+}
+
+#[tokio::test]
+async fn tags_can_be_added_and_removed_on_a_subscriber() {
+    let app = spawn_app().await;
+    let subscriber_id = app
+        .insert_subscriber(TestSubscriber::new("ursula@example.com").name("Ursula Le Guin"))
+        .await;
+    app.test_user.login(&app).await;
+
+    let post_tags = |action: &'static str, tags: &'static str| {
+        app.http_client
+            .post(format!(
+                "{}/admin/subscribers/{}/tags",
+                &app.address, subscriber_id
+            ))
+            .form(&[("action", action), ("tags", tags)])
+            .send()
+    };
+
+    let response = post_tags("add", "VIP, beta-tester").await.unwrap();
+    assert_is_redirect_to(&response, &format!("/admin/subscribers/{}", subscriber_id));
+    assert_eq!(
+        tags_of(&app.db, "ursula@example.com").await,
+        vec!["beta-tester", "vip"]
+    );
+    let html_page = app.get_admin_subscriber_html(subscriber_id).await;
+    assert!(html_page.contains("<p>Tags: beta-tester, vip</p>"));
+    assert!(html_page.contains("<i>The tags have been updated.</i>"));
+
+    post_tags("remove", "vip").await.unwrap();
+    assert_eq!(
+        tags_of(&app.db, "ursula@example.com").await,
+        vec!["beta-tester"]
+    );
+
+    post_tags("add", "not a tag").await.unwrap();
+    let html_page = app.get_admin_subscriber_html(subscriber_id).await;
+    assert!(html_page.contains("not a tag is not a valid tag"));
+    assert_eq!(
+        tags_of(&app.db, "ursula@example.com").await,
+        vec!["beta-tester"]
+    );
+}
+
+#[tokio::test]
+async fn tags_are_applied_to_every_subscriber_found() {
+    let app = spawn_app().await;
+    for i in 0..30 {
+        app.insert_subscriber(TestSubscriber::new(&format!("reader{}@example.com", i)))
+            .await;
+    }
+    app.insert_subscriber(TestSubscriber::new("ursula@example.com").name("Ursula Le Guin"))
+        .await;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .http_client
+        .post(format!("{}/admin/subscribers/tags", &app.address))
+        .form(&[
+            ("q", "reader"),
+            ("status", ""),
+            ("tag", ""),
+            ("action", "add"),
+            ("tags", "conference-2026"),
+        ])
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/admin/subscribers?q=reader&status=&tag=&page=1");
+
+    let tagged = sqlx::query!(
+        r#"SELECT COUNT(*) AS "count!" FROM subscription_tags WHERE tag = 'conference-2026'"#
+    )
+    .fetch_one(&app.db)
+    .await
+    .unwrap();
+    assert_eq!(tagged.count, 30);
+    assert!(tags_of(&app.db, "ursula@example.com").await.is_empty());
+
+    let html_page = app.get_admin_subscribers_html("tag=conference-2026").await;
+    assert!(html_page.contains("(30 subscribers)"));
+    assert!(!html_page.contains("ursula@example.com"));
+
+    app.http_client
+        .post(format!("{}/admin/subscribers/tags", &app.address))
+        .form(&[
+            ("q", "reader1"),
+            ("action", "remove"),
+            ("tags", "conference-2026"),
+        ])
+        .send()
+        .await
+        .unwrap();
+    let html_page = app.get_admin_subscribers_html("tag=conference-2026").await;
+    // reader1 and reader10 to reader19
+    assert!(html_page.contains("(19 subscribers)"));
+}
+
+#[tokio::test]
+async fn signed_tags_are_attached_at_signup() {
+    let app = spawn_app().await;
+    app.mock_email_server().await;
+    app.test_user.login(&app).await;
+    let token = signup_token(&app, "beta-tester,vip").await;
+
+    let response = app
+        .post_subscriptions(&[
+            ("name", "le guin"),
+            ("email", "ursula@example.com"),
+            ("tags", token.as_str()),
+        ])
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        tags_of(&app.db, "ursula@example.com").await,
+        vec!["beta-tester", "vip"]
+    );
+
+    let response = app
+        .http_client
+        .post(format!(
+            "{}/subscriptions?tags={}",
+            &app.address,
+            urlencoding::encode(&token)
+        ))
+        .json(&serde_json::json!({
+            "name": "butler",
+            "email": "octavia@example.com",
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        tags_of(&app.db, "octavia@example.com").await,
+        vec!["beta-tester", "vip"]
+    );
+
+    let html_page = app
+        .http_client
+        .get(format!(
+            "{}/?tags={}",
+            &app.address,
+            urlencoding::encode(&token)
+        ))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains(&format!(
+        r#"<input type="hidden" name="tags" value="{}">"#,
+        htmlescape::encode_attribute(&token)
+    )));
+}
+
+#[tokio::test]
+async fn tags_with_a_bad_signature_are_ignored() {
+    let app = spawn_app().await;
+    app.mock_email_server().await;
+    app.test_user.login(&app).await;
+    let token = signup_token(&app, "beta-tester").await;
+    let (_, signature) = token.rsplit_once('.').unwrap();
+
+    let response = app
+        .post_subscriptions(&[
+            ("name", "le guin"),
+            ("email", "ursula@example.com"),
+            ("tags", &format!("vip.{}", signature)),
+        ])
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(tags_of(&app.db, "ursula@example.com").await.is_empty());
+}
+
+#[tokio::test]
+async fn deleting_a_tag_takes_it_off_every_subscriber() {
+    let app = spawn_app().await;
+    let subscriber_id = app
+        .insert_subscriber(TestSubscriber::new("ursula@example.com").name("Ursula Le Guin"))
+        .await;
+    app.test_user.login(&app).await;
+    app.http_client
+        .post(format!(
+            "{}/admin/subscribers/{}/tags",
+            &app.address, subscriber_id
+        ))
+        .form(&[("action", "add"), ("tags", "vip")])
+        .send()
+        .await
+        .unwrap();
+
+    let html_page = app
+        .http_client
+        .get(format!("{}/admin/tags", &app.address))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains(r#"<a href="/admin/subscribers?tag=vip">vip</a></td><td>1</td>"#));
+
+    let response = app
+        .http_client
+        .post(format!("{}/admin/tags/vip/delete", &app.address))
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/admin/tags");
+    assert!(tags_of(&app.db, "ursula@example.com").await.is_empty());
+}
@@ -3,7 +3,7 @@ use wiremock::{
     Mock, ResponseTemplate,
 };
 
-use crate::helpers::spawn_app;
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with};
 
 #[tokio::test]
 async fn subscribe_returns_200_for_valid_form_data() {
@@ -55,7 +55,6 @@ async fn subscribe_persists_the_new_subscriber() {
     assert_eq!(saved.email, email);
     assert_eq!(saved.name, name);
     assert_eq!(saved.status, "pending_confirmation");
-    ()
 }
 
 #[tokio::test]
@@ -91,6 +90,34 @@ async fn subscribe_returns_409_when_email_already_subscribed() {
     assert_eq!(409, response.status().as_u16());
 }
 
+#[tokio::test]
+async fn subscribe_normalizes_the_email_and_ignores_case_for_duplicates() {
+    let app = spawn_app().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscriptions(&[("email", " Ursula@Example.COM "), ("name", "le guin")])
+        .await;
+    assert_eq!(200, response.status().as_u16());
+
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db)
+        .await
+        .expect("failed to fetch saved subscription");
+    assert_eq!(saved.email, "Ursula@example.com");
+
+    let response = app
+        .post_subscriptions(&[("email", "ursula@example.com"), ("name", "le guin")])
+        .await;
+    assert_eq!(409, response.status().as_u16());
+}
+
 #[tokio::test]
 async fn subscribe_returns_422_when_data_is_missing() {
     let app = spawn_app().await;
@@ -102,7 +129,7 @@ async fn subscribe_returns_422_when_data_is_missing() {
     ];
 
     for (invalid_body, error_message) in test_cases {
-        let response = app.post_subscriptions_raw(&invalid_body).await;
+        let response = app.post_subscriptions_raw(invalid_body).await;
 
         assert_eq!(
             422,
@@ -124,7 +151,7 @@ async fn subscribe_returns_400_when_fields_are_invalid() {
     ];
 
     for (invalid_body, error_message) in test_cases {
-        let response = app.post_subscriptions_raw(&invalid_body).await;
+        let response = app.post_subscriptions_raw(invalid_body).await;
 
         assert_eq!(
             400,
@@ -169,7 +196,84 @@ async fn subscribe_sends_a_confirmation_email_with_a_link() {
         .await;
 
     let email_request = &app.email_server.received_requests().await.unwrap()[0];
-    let confirmation_links = app.get_confirmation_links(&email_request);
+    let confirmation_links = app.get_confirmation_links(email_request);
 
     assert_eq!(confirmation_links.html, confirmation_links.plain_text);
 }
+
+#[tokio::test]
+async fn subscribe_shows_every_field_problem_on_an_error_page() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_subscriptions(&[("email", "not-an-email"), ("name", "<le guin>")])
+        .await;
+
+    assert_eq!(400, response.status().as_u16());
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("<li>name: &lt;le guin&gt; is not a valid subscriber name.</li>"));
+    assert!(html_page.contains("<li>email: not-an-email is not a valid subscriber email</li>"));
+}
+
+#[tokio::test]
+async fn subscribe_returns_json_errors_to_json_clients() {
+    let app = spawn_app().await;
+
+    let response = app
+        .http_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Accept", "application/json")
+        .form(&[("email", "not-an-email"), ("name", "le guin")])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(400, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["error"]["code"], "invalid_fields");
+    assert_eq!(
+        body["error"]["fields"]["email"],
+        "not-an-email is not a valid subscriber email"
+    );
+    assert!(body["error"]["fields"].get("name").is_none());
+
+    let response = app
+        .http_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Accept", "application/json")
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body("name=le%20guin")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(422, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["error"]["code"], "missing_fields");
+}
+
+#[tokio::test]
+async fn subscribe_redirects_to_the_configured_pages() {
+    let app = spawn_app_with(|c| {
+        c.subscription_pages.thank_you_url = Some("https://example.com/thanks".into());
+        c.subscription_pages.error_url = Some("https://example.com/oops?lang=en".into());
+    })
+    .await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscriptions(&[("email", "ursula@example.com"), ("name", "le guin")])
+        .await;
+    assert_is_redirect_to(&response, "https://example.com/thanks?outcome=subscribed");
+
+    let response = app
+        .post_subscriptions(&[("email", "ursula@example.com"), ("name", "le guin")])
+        .await;
+    assert_is_redirect_to(
+        &response,
+        "https://example.com/oops?lang=en&error=already_subscribed",
+    );
+}
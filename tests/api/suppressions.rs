@@ -0,0 +1,192 @@
+use uuid::Uuid;
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp, TestSubscriber};
+
+async fn add_suppression(app: &TestApp, pattern: &str, reason: &str) -> reqwest::Response {
+    app.http_client
+        .post(format!("{}/admin/suppressions", &app.address))
+        .form(&[("pattern", pattern), ("reason", reason), ("source", "")])
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn get_suppressions_html(app: &TestApp) -> String {
+    app.http_client
+        .get(format!("{}/admin/suppressions", &app.address))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap()
+}
+
+/// Recipients of the emails sent so far.
+async fn recipients(app: &TestApp) -> Vec<String> {
+    let mut recipients: Vec<String> = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .map(|request| {
+            let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+            body["To"].as_str().unwrap().to_owned()
+        })
+        .collect();
+    recipients.sort();
+    recipients
+}
+
+#[tokio::test]
+async fn suppressed_addresses_are_not_sent_a_confirmation_email() {
+    let app = spawn_app().await;
+    app.mock_email_server().await;
+    app.test_user.login(&app).await;
+
+    let response = add_suppression(&app, "Abuse@*", "role_address").await;
+    assert_is_redirect_to(&response, "/admin/suppressions");
+    let html_page = get_suppressions_html(&app).await;
+    assert!(html_page.contains("<i>The address has been added.</i>"));
+    assert!(html_page.contains("<td>abuse@*</td><td>role_address</td><td>manual</td>"));
+
+    let response = app
+        .post_subscriptions(&[("name", "abuse desk"), ("email", "abuse@example.com")])
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    app.post_subscriptions(&[("name", "le guin"), ("email", "ursula@example.com")])
+        .await;
+
+    assert_eq!(recipients(&app).await, vec!["ursula@example.com"]);
+}
+
+#[tokio::test]
+async fn newsletters_skip_suppressed_addresses_whatever_their_status() {
+    let app = spawn_app().await;
+    app.mock_email_server().await;
+    app.test_user.login(&app).await;
+    app.insert_subscriber(TestSubscriber::new("ursula@example.com"))
+        .await;
+    app.insert_subscriber(TestSubscriber::new("qa@test.example"))
+        .await;
+    app.insert_subscriber(TestSubscriber::new("Octavia@Example.com"))
+        .await;
+    app.insert_subscriber(TestSubscriber::new("reader@example.com"))
+        .await;
+    add_suppression(&app, "test.example", "internal").await;
+    add_suppression(&app, "octavia@example.com", "legal_request").await;
+    // Only `*` is a wildcard
+    add_suppression(&app, "r_ader@example.com", "other").await;
+
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    assert_eq!(
+        recipients(&app).await,
+        vec!["reader@example.com", "ursula@example.com"]
+    );
+}
+
+#[tokio::test]
+async fn lists_can_be_uploaded_in_bulk() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    add_suppression(&app, "bounced@example.com", "hard_bounce").await;
+
+    let boundary = "upload-boundary";
+    let body = format!(
+        "--{boundary}\r\n\
+        Content-Disposition: form-data; name=\"reason\"\r\n\r\n\
+        hard_bounce\r\n\
+        --{boundary}\r\n\
+        Content-Disposition: form-data; name=\"source\"\r\n\r\n\
+        \r\n\
+        --{boundary}\r\n\
+        Content-Disposition: form-data; name=\"file\"; filename=\"bounces.csv\"\r\n\
+        Content-Type: text/csv\r\n\r\n\
+        email,code\nbounced@example.com,550\ngone@example.com,550\nGone@example.com,550\nnot an address,550\r\n\
+        --{boundary}--\r\n"
+    );
+    let response = app
+        .http_client
+        .post(format!("{}/admin/suppressions/upload", &app.address))
+        .header(
+            "Content-Type",
+            format!("multipart/form-data; boundary={}", boundary),
+        )
+        .body(body)
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/admin/suppressions");
+
+    let html_page = get_suppressions_html(&app).await;
+    assert!(html_page.contains(
+        "1 addresses added, 1 were already on the list. 1 lines were not valid: not an address"
+    ));
+    assert!(html_page
+        .contains("<td>gone@example.com</td><td>hard_bounce</td><td>upload of bounces.csv</td>"));
+    assert!(html_page.contains("2 entries"));
+}
+
+#[tokio::test]
+async fn entries_can_be_removed() {
+    let app = spawn_app().await;
+    app.mock_email_server().await;
+    app.test_user.login(&app).await;
+    app.insert_subscriber(TestSubscriber::new("ursula@example.com"))
+        .await;
+    add_suppression(&app, "ursula@example.com", "other").await;
+
+    let suppression = sqlx::query!("SELECT suppression_id FROM email_suppressions")
+        .fetch_one(&app.db)
+        .await
+        .unwrap();
+    let response = app
+        .http_client
+        .post(format!(
+            "{}/admin/suppressions/{}/delete",
+            &app.address, suppression.suppression_id
+        ))
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/admin/suppressions");
+
+    app.post_newsletters(serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string()
+    }))
+    .await;
+    assert_eq!(recipients(&app).await, vec!["ursula@example.com"]);
+}
+
+#[tokio::test]
+async fn invalid_entries_are_refused() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    add_suppression(&app, "*@*", "other").await;
+    let html_page = get_suppressions_html(&app).await;
+    assert!(html_page.contains("<i>The pattern would match every address.</i>"));
+
+    add_suppression(&app, "ursula@example.com", "spam").await;
+    let html_page = get_suppressions_html(&app).await;
+    assert!(html_page.contains("<i>spam is not a valid suppression reason.</i>"));
+
+    let entries = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM email_suppressions"#)
+        .fetch_one(&app.db)
+        .await
+        .unwrap();
+    assert_eq!(entries.count, 0);
+}
@@ -0,0 +1,206 @@
+use totp_rs::{Algorithm, Secret, TOTP};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with, TestApp};
+
+fn extract_between<'a>(html: &'a str, start: &str, end: &str) -> Vec<&'a str> {
+    html.split(start)
+        .skip(1)
+        .map(|s| s.split(end).next().unwrap())
+        .collect()
+}
+
+/// Returns the code for the given number of time steps from now.
+fn code(secret: &str, steps_from_now: i64) -> String {
+    let bytes = Secret::Encoded(secret.to_owned()).to_bytes().unwrap();
+    let totp = TOTP::new(Algorithm::SHA1, 6, 1, 30, bytes, None, "".into()).unwrap();
+    let now = chrono::Utc::now().timestamp();
+    totp.generate((now + steps_from_now * 30) as u64)
+}
+
+/// Enrolls the logged in test user and returns their secret and recovery codes.
+async fn enroll(app: &TestApp) -> (String, Vec<String>) {
+    let html_page = app.get_two_factor_html().await;
+    assert!(html_page.contains("otpauth://totp/"));
+
+    let secret = extract_between(&html_page, "Secret: <code>", "</code>")[0].to_owned();
+
+    let response = app
+        .post_two_factor(&serde_json::json!({ "code": code(&secret, 0) }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let html_page = response.text().await.unwrap();
+    let recovery_codes: Vec<String> = extract_between(&html_page, "<li><code>", "</code>")
+        .into_iter()
+        .map(String::from)
+        .collect();
+    assert_eq!(recovery_codes.len(), 10);
+
+    (secret, recovery_codes)
+}
+
+#[tokio::test]
+async fn login_requires_a_code_once_two_factor_is_enabled() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let (secret, _) = enroll(&app).await;
+    app.post_logout().await;
+
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login/two-factor");
+
+    // The password alone is not enough to reach the admin area
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+
+    let response = app
+        .post_login_two_factor(&serde_json::json!({ "code": "000000" }))
+        .await;
+    assert_is_redirect_to(&response, "/login/two-factor");
+
+    // The code used for enrollment cannot be replayed, use the next one
+    let response = app
+        .post_login_two_factor(&serde_json::json!({ "code": code(&secret, 1) }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    let response = app.get_admin_dashboard().await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn recovery_codes_can_only_be_used_once() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let (_, recovery_codes) = enroll(&app).await;
+
+    for expected in ["/admin/dashboard", "/login/two-factor"] {
+        app.post_logout().await;
+        app.test_user.login(&app).await;
+
+        let response = app
+            .post_login_two_factor(&serde_json::json!({ "code": &recovery_codes[0] }))
+            .await;
+        assert_is_redirect_to(&response, expected);
+    }
+}
+
+#[tokio::test]
+async fn the_second_step_requires_a_verified_password() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_login_two_factor(&serde_json::json!({ "code": "000000" }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn enforced_two_factor_sends_unenrolled_users_to_enrollment() {
+    let app = spawn_app_with(|c| c.two_factor.enforce = true).await;
+    app.test_user.login(&app).await;
+
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/admin/two-factor");
+
+    enroll(&app).await;
+
+    let response = app.get_admin_dashboard().await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn the_password_must_be_entered_again_after_too_many_wrong_codes() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let (secret, _) = enroll(&app).await;
+    app.post_logout().await;
+    app.test_user.login(&app).await;
+
+    for _ in 0..4 {
+        let response = app
+            .post_login_two_factor(&serde_json::json!({ "code": "000000" }))
+            .await;
+        assert_is_redirect_to(&response, "/login/two-factor");
+    }
+    let response = app
+        .post_login_two_factor(&serde_json::json!({ "code": "000000" }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+
+    // Even the right code is refused without the password
+    let response = app
+        .post_login_two_factor(&serde_json::json!({ "code": code(&secret, 1) }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn wrong_codes_count_towards_the_login_lockout() {
+    let app = spawn_app_with(|c| c.login_throttling.max_failures = 3).await;
+    app.test_user.login(&app).await;
+
+    let (secret, _) = enroll(&app).await;
+
+    // A new password login does not reset the count
+    for _ in 0..3 {
+        app.post_logout().await;
+        app.test_user.login(&app).await;
+        app.post_login_two_factor(&serde_json::json!({ "code": "000000" }))
+            .await;
+    }
+
+    app.post_logout().await;
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+    let locked =
+        sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM login_attempts WHERE outcome = 'locked'"#)
+            .fetch_one(&app.db)
+            .await
+            .unwrap();
+    assert_eq!(locked.count, 1);
+
+    let response = app
+        .post_login_two_factor(&serde_json::json!({ "code": code(&secret, 1) }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn wrong_codes_when_disabling_are_throttled_like_at_login() {
+    let app = spawn_app_with(|c| c.login_throttling.max_failures = 3).await;
+    app.test_user.login(&app).await;
+
+    let (secret, _) = enroll(&app).await;
+
+    for _ in 0..3 {
+        let response = app
+            .post_two_factor_disable(&serde_json::json!({ "code": "000000" }))
+            .await;
+        assert_is_redirect_to(&response, "/admin/two-factor");
+    }
+    let html_page = app.get_two_factor_html().await;
+    assert!(html_page.contains("The code is not valid, please try again."));
+
+    // Even the right code is refused until the lockout is over
+    app.post_two_factor_disable(&serde_json::json!({ "code": code(&secret, 1) }))
+        .await;
+    let html_page = app.get_two_factor_html().await;
+    assert!(html_page.contains("Too many failed attempts, please try again later."));
+    assert!(html_page.contains("Two-factor authentication is enabled."));
+}
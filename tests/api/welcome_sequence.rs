@@ -0,0 +1,275 @@
+use wiremock::matchers::{body_partial_json, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use email_service::welcome_sequence::{send_due_emails, stop_departed_subscribers};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp, TestSubscriber};
+
+async fn add_step(app: &TestApp, delay_days: &str, subject: &str) {
+    let response = app
+        .http_client
+        .post(format!("{}/admin/welcome-sequence", &app.address))
+        .form(&[
+            ("delay_days", delay_days),
+            ("subject", subject),
+            ("text_content", "Hello"),
+            ("html_content", "<p>Hello</p>"),
+        ])
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/admin/welcome-sequence");
+}
+
+/// Subjects of the emails sent, other than the confirmation ones.
+async fn sequence_subjects(app: &TestApp) -> Vec<String> {
+    app.email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .map(|request| {
+            let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+            body["Subject"].as_str().unwrap().to_owned()
+        })
+        .filter(|subject| subject != "Welcome!")
+        .collect()
+}
+
+/// Moves the enrollments and the steps back in time.
+async fn travel_days(app: &TestApp, days: i32) {
+    sqlx::query!(
+        "UPDATE sequence_enrollments SET enrolled_at = enrolled_at - make_interval(days => $1)",
+        days
+    )
+    .execute(&app.db)
+    .await
+    .unwrap();
+    sqlx::query!(
+        "UPDATE sequence_steps SET created_at = created_at - make_interval(days => $1)",
+        days
+    )
+    .execute(&app.db)
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn confirmed_subscribers_are_sent_each_step_when_it_is_due() {
+    let app = spawn_app().await;
+    app.mock_email_server().await;
+    app.test_user.login(&app).await;
+    add_step(&app, "0", "Start here").await;
+    add_step(&app, "3", "The best of").await;
+    let client = app.email_client();
+
+    app.post_subscriptions(&[("name", "le guin"), ("email", "ursula@example.com")])
+        .await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    assert_eq!(send_due_emails(&app.db, &client, 10).await.unwrap(), 1);
+    assert_eq!(sequence_subjects(&app).await, vec!["Start here"]);
+    assert_eq!(send_due_emails(&app.db, &client, 10).await.unwrap(), 0);
+
+    travel_days(&app, 4).await;
+    assert_eq!(send_due_emails(&app.db, &client, 10).await.unwrap(), 1);
+    assert_eq!(
+        sequence_subjects(&app).await,
+        vec!["Start here", "The best of"]
+    );
+
+    let html_page = app
+        .http_client
+        .get(format!("{}/admin/welcome-sequence", &app.address))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("<td>after 3 days</td><td>The best of</td><td>1</td>"));
+
+    let subscriber = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db)
+        .await
+        .unwrap();
+    let html_page = app.get_admin_subscriber_html(subscriber.id).await;
+    assert!(html_page.contains("<h2>Welcome sequence</h2>"));
+    assert!(!html_page.contains("pending"));
+}
+
+#[tokio::test]
+async fn unsubscribing_stops_the_sequence() {
+    let app = spawn_app().await;
+    app.mock_email_server().await;
+    app.test_user.login(&app).await;
+    add_step(&app, "0", "Start here").await;
+    add_step(&app, "14", "How are we doing?").await;
+    let client = app.email_client();
+    let subscriber_id = app
+        .insert_subscriber(
+            TestSubscriber::new("ursula@example.com")
+                .name("Ursula Le Guin")
+                .status("pending_confirmation"),
+        )
+        .await;
+
+    app.post_admin_subscriber_action(subscriber_id, "confirm")
+        .await;
+    send_due_emails(&app.db, &client, 10).await.unwrap();
+    let html_page = app.get_admin_subscriber_html(subscriber_id).await;
+    assert!(html_page.contains("<td>How are we doing?</td>"));
+    assert!(html_page.contains("<td>pending</td>"));
+
+    app.post_admin_subscriber_action(subscriber_id, "unsubscribe")
+        .await;
+    travel_days(&app, 15).await;
+    assert_eq!(send_due_emails(&app.db, &client, 10).await.unwrap(), 0);
+    assert_eq!(sequence_subjects(&app).await, vec!["Start here"]);
+
+    let html_page = app.get_admin_subscriber_html(subscriber_id).await;
+    assert!(html_page.contains(", stopped "));
+    assert!(html_page.contains("<td>not sent</td>"));
+}
+
+#[tokio::test]
+async fn subscribers_leaving_through_an_import_are_stopped() {
+    let app = spawn_app().await;
+    app.mock_email_server().await;
+    app.test_user.login(&app).await;
+    add_step(&app, "1", "Day one").await;
+    let client = app.email_client();
+    let subscriber_id = app
+        .insert_subscriber(
+            TestSubscriber::new("ursula@example.com")
+                .name("Ursula Le Guin")
+                .status("pending_confirmation"),
+        )
+        .await;
+    app.post_admin_subscriber_action(subscriber_id, "confirm")
+        .await;
+
+    let response = app
+        .post_subscriber_import(
+            "email,name,status\nursula@example.com,Ursula Le Guin,unsubscribed\n",
+            "mark_confirmed",
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 303);
+
+    assert_eq!(stop_departed_subscribers(&app.db).await.unwrap(), 1);
+    travel_days(&app, 2).await;
+    assert_eq!(send_due_emails(&app.db, &client, 10).await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn steps_added_later_are_only_sent_to_those_they_are_not_yet_due_for() {
+    let app = spawn_app().await;
+    app.mock_email_server().await;
+    app.test_user.login(&app).await;
+    let client = app.email_client();
+    let subscriber_id = app
+        .insert_subscriber(
+            TestSubscriber::new("ursula@example.com")
+                .name("Ursula Le Guin")
+                .status("pending_confirmation"),
+        )
+        .await;
+    app.post_admin_subscriber_action(subscriber_id, "confirm")
+        .await;
+    travel_days(&app, 10).await;
+
+    add_step(&app, "3", "The best of").await;
+    add_step(&app, "14", "How are we doing?").await;
+    assert_eq!(send_due_emails(&app.db, &client, 10).await.unwrap(), 0);
+
+    travel_days(&app, 5).await;
+    assert_eq!(send_due_emails(&app.db, &client, 10).await.unwrap(), 1);
+    assert_eq!(sequence_subjects(&app).await, vec!["How are we doing?"]);
+
+    let html_page = app.get_admin_subscriber_html(subscriber_id).await;
+    assert!(!html_page.contains("The best of"));
+}
+
+#[tokio::test]
+async fn a_failed_email_does_not_hold_back_the_rest_of_the_batch() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    add_step(&app, "0", "Start here").await;
+    let client = app.email_client();
+    for email in ["octavia@example.com", "ursula@example.com"] {
+        let subscriber_id = app
+            .insert_subscriber(TestSubscriber::new(email).status("pending_confirmation"))
+            .await;
+        app.post_admin_subscriber_action(subscriber_id, "confirm")
+            .await;
+    }
+
+    // Octavia confirmed first, her step comes first in the batch
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .and(body_partial_json(
+            serde_json::json!({ "To": "octavia@example.com" }),
+        ))
+        .respond_with(ResponseTemplate::new(500))
+        .up_to_n_times(1)
+        .mount(&app.email_server)
+        .await;
+    app.mock_email_server().await;
+
+    assert_eq!(send_due_emails(&app.db, &client, 10).await.unwrap(), 1);
+    // The failed step is tried again on the next run
+    assert_eq!(send_due_emails(&app.db, &client, 10).await.unwrap(), 1);
+    assert_eq!(send_due_emails(&app.db, &client, 10).await.unwrap(), 0);
+
+    let recipients: Vec<String> = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .map(|request| {
+            let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+            body["To"].as_str().unwrap().to_owned()
+        })
+        .collect();
+    assert_eq!(
+        recipients,
+        [
+            "octavia@example.com",
+            "ursula@example.com",
+            "octavia@example.com"
+        ]
+    );
+}
+
+#[tokio::test]
+async fn invalid_steps_are_refused() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    add_step(&app, "-1", "Too soon").await;
+    add_step(&app, "3", " ").await;
+
+    let html_page = app
+        .http_client
+        .get(format!("{}/admin/welcome-sequence", &app.address))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("<i>The subject is missing.</i>"));
+    let steps = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM sequence_steps"#)
+        .fetch_one(&app.db)
+        .await
+        .unwrap();
+    assert_eq!(steps.count, 0);
+}
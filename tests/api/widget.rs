@@ -0,0 +1,132 @@
+use reqwest::Method;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::{spawn_app, spawn_app_with, TestApp};
+
+async fn post_subscriptions_json(app: &TestApp, body: serde_json::Value) -> reqwest::Response {
+    app.http_client
+        .post(format!("{}/subscriptions", &app.address))
+        .json(&body)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+#[tokio::test]
+async fn subscriptions_can_be_sent_as_json() {
+    let app = spawn_app().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = post_subscriptions_json(
+        &app,
+        serde_json::json!({
+            "name": "le guin",
+            "email": "ursula@example.com",
+            "source": "https://www.example.com/blog",
+        }),
+    )
+    .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "subscribed");
+
+    let consent = sqlx::query!("SELECT source FROM subscription_consents")
+        .fetch_one(&app.db)
+        .await
+        .unwrap();
+    assert_eq!(
+        consent.source.as_deref(),
+        Some("https://www.example.com/blog")
+    );
+}
+
+#[tokio::test]
+async fn json_subscriptions_get_structured_errors() {
+    let app = spawn_app().await;
+
+    let response = post_subscriptions_json(
+        &app,
+        serde_json::json!({ "name": "", "email": "not-an-email" }),
+    )
+    .await;
+    assert_eq!(response.status().as_u16(), 400);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["error"]["code"], "invalid_fields");
+    assert!(body["error"]["fields"]["name"].is_string());
+    assert!(body["error"]["fields"]["email"].is_string());
+
+    let response = post_subscriptions_json(&app, serde_json::json!({ "name": "le guin" })).await;
+    assert_eq!(response.status().as_u16(), 422);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["error"]["code"], "missing_fields");
+}
+
+#[tokio::test]
+async fn only_allowed_origins_may_call_the_subscription_endpoints() {
+    let app = spawn_app_with(|c| {
+        c.widget.allowed_origins = vec!["https://www.example.com".into()];
+    })
+    .await;
+
+    let preflight = |origin: &'static str| {
+        app.http_client
+            .request(Method::OPTIONS, format!("{}/subscriptions", &app.address))
+            .header("Origin", origin)
+            .header("Access-Control-Request-Method", "POST")
+            .header("Access-Control-Request-Headers", "content-type")
+            .send()
+    };
+
+    let response = preflight("https://www.example.com").await.unwrap();
+    assert_eq!(
+        response
+            .headers()
+            .get("Access-Control-Allow-Origin")
+            .unwrap(),
+        "https://www.example.com"
+    );
+
+    let response = preflight("https://evil.example.net").await.unwrap();
+    assert!(response
+        .headers()
+        .get("Access-Control-Allow-Origin")
+        .is_none());
+}
+
+#[tokio::test]
+async fn the_widget_script_posts_to_this_app() {
+    let app = spawn_app().await;
+
+    let response = app
+        .http_client
+        .get(format!("{}/widget.js", &app.address))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(
+        response.headers().get("Content-Type").unwrap(),
+        "text/javascript; charset=utf-8"
+    );
+    let script = response.text().await.unwrap();
+    assert!(script.contains(r#"const baseUrl = "http://127.0.0.1";"#));
+
+    let token: serde_json::Value = app
+        .http_client
+        .get(format!("{}/subscriptions/form-token", &app.address))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert!(token["form_token"].is_string());
+    assert_eq!(token["pow_difficulty"], 0);
+}